@@ -0,0 +1,94 @@
+// Times the construction and backward pass of the graphs built by `engine::build_*`, training steps with and
+// without a `CompiledGraph`, and inference through an `MLP` by building its graph against `MLP::eval_f64`.
+// `cargo bench --features bench` runs the full measurements; without the `--bench` flag passed by
+// `cargo bench` (e.g. `cargo test --benches --features bench`) each body runs once as a smoke test.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use angstromgrad::engine::{build_chain, build_mlp_graph, build_tree};
+use angstromgrad::nn::MLP;
+use angstromgrad::Value;
+
+fn bench(name: &str, iterations: u32, build: impl Fn() -> Value) {
+    let mut construct = Duration::ZERO;
+    let mut backward = Duration::ZERO;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let root = black_box(build());
+        construct += start.elapsed();
+
+        let start = Instant::now();
+        root.backward_unchecked();
+        backward += start.elapsed();
+    }
+    println!(
+        "{:<24} construct {:>12?}   backward {:>12?}",
+        name,
+        construct / iterations,
+        backward / iterations
+    );
+}
+
+fn bench_inference(name: &str, iterations: u32, mlp: &MLP, x: &[f64]) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let inputs: Vec<Value> = x.iter().map(|&x| Value::from(x)).collect();
+        black_box(mlp.forward(black_box(inputs)));
+    }
+    let graph = start.elapsed() / iterations;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(mlp.eval_f64(black_box(x)));
+    }
+    let plain = start.elapsed() / iterations;
+
+    let speedup = graph.as_secs_f64() / plain.as_secs_f64().max(f64::MIN_POSITIVE);
+    println!("{:<24} graph {:>12?}   eval_f64 {:>12?}   ({:.1}x)", name, graph, plain, speedup);
+    // an order of magnitude at least, in the optimized builds of `cargo bench`
+    if iterations > 1 && !cfg!(debug_assertions) {
+        assert!(speedup >= 10.0, "eval_f64 is only {:.1}x faster than building the graph", speedup);
+    }
+}
+
+// One training step over the same graph, back-propagated from scratch against through a `CompiledGraph`, which
+// reuses its topological order and recomputes the data in place.
+fn bench_compiled(name: &str, iterations: u32, root: &Value) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        root.zero_grad_all();
+        root.backward_unchecked();
+    }
+    let uncached = start.elapsed() / iterations;
+
+    let mut compiled = root.compile();
+    let inputs: Vec<f64> = compiled.leaves().iter().map(Value::data).collect();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(compiled.forward(&inputs));
+        for leaf in compiled.leaves() {
+            leaf.zero_grad();
+        }
+        compiled.backward().unwrap();
+    }
+    let cached = start.elapsed() / iterations;
+
+    let speedup = uncached.as_secs_f64() / cached.as_secs_f64().max(f64::MIN_POSITIVE);
+    println!("{:<24} backward {:>12?}   compiled {:>12?}   ({:.1}x)", name, uncached, cached, speedup);
+}
+
+fn main() {
+    let iterations = if std::env::args().any(|arg| arg == "--bench") { 100 } else { 1 };
+
+    bench("chain(1000)", iterations, || build_chain(1000));
+    bench("tree(12)", iterations, || build_tree(12));
+    bench("mlp([8, 32, 32, 1])", iterations, || build_mlp_graph(&[8, 32, 32, 1]));
+
+    // about 50k nodes, see `build_mlp_graph`
+    bench_compiled("step(mlp, 50k nodes)", iterations, &build_mlp_graph(&[32, 128, 160, 1]));
+
+    angstromgrad::seed(0);
+    let mlp = MLP::new(8, vec![32, 32, 1]);
+    bench_inference("infer([8, 32, 32, 1])", iterations, &mlp, &[0.5; 8]);
+}
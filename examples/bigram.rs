@@ -0,0 +1,112 @@
+// A character-level bigram language model: each character is embedded, and a linear head turns the embedding
+// into the logits of the next character. Training on a short text brings the cross-entropy below the entropy
+// of the character frequencies, the best a model ignoring the previous character can do, which is checked
+// before sampling some text from the model.
+//
+// cargo run --release --example bigram
+
+use angstromgrad::data::CharVocab;
+use angstromgrad::loss::{cross_entropy, mean};
+use angstromgrad::nn::{Init, Module};
+use angstromgrad::ops::softmax;
+use angstromgrad::rand::Rng;
+use angstromgrad::{Embedding, Linear, Value};
+
+const TEXT: &str = "the quick brown fox jumps over the lazy dog. the dog sleeps in the sun and the fox \
+runs into the woods. then the fox comes back to the dog and the two of them sit in the shade of the old \
+tree. the sun sets over the hills, the wind turns cold, and the fox and the dog go home to rest. in the \
+morning the sun rises again, the birds sing in the tree, and the quick brown fox jumps over the lazy dog \
+once more.";
+
+struct Bigram {
+    embedding: Embedding,
+    head: Linear,
+}
+
+impl Bigram {
+    fn new(vocab_size: usize, dim: usize, rng: &mut Rng) -> Bigram {
+        Bigram {
+            embedding: Embedding::new(vocab_size, dim, rng),
+            head: Linear::new(dim, vocab_size, true, Init::Xavier, rng),
+        }
+    }
+
+    fn parameters(&self) -> Vec<&Value> {
+        self.embedding.parameters().into_iter().chain(self.head.parameters()).collect()
+    }
+
+    // The logits of the character following `previous`.
+    fn logits(&self, previous: usize) -> Vec<Value> {
+        self.head.forward(&self.embedding.lookup(previous))
+    }
+
+    // The mean cross-entropy of predicting each character of `pairs` from the one before it.
+    fn loss(&self, pairs: &[(usize, usize)]) -> Value {
+        let terms: Vec<Value> = pairs
+            .iter()
+            .map(|&(previous, next)| cross_entropy(&self.logits(previous), next, 0.0))
+            .collect();
+        mean(&terms)
+    }
+}
+
+// The entropy, in nats, of the frequencies of the characters that follow another one.
+fn unigram_entropy(pairs: &[(usize, usize)], vocab_size: usize) -> f64 {
+    let mut counts = vec![0.0; vocab_size];
+    for &(_, next) in pairs {
+        counts[next] += 1.0;
+    }
+    let n = pairs.len() as f64;
+    counts.iter().filter(|&&c| c > 0.0).map(|&c| -(c / n) * (c / n).ln()).sum()
+}
+
+fn main() {
+    let vocab = CharVocab::fit(TEXT);
+    let encoded = vocab.encode(TEXT);
+    let pairs: Vec<(usize, usize)> = encoded.windows(2).map(|w| (w[0], w[1])).collect();
+    let baseline = unigram_entropy(&pairs, vocab.len());
+    println!("{} characters, {} in the vocabulary, unigram entropy {:.4}", TEXT.len(), vocab.len(), baseline);
+
+    let mut rng = Rng::seed(0);
+    let model = Bigram::new(vocab.len(), 8, &mut rng);
+
+    for step in 0..500 {
+        let batch: Vec<(usize, usize)> = (0..32)
+            .map(|_| pairs[rng.next_u64() as usize % pairs.len()])
+            .collect();
+        for parameter in model.parameters() {
+            parameter.zero_grad();
+        }
+        let loss = model.loss(&batch);
+        loss.backward().expect("the loss graph is well formed");
+        for parameter in model.parameters() {
+            parameter.descend(0.5);
+        }
+        if step % 50 == 0 {
+            println!("step {:>3}  batch loss {:.4}", step, loss.data());
+        }
+    }
+
+    let loss = model.loss(&pairs).data();
+    println!("loss over the text {:.4}", loss);
+    assert!(loss < baseline, "loss {} should be below the unigram entropy {}", loss, baseline);
+
+    let mut sample = vec![vocab.index('t').expect("the text contains a t")];
+    for _ in 0..80 {
+        let probabilities: Vec<f64> = softmax(&model.logits(*sample.last().unwrap()))
+            .iter()
+            .map(Value::data)
+            .collect();
+        sample.push(rng.categorical(&probabilities));
+    }
+    println!("{}", vocab.decode(&sample));
+}
+
+#[cfg(test)]
+mod tests {
+    // `cargo test` trains the model and checks its loss against the baseline
+    #[test]
+    fn beats_the_unigram_baseline() {
+        super::main();
+    }
+}
@@ -0,0 +1,57 @@
+// The classifier of the `xor`, `moons` and `prune_xor` examples: an `MLP` from points of the plane to the logit
+// of the probability that a point has label 1, trained by gradient descent on the binary cross-entropy.
+
+use angstromgrad::loss::{bce_with_logits, mean};
+use angstromgrad::nn::{Activation, MLP};
+use angstromgrad::optim::Sgd;
+use angstromgrad::train::Trainer;
+use angstromgrad::Value;
+
+// `hidden` tanh neurons and a linear output neuron, initialized from a fixed seed so that runs are reproducible.
+pub fn classifier(hidden: usize) -> Trainer<MLP, Sgd> {
+    angstromgrad::seed(0);
+    let model = MLP::new(2, vec![hidden, 1]).with_activations(Activation::Tanh, Activation::Identity);
+    Trainer::new(model, Sgd::new(0.5))
+}
+
+// Trains for `epochs` passes over the points, one step per mini-batch of `batch_size` consecutive points on the
+// mean loss of the batch, calling `after_step` after each step, and returns the mean loss of each epoch.
+pub fn train(
+    trainer: &mut Trainer<MLP, Sgd>,
+    points: &[Vec<f64>],
+    labels: &[f64],
+    batch_size: usize,
+    epochs: usize,
+    mut after_step: impl FnMut(&MLP),
+) -> Vec<f64> {
+    let mut epoch_losses = Vec::new();
+    for _ in 0..epochs {
+        let mut losses = Vec::new();
+        for start in (0..points.len()).step_by(batch_size) {
+            let batch = start..points.len().min(start + batch_size);
+            let loss = trainer.step(|model| {
+                let terms: Vec<Value> = std::iter::zip(&points[batch.clone()], &labels[batch.clone()])
+                    .map(|(point, &label)| bce_with_logits(&logit(model, point), label))
+                    .collect();
+                Ok(mean(&terms))
+            });
+            losses.push(loss.expect("the loss and its gradients are finite"));
+            after_step(trainer.model());
+        }
+        epoch_losses.push(losses.iter().sum::<f64>() / losses.len() as f64);
+        trainer.end_epoch().expect("no step monitor limits the growth of the graph");
+    }
+    epoch_losses
+}
+
+// The fraction of `points` on the side of the decision boundary of their label.
+pub fn accuracy(model: &MLP, points: &[Vec<f64>], labels: &[f64]) -> f64 {
+    let correct = std::iter::zip(points, labels)
+        .filter(|(point, &label)| (model.eval_f64(point)[0] > 0.0) == (label == 1.0))
+        .count();
+    correct as f64 / points.len() as f64
+}
+
+fn logit(model: &MLP, point: &[f64]) -> Value {
+    model.forward(point.iter().map(|&x| Value::constant(x)).collect()).remove(0)
+}
@@ -0,0 +1,34 @@
+// Trains a small network to separate the two interleaving half circles of `data::make_moons`, printing the
+// loss as it decreases, and checks that the final accuracy on the training set is above 95%.
+//
+// cargo run --release --example moons
+
+mod common;
+
+use angstromgrad::data::make_moons;
+
+// Trains the classifier on 200 points and returns its accuracy on them.
+fn train_and_evaluate() -> f64 {
+    let (points, labels) = make_moons(200, 0.1, 0);
+    let mut trainer = common::classifier(16);
+    let losses = common::train(&mut trainer, &points, &labels, 20, 50, |_| {});
+    for (epoch, loss) in losses.iter().enumerate().step_by(5) {
+        println!("epoch {:>3}  loss {:.4}", epoch, loss);
+    }
+    common::accuracy(trainer.model(), &points, &labels)
+}
+
+fn main() {
+    let accuracy = train_and_evaluate();
+    println!("accuracy {:.1}%", 100.0 * accuracy);
+    assert!(accuracy > 0.95, "accuracy {} should be above 95%", accuracy);
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn separates_the_moons() {
+        let accuracy = super::train_and_evaluate();
+        assert!(accuracy > 0.95, "accuracy {} should be above 95%", accuracy);
+    }
+}
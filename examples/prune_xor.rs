@@ -0,0 +1,48 @@
+// Trains the XOR classifier of the `xor` example, prunes half of its weights by magnitude and retrains it
+// with the pruned weights held at zero, checking that accuracy on the training set stays above 90%.
+//
+// cargo run --release --example prune_xor
+
+mod common;
+
+use angstromgrad::data::make_xor;
+use angstromgrad::nn::prune_by_magnitude;
+
+// Trains, prunes and retrains the classifier on 200 points and returns its accuracy on them at the end.
+fn train_prune_and_retrain() -> f64 {
+    let (points, labels) = make_xor(200, 0);
+    let mut trainer = common::classifier(16);
+
+    common::train(&mut trainer, &points, &labels, 20, 50, |_| {});
+    let dense = common::accuracy(trainer.model(), &points, &labels);
+    println!("dense accuracy {:.1}%", 100.0 * dense);
+
+    let parameters = trainer.model().parameters();
+    let mask = prune_by_magnitude(&parameters, 0.5);
+    println!("pruned {} of {} parameters", mask.count(), parameters.len());
+    println!("accuracy after pruning {:.1}%", 100.0 * common::accuracy(trainer.model(), &points, &labels));
+
+    common::train(&mut trainer, &points, &labels, 20, 10, |_| mask.apply_after_step(&parameters));
+    let sparse = common::accuracy(trainer.model(), &points, &labels);
+    println!("accuracy after retraining {:.1}%", 100.0 * sparse);
+
+    for (i, parameter) in parameters.iter().enumerate() {
+        assert!(!mask.is_pruned(i) || parameter.data() == 0.0, "pruned parameter {} moved", i);
+    }
+    sparse
+}
+
+fn main() {
+    let sparse = train_prune_and_retrain();
+    assert!(sparse > 0.9, "accuracy {} at 50% sparsity should be above 90%", sparse);
+}
+
+#[cfg(test)]
+mod tests {
+    // `cargo test` trains, prunes and retrains the classifier, checking the accuracy at 50% sparsity
+    #[test]
+    fn stays_accurate_at_half_sparsity() {
+        let sparse = super::train_prune_and_retrain();
+        assert!(sparse > 0.9, "accuracy {} at 50% sparsity should be above 90%", sparse);
+    }
+}
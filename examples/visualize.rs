@@ -0,0 +1,30 @@
+// Builds the single neuron `o = tanh(w1*x1 + w2*x2 + b)` and writes its graph in the Graphviz DOT language
+// twice: `graph_forward.dot` after the forward pass, without the grads that are all still zero, and
+// `graph_backward.dot` after the backward pass, with the grads.
+//
+// cargo run --example visualize && dot -Tsvg graph_backward.dot -o graph_backward.svg
+
+use angstromgrad::engine::{DotOptions, RankDir};
+use angstromgrad::Value;
+
+fn main() -> std::io::Result<()> {
+    let x1 = Value::from(2.0).add_label("x1");
+    let x2 = Value::from(0.0).add_label("x2");
+    let w1 = Value::from(-3.0).add_label("w1");
+    let w2 = Value::from(1.0).add_label("w2");
+    let b = Value::from(6.881_373_587_019_543).add_label("b");
+
+    let x1w1 = (&x1 * &w1).add_label("x1*w1");
+    let x2w2 = (&x2 * &w2).add_label("x2*w2");
+    let n = (&(&x1w1 + &x2w2) + &b).add_label("n");
+    let o = n.tanh().add_label("o");
+
+    let options = DotOptions { show_grad: false, rankdir: RankDir::LeftRight, precision: 4 };
+    o.write_dot_with_options("graph_forward.dot", &options)?;
+
+    o.backward().expect("the graph is well formed");
+    o.write_dot_with_options("graph_backward.dot", &DotOptions { show_grad: true, ..options })?;
+
+    println!("wrote graph_forward.dot and graph_backward.dot");
+    Ok(())
+}
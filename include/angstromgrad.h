@@ -0,0 +1,32 @@
+/* C interface to angstromgrad, built with `cargo build --release --features ffi`.
+ *
+ * Every function returning an AgValue* gives the caller a new handle, to be released with ag_free_graph
+ * exactly once; functions taking handles leave them owned by the caller. Invalid handles (null, released,
+ * or created on another thread) make the handle-returning functions return NULL and the others
+ * AG_INVALID_HANDLE. */
+
+#ifndef ANGSTROMGRAD_H
+#define ANGSTROMGRAD_H
+
+typedef struct AgValue AgValue;
+
+typedef enum AgStatus {
+    AG_OK = 0,
+    AG_INVALID_HANDLE = 1,
+    AG_NULL_POINTER = 2,
+    AG_BACKWARD_FAILED = 3,
+    AG_PANIC = 4,
+} AgStatus;
+
+AgValue *ag_value_new(double data);
+AgValue *ag_add(const AgValue *a, const AgValue *b);
+AgValue *ag_mul(const AgValue *a, const AgValue *b);
+AgValue *ag_tanh(const AgValue *a);
+
+AgStatus ag_backward(const AgValue *a);
+AgStatus ag_data(const AgValue *a, double *out);
+AgStatus ag_grad(const AgValue *a, double *out);
+
+AgStatus ag_free_graph(AgValue *a);
+
+#endif
@@ -0,0 +1,51 @@
+# Tests of the Python bindings, run against the extension module built by maturin:
+#
+# maturin develop --release && pytest python/tests
+
+import math
+
+from angstromgrad import MLP, Value
+
+
+def test_shared_node_gradient():
+    x = Value(2.0)
+    y = Value(3.0)
+    z = x + y * x
+    z.backward()
+    assert z.data == 8.0
+    assert x.grad == 4.0
+    assert y.grad == 2.0
+
+
+def test_numbers_on_either_side():
+    x = Value(3.0)
+    z = 2 * x - 1 + x / 2 + x ** 2
+    z.backward()
+    assert z.data == 15.5
+    assert x.grad == 2 + 0.5 + 2 * 3.0
+
+
+def test_tanh():
+    x = Value(0.5)
+    y = x.tanh()
+    y.backward()
+    assert math.isclose(y.data, math.tanh(0.5))
+    assert math.isclose(x.grad, 1 - math.tanh(0.5) ** 2)
+
+
+def test_mlp_training_lowers_the_loss():
+    model = MLP(3, [4, 4, 1])
+    xs = [[2.0, 3.0, -1.0], [3.0, -1.0, 0.5], [0.5, 1.0, 1.0], [1.0, 1.0, -1.0]]
+    ys = [1.0, -1.0, -1.0, 1.0]
+
+    def loss():
+        return sum(((model(x) - y) ** 2 for x, y in zip(xs, ys)), Value(0.0))
+
+    first = loss().data
+    for _ in range(50):
+        model.zero_grad()
+        current = loss()
+        current.backward()
+        model.step(0.05)
+    assert loss().data < first
+    assert len(model.parameters()) == 4 * (3 + 1) + 4 * (4 + 1) + 1 * (4 + 1)
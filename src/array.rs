@@ -0,0 +1,90 @@
+// Conversions between graph nodes and `ndarray` arrays, so that data pipelines built on ndarray can feed models
+// without copying element by element. Arrays are always read and written in logical (row-major) order, whatever
+// their memory layout.
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2};
+
+use crate::engine::Value;
+use crate::tensor::Matrix;
+
+impl Matrix {
+    /// A matrix of new leaves holding the entries of `array`, with the same shape.
+    pub fn from_array2(array: ArrayView2<f64>) -> Matrix {
+        let (rows, cols) = array.dim();
+        Matrix::new(rows, cols, array.iter().map(|&x| Value::from(x)).collect())
+    }
+
+    /// The data of the entries, with the shape of the matrix.
+    pub fn to_array2(&self) -> Array2<f64> {
+        self.array2_of(Value::data)
+    }
+
+    /// The grads of the entries, with the shape of the matrix.
+    pub fn grads_to_array2(&self) -> Array2<f64> {
+        self.array2_of(Value::grad)
+    }
+
+    fn array2_of(&self, f: impl Fn(&Value) -> f64) -> Array2<f64> {
+        let entries = self.data().iter().map(f).collect();
+        Array2::from_shape_vec(self.shape(), entries).expect("a matrix holds rows * cols entries")
+    }
+}
+
+/// New leaves holding the elements of `array`, in order.
+pub fn values_from_array1(array: ArrayView1<f64>) -> Vec<Value> {
+    array.iter().map(|&x| Value::from(x)).collect()
+}
+
+/// The data of `values`, in order.
+pub fn values_to_array1(values: &[Value]) -> Array1<f64> {
+    values.iter().map(Value::data).collect()
+}
+
+/// The grads of `values`, in order.
+pub fn grads_to_array1(values: &[Value]) -> Array1<f64> {
+    values.iter().map(Value::grad).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{arr1, arr2, ShapeBuilder};
+
+    #[test]
+    fn matrices_round_trip_with_their_shape() {
+        let array = arr2(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        let matrix = Matrix::from_array2(array.view());
+        assert_eq!(matrix.shape(), (2, 3));
+        assert_eq!(matrix.get(1, 0).data(), 4.0);
+        assert_eq!(matrix.to_array2(), array);
+
+        let values = values_from_array1(arr1(&[0.5, -1.0]).view());
+        assert_eq!(values_to_array1(&values), arr1(&[0.5, -1.0]));
+    }
+
+    #[test]
+    fn column_major_arrays_are_read_in_logical_order() {
+        let fortran = Array2::from_shape_vec((2, 3).f(), vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]).unwrap();
+        let matrix = Matrix::from_array2(fortran.view());
+        let data: Vec<f64> = matrix.data().iter().map(Value::data).collect();
+        assert_eq!(data, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        // and a transposed view
+        let transposed = Matrix::from_array2(fortran.t());
+        assert_eq!((transposed.shape(), transposed.get(2, 1).data()), ((3, 2), 6.0));
+    }
+
+    #[test]
+    fn grads_come_out_in_element_order() {
+        let matrix = Matrix::from_array2(arr2(&[[1.0, 2.0], [3.0, 4.0]]).view());
+        // the k-th entry weighted by k + 1 in a sum of squares, so its grad is 2·(k + 1)·m
+        let terms: Vec<Value> = matrix
+            .data()
+            .iter()
+            .enumerate()
+            .map(|(k, m)| &(m * m) * &Value::from(k as f64 + 1.0))
+            .collect();
+        crate::ops::add_n(&terms).backward().unwrap();
+        assert_eq!(matrix.grads_to_array2(), arr2(&[[2.0, 8.0], [18.0, 32.0]]));
+        assert_eq!(grads_to_array1(&matrix.data()[2..]), arr1(&[18.0, 32.0]));
+    }
+}
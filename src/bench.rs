@@ -0,0 +1,86 @@
+use crate::engine::Value;
+use crate::nn::{Init, Linear};
+use crate::ops;
+use crate::rand::Rng;
+
+// Graphs of known shape and size, built deterministically, for the benchmarks in `benches/`
+// and for performance-regression checks.
+
+/// `n` tanh nodes applied one after the other to a single leaf: `n + 1` nodes, `n` deep.
+pub fn build_chain(n: usize) -> Value {
+    let mut value = Value::from(0.5);
+    for _ in 0..n {
+        value = value.tanh();
+    }
+    value
+}
+
+/// The sum of the outputs of a tanh network with layer sizes `shape` (inputs first), applied to an input
+/// of ones, with weights drawn from `Rng::seed(0)`.
+///
+/// Each layer from `i` to `o` units adds `2·i·o + 4·o` nodes (weights, biases, products, sums, bias
+/// additions and tanh), so the graph has `shape[0] + Σ (2·i·o + 4·o) + 1` nodes in total.
+pub fn build_mlp_graph(shape: &[usize]) -> Value {
+    let mut rng = Rng::seed(0);
+    let mut xs: Vec<Value> = (0..shape.first().copied().unwrap_or(0)).map(|_| Value::from(1.0)).collect();
+    for sizes in shape.windows(2) {
+        let layer = Linear::new(sizes[0], sizes[1], true, Init::Xavier, &mut rng);
+        xs = layer.forward(&xs).iter().map(Value::tanh).collect();
+    }
+    ops::add_n(&xs)
+}
+
+/// A complete binary tree of additions over `2^depth` distinct leaves: `2^(depth + 1) - 1` nodes, no sharing.
+pub fn build_tree(depth: u32) -> Value {
+    let mut level: Vec<Value> = (0..1usize << depth).map(|i| Value::from(i as f64)).collect();
+    while level.len() > 1 {
+        level = level.chunks(2).map(|pair| &pair[0] + &pair[1]).collect();
+    }
+    level.pop().expect("a tree has at least one leaf")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes(root: &Value) -> usize {
+        root.topo_order().len()
+    }
+
+    #[test]
+    fn chains_have_documented_size() {
+        for n in [0, 1, 10, 100] {
+            assert_eq!(nodes(&build_chain(n)), n + 1);
+        }
+        let chain = build_chain(3);
+        assert_eq!(chain.data(), 0.5f64.tanh().tanh().tanh());
+    }
+
+    #[test]
+    fn mlp_graphs_have_documented_size() {
+        for shape in [vec![2, 1], vec![3, 4, 2], vec![8, 32, 32, 1]] {
+            let layers: usize = shape.windows(2).map(|sizes| 2 * sizes[0] * sizes[1] + 4 * sizes[1]).sum();
+            assert_eq!(nodes(&build_mlp_graph(&shape)), shape[0] + layers + 1, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn mlp_graphs_are_deterministic() {
+        let (a, b) = (build_mlp_graph(&[3, 4, 2]), build_mlp_graph(&[3, 4, 2]));
+        assert_eq!(a.data(), b.data());
+        a.backward_unchecked();
+        b.backward_unchecked();
+        let grads = |root: &Value| root.topo_order().iter().map(Value::grad).collect::<Vec<_>>();
+        assert_eq!(grads(&a), grads(&b));
+    }
+
+    #[test]
+    fn trees_have_documented_size() {
+        for depth in [0, 1, 5, 10] {
+            let tree = build_tree(depth);
+            assert_eq!(nodes(&tree), (1 << (depth + 1)) - 1);
+            let leaves = 1u64 << depth;
+            assert_eq!(tree.data(), (leaves * (leaves - 1) / 2) as f64);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+// Gradient checkpointing: a segment of a graph whose intermediate nodes are not kept, only its inputs and
+// outputs, and which is run again during backward passes to propagate through it.
+//
+// The functions computing the segments live in a per-thread registry, as nodes can't hold closures: the output
+// nodes refer to their segment with a handle, `Op::Checkpoint`, and the registry counts the live nodes using each
+// segment so that the function, and whatever it captures, is dropped with the last of them.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::rc::Rc;
+
+use crate::engine::{propagate_all, NodeMap, Op, PropagateFn, Value, _Value};
+use crate::error::BackwardError;
+use crate::forward_diff::Dual;
+use crate::rewrite::{rewrite, GraphPass};
+
+type SegmentFn = Rc<dyn Fn(&[Value]) -> Vec<Value>>;
+
+struct Entry {
+    function: SegmentFn,
+    // the live nodes of `Op::Checkpoint` of this segment
+    nodes: usize,
+}
+
+thread_local! {
+    static SEGMENTS: RefCell<HashMap<u64, Entry>> = RefCell::new(HashMap::new());
+    static NEXT_SEGMENT: Cell<u64> = const { Cell::new(0) };
+}
+
+/// An output of a checkpointed segment, as held by `Op::Checkpoint`: a handle to the function computing the
+/// segment, the number of inputs of the segment and the position of the output among its results.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Segment {
+    id: u64,
+    inputs: usize,
+    output: usize,
+}
+
+impl Segment {
+    /// The number of inputs of the segment.
+    pub fn arity(self) -> usize {
+        self.inputs
+    }
+
+    /// Whether the function of the segment is still registered, i.e. some node of it is still alive. A handle
+    /// copied out of the last node, such as the `Op` of a dropped node, can't be run anymore.
+    pub fn is_live(self) -> bool {
+        SEGMENTS.with(|segments| segments.borrow().contains_key(&self.id))
+    }
+
+    /// The position of this output among the results of the segment.
+    pub fn output(self) -> usize {
+        self.output
+    }
+
+    // Runs the segment on `inputs`. The registry isn't borrowed while it runs, as the segment may build
+    // checkpoints of its own.
+    fn run(self, inputs: &[Value]) -> Value {
+        let function = SEGMENTS.with(|segments| {
+            let segments = segments.borrow();
+            let entry = segments.get(&self.id).expect("a checkpointed segment is registered while a node uses it");
+            entry.function.clone()
+        });
+        let mut outputs = function(inputs);
+        assert!(self.output < outputs.len(), "a checkpointed segment returned fewer outputs on a second run");
+        outputs.swap_remove(self.output)
+    }
+
+    // The output computed on new leaves holding `inputs`, which are returned with it.
+    fn run_detached(self, inputs: &[f64]) -> (Vec<Value>, Value) {
+        let leaves: Vec<Value> = inputs.iter().map(|&x| Value::from(x)).collect();
+        let output = self.run(&leaves);
+        (leaves, output)
+    }
+
+    // The data of the output for the data `inputs` of the inputs of the segment.
+    pub(crate) fn forward(self, inputs: &[f64]) -> f64 {
+        self.run_detached(inputs).1.data()
+    }
+
+    // The gradients of the inputs when `grad` flows into the output, computed by running the segment again and
+    // back-propagating through it. Leaves captured by the segment, such as the parameters of a layer, receive
+    // their gradients directly. An error of the inner backward pass, e.g. a failed assertion within the
+    // segment, is returned as it is.
+    pub(crate) fn backward(self, inputs: &[f64], grad: f64) -> Result<Vec<f64>, BackwardError> {
+        let (leaves, output) = self.run_detached(inputs);
+        let order = output.topo_order();
+        output.set_grad(grad);
+        propagate_all(&order)?;
+        Ok(leaves.iter().map(Value::grad).collect())
+    }
+
+    // Adds the gradients of the children of `node`, an output of the segment, as its propagation function
+    // would, but stops with the errors of `backward`. Backward passes call it instead of the propagation
+    // function of the node, see `engine::propagate_all`.
+    pub(crate) fn propagate(self, node: &_Value) -> Result<(), BackwardError> {
+        let inputs: Vec<f64> = node._prev.iter().map(Value::data).collect();
+        for (child, grad) in std::iter::zip(&node._prev, self.backward(&inputs, node.grad)?) {
+            child.add_grad(grad);
+        }
+        Ok(())
+    }
+
+    // The derivatives of the output with respect to each of `children`, as graph nodes computed from them, see
+    // `Value::backward_graph`.
+    pub(crate) fn backward_graph(self, children: &[Value]) -> Vec<Value> {
+        let leaves: Vec<Value> = children.iter().map(|child| Value::from(child.data())).collect();
+        let output = self.run(&leaves);
+        let mut substitution = Substitute { children: NodeMap::new() };
+        for (leaf, child) in std::iter::zip(&leaves, children) {
+            substitution.children.insert(leaf, child.clone());
+        }
+        output
+            .backward_graph(&leaves)
+            .iter()
+            .map(|grad| rewrite(grad, &substitution))
+            .collect()
+    }
+
+    // Same as `forward` in dual arithmetic, see `Op::forward_dual`.
+    pub(crate) fn forward_dual(self, inputs: &[Dual]) -> Dual {
+        let values: Vec<f64> = inputs.iter().map(|input| input.val).collect();
+        let (leaves, output) = self.run_detached(&values);
+        let seed: Vec<(Value, f64)> =
+            std::iter::zip(leaves, inputs).map(|(leaf, input)| (leaf, input.eps)).collect();
+        Dual { val: output.data(), eps: output.eval_jvp(&seed) }
+    }
+
+    // Builds a node for the output applied to `children`, which must be `arity()` of them.
+    pub(crate) fn build(self, children: &[Value]) -> Value {
+        let inputs: Vec<f64> = children.iter().map(Value::data).collect();
+        self.node(children, self.forward(&inputs))
+    }
+
+    // A node for the output applied to `children`, holding the already computed `result`.
+    #[track_caller]
+    fn node(self, children: &[Value], result: f64) -> Value {
+        // backward passes call `Segment::propagate`, which reports errors, instead of this
+        let propagate_fn: PropagateFn = |value| {
+            let Some(Op::Checkpoint(segment)) = value._op else {
+                unreachable!("checkpoint propagation on a node of another op");
+            };
+            if let Err(error) = segment.propagate(value) {
+                panic!("{}", error);
+            }
+        };
+
+        Value::new(_Value::new(result, None, Some(Op::Checkpoint(self)), children.to_vec(), Some(propagate_fn)))
+    }
+
+    // Counts a new node of the segment, see `_Value::new`.
+    pub(crate) fn retain(self) {
+        SEGMENTS.with(|segments| {
+            if let Some(entry) = segments.borrow_mut().get_mut(&self.id) {
+                entry.nodes += 1;
+            }
+        });
+    }
+
+    // Forgets a dropped node of the segment, and the segment itself with its last node.
+    pub(crate) fn release(self) {
+        // the function is dropped outside of the borrow, as dropping what it captures may release other segments
+        let _removed = SEGMENTS
+            .try_with(|segments| {
+                let mut segments = segments.borrow_mut();
+                let entry = segments.get_mut(&self.id)?;
+                entry.nodes -= 1;
+                if entry.nodes == 0 {
+                    segments.remove(&self.id)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .flatten();
+    }
+}
+
+impl Debug for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Segment").field("id", &self.id).field("output", &self.output).finish()
+    }
+}
+
+// The functions of segments can't be written out, so graphs holding checkpoints can't be serialized.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Segment {
+    fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("a graph holding checkpointed segments can't be serialized"))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Segment {
+    fn deserialize<D: serde::Deserializer<'de>>(_deserializer: D) -> Result<Segment, D::Error> {
+        Err(serde::de::Error::custom("checkpointed segments can't be deserialized"))
+    }
+}
+
+// Replaces the leaves a segment was run on with the nodes it is applied to.
+struct Substitute {
+    children: NodeMap<Value>,
+}
+
+impl GraphPass for Substitute {
+    fn rewrite(&self, node: &Value, _children: &[Value]) -> Option<Value> {
+        self.children.get(node).cloned()
+    }
+}
+
+/// Computes `segment(inputs)` without keeping the nodes built inside it: the segment runs on new leaves holding
+/// the data of `inputs`, and only its outputs are returned, as nodes computed directly from `inputs`. Every
+/// backward pass through an output runs the segment again, with graph tracking, to propagate its gradient, so
+/// that long chains of segments trade compute for the memory of their intermediate nodes.
+///
+/// The segment must compute the same function every time it is called: it is run again by backward passes,
+/// `Value::compile`, rewrites and nested gradients. Parameters it captures (clones of their `Value`s) are
+/// leaves of every run and receive their gradients during the backward pass. Each output is run again on its
+/// own, so a segment of `k` outputs costs `k` runs per pass, and gradients reaching an input through several
+/// outputs may differ from those of the plain graph in the last bits. Segments may checkpoint segments of their
+/// own. Graphs holding checkpoints can't be serialized: `Value::to_bytes` returns `EncodeError::Checkpoint`.
+#[track_caller]
+pub fn checkpoint(segment: impl Fn(&[Value]) -> Vec<Value> + 'static, inputs: &[Value]) -> Vec<Value> {
+    let id = NEXT_SEGMENT.with(|next| next.replace(next.get() + 1));
+    let function: SegmentFn = Rc::new(segment);
+    let entry = Entry { function: function.clone(), nodes: 0 };
+    SEGMENTS.with(|segments| segments.borrow_mut().insert(id, entry));
+
+    let leaves: Vec<Value> = inputs.iter().map(|input| Value::from(input.data())).collect();
+    let data: Vec<f64> = function(&leaves).iter().map(Value::data).collect();
+
+    let outputs: Vec<Value> = (0..data.len())
+        .map(|output| Segment { id, inputs: inputs.len(), output }.node(inputs, data[output]))
+        .collect();
+    if outputs.is_empty() {
+        let removed = SEGMENTS.with(|segments| segments.borrow_mut().remove(&id));
+        drop(removed);
+    }
+    outputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A layer `tanh(w·x + b)` with parameters captured by the segments running it.
+    fn layer(w: &Value, b: &Value) -> impl Fn(&[Value]) -> Vec<Value> + 'static {
+        let (w, b) = (w.clone(), b.clone());
+        move |xs: &[Value]| vec![(&(&xs[0] * &w) + &b).tanh()]
+    }
+
+    fn three_layers() -> Vec<(Value, Value)> {
+        [(0.7, 0.1), (-1.3, 0.4), (0.9, -0.2)].iter().map(|&(w, b)| (Value::from(w), Value::from(b))).collect()
+    }
+
+    fn grads(x: &Value, params: &[(Value, Value)]) -> Vec<f64> {
+        std::iter::once(x.grad()).chain(params.iter().flat_map(|(w, b)| [w.grad(), b.grad()])).collect()
+    }
+
+    #[test]
+    fn a_chain_of_three_segments_has_the_gradients_of_the_plain_chain() {
+        let (x, params) = (Value::from(0.5), three_layers());
+        let plain = params.iter().fold(x.clone(), |h, (w, b)| layer(w, b)(&[h]).remove(0));
+        plain.backward().unwrap();
+        let expected = grads(&x, &params);
+
+        let (x, params) = (Value::from(0.5), three_layers());
+        let checkpointed = params.iter().fold(x.clone(), |h, (w, b)| checkpoint(layer(w, b), &[h]).remove(0));
+        assert_eq!(checkpointed.data(), plain.data());
+        // neither the intermediate nodes of the segments nor the parameters they capture are part of the graph
+        assert_eq!(checkpointed.topo_order().len(), 1 + 3);
+        checkpointed.backward().unwrap();
+        assert_eq!(grads(&x, &params), expected);
+    }
+
+    #[test]
+    fn nested_checkpoints_have_the_gradients_of_the_plain_graph() {
+        let (x, params) = (Value::from(-0.3), three_layers());
+        let plain = params.iter().fold(x.clone(), |h, (w, b)| layer(w, b)(&[h]).remove(0));
+        plain.backward().unwrap();
+        let expected = grads(&x, &params);
+
+        let (x, params) = (Value::from(-0.3), three_layers());
+        let inner = params.clone();
+        let outer = move |xs: &[Value]| {
+            vec![inner.iter().fold(xs[0].clone(), |h, (w, b)| checkpoint(layer(w, b), &[h]).remove(0))]
+        };
+        let nested = checkpoint(outer, std::slice::from_ref(&x)).remove(0);
+        assert_eq!(nested.data(), plain.data());
+        nested.backward().unwrap();
+        assert_eq!(grads(&x, &params), expected);
+    }
+
+    #[test]
+    fn segments_of_several_inputs_and_outputs() {
+        let (x, y) = (Value::from(1.5), Value::from(-2.0));
+        let outputs = checkpoint(|xs: &[Value]| vec![&xs[0] * &xs[1], xs[0].exp()], &[x.clone(), y.clone()]);
+        assert_eq!(outputs.iter().map(Value::data).collect::<Vec<_>>(), vec![-3.0, 1.5f64.exp()]);
+        assert_eq!(outputs[0].op().unwrap().arity(), Some(2));
+
+        (&outputs[0] + &outputs[1]).backward().unwrap();
+        assert_eq!((x.grad(), y.grad()), (-2.0 + 1.5f64.exp(), 1.5));
+    }
+
+    #[test]
+    fn a_handle_outliving_its_nodes_keeps_its_arity_but_cant_be_applied() {
+        let (x, y) = (Value::from(1.0), Value::from(2.0));
+        let output = checkpoint(|xs: &[Value]| vec![&xs[0] + &xs[1]], &[x.clone(), y.clone()]).remove(0);
+        let op = output.op().unwrap();
+        assert!(op.apply(&[x.clone(), y.clone()]).is_some());
+        drop(output);
+        assert_eq!(op.arity(), Some(2));
+        assert!(op.apply(&[x, y]).is_none());
+    }
+
+    #[test]
+    fn errors_of_the_backward_pass_within_a_segment_are_returned() {
+        // the gradient of the square root at 0 is infinite
+        let segment = |xs: &[Value]| vec![xs[0].assert_finite("inner").sqrt()];
+        let output = checkpoint(segment, &[Value::from(0.0)]).remove(0);
+        let error = output.backward().unwrap_err();
+        let BackwardError::AssertionFailed { message, grad, .. } = error else {
+            panic!("expected a failed assertion, got {:?}", error);
+        };
+        assert_eq!(message, "inner");
+        assert_eq!(grad, f64::INFINITY);
+    }
+
+    #[test]
+    fn graphs_holding_checkpoints_are_not_serialized() {
+        let x = Value::from(2.0);
+        let output = checkpoint(|xs: &[Value]| vec![xs[0].tanh()], std::slice::from_ref(&x)).remove(0);
+        let root = &output * &x;
+        // the checkpoint follows its input `x` in topological order
+        assert_eq!(root.to_bytes(), Err(crate::error::EncodeError::Checkpoint { node: 1 }));
+        #[cfg(feature = "serde")]
+        assert!(root.to_graph_json().is_err());
+    }
+}
@@ -0,0 +1,373 @@
+// A suite checking an implementation of the ops against the reference semantics of this crate, so that
+// alternative backends (e.g. arena-based storage instead of `Rc`) can be verified against it.
+
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+
+use crate::engine::{Op, Value};
+
+/// A handle to a node created by a `Backend`, meaningful to that backend only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(pub usize);
+
+/// The storage and evaluation strategy under test: how nodes are created, how ops are applied to them and how
+/// gradients are back-propagated. Backends are used through shared references, so they keep their nodes behind
+/// interior mutability.
+pub trait Backend {
+    /// A new leaf holding `data`.
+    fn leaf(&self, data: f64) -> NodeId;
+    /// A new node applying `op` to `children`, in order. The same node may appear several times.
+    fn apply(&self, op: Op, children: &[NodeId]) -> NodeId;
+    fn data(&self, node: NodeId) -> f64;
+    /// Back-propagates from `root`, seeding its gradient with 1, into grads that start at zero.
+    fn backward(&self, root: NodeId);
+    fn grad(&self, node: NodeId) -> f64;
+}
+
+/// The reference backend: the nodes of this crate, built by the same constructors as the operators.
+#[derive(Default)]
+pub struct RcBackend {
+    nodes: RefCell<Vec<Value>>,
+}
+
+impl RcBackend {
+    pub fn new() -> RcBackend {
+        RcBackend::default()
+    }
+
+    fn push(&self, value: Value) -> NodeId {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(value);
+        NodeId(nodes.len() - 1)
+    }
+
+    fn node(&self, node: NodeId) -> Value {
+        self.nodes.borrow()[node.0].clone()
+    }
+}
+
+impl Backend for RcBackend {
+    fn leaf(&self, data: f64) -> NodeId {
+        self.push(Value::from(data))
+    }
+
+    fn apply(&self, op: Op, children: &[NodeId]) -> NodeId {
+        let children: Vec<Value> = children.iter().map(|&child| self.node(child)).collect();
+        let value = op.apply(&children);
+        self.push(value.unwrap_or_else(|| panic!("{} doesn't take {} children", op, children.len())))
+    }
+
+    fn data(&self, node: NodeId) -> f64 {
+        self.node(node).data()
+    }
+
+    fn backward(&self, root: NodeId) {
+        self.node(root).backward_unchecked();
+    }
+
+    fn grad(&self, node: NodeId) -> f64 {
+        self.node(node).grad()
+    }
+}
+
+/// The results of the cases of one op, see `run_all`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpReport {
+    pub op: Op,
+    pub cases: usize,
+    /// The cases whose data or any gradient is off by more than the tolerance.
+    pub failures: Vec<String>,
+    pub max_data_error: f64,
+    pub max_grad_error: f64,
+}
+
+impl OpReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// What `run_all` found, one entry per op.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConformanceReport {
+    pub ops: Vec<OpReport>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> bool {
+        self.ops.iter().all(OpReport::passed)
+    }
+}
+
+impl Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<14} {:>6} {:>8} {:>14} {:>14}", "Op", "Cases", "Result", "Max data err", "Max grad err")?;
+        for op in &self.ops {
+            let result = if op.passed() { "pass" } else { "FAIL" };
+            writeln!(
+                f,
+                "{:<14} {:>6} {:>8} {:>14.3e} {:>14.3e}",
+                op.op.to_string(),
+                op.cases,
+                result,
+                op.max_data_error,
+                op.max_grad_error
+            )?;
+            for failure in &op.failures {
+                writeln!(f, "  {}", failure)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Absolute differences up to this, or this much relative to the reference, are accepted.
+const TOLERANCE: f64 = 1e-9;
+
+// A case: the data of the leaves, and the leaves the op is applied to, by index (repeated for shared children).
+struct Case {
+    leaves: Vec<f64>,
+    children: Vec<usize>,
+}
+
+fn case(leaves: &[f64]) -> Case {
+    Case { leaves: leaves.to_vec(), children: (0..leaves.len()).collect() }
+}
+
+fn shared(leaf: f64, times: usize) -> Case {
+    Case { leaves: vec![leaf], children: vec![0; times] }
+}
+
+// Inputs chosen away from the kinks of relu, clamp and the straight-through ops, so central differences aren't
+// needed.
+fn cases(op: Op) -> Vec<Case> {
+    match op {
+        Op::Add | Op::Mul | Op::SumCompensated => vec![
+            case(&[1.5, -2.0]),
+            case(&[0.0, 3.0]),
+            case(&[1.0, 2.0, -3.0, 0.5]),
+            case(&[2.0, 0.0, 0.0]),
+            shared(1.5, 2),
+        ],
+        Op::Pow => {
+            vec![case(&[2.0, 3.0]), case(&[1.5, 0.5]), case(&[-2.0, 2.0]), case(&[0.7, -1.0]), shared(1.5, 2)]
+        }
+        Op::Tanh | Op::Exp | Op::Softplus => vec![case(&[-1.0]), case(&[0.0]), case(&[0.5]), case(&[8.0])],
+        Op::Ln => vec![case(&[0.5]), case(&[1.0]), case(&[20.0])],
+        Op::Relu => vec![case(&[-1.0]), case(&[2.0])],
+        Op::RoundSte => vec![case(&[1.4]), case(&[-2.6]), case(&[3.0])],
+        Op::BinarizeSte => vec![case(&[0.3, 0.0]), case(&[-0.5, 0.0]), case(&[2.5, 0.0]), case(&[1.2, 1.0])],
+        Op::Assert => vec![case(&[0.5, -1.0, 1.0]), case(&[-3.0, f64::MIN, f64::MAX])],
+        Op::Clamp => vec![case(&[0.5, 0.0, 1.0]), case(&[-2.0, 0.0, 1.0]), case(&[3.0, -1.0, 1.0])],
+        // user-defined ops and segments have no reference to be checked against
+        Op::Custom(_) | Op::Checkpoint(_) => Vec::new(),
+    }
+}
+
+// The reference data of the op applied to `x`.
+fn reference_data(op: Op, x: &[f64]) -> f64 {
+    match op {
+        Op::Add | Op::SumCompensated => x.iter().sum(),
+        Op::Mul => x.iter().product(),
+        Op::Pow => x[0].powf(x[1]),
+        Op::Tanh => x[0].tanh(),
+        Op::Exp => x[0].exp(),
+        Op::Ln => x[0].ln(),
+        Op::Relu => x[0].max(0.0),
+        Op::Softplus => (1.0 + x[0].exp()).ln(),
+        Op::RoundSte => x[0].round(),
+        Op::BinarizeSte => f64::from(u8::from(x[0] > x[1])),
+        Op::Assert => x[0],
+        Op::Clamp => x[0].clamp(x[1], x[2]),
+        Op::Custom(_) | Op::Checkpoint(_) => unreachable!("custom ops have no conformance cases"),
+    }
+}
+
+// The reference gradient of the op with respect to each of its inputs `x`, in order. Exponents and thresholds
+// receive no gradient, and the straight-through ops pass the gradient through as if they were the identity
+// (within 1 of the threshold, for binarization).
+fn reference_grads(op: Op, x: &[f64]) -> Vec<f64> {
+    match op {
+        Op::Add | Op::SumCompensated => vec![1.0; x.len()],
+        Op::Mul => (0..x.len())
+            .map(|i| x.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, x)| x).product())
+            .collect(),
+        Op::Pow => vec![x[1] * x[0].powf(x[1] - 1.0), 0.0],
+        Op::Tanh => vec![1.0 - x[0].tanh().powi(2)],
+        Op::Exp => vec![x[0].exp()],
+        Op::Ln => vec![1.0 / x[0]],
+        Op::Relu => vec![if x[0] > 0.0 { 1.0 } else { 0.0 }],
+        Op::Softplus => vec![1.0 / (1.0 + (-x[0]).exp())],
+        Op::RoundSte => vec![1.0],
+        Op::BinarizeSte => vec![if (x[0] - x[1]).abs() <= 1.0 { 1.0 } else { 0.0 }, 0.0],
+        Op::Assert => vec![1.0, 0.0, 0.0],
+        Op::Clamp => vec![if x[1] <= x[0] && x[0] <= x[2] { 1.0 } else { 0.0 }, 0.0, 0.0],
+        Op::Custom(_) | Op::Checkpoint(_) => unreachable!("custom ops have no conformance cases"),
+    }
+}
+
+fn error(actual: f64, expected: f64) -> f64 {
+    if actual == expected {
+        return 0.0;
+    }
+    (actual - expected).abs() / expected.abs().max(1.0)
+}
+
+/// Applies every op of the crate to a set of cases on `backend` and compares the data of the results and the
+/// gradients of the leaves with the reference semantics: the f64 definitions of the ops and their derivatives,
+/// with the conventions of this crate (no gradient into exponents and thresholds, relu's gradient 0 at 0,
+/// straight-through gradients for the quantizing ops). Cases include n-ary sums and products, zero factors and
+/// a child used several times.
+pub fn run_all(backend: &dyn Backend) -> ConformanceReport {
+    let ops = Op::ALL
+        .iter()
+        .map(|&op| {
+            let cases = cases(op);
+            let mut report =
+                OpReport { op, cases: cases.len(), failures: Vec::new(), max_data_error: 0.0, max_grad_error: 0.0 };
+            for case in cases {
+                let leaves: Vec<NodeId> = case.leaves.iter().map(|&data| backend.leaf(data)).collect();
+                let children: Vec<NodeId> = case.children.iter().map(|&i| leaves[i]).collect();
+                let inputs: Vec<f64> = case.children.iter().map(|&i| case.leaves[i]).collect();
+                let root = backend.apply(op, &children);
+                backend.backward(root);
+
+                let data_error = error(backend.data(root), reference_data(op, &inputs));
+                let mut expected = vec![0.0; leaves.len()];
+                for (&i, grad) in std::iter::zip(&case.children, reference_grads(op, &inputs)) {
+                    expected[i] += grad;
+                }
+                let grad_error = std::iter::zip(&leaves, &expected)
+                    .map(|(&leaf, &grad)| error(backend.grad(leaf), grad))
+                    .fold(0.0, f64::max);
+
+                report.max_data_error = report.max_data_error.max(data_error);
+                report.max_grad_error = report.max_grad_error.max(grad_error);
+                if !(data_error <= TOLERANCE && grad_error <= TOLERANCE) {
+                    report.failures.push(format!(
+                        "{} of {:?}: data error {:e}, grad error {:e}",
+                        op, inputs, data_error, grad_error
+                    ));
+                }
+            }
+            report
+        })
+        .collect();
+    ConformanceReport { ops }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An arena of plain numbers: nodes are created in topological order, so backward visits them in reverse.
+    struct ArenaNode {
+        op: Option<Op>,
+        children: Vec<NodeId>,
+        data: f64,
+    }
+
+    #[derive(Default)]
+    struct ArenaBackend {
+        nodes: RefCell<Vec<ArenaNode>>,
+        grads: RefCell<Vec<f64>>,
+    }
+
+    impl ArenaBackend {
+        fn push(&self, op: Option<Op>, children: &[NodeId], data: f64) -> NodeId {
+            let mut nodes = self.nodes.borrow_mut();
+            nodes.push(ArenaNode { op, children: children.to_vec(), data });
+            self.grads.borrow_mut().push(0.0);
+            NodeId(nodes.len() - 1)
+        }
+    }
+
+    impl Backend for ArenaBackend {
+        fn leaf(&self, data: f64) -> NodeId {
+            self.push(None, &[], data)
+        }
+
+        fn apply(&self, op: Op, children: &[NodeId]) -> NodeId {
+            let inputs: Vec<f64> = children.iter().map(|&child| self.data(child)).collect();
+            self.push(Some(op), children, reference_data(op, &inputs))
+        }
+
+        fn data(&self, node: NodeId) -> f64 {
+            self.nodes.borrow()[node.0].data
+        }
+
+        fn backward(&self, root: NodeId) {
+            let nodes = self.nodes.borrow();
+            let mut grads = self.grads.borrow_mut();
+            grads[root.0] = 1.0;
+            for i in (0..=root.0).rev() {
+                let ArenaNode { op: Some(op), children, .. } = &nodes[i] else { continue };
+                let inputs: Vec<f64> = children.iter().map(|child| nodes[child.0].data).collect();
+                let grad = grads[i];
+                for (child, local) in std::iter::zip(children, reference_grads(*op, &inputs)) {
+                    grads[child.0] += grad * local;
+                }
+            }
+        }
+
+        fn grad(&self, node: NodeId) -> f64 {
+            self.grads.borrow()[node.0]
+        }
+    }
+
+    // The reference backend with the derivative of tanh off by 1%.
+    struct WrongTanh(RcBackend);
+
+    impl Backend for WrongTanh {
+        fn leaf(&self, data: f64) -> NodeId {
+            self.0.leaf(data)
+        }
+
+        fn apply(&self, op: Op, children: &[NodeId]) -> NodeId {
+            if op != Op::Tanh {
+                return self.0.apply(op, children);
+            }
+            // tanh(x) + c·(x - x0) at x0 = x, whose derivative is that of tanh plus c
+            let x = self.0.node(children[0]);
+            let c = 0.01 * (1.0 - x.data().tanh().powi(2));
+            let offset = &(&x * &Value::constant(c)) - &Value::constant(x.data() * c);
+            self.0.push(&x.tanh() + &offset)
+        }
+
+        fn data(&self, node: NodeId) -> f64 {
+            self.0.data(node)
+        }
+
+        fn backward(&self, root: NodeId) {
+            self.0.backward(root)
+        }
+
+        fn grad(&self, node: NodeId) -> f64 {
+            self.0.grad(node)
+        }
+    }
+
+    #[test]
+    fn the_reference_and_an_arena_backend_pass() {
+        for report in [run_all(&RcBackend::new()), run_all(&ArenaBackend::default())] {
+            assert!(report.passed(), "{}", report);
+            assert_eq!(report.ops.len(), Op::ALL.len());
+            let add = report.ops.iter().find(|op| op.op == Op::Add).unwrap();
+            assert_eq!((add.cases, add.max_grad_error), (5, 0.0));
+        }
+    }
+
+    #[test]
+    fn a_wrong_derivative_fails_its_op_only() {
+        let report = run_all(&WrongTanh(RcBackend::new()));
+        assert!(!report.passed());
+        for op in &report.ops {
+            assert_eq!(op.passed(), op.op != Op::Tanh, "{}", op.op);
+        }
+        let tanh = report.ops.iter().find(|op| op.op == Op::Tanh).unwrap();
+        assert_eq!(tanh.max_data_error, 0.0);
+        assert!((tanh.max_grad_error - 0.01).abs() < 1e-9, "{}", tanh.max_grad_error);
+        assert_eq!(tanh.failures.len(), 4);
+        assert!(report.to_string().contains("FAIL"));
+    }
+}
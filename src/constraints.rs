@@ -0,0 +1,89 @@
+// Constraints on the data of parameters, enforced by projecting the parameters back onto the feasible set after each
+// optimizer step (projected gradient descent), see `optim::Constrained`.
+
+use crate::engine::Value;
+
+/// A set of feasible values for a group of parameters.
+pub trait Constraint {
+    /// Moves the data of `params`, if needed, to the nearest point of the feasible set.
+    fn project(&self, params: &[Value]);
+}
+
+/// Every parameter is at least 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NonNegative;
+
+impl Constraint for NonNegative {
+    fn project(&self, params: &[Value]) {
+        Interval(0.0, f64::INFINITY).project(params);
+    }
+}
+
+/// Every parameter lies in `[lo, hi]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval(pub f64, pub f64);
+
+impl Constraint for Interval {
+    fn project(&self, params: &[Value]) {
+        assert!(self.0 <= self.1, "the interval [{}, {}] is empty", self.0, self.1);
+        for param in params {
+            let data = param.data();
+            if data < self.0 || data > self.1 {
+                param.set_data(data.clamp(self.0, self.1));
+            }
+        }
+    }
+}
+
+/// The parameters, taken together as one vector, have a Euclidean norm of 1: they are rescaled jointly, keeping
+/// their direction. A group whose norm is 0 has no direction and is left as it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnitNorm;
+
+impl Constraint for UnitNorm {
+    fn project(&self, params: &[Value]) {
+        let norm = params.iter().map(|param| param.data().powi(2)).sum::<f64>().sqrt();
+        if norm == 0.0 || norm == 1.0 {
+            return;
+        }
+        for param in params {
+            param.set_data(param.data() / norm);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::values_from;
+
+    fn data(params: &[Value]) -> Vec<f64> {
+        params.iter().map(Value::data).collect()
+    }
+
+    #[test]
+    fn intervals_clamp_only_the_values_outside() {
+        let params = values_from(&[-3.0, 0.25, 7.0]);
+        Interval(-1.0, 1.0).project(&params);
+        assert_eq!(data(&params), [-1.0, 0.25, 1.0]);
+        NonNegative.project(&params);
+        assert_eq!(data(&params), [0.0, 0.25, 1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the interval [1, 0] is empty")]
+    fn empty_intervals_are_rejected() {
+        Interval(1.0, 0.0).project(&values_from(&[0.5]));
+    }
+
+    #[test]
+    fn unit_norm_rescales_the_group_jointly() {
+        let params = values_from(&[3.0, -4.0]);
+        UnitNorm.project(&params);
+        // the direction is kept, not each parameter clamped on its own
+        assert_eq!(data(&params), [0.6, -0.8]);
+        let zero = values_from(&[0.0, 0.0]);
+        UnitNorm.project(&zero);
+        assert_eq!(data(&zero), [0.0, 0.0]);
+    }
+}
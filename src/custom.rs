@@ -0,0 +1,315 @@
+// Ops defined outside the crate, e.g. a domain-specific link function, registered once by name so that the
+// graphs using them can be exported and loaded back like any other.
+//
+// The definitions live in a process-wide registry, so that a node built on one thread and a graph loaded on
+// another agree on what an op name means. The registry is only locked to register an op or to look one up by
+// name, e.g. while loading a graph: the handle returned by registration points to the definition directly.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use crate::engine::{Op, PropagateFn, Value, _Value};
+use crate::error::RegisterError;
+
+// The forward and backward functions of a user-defined op.
+#[derive(Clone, Copy)]
+enum CustomFns {
+    Unary {
+        forward: fn(f64) -> f64,
+        backward: fn(f64, f64, f64) -> f64,
+    },
+    Binary {
+        forward: fn(f64, f64) -> f64,
+        backward: fn(f64, f64, f64, f64) -> (f64, f64),
+    },
+}
+
+impl CustomFns {
+    fn arity(self) -> usize {
+        match self {
+            CustomFns::Unary { .. } => 1,
+            CustomFns::Binary { .. } => 2,
+        }
+    }
+
+    // Whether both hold the same functions.
+    fn same(self, other: CustomFns) -> bool {
+        use std::ptr::fn_addr_eq;
+        match (self, other) {
+            (CustomFns::Unary { forward: f, backward: b }, CustomFns::Unary { forward, backward }) => {
+                fn_addr_eq(f, forward) && fn_addr_eq(b, backward)
+            }
+            (CustomFns::Binary { forward: f, backward: b }, CustomFns::Binary { forward, backward }) => {
+                fn_addr_eq(f, forward) && fn_addr_eq(b, backward)
+            }
+            _ => false,
+        }
+    }
+}
+
+struct Definition {
+    name: &'static str,
+    fns: CustomFns,
+}
+
+// The definitions by name. They are never removed, so they are leaked to be shared by the handles.
+static REGISTRY: Mutex<BTreeMap<&'static str, &'static Definition>> = Mutex::new(BTreeMap::new());
+
+/// A user-defined op, as held by `Op::Custom` and returned by `register_unary` and `register_binary`: a handle to
+/// its definition. Two handles are equal when they stand for the same name.
+#[derive(Clone, Copy)]
+pub struct CustomOp(&'static Definition);
+
+impl PartialEq for CustomOp {
+    fn eq(&self, other: &CustomOp) -> bool {
+        self.0.name == other.0.name
+    }
+}
+
+impl Eq for CustomOp {}
+
+impl Hash for CustomOp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.name.hash(state);
+    }
+}
+
+impl CustomOp {
+    /// The op registered under `name`, if any.
+    pub fn by_name(name: &str) -> Option<CustomOp> {
+        REGISTRY.lock().unwrap().get(name).map(|&definition| CustomOp(definition))
+    }
+
+    /// The name the op was registered under.
+    pub fn name(self) -> &'static str {
+        self.0.name
+    }
+
+    /// The number of children the op takes, 1 or 2.
+    pub fn arity(self) -> usize {
+        self.0.fns.arity()
+    }
+
+    // The data of the op applied to `inputs`, which hold `arity()` numbers.
+    pub(crate) fn forward(self, inputs: &[f64]) -> f64 {
+        match self.0.fns {
+            CustomFns::Unary { forward, .. } => forward(inputs[0]),
+            CustomFns::Binary { forward, .. } => forward(inputs[0], inputs[1]),
+        }
+    }
+
+    // The gradients of the inputs when `grad` flows into the output `output` of the op applied to `inputs`.
+    pub(crate) fn backward(self, inputs: &[f64], output: f64, grad: f64) -> Vec<f64> {
+        match self.0.fns {
+            CustomFns::Unary { backward, .. } => vec![backward(inputs[0], output, grad)],
+            CustomFns::Binary { backward, .. } => {
+                let (a, b) = backward(inputs[0], inputs[1], output, grad);
+                vec![a, b]
+            }
+        }
+    }
+
+    // Builds a node applying the op to `children`, which must be `arity()` of them.
+    #[track_caller]
+    pub(crate) fn build(self, children: &[Value]) -> Value {
+        let inputs: Vec<f64> = children.iter().map(Value::data).collect();
+        let result = self.forward(&inputs);
+
+        let propagate_fn: PropagateFn = |value| {
+            let Some(Op::Custom(op)) = value._op else {
+                unreachable!("custom propagation on a node of another op");
+            };
+            let inputs: Vec<f64> = value._prev.iter().map(Value::data).collect();
+            for (child, grad) in std::iter::zip(&value._prev, op.backward(&inputs, value.data, value.grad)) {
+                child.add_grad(grad);
+            }
+        };
+
+        Value::new(_Value::new(result, None, Some(Op::Custom(self)), children.to_vec(), Some(propagate_fn)))
+    }
+}
+
+impl Debug for CustomOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CustomOp").field(&self.name()).finish()
+    }
+}
+
+// Serialized as its name, which has to be registered again before the graph is loaded.
+#[cfg(feature = "serde")]
+impl serde::Serialize for CustomOp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CustomOp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<CustomOp, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        CustomOp::by_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown custom op `{}`: register it first", name)))
+    }
+}
+
+fn register(name: &'static str, fns: CustomFns) -> Result<CustomOp, RegisterError> {
+    let mut registry = REGISTRY.lock().unwrap();
+    match registry.get(name) {
+        Some(definition) if definition.fns.same(fns) => Ok(CustomOp(definition)),
+        Some(definition) => Err(RegisterError::AlreadyRegistered { name, arity: definition.fns.arity() }),
+        None => {
+            let definition: &'static Definition = Box::leak(Box::new(Definition { name, fns }));
+            registry.insert(name, definition);
+            Ok(CustomOp(definition))
+        }
+    }
+}
+
+/// Registers the unary op `name`, see `Value::custom_unary`, and returns its handle, which builds nodes of the op
+/// without going through the registry again. Registering the same functions under the same name again returns
+/// the same op, e.g. so that a library can register its ops lazily; any other definition of a registered name is
+/// rejected, and the registered one is kept. Ops are registered for the lifetime of the process.
+///
+/// Nested gradients aren't supported through custom ops: only the numbers `backward` returns are known, so
+/// `Value::backward_graph` takes the local derivatives of the op as constants. Its first derivatives are right,
+/// but derivatives of those miss the curvature of the op.
+pub fn register_unary(
+    name: &'static str,
+    forward: fn(f64) -> f64,
+    backward: fn(x: f64, y: f64, grad: f64) -> f64,
+) -> Result<CustomOp, RegisterError> {
+    register(name, CustomFns::Unary { forward, backward })
+}
+
+/// Same as `register_unary` for a binary op, see `Value::custom_binary`.
+pub fn register_binary(
+    name: &'static str,
+    forward: fn(f64, f64) -> f64,
+    backward: fn(a: f64, b: f64, y: f64, grad: f64) -> (f64, f64),
+) -> Result<CustomOp, RegisterError> {
+    register(name, CustomFns::Binary { forward, backward })
+}
+
+impl Value {
+    /// Applies the user-defined unary op `op`, registered by `register_unary(name, forward, backward)`: the data
+    /// of the result is `forward(x)`, and a backward pass adds `backward(x, y, grad)` to the grad of `self`, where
+    /// `x` is the data of `self`, `y` that of the result and `grad` the gradient flowing into the result; e.g.
+    /// `grad * (1.0 - y * y)` for tanh. The name of the op is how it shows in DOT and LaTeX output and in
+    /// serialized graphs. Panics if `op` is binary.
+    #[track_caller]
+    pub fn custom_unary(&self, op: CustomOp) -> Value {
+        assert_eq!(op.arity(), 1, "custom op `{}` takes {} inputs", op.name(), op.arity());
+        op.build(std::slice::from_ref(self))
+    }
+
+    /// Same as `custom_unary` for a binary op, see `register_binary`: the result is `forward(a, b)` for the data
+    /// `a` of `self` and `b` of `other`, and `backward(a, b, y, grad)` returns the gradients to add to `self` and
+    /// `other`. Panics if `op` is unary.
+    #[track_caller]
+    pub fn custom_binary(&self, other: &Value, op: CustomOp) -> Value {
+        assert_eq!(op.arity(), 2, "custom op `{}` takes {} input", op.name(), op.arity());
+        op.build(&[self.clone(), other.clone()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RegisterError;
+
+    fn softplus(x: f64) -> f64 {
+        x.max(0.0) + (-x.abs()).exp().ln_1p()
+    }
+
+    // d/dx softplus(x) = sigmoid(x) = 1 - e^-y
+    fn softplus_backward(_: f64, y: f64, grad: f64) -> f64 {
+        grad * -(-y).exp_m1()
+    }
+
+    fn user_softplus() -> CustomOp {
+        register_unary("custom_test_softplus", softplus, softplus_backward).unwrap()
+    }
+
+    #[test]
+    fn a_user_softplus_matches_the_built_in() {
+        for at in [-30.0, -1.5, 0.0, 0.7, 40.0] {
+            let (x, builtin) = (Value::from(at), Value::from(at));
+            let y = x.custom_unary(user_softplus());
+            let expected = builtin.softplus();
+            assert!((y.data() - expected.data()).abs() < 1e-14);
+            y.backward().unwrap();
+            expected.backward().unwrap();
+            assert!((x.grad() - builtin.grad()).abs() < 1e-14, "at {}", at);
+        }
+    }
+
+    #[test]
+    fn custom_ops_show_by_name_and_pass_grad_checks() {
+        let (a, b) = (Value::from(0.3).add_label("a"), Value::from(-1.2));
+        let hypot = register_binary("custom_test_hypot", f64::hypot, |a, b, y, grad| (grad * a / y, grad * b / y));
+        let root = &a.custom_binary(&b, hypot.unwrap()).custom_unary(user_softplus()) * &a;
+        assert!(root.to_dot().contains("custom_test_hypot") && root.to_dot().contains("custom_test_softplus"));
+        assert_eq!(root.children()[0].op().unwrap().to_string(), "custom_test_softplus");
+
+        root.backward().unwrap();
+        for leaf in [&a, &b] {
+            let data = leaf.data();
+            let at = |x: f64| {
+                leaf.set_data(x);
+                let y = a.data().hypot(b.data());
+                softplus(y) * a.data()
+            };
+            let numeric = (at(data + 1e-6) - at(data - 1e-6)) / 2e-6;
+            leaf.set_data(data);
+            assert!((leaf.grad() - numeric).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn registering_is_once_per_name() {
+        let first = user_softplus();
+        assert_eq!(user_softplus(), first);
+        assert_eq!(CustomOp::by_name("custom_test_softplus"), Some(first));
+        assert_eq!(CustomOp::by_name("custom_test_unknown"), None);
+
+        // other functions, or another number of inputs, are rejected and the first definition kept
+        let conflict = register_unary("custom_test_softplus", f64::exp, |_, y, grad| y * grad);
+        let expected = RegisterError::AlreadyRegistered { name: "custom_test_softplus", arity: 1 };
+        assert_eq!(conflict, Err(expected));
+        let binary = register_binary("custom_test_softplus", f64::max, |_, _, _, grad| (grad, 0.0));
+        assert!(binary.is_err());
+        assert_eq!(Value::from(0.0).custom_unary(first).data(), 2f64.ln());
+    }
+
+    #[test]
+    #[should_panic(expected = "custom op `custom_test_softplus` takes 1 input")]
+    fn unary_ops_are_not_binary() {
+        Value::from(1.0).custom_binary(&Value::from(2.0), user_softplus());
+    }
+
+    #[test]
+    fn nested_gradients_take_the_derivatives_of_custom_ops_as_constants() {
+        let (x, builtin) = (Value::from(0.5), Value::from(0.5));
+        let grad = x.custom_unary(user_softplus()).backward_graph(std::slice::from_ref(&x)).remove(0);
+        let expected = builtin.softplus().backward_graph(std::slice::from_ref(&builtin)).remove(0);
+        // the first derivatives agree, the second doesn't see the curvature of the custom op
+        assert!((grad.data() - expected.data()).abs() < 1e-14);
+        grad.backward().unwrap();
+        expected.backward().unwrap();
+        assert_eq!(x.grad(), 0.0);
+        assert!(builtin.grad() > 0.2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_graphs_load_registered_ops_and_reject_others() {
+        let root = Value::from(1.5).custom_unary(user_softplus());
+        let json = root.to_graph_json().unwrap();
+        assert_eq!(Value::from_graph_json(&json).unwrap().op(), root.op());
+        let unknown = json.replace("custom_test_softplus", "custom_test_missing");
+        let error = Value::from_graph_json(&unknown).unwrap_err().to_string();
+        assert!(error.contains("unknown custom op `custom_test_missing`: register it first"), "{}", error);
+    }
+}
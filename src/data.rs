@@ -0,0 +1,134 @@
+// Small datasets and helpers for examples and experiments. The synthetic datasets are generated with the
+// crate's own `Rng`, so that a seed always gives the same points.
+
+use std::collections::BTreeSet;
+use std::f64::consts::PI;
+
+use crate::rand::Rng;
+
+/// Two interleaving half circles in the plane: `n / 2` points on the upper moon with label 0, and the rest on the
+/// lower one, shifted to interlock with it, with label 1. Each coordinate gets Gaussian noise of standard
+/// deviation `noise`.
+///
+/// Returns the points and their labels, in matching order; the two classes alternate.
+pub fn make_moons(n: usize, noise: f64, seed: u64) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let mut rng = Rng::seed(seed);
+    let mut points = Vec::with_capacity(n);
+    let mut labels = Vec::with_capacity(n);
+    for i in 0..n {
+        let t = PI * rng.next_f64();
+        let (x, y, label) = if i % 2 == 0 {
+            (t.cos(), t.sin(), 0.0)
+        } else {
+            (1.0 - t.cos(), 0.5 - t.sin(), 1.0)
+        };
+        points.push(vec![x + rng.normal(0.0, noise), y + rng.normal(0.0, noise)]);
+        labels.push(label);
+    }
+    (points, labels)
+}
+
+/// `n` points uniform in the square [-1, 1)², labelled 1 when their coordinates have opposite signs and 0
+/// otherwise, the continuous version of the XOR truth table.
+pub fn make_xor(n: usize, seed: u64) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let mut rng = Rng::seed(seed);
+    (0..n)
+        .map(|_| {
+            let (x, y) = (rng.uniform(-1.0, 1.0), rng.uniform(-1.0, 1.0));
+            let label = if (x < 0.0) != (y < 0.0) { 1.0 } else { 0.0 };
+            (vec![x, y], label)
+        })
+        .unzip()
+}
+
+/// `n` points in the plane, spread in `centers` Gaussian blobs of standard deviation `std` around centers drawn
+/// uniformly in [-10, 10)², labelled with the index of their blob; the blobs take turns, so each has about
+/// `n / centers` points.
+pub fn make_blobs(n: usize, centers: usize, std: f64, seed: u64) -> (Vec<Vec<f64>>, Vec<usize>) {
+    let mut rng = Rng::seed(seed);
+    let means: Vec<(f64, f64)> = (0..centers)
+        .map(|_| (rng.uniform(-10.0, 10.0), rng.uniform(-10.0, 10.0)))
+        .collect();
+    (0..n)
+        .map(|i| {
+            let blob = i % centers;
+            let (x, y) = means[blob];
+            (vec![x + rng.normal(0.0, std), y + rng.normal(0.0, std)], blob)
+        })
+        .unzip()
+}
+
+/// The characters of a text, each mapped to an index, for character-level language models.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CharVocab {
+    chars: Vec<char>,
+}
+
+impl CharVocab {
+    /// The distinct characters of `text`, indexed in increasing order.
+    pub fn fit(text: &str) -> CharVocab {
+        CharVocab { chars: text.chars().collect::<BTreeSet<_>>().into_iter().collect() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// The index of `c`, if it is part of the vocabulary.
+    pub fn index(&self, c: char) -> Option<usize> {
+        self.chars.binary_search(&c).ok()
+    }
+
+    /// The character of `index`. Panics if `index` is not below `len`.
+    pub fn char(&self, index: usize) -> char {
+        self.chars[index]
+    }
+
+    /// The index of every character of `text`. Panics on a character outside the vocabulary.
+    pub fn encode(&self, text: &str) -> Vec<usize> {
+        text.chars()
+            .map(|c| self.index(c).unwrap_or_else(|| panic!("{:?} is not in the vocabulary", c)))
+            .collect()
+    }
+
+    /// The text made of the characters of `indices`, the inverse of `encode`.
+    pub fn decode(&self, indices: &[usize]) -> String {
+        indices.iter().map(|&i| self.char(i)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vocabularies_index_distinct_characters_in_order() {
+        let vocab = CharVocab::fit("hello, world");
+        assert_eq!(vocab.decode(&(0..vocab.len()).collect::<Vec<usize>>()), " ,dehlorw");
+        assert_eq!((vocab.index('h'), vocab.index('z')), (Some(4), None));
+        assert_eq!(vocab.decode(&vocab.encode("hello, world")), "hello, world");
+        assert_eq!(vocab.encode("low"), [5, 6, 8]);
+        assert!(CharVocab::fit("").is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "'z' is not in the vocabulary")]
+    fn encoding_an_unknown_character_panics() {
+        CharVocab::fit("abc").encode("abz");
+    }
+
+    #[test]
+    fn datasets_are_reproducible_and_labelled() {
+        assert_eq!(make_moons(10, 0.1, 3), make_moons(10, 0.1, 3));
+        let (points, labels) = make_xor(100, 1);
+        for (point, label) in std::iter::zip(&points, &labels) {
+            assert_eq!(*label == 1.0, (point[0] < 0.0) != (point[1] < 0.0));
+        }
+        let (_, blobs) = make_blobs(9, 3, 0.5, 2);
+        assert_eq!(blobs, [0, 1, 2, 0, 1, 2, 0, 1, 2]);
+    }
+}
@@ -0,0 +1,347 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::engine::Value;
+
+/// The direction in which Graphviz lays out the graph, from the leaves towards the root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RankDir {
+    LeftRight,
+    RightLeft,
+    TopBottom,
+    BottomTop,
+}
+
+impl RankDir {
+    fn as_str(self) -> &'static str {
+        match self {
+            RankDir::LeftRight => "LR",
+            RankDir::RightLeft => "RL",
+            RankDir::TopBottom => "TB",
+            RankDir::BottomTop => "BT",
+        }
+    }
+}
+
+/// What `Value::to_dot_with_options` shows of each node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Whether nodes show their grad next to their data; before a backward pass every grad is zero and
+    /// only adds noise.
+    pub show_grad: bool,
+    pub rankdir: RankDir,
+    /// The number of decimals data and grads are rounded to.
+    pub precision: usize,
+}
+
+impl Default for DotOptions {
+    fn default() -> DotOptions {
+        DotOptions { show_grad: true, rankdir: RankDir::LeftRight, precision: 4 }
+    }
+}
+
+impl Value {
+    /// Renders the graph computing `self` in the Graphviz DOT language with the default options, see
+    /// `to_dot_with_options`.
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with_options(&DotOptions::default())
+    }
+
+    /// Renders the graph computing `self` in the Graphviz DOT language: one record per node with its id, label,
+    /// data and (optionally) grad, and for each interior node a small node for its op, pointing to it.
+    ///
+    /// Nodes are named after their ids, which their records show as `#id` to match `Value::id` and the debug
+    /// output, so the same graph always gives the same text. Nodes labeled with a dotted path, such as the
+    /// parameters of an `nn` module (`layer1.neuron3.w2`), are drawn in clusters named after the prefix of the
+    /// path, nested like it: `neuron3` inside `layer1`.
+    pub fn to_dot_with_options(&self, options: &DotOptions) -> String {
+        let precision = options.precision;
+        let record = |value: &Value| {
+            let node = value.borrow();
+            let mut fields = vec![format!("#{}", node.id), format!("data {:.*}", precision, node.data)];
+            if let Some(label) = &node.label {
+                fields.insert(1, escape(label));
+            }
+            if options.show_grad {
+                fields.push(format!("grad {:.*}", precision, node.grad));
+            }
+            format!("n{} [shape=record, label=\"{{ {} }}\"];\n", node.id, fields.join(" | "))
+        };
+        let mut out = format!("digraph {{\n  rankdir={};\n", options.rankdir.as_str());
+        let mut clusters = Group::default();
+
+        for value in self.topo_order() {
+            match prefix(&value) {
+                Some(prefix) => clusters.descend(&prefix).nodes.push(value.clone()),
+                None => out += &format!("  {}", record(&value)),
+            }
+            let node = value.borrow();
+            if let Some(op) = node._op {
+                out += &format!("  n{}_op [label=\"{}\"];\n", node.id, op);
+                out += &format!("  n{}_op -> n{};\n", node.id, node.id);
+                for child in &node._prev {
+                    out += &format!("  n{} -> n{}_op;\n", child.id(), node.id);
+                }
+            }
+        }
+
+        write_clusters(&clusters, "", 1, &record, &mut out);
+        out + "}\n"
+    }
+
+    /// Renders the graph computing `self` as a Mermaid flowchart with the default options, see
+    /// `to_mermaid_with_options`.
+    pub fn to_mermaid(&self) -> String {
+        self.to_mermaid_with_options(&DotOptions::default())
+    }
+
+    /// Renders the graph computing `self` as a Mermaid flowchart, e.g. to embed it in Markdown: the same nodes,
+    /// edges and clusters as `to_dot_with_options`, the clusters as nested subgraphs.
+    pub fn to_mermaid_with_options(&self, options: &DotOptions) -> String {
+        let precision = options.precision;
+        let text = |value: &Value| {
+            let node = value.borrow();
+            let mut fields = vec![format!("#{}", node.id), format!("data {:.*}", precision, node.data)];
+            if let Some(label) = &node.label {
+                fields.insert(1, label.replace('"', "#quot;"));
+            }
+            if options.show_grad {
+                fields.push(format!("grad {:.*}", precision, node.grad));
+            }
+            format!("n{}[\"{}\"]\n", node.id, fields.join(" | "))
+        };
+        // the subgraphs go first, so that each node is declared in its subgraph before any edge refers to it
+        let mut body = String::new();
+        let mut clusters = Group::default();
+        for value in self.topo_order() {
+            match prefix(&value) {
+                Some(prefix) => clusters.descend(&prefix).nodes.push(value.clone()),
+                None => body += &format!("  {}", text(&value)),
+            }
+            let node = value.borrow();
+            if let Some(op) = node._op {
+                body += &format!("  n{}_op((\"{}\"))\n", node.id, op);
+                body += &format!("  n{}_op --> n{}\n", node.id, node.id);
+                for child in &node._prev {
+                    body += &format!("  n{} --> n{}_op\n", child.id(), node.id);
+                }
+            }
+        }
+
+        let mut out = format!("flowchart {}\n", options.rankdir.as_str());
+        write_subgraphs(&clusters, 1, &mut 0, &text, &mut out);
+        out + &body
+    }
+
+    /// Writes `to_dot` to `path`, e.g. to be rendered with `dot -Tsvg`.
+    pub fn write_dot(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_dot())
+    }
+
+    /// Writes `to_dot_with_options` to `path`.
+    pub fn write_dot_with_options(&self, path: impl AsRef<Path>, options: &DotOptions) -> io::Result<()> {
+        fs::write(path, self.to_dot_with_options(options))
+    }
+
+    /// Writes `to_mermaid` to `path`.
+    pub fn write_mermaid(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_mermaid())
+    }
+}
+
+// The labeled nodes in clusters, by the prefix of their labels, and the clusters nested inside them in the order
+// they were first reached.
+#[derive(Default)]
+struct Group {
+    nodes: Vec<Value>,
+    children: Vec<(String, Group)>,
+}
+
+impl Group {
+    // the cluster at `path` under this one, created if needed
+    fn descend(&mut self, path: &[String]) -> &mut Group {
+        let Some((name, rest)) = path.split_first() else {
+            return self;
+        };
+        let i = match self.children.iter().position(|(child, _)| child == name) {
+            Some(i) => i,
+            None => {
+                self.children.push((name.clone(), Group::default()));
+                self.children.len() - 1
+            }
+        };
+        self.children[i].1.descend(rest)
+    }
+}
+
+// the components of the label of `value` before the last dot, `["layer1", "neuron3"]` for `layer1.neuron3.w2`,
+// if it has any
+fn prefix(value: &Value) -> Option<Vec<String>> {
+    let label = value.label()?;
+    let (prefix, _) = label.rsplit_once('.')?;
+    Some(prefix.split('.').map(str::to_string).collect())
+}
+
+// The clusters of `group` as DOT subgraphs, at `depth` levels of indentation, with the records given by `record`.
+// Graphviz draws a subgraph as a box only if its name starts with `cluster`.
+fn write_clusters(group: &Group, path: &str, depth: usize, record: &dyn Fn(&Value) -> String, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    for (name, child) in &group.children {
+        let path = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+        *out += &format!("{}subgraph \"cluster_{}\" {{\n", indent, escape_quotes(&path));
+        *out += &format!("{}  label=\"{}\";\n", indent, escape_quotes(name));
+        for node in &child.nodes {
+            *out += &format!("{}  {}", indent, record(node));
+        }
+        write_clusters(child, &path, depth + 1, record, out);
+        *out += &format!("{}}}\n", indent);
+    }
+}
+
+// Same as `write_clusters` for Mermaid. Subgraphs are numbered `g0`, `g1`, ... in the order they are written, as
+// their ids can't hold every character a label can; the names are their titles.
+fn write_subgraphs(
+    group: &Group,
+    depth: usize,
+    next: &mut usize,
+    text: &dyn Fn(&Value) -> String,
+    out: &mut String,
+) {
+    let indent = "  ".repeat(depth);
+    for (name, child) in &group.children {
+        *out += &format!("{}subgraph g{} [\"{}\"]\n", indent, next, name.replace('"', "#quot;"));
+        *next += 1;
+        for node in &child.nodes {
+            *out += &format!("{}  {}", indent, text(node));
+        }
+        write_subgraphs(child, depth + 1, next, text, out);
+        *out += &format!("{}end\n", indent);
+    }
+}
+
+fn escape_quotes(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// the characters with a meaning inside a record label, and the quotes around it
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\"\\{}|<>".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_show_ids() {
+        let x = Value::from(2.0).add_label("x");
+        let y = x.tanh();
+        let dot = y.to_dot();
+        let id = x.id();
+        let record = format!("n{} [shape=record, label=\"{{ #{} | x | data 2.0000 | grad 0.0000 }}\"];", id, id);
+        assert!(dot.contains(&record), "{}", dot);
+        assert!(dot.contains(&format!("n{} [shape=record, label=\"{{ #{} | data", y.id(), y.id())));
+    }
+
+    #[test]
+    fn graphs_without_paths_have_no_clusters() {
+        let x = Value::from(2.0).add_label("x");
+        let dot = (&x * &Value::from(3.0).add_label("scale")).to_dot();
+        assert!(!dot.contains("subgraph"));
+    }
+
+    #[test]
+    fn parameters_of_a_model_are_drawn_in_clusters_per_layer() {
+        crate::seed(1);
+        let mlp = crate::MLP::new(2, vec![2, 1]);
+        let output = mlp.forward(vec![Value::from(0.5), Value::from(-1.0)]).remove(0);
+        let dot = output.to_dot();
+        for layer in ["layer0", "layer1"] {
+            assert_eq!(dot.matches(&format!("subgraph \"cluster_{}\" {{", layer)).count(), 1, "{}", dot);
+        }
+        assert!(dot.contains("subgraph \"cluster_layer0.neuron1\" {\n      label=\"neuron1\";\n      n"));
+        // every parameter is drawn once, inside its cluster
+        let w = output.find_by_label("layer0.neuron1.w0").unwrap();
+        let record = format!("n{} [shape=record", w.id());
+        assert_eq!(dot.matches(&record).count(), 1);
+        assert!(dot.contains(&format!("      {}", record)));
+        assert_eq!(dot.matches('{').count(), dot.matches('}').count());
+    }
+
+    #[test]
+    fn mermaid_has_the_same_nodes_and_nested_subgraphs() {
+        crate::seed(1);
+        let mlp = crate::MLP::new(2, vec![1]);
+        let x = Value::from(0.5).add_label("x");
+        let output = mlp.forward(vec![x.clone(), Value::from(-1.0)]).remove(0);
+        let mermaid = output.to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR\n  subgraph g0 [\"layer0\"]\n    subgraph g1 [\"neuron0\"]\n"));
+        assert_eq!(mermaid.matches("subgraph ").count(), mermaid.matches(" end\n").count());
+        assert!(mermaid.contains(&format!("  n{}[\"#{} | x | data 0.5000 | grad 0.0000\"]\n", x.id(), x.id())));
+        let nodes = output.topo_order().len();
+        assert_eq!(mermaid.matches("[\"#").count(), nodes);
+        let id = output.id();
+        assert!(mermaid.contains(&format!("  n{}_op((\"tanh\"))\n  n{}_op --> n{}\n", id, id, id)));
+    }
+
+    // `o = tanh(w·x + b)`, the graph of the visualization example, after backward
+    fn neuron() -> (Value, Value) {
+        let (w, x, b) = (Value::from(-3.0), Value::from(2.0), Value::from(6.881_373_587));
+        let o = (&(&w * &x) + &b).tanh();
+        o.backward().unwrap();
+        (o, w)
+    }
+
+    #[test]
+    fn grads_are_shown_only_when_asked() {
+        let (o, w) = neuron();
+        let hidden = DotOptions { show_grad: false, ..DotOptions::default() };
+        assert!(!o.to_dot_with_options(&hidden).contains("grad "));
+        let dot = o.to_dot();
+        assert_eq!(dot.matches("| grad ").count(), o.topo_order().len());
+        let record = format!("n{0} [shape=record, label=\"{{ #{0} | data -3.0000 | grad 1.0000 }}\"];", w.id());
+        assert!(dot.contains(&record), "{}", dot);
+    }
+
+    #[test]
+    fn rankdir_appears_in_the_header() {
+        let (o, _) = neuron();
+        for (rankdir, name) in [
+            (RankDir::LeftRight, "LR"),
+            (RankDir::RightLeft, "RL"),
+            (RankDir::TopBottom, "TB"),
+            (RankDir::BottomTop, "BT"),
+        ] {
+            let dot = o.to_dot_with_options(&DotOptions { rankdir, ..DotOptions::default() });
+            assert!(dot.starts_with(&format!("digraph {{\n  rankdir={};\n", name)), "{}", dot);
+        }
+    }
+
+    #[test]
+    fn precision_rounds_data_and_grads() {
+        let (o, _) = neuron();
+        let at = |precision| o.to_dot_with_options(&DotOptions { precision, ..DotOptions::default() });
+        // o = tanh(0.881373587) = 0.7071067...
+        assert!(at(2).contains("data 0.71 | grad 1.00"));
+        assert!(at(6).contains("data 0.707107 | grad 1.000000"));
+        assert!(at(0).contains("data 1 | grad 1 }"));
+    }
+
+    #[test]
+    fn options_are_written_to_the_file() {
+        let (o, _) = neuron();
+        let path = std::env::temp_dir().join(format!("angstromgrad-dot-{}.dot", std::process::id()));
+        let options = DotOptions { show_grad: false, rankdir: RankDir::TopBottom, precision: 1 };
+        o.write_dot_with_options(&path, &options).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), o.to_dot_with_options(&options));
+        fs::remove_file(&path).unwrap();
+    }
+}
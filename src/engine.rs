@@ -1,39 +1,547 @@
-use std::cell::{Ref, RefCell};
-use std::iter::Sum;
-use std::ops::{Add, Deref, Mul, Neg, Sub};
-use std::rc::Rc;
+use std::cell::{Cell, Ref, RefCell};
+use std::iter::{Product, Sum};
+use std::ops::{Add, AddAssign, Deref, Div, Mul, Neg, Sub};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
-use std::collections::HashSet;
-use std::fmt::Debug;
+use crate::error::{BackwardError, BindError, GradError, JacobianCheckFailure};
+use crate::ops;
+use crate::profile;
+
+pub use crate::parser::{parse, parse_labeled, ParseError};
+pub use crate::profile::{profile, OpProfile, ProfileReport};
+pub use crate::rewrite::{replace_all_subgraphs, replace_subgraph, rewrite, ConstantFold, Cse, GraphPass};
+use crate::rewrite::StripAssertions;
+pub use crate::grad_flow::{GradFlowEntry, GradFlowIssue};
+pub use crate::stats::{grad_stats, histogram, tensor_stats, DataOrGrad, Stats};
+pub use crate::tape::Tape;
+pub use crate::dot::{DotOptions, RankDir};
+pub use crate::graph_diff::{
+    graph_diff, graph_diff_with_options, DiffOptions, Divergence, DivergenceKind, GraphDiff,
+};
+pub use crate::custom::{register_binary, register_unary, CustomOp};
+pub use crate::checkpoint::{checkpoint, Segment};
+#[cfg(feature = "bench")]
+pub use crate::bench::{build_chain, build_mlp_graph, build_tree};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display};
 use std::hash::Hash;
 
-type PropagateFn = fn(value: &Ref<_Value>);
+pub(crate) type PropagateFn = fn(value: &Ref<_Value>);
+
+// The operation that created a node. Leaves carry no op.
+// Add and Mul also describe the n-ary nodes built by `ops::add_n` and `ops::mul_n`, SumCompensated the sums of
+// `ops::sum_compensated`, which are those of `add_n` computed by Kahan summation, Custom the ops defined
+// outside the crate with `Value::custom_unary` and `Value::custom_binary`, and Checkpoint the outputs of the
+// segments computed by `checkpoint`. Clamp comes last so that the binary format keeps the tags of the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Op {
+    Add,
+    Mul,
+    Pow,
+    Tanh,
+    Exp,
+    Ln,
+    Relu,
+    Softplus,
+    RoundSte,
+    BinarizeSte,
+    Assert,
+    SumCompensated,
+    Clamp,
+    Custom(CustomOp),
+    Checkpoint(Segment),
+}
+
+impl Op {
+    // Every built-in op, in the order of declaration.
+    pub(crate) const ALL: [Op; 13] = [
+        Op::Add,
+        Op::Mul,
+        Op::Pow,
+        Op::Tanh,
+        Op::Exp,
+        Op::Ln,
+        Op::Relu,
+        Op::Softplus,
+        Op::RoundSte,
+        Op::BinarizeSte,
+        Op::Assert,
+        Op::SumCompensated,
+        Op::Clamp,
+    ];
+
+    // Recomputes the data of a node from the data of its children, in the order they are stored in.
+    pub(crate) fn forward(&self, inputs: &[f64]) -> f64 {
+        match self {
+            Op::Add => inputs.iter().sum(),
+            Op::Mul => inputs.iter().product(),
+            Op::Pow => inputs[0].powf(inputs[1]),
+            Op::Tanh => inputs[0].tanh(),
+            Op::Exp => inputs[0].exp(),
+            Op::Ln => inputs[0].ln(),
+            Op::Relu => inputs[0].max(0.0),
+            Op::Softplus => softplus(inputs[0]),
+            Op::RoundSte => inputs[0].round(),
+            Op::BinarizeSte => binarize(inputs[0], inputs[1]),
+            Op::Assert => inputs[0],
+            Op::SumCompensated => ops::kahan_sum(inputs.iter().copied()),
+            Op::Clamp => inputs[0].clamp(inputs[1], inputs[2]),
+            Op::Custom(op) => op.forward(inputs),
+            Op::Checkpoint(segment) => segment.forward(inputs),
+        }
+    }
+
+    // Builds a new node applying this op to `children`, through the same constructor as the original.
+    // Returns None if the number of children doesn't fit the op.
+    pub(crate) fn apply(&self, children: &[Value]) -> Option<Value> {
+        match (self, children) {
+            (Op::Add, [a, b]) => Some(add(a, b)),
+            (Op::Add, [_, ..]) => Some(ops::add_n(children)),
+            (Op::Mul, [a, b]) => Some(mul(a, b)),
+            (Op::Mul, [_, ..]) => Some(ops::mul_n(children)),
+            (Op::Pow, [base, power]) => Some(base.pow(power)),
+            (Op::Tanh, [x]) => Some(x.tanh()),
+            (Op::Exp, [x]) => Some(x.exp()),
+            (Op::Ln, [x]) => Some(x.ln()),
+            (Op::Relu, [x]) => Some(x.relu()),
+            (Op::Softplus, [x]) => Some(x.softplus()),
+            (Op::RoundSte, [x]) => Some(x.round_ste()),
+            (Op::BinarizeSte, [x, threshold]) => Some(x.binarize_ste(threshold.data())),
+            (Op::Assert, [x, lo, hi]) => Some(assertion(x, lo.data(), hi.data())),
+            (Op::SumCompensated, [_, ..]) => Some(ops::sum_compensated(children)),
+            (Op::Clamp, [x, lo, hi]) => Some(x.clamp(lo.data(), hi.data())),
+            (Op::Custom(op), _) if children.len() == op.arity() => Some(op.build(children)),
+            (Op::Checkpoint(segment), _) if children.len() == segment.arity() && segment.is_live() => {
+                Some(segment.build(children))
+            }
+            _ => None,
+        }
+    }
+
+    // The number of children a node of this op has, None for the sums and Mul which take any number.
+    pub(crate) fn arity(&self) -> Option<usize> {
+        match self {
+            Op::Add | Op::Mul | Op::SumCompensated => None,
+            Op::Pow | Op::BinarizeSte => Some(2),
+            Op::Assert | Op::Clamp => Some(3),
+            Op::Tanh | Op::Exp | Op::Ln | Op::Relu | Op::Softplus | Op::RoundSte => Some(1),
+            Op::Custom(op) => Some(op.arity()),
+            Op::Checkpoint(segment) => Some(segment.arity()),
+        }
+    }
+
+    // The inputs at which the op is safe to evaluate and differentiate, by position of the input: the data and
+    // derivatives of the op are finite and of moderate size there, away from poles and overflow. Generators of
+    // random graphs (see `testing::arb_expression`) keep the inputs of every op within its domain.
+    pub fn domain(&self, input: usize) -> Domain {
+        match (self, input) {
+            (Op::Pow, 0) => Domain { lo: 0.1, hi: 10.0 },
+            (Op::Pow, _) => Domain { lo: -3.0, hi: 3.0 },
+            (Op::Exp, _) => Domain { lo: f64::NEG_INFINITY, hi: 10.0 },
+            (Op::Ln, _) => Domain { lo: 0.1, hi: f64::INFINITY },
+            _ => Domain::ALL,
+        }
+    }
+
+    // Whether the gradients of the op are its derivatives and are continuous, so that they can be checked
+    // against finite differences: not for relu and clamp, whose derivatives jump, nor for the straight-through ops,
+    // whose gradients are deliberately not their derivatives.
+    pub fn is_smooth(&self) -> bool {
+        !matches!(self, Op::Relu | Op::RoundSte | Op::BinarizeSte | Op::Clamp)
+    }
+
+    // Whether gradients flow into the input at position `input`: not into the exponent of a power, the
+    // threshold of a binarization nor the bounds of an assertion or a clamp, which are treated as constants.
+    pub fn propagates_to(&self, input: usize) -> bool {
+        !matches!((self, input), (Op::Pow | Op::BinarizeSte, 1) | (Op::Assert | Op::Clamp, 1 | 2))
+    }
+
+    // The contribution of `grad` (the gradient flowing into `node`) to the gradient of each child of `node`,
+    // expressed with graph ops so that the result can itself be back-propagated.
+    // This mirrors the propagation functions, which do the same computation on plain f64s.
+    pub(crate) fn symbolic_backward(&self, node: &Value, grad: &Value) -> Vec<Value> {
+        let children = node.borrow()._prev.clone();
+        match self {
+            Op::Add | Op::SumCompensated => children.iter().map(|_| grad.clone()).collect(),
+            Op::Mul => (0..children.len())
+                .map(|i| {
+                    let others: Vec<Value> = children
+                        .iter()
+                        .enumerate()
+                        .filter(|&(j, _)| j != i)
+                        .map(|(_, child)| child.clone())
+                        .collect();
+                    match others.len() {
+                        0 => grad.clone(),
+                        1 => grad * &others[0],
+                        _ => grad * &ops::mul_n(&others),
+                    }
+                })
+                .collect(),
+            Op::Pow => {
+                let (base, power) = (&children[0], &children[1]);
+                let lowered = power + &Value::constant(-1.0);
+                // like the propagation function, the exponent doesn't receive a gradient
+                vec![grad * &(power * &base.pow(&lowered)), Value::constant(0.0)]
+            }
+            Op::Tanh => {
+                let slope = &Value::constant(1.0) + &-(&(node * node));
+                vec![grad * &slope]
+            }
+            Op::Exp => vec![grad * node],
+            Op::Ln => vec![grad / &children[0]],
+            Op::Relu => {
+                let slope = if children[0].data() > 0.0 { 1.0 } else { 0.0 };
+                vec![grad * &Value::constant(slope)]
+            }
+            Op::Softplus => {
+                let one = Value::constant(1.0);
+                let sigmoid = &one / &(&one + &(-&children[0]).exp());
+                vec![grad * &sigmoid]
+            }
+            Op::RoundSte => vec![grad.clone()],
+            Op::BinarizeSte => {
+                let slope = if ste_passes(children[0].data(), children[1].data()) { 1.0 } else { 0.0 };
+                vec![grad * &Value::constant(slope), Value::constant(0.0)]
+            }
+            Op::Assert => vec![grad.clone(), Value::constant(0.0), Value::constant(0.0)],
+            Op::Clamp => {
+                let inside = clamp_passes(children[0].data(), children[1].data(), children[2].data());
+                let slope = if inside { 1.0 } else { 0.0 };
+                vec![grad * &Value::constant(slope), Value::constant(0.0), Value::constant(0.0)]
+            }
+            // only the numbers of the user's backward are known: the local derivatives are taken as constants
+            Op::Custom(op) => {
+                let inputs: Vec<f64> = children.iter().map(Value::data).collect();
+                let slopes = op.backward(&inputs, node.data(), 1.0);
+                slopes.into_iter().map(|slope| grad * &Value::constant(slope)).collect()
+            }
+            Op::Checkpoint(segment) => segment.backward_graph(&children).iter().map(|slope| grad * slope).collect(),
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Op::Add => "+",
+            Op::Mul => "*",
+            Op::Pow => "^",
+            Op::Tanh => "tanh",
+            Op::Exp => "exp",
+            Op::Ln => "ln",
+            Op::Relu => "relu",
+            Op::Softplus => "softplus",
+            Op::RoundSte => "round_ste",
+            Op::BinarizeSte => "binarize_ste",
+            Op::Assert => "assert",
+            Op::SumCompensated => "sum_compensated",
+            Op::Clamp => "clamp",
+            Op::Custom(op) => op.name(),
+            Op::Checkpoint(_) => "checkpoint",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+// A closed interval of inputs, see `Op::domain`. Either bound may be infinite.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Domain {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Domain {
+    pub const ALL: Domain = Domain { lo: f64::NEG_INFINITY, hi: f64::INFINITY };
+
+    pub fn contains(&self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+}
+
+// Source of node ids, shared by all threads so that ids stay unique process-wide.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// Number of nodes currently alive, across all threads, and the most that may be alive at once.
+static LIVE_NODES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_LIVE_NODES: AtomicUsize = AtomicUsize::new(0);
+static NODE_LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+// Sets (Some) or removes (None) a process-wide limit on the number of live nodes, to fail loudly on a runaway
+// graph instead of running out of memory. Past the limit the checked `try_` ops return `GradError::NodeLimit`
+// and every other node construction panics with the count and the op being constructed.
+pub fn set_node_limit(limit: Option<usize>) {
+    NODE_LIMIT.store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+// Number of nodes currently alive, across all threads.
+pub fn live_node_count() -> usize {
+    LIVE_NODES.load(Ordering::Relaxed)
+}
+
+// The most nodes that have been alive at once, across all threads, since the last `reset_peak_live_node_count`.
+pub fn peak_live_node_count() -> usize {
+    PEAK_LIVE_NODES.load(Ordering::Relaxed)
+}
+
+// Restarts `peak_live_node_count` from the number of nodes alive now, e.g. at the start of a training step.
+pub fn reset_peak_live_node_count() {
+    PEAK_LIVE_NODES.store(live_node_count(), Ordering::Relaxed);
+}
+
+// Number of nodes created so far, across all threads, including those that have been dropped since.
+pub fn created_node_count() -> u64 {
+    NEXT_ID.load(Ordering::Relaxed)
+}
+
+// Whether `needed` more nodes fit under the node limit.
+fn within_node_limit(needed: usize, op: &'static str) -> Result<(), GradError> {
+    let limit = NODE_LIMIT.load(Ordering::Relaxed);
+    if live_node_count().saturating_add(needed) > limit {
+        return Err(GradError::NodeLimit { limit, op });
+    }
+    Ok(())
+}
+
+// The constants shared by `Value::constant`, created on first use.
+const INTERNED: [f64; 3] = [-1.0, 0.0, 1.0];
+
+thread_local! {
+    static CONSTANTS: RefCell<[Option<Value>; INTERNED.len()]> = const { RefCell::new([None, None, None]) };
+    static INTERNING: Cell<bool> = const { Cell::new(true) };
+}
+
+// Turns the sharing of common constants by `Value::constant` on (the default) or off for the current thread.
+// With interning off, every constant is a new node, as it was before interning was introduced.
+pub fn interning(enabled: bool) {
+    INTERNING.with(|interning| interning.set(enabled));
+}
+
+// A function called with a node and its new grad after each write to that grad, see `Value::register_hook`.
+type GradHook = Rc<dyn Fn(&Value, f64)>;
+
+thread_local! {
+    // The hooks registered on this thread, by node. Empty in most programs, which is all a grad write checks then.
+    static GRAD_HOOKS: RefCell<HashMap<NodeId, Vec<GradHook>>> = RefCell::new(HashMap::new());
+}
+
+// Calls the hooks of `value`, if any, with its new grad `grad`. The hooks are cloned out of the table first, so
+// that they may themselves register hooks or write grads.
+fn run_grad_hooks(value: &Value, grad: f64) {
+    let hooks = GRAD_HOOKS.with(|hooks| {
+        let hooks = hooks.borrow();
+        if hooks.is_empty() {
+            return None;
+        }
+        hooks.get(&value.id()).cloned()
+    });
+    for hook in hooks.into_iter().flatten() {
+        hook(value, grad);
+    }
+}
+
+thread_local! {
+    static SKIP_ZERO_GRADIENTS: Cell<bool> = const { Cell::new(true) };
+}
+
+// Turns off (or back on, the default) skipping the propagation functions of nodes whose grad is exactly zero in
+// the backward passes of the current thread. Behind relu, `select` and other gates whole subgraphs receive no
+// gradient, and propagating zeros through them changes nothing, except where a local derivative is infinite or
+// NaN, which a zero gradient would otherwise turn into NaN. A node whose children have hooks (see
+// `Value::register_hook`) is never skipped, so that the hooks still see the zeros written to them.
+pub fn skip_zero_gradients(enabled: bool) {
+    SKIP_ZERO_GRADIENTS.with(|skip| skip.set(enabled));
+}
+
+thread_local! {
+    static AUTO_LABEL: Cell<bool> = const { Cell::new(false) };
+}
+
+// Turns on (or back off, the default) labelling every node built by an op in the current thread with the place
+// in the source that built it, `file:line`, e.g. to find the line behind a node of a DOT dump. The ops and
+// operators of `Value` and `ops::add_n` and `mul_n` report the line calling them; nodes built inside other
+// helpers, such as the losses, report the line of the helper. Explicit labels (`add_label`) replace them.
+pub fn set_auto_label(enabled: bool) {
+    AUTO_LABEL.with(|auto_label| auto_label.set(enabled));
+}
+
+// Whether any hook is registered on one of `nodes`. With no hook on the thread, this is a single check.
+pub(crate) fn has_hooks(nodes: &[Value]) -> bool {
+    GRAD_HOOKS.with(|hooks| {
+        let hooks = hooks.borrow();
+        !hooks.is_empty() && nodes.iter().any(|node| hooks.contains_key(&node.id()))
+    })
+}
+
+thread_local! {
+    static TRACK_CONSUMERS: Cell<bool> = const { Cell::new(false) };
+}
+
+// Turns on (or back off, the default) recording, in each node built by an op in the current thread, a weak link
+// from each of its children back to it, so that `Value::consumers` can walk the graph from the leaves up. The
+// links don't keep the consumers alive; nodes built while it is off aren't recorded.
+pub fn track_consumers(enabled: bool) {
+    TRACK_CONSUMERS.with(|track| track.set(enabled));
+}
+
+// What `backward` does when leaves of the graph still hold the gradients of an earlier pass, which it would
+// otherwise silently add to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccumulationPolicy {
+    // add to the stale gradients, e.g. to accumulate gradients over several batches (the default)
+    Accumulate,
+    // add to them, printing a warning to stderr
+    Warn,
+    // refuse the pass: `backward` returns `BackwardError::StaleGradients`
+    Error,
+}
+
+thread_local! {
+    static ACCUMULATION_POLICY: Cell<AccumulationPolicy> = const { Cell::new(AccumulationPolicy::Accumulate) };
+}
+
+// Sets the response to stale gradients for the backward passes of the current thread.
+pub fn set_accumulation_policy(policy: AccumulationPolicy) {
+    ACCUMULATION_POLICY.with(|current| current.set(policy));
+}
+
+// Applies the accumulation policy to a topological order about to be back-propagated.
+// A leaf is stale when an earlier pass reached it and its grad hasn't been zeroed since.
+fn check_stale(order: &[Value]) -> Result<(), BackwardError> {
+    let policy = ACCUMULATION_POLICY.with(Cell::get);
+    if policy == AccumulationPolicy::Accumulate {
+        return Ok(());
+    }
+    let leaves = order
+        .iter()
+        .filter(|value| {
+            let node = value.borrow();
+            node._prev.is_empty() && node.propagated && node.grad != 0.0
+        })
+        .count();
+    if leaves == 0 {
+        return Ok(());
+    }
+    let error = BackwardError::StaleGradients { leaves };
+    if policy == AccumulationPolicy::Error {
+        return Err(error);
+    }
+    eprintln!("warning: {}; call zero_grad_all first unless accumulating on purpose", error);
+    Ok(())
+}
+
+// The precision in which the data or the grads of nodes are kept, see `set_data_precision`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precision {
+    // single precision: every value is rounded to the nearest f32 as it is stored
+    F32,
+    // double precision, the default
+    F64,
+}
+
+impl Precision {
+    // `x` rounded to the precision.
+    pub fn round(self, x: f64) -> f64 {
+        match self {
+            Precision::F32 => x as f32 as f64,
+            Precision::F64 => x,
+        }
+    }
+}
+
+thread_local! {
+    static DATA_PRECISION: Cell<Precision> = const { Cell::new(Precision::F64) };
+    static GRAD_PRECISION: Cell<Precision> = const { Cell::new(Precision::F64) };
+}
+
+// Sets the precision of the data of the nodes built or updated by the current thread: with `F32`, the result of
+// every op, every leaf and every write through `set_data`, `descend` and the optimizers is rounded to f32, as if
+// the data were stored in f32. The grads keep their own precision, f64 unless set otherwise by
+// `set_grad_precision`, so that gradients summed over thousands of contributions don't drift with the data's
+// rounding. Both are still held in f64 fields, so exporters and serialization carry them unchanged.
+pub fn set_data_precision(precision: Precision) {
+    DATA_PRECISION.with(|current| current.set(precision));
+}
+
+// Sets the precision in which the current thread stores and accumulates grads: with `F32`, every write through
+// `set_grad` and every contribution added by `add_grad`, and so by the backward passes, is rounded to f32.
+pub fn set_grad_precision(precision: Precision) {
+    GRAD_PRECISION.with(|current| current.set(precision));
+}
+
+pub fn data_precision() -> Precision {
+    DATA_PRECISION.with(Cell::get)
+}
+
+pub fn grad_precision() -> Precision {
+    GRAD_PRECISION.with(Cell::get)
+}
 
 pub struct _Value {
-    data: f64,
-    grad: f64,
-    _op: Option<String>,
-    _prev: Vec<Value>,
-    propagate: Option<PropagateFn>,
-    label: Option<String>,
+    pub(crate) id: u64,
+    pub(crate) data: f64,
+    pub(crate) grad: f64,
+    pub(crate) _op: Option<Op>,
+    pub(crate) _prev: Vec<Value>,
+    pub(crate) propagate: Option<PropagateFn>,
+    pub(crate) label: Option<String>,
+    pub(crate) requires_grad: bool,
+    pub(crate) propagated: bool,
+    // the nodes built on top of this one while `track_consumers` was on, some of which may be gone
+    consumers: Vec<Weak<RefCell<_Value>>>,
 }
 
 impl _Value {
-    fn new(
+    pub(crate) fn new(
         data: f64,
         label: Option<String>,
-        op: Option<String>,
+        op: Option<Op>,
         prev: Vec<Value>,
         propagate: Option<PropagateFn>,
     ) -> _Value {
+        let live = LIVE_NODES.fetch_add(1, Ordering::Relaxed);
+        let limit = NODE_LIMIT.load(Ordering::Relaxed);
+        if live >= limit {
+            LIVE_NODES.fetch_sub(1, Ordering::Relaxed);
+            let op = op.map_or("leaf".to_string(), |op| op.to_string());
+            panic!("node limit of {} reached with {} live nodes while constructing a {} node", limit, live, op);
+        }
+        PEAK_LIVE_NODES.fetch_max(live + 1, Ordering::Relaxed);
+        if let Some(Op::Checkpoint(segment)) = op {
+            segment.retain();
+        }
         _Value {
-            data, // the actual numerical value
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed), // unique, increasing identifier of the node
+            data: data_precision().round(data), // the actual numerical value
             grad: 0.0, // gradient of the value with respect to some loss
             label, // optional label for the value
-            _op: op, // optional string to describe the operation that created this value
+            _op: op, // optional operation that created this value
             _prev: prev, // vector of previous _Value instances linked to this value
             propagate, // optional function for propagating gradients back through the network
+            requires_grad: true, // false for frozen parameters, whose gradient is discarded
+            propagated: false, // whether a backward pass has run the propagation function
+            consumers: Vec::new(), // weak links to the parents, see `track_consumers`
+        }
+    }
+}
+
+impl Drop for _Value {
+    fn drop(&mut self) {
+        LIVE_NODES.fetch_sub(1, Ordering::Relaxed);
+        if let Some(Op::Checkpoint(segment)) = self._op {
+            segment.release();
         }
+        // the table may already be gone as the thread exits, or be borrowed by the code dropping the node
+        let _ = GRAD_HOOKS.try_with(|hooks| {
+            if let Ok(mut hooks) = hooks.try_borrow_mut() {
+                if !hooks.is_empty() {
+                    hooks.remove(&NodeId(self.id));
+                }
+            }
+        });
     }
 }
 
@@ -64,6 +572,7 @@ impl Hash for _Value {
 impl Debug for _Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("_Value")
+            .field("id", &self.id)
             .field("data", &self.data)
             .field("grad", &self.grad)
             .field("label", &self.label)
@@ -73,6 +582,125 @@ impl Debug for _Value {
     }
 }
 
+// The identity of a node, as returned by `Value::id`: the same for every handle to the node, different for every
+// other node, whatever their data, and never reused within the process. Unlike `Value`, whose `Eq` and `Hash`
+// compare structure, it is the key to use for side tables about particular nodes, see `NodeMap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u64);
+
+impl Display for NodeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// A map from nodes to `T`, keyed by `NodeId` so that lookups don't depend on the data or grads of the nodes,
+// which may change in the meantime.
+#[derive(Clone, Debug)]
+pub struct NodeMap<T> {
+    map: HashMap<NodeId, T>,
+}
+
+impl<T> Default for NodeMap<T> {
+    fn default() -> Self {
+        NodeMap { map: HashMap::new() }
+    }
+}
+
+impl<T> NodeMap<T> {
+    pub fn new() -> NodeMap<T> {
+        NodeMap::default()
+    }
+
+    pub fn insert(&mut self, node: &Value, value: T) -> Option<T> {
+        self.map.insert(node.id(), value)
+    }
+
+    pub fn get(&self, node: &Value) -> Option<&T> {
+        self.map.get(&node.id())
+    }
+
+    pub fn get_mut(&mut self, node: &Value) -> Option<&mut T> {
+        self.map.get_mut(&node.id())
+    }
+
+    pub fn remove(&mut self, node: &Value) -> Option<T> {
+        self.map.remove(&node.id())
+    }
+
+    pub fn contains(&self, node: &Value) -> bool {
+        self.map.contains_key(&node.id())
+    }
+
+    pub fn entry(&mut self, node: &Value) -> std::collections::hash_map::Entry<'_, NodeId, T> {
+        self.map.entry(node.id())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    // The entries by id, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.map.iter().map(|(&id, value)| (id, value))
+    }
+}
+
+// Looks up the entry of `node`, panicking if there is none.
+impl<T> std::ops::Index<&Value> for NodeMap<T> {
+    type Output = T;
+    fn index(&self, node: &Value) -> &T {
+        &self.map[&node.id()]
+    }
+}
+
+// A set of nodes, by `NodeId` like `NodeMap`, e.g. the graph of a root as returned by `Value::reachable_from`.
+#[derive(Clone, Debug, Default)]
+pub struct NodeSet {
+    set: HashSet<NodeId>,
+}
+
+impl NodeSet {
+    pub fn new() -> NodeSet {
+        NodeSet::default()
+    }
+
+    // Adds `node`, returning whether it wasn't in the set yet.
+    pub fn insert(&mut self, node: &Value) -> bool {
+        self.set.insert(node.id())
+    }
+
+    pub fn remove(&mut self, node: &Value) -> bool {
+        self.set.remove(&node.id())
+    }
+
+    pub fn contains(&self, node: &Value) -> bool {
+        self.set.contains(&node.id())
+    }
+
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    // Adds every node of `other`.
+    pub fn extend(&mut self, other: &NodeSet) {
+        self.set.extend(&other.set);
+    }
+
+    // The ids of the nodes, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.set.iter().copied()
+    }
+}
+
 // Wrapper around _Value to allow for multiple references to the same _Value instance 
 // while allowing for interior mutability by using Rc<RefCell<...>>  
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -87,193 +715,2542 @@ impl Value {
         t.into()
     }
 
-    // A new `Value` that wraps the given `_Value` in `Rc<RefCell<_Value>>`.
-    fn new(value: _Value) -> Value {
-        Value(Rc::new(RefCell::new(value)))
+    // A new `Value` that wraps the given `_Value` in `Rc<RefCell<_Value>>`, labelled with the place it was built
+    // at if it is built by an op, unlabelled and `set_auto_label` is on.
+    #[track_caller]
+    pub(crate) fn new(mut value: _Value) -> Value {
+        if value._op.is_some() && value.label.is_none() && AUTO_LABEL.with(Cell::get) {
+            let caller = std::panic::Location::caller();
+            value.label = Some(format!("{}:{}", caller.file(), caller.line()));
+        }
+        let start = profile::start();
+        let op = value._op;
+        let value = Value(Rc::new(RefCell::new(value)));
+        if op.is_some() && TRACK_CONSUMERS.with(Cell::get) {
+            for child in &value.borrow()._prev {
+                let mut child = child.borrow_mut();
+                // links to dropped consumers are cleared whenever the list would grow
+                if child.consumers.len() == child.consumers.capacity() {
+                    child.consumers.retain(|consumer| consumer.strong_count() > 0);
+                }
+                child.consumers.push(Rc::downgrade(&value.0));
+            }
+        }
+        if let Some(start) = start {
+            profile::record_construct(op, start.elapsed());
+        }
+        value
+    }
+
+    // applies the propagation function defined in `_Value` nodes, parents before children,
+    // so that a shared node has received the gradient of all its consumers before it propagates.
+    // The order only depends on the structure of the graph, never on hashing or addresses, so gradients
+    // are accumulated in the same order on every run: the same program gives bit-identical grads.
+    // Stale gradients are handled according to `set_accumulation_policy`, and refusing them modifies nothing.
+    // A node that can't be propagated through (see `BackwardError`) stops the pass; the nodes before it in
+    // the order have already passed their gradients on.
+    pub fn backward(&self) -> Result<(), BackwardError> {
+        let order = self.try_topo_order()?;
+        check_stale(&order)?;
+        self.set_grad(1.0);
+        propagate_all(&order)
     }
 
-    // recursively applies the propagation function defined in `_Value` nodes.
-    pub fn backward(&self) {
-        let mut visited: HashSet<Value> = HashSet::new();
+    // Same as `backward`, panicking with the error instead of returning it.
+    pub fn backward_unchecked(&self) {
+        if let Err(error) = self.backward() {
+            panic!("{}", error);
+        }
+    }
 
-        self.borrow_mut().grad = 1.0;
+    // Every node reachable from `self`, each exactly once, with children placed before their parents.
+    // Nodes are told apart by identity (their `NodeId`), not by the structural `Eq`.
+    // The order is a depth-first post-order visiting children in the order they are stored in, so it is
+    // the same on every run; the visited set is only ever queried, never iterated.
+    // The traversal uses an explicit stack so that long chains don't overflow the call stack.
+    pub(crate) fn topo_order(&self) -> Vec<Value> {
+        self.try_topo_order().unwrap_or_else(|error| panic!("{}", error))
+    }
 
-        fn _backward(visited: &mut HashSet<Value>, value: &Value) {
-            if !visited.contains(&value) {
-                visited.insert(value.clone());
+    // Same as `topo_order`, reporting a child that is already mutably borrowed as an error naming its parent
+    // instead of panicking.
+    fn try_topo_order(&self) -> Result<Vec<Value>, BackwardError> {
+        let mut order = Vec::new();
+        let mut visited: HashSet<NodeId> = HashSet::new();
+        let mut stack = vec![(self.clone(), false)];
 
-                let borrowed_value = value.borrow();
-                if let Some(propagate_fn) = borrowed_value.propagate {
-                    propagate_fn(&borrowed_value);
+        while let Some((value, children_done)) = stack.pop() {
+            if children_done {
+                order.push(value);
+                continue;
+            }
+            if !visited.insert(value.id()) {
+                continue;
+            }
+            stack.push((value.clone(), true));
+            let node = value.borrow();
+            for child in node._prev.iter().rev() {
+                if child.try_borrow().is_err() {
+                    return Err(borrowed(&node));
                 }
-
-                for child_id in &value.borrow()._prev {
-                    _backward(visited, child_id);
+                if !visited.contains(&child.id()) {
+                    stack.push((child.clone(), false));
                 }
             }
         }
+        Ok(order)
+    }
+
+    // A copy of this node, with the same data, label and op, computed from `children` instead and with a zero grad.
+    pub(crate) fn with_children(&self, children: Vec<Value>) -> Value {
+        let node = self.borrow();
+        let copy = Value::new(_Value::new(node.data, node.label.clone(), node._op, children, node.propagate));
+        // a copy isn't labelled with the place it was built at
+        copy.borrow_mut().label = node.label.clone();
+        copy.borrow_mut().requires_grad = node.requires_grad;
+        copy
+    }
+
+    // Duplicates every node reachable from `self`, leaves included, and returns the copy of `self`.
+    // Sharing is preserved among the copies (the copy of `x + x` still has a single leaf), the copies
+    // start with zero grads, and nothing done to the copy affects the original graph.
+    pub fn clone_graph(&self) -> Value {
+        self.clone_graph_with(&mut NodeMap::new())
+    }
+
+    // Same as `clone_graph`, through `copies`, the copies of the nodes of the graphs cloned so far: nodes found
+    // there aren't copied again, so graphs cloned through the same map share the copies of their common nodes,
+    // e.g. the tied parameters of a module (see `Module::clone_module_with`).
+    pub fn clone_graph_with(&self, copies: &mut NodeMap<Value>) -> Value {
+        for value in self.topo_order() {
+            if copies.contains(&value) {
+                continue;
+            }
+            let children = value.borrow()._prev.iter().map(|child| copies[child].clone()).collect();
+            copies.insert(&value, value.with_children(children));
+        }
+        copies[self].clone()
+    }
+
+    // The gradients of `self` with respect to each of `wrt`, built as graph nodes rather than accumulated into `grad`.
+    // Since they are ordinary values, calling `backward` on a function of them gives second derivatives
+    // (e.g. to penalize a gradient norm, or to compute a Hessian one row at a time).
+    // A node of `wrt` that `self` doesn't depend on gets a constant zero gradient.
+    // Custom ops (see `engine::register_unary`) only support first derivatives here: their local derivatives are
+    // constants of the result, so derivatives of it ignore the curvature of those ops.
+    pub fn backward_graph(&self, wrt: &[Value]) -> Vec<Value> {
+        let mut grads: NodeMap<Value> = NodeMap::new();
+        grads.insert(self, Value::from(1.0));
+
+        for value in self.topo_order().iter().rev() {
+            let op = value.borrow()._op;
+            let (Some(op), Some(grad)) = (op, grads.get(value).cloned()) else {
+                continue;
+            };
+            let children = value.borrow()._prev.clone();
+            for (child, contribution) in std::iter::zip(&children, op.symbolic_backward(value, &grad)) {
+                let accumulated = match grads.remove(child) {
+                    Some(previous) => &previous + &contribution,
+                    None => contribution,
+                };
+                grads.insert(child, accumulated);
+            }
+        }
+
+        wrt.iter()
+            .map(|value| grads.get(value).cloned().unwrap_or_else(|| Value::from(0.0)))
+            .collect()
+    }
 
-        _backward(&mut visited, self);
+    // Computes the topological order once so that the graph can be re-evaluated and
+    // back-propagated repeatedly without traversing it again, see `CompiledGraph`.
+    // Leaves frozen at this point, such as `Value::constant`s, keep their data and aren't inputs of the graph.
+    pub fn compile(&self) -> CompiledGraph {
+        let order = self.topo_order();
+        let leaves: Vec<Value> = order
+            .iter()
+            .filter(|value| {
+                let node = value.borrow();
+                node._prev.is_empty() && node.requires_grad
+            })
+            .cloned()
+            .collect();
+        let bound = vec![false; leaves.len()];
+        CompiledGraph { order, leaves, bound }
     }
     
+    #[track_caller]
     pub fn pow(&self, other: &Value) -> Value {
         let result = self.borrow().data.powf(other.borrow().data);
+        self.pow_node(other, result)
+    }
+
+    // `self ^ n` for a constant exponent, held by a frozen leaf so that it is neither collected as a parameter
+    // nor given a gradient.
+    #[track_caller]
+    pub fn powf(&self, n: f64) -> Value {
+        self.pow(&Value::constant(n))
+    }
 
+    // Same as `powf` for an integer exponent, computing the data with `f64::powi`, which is faster (e.g. for
+    // squares in losses) and works for negative bases like any integer power.
+    #[track_caller]
+    pub fn powi(&self, n: i32) -> Value {
+        let result = self.borrow().data.powi(n);
+        self.pow_node(&Value::constant(n as f64), result)
+    }
+
+    // The `Op::Pow` node of `self ^ other`, whose data `result` has already been computed.
+    #[track_caller]
+    fn pow_node(&self, other: &Value, result: f64) -> Value {
         let propagate_fn: PropagateFn = |value| {
-            let mut base = value._prev[0].borrow_mut();
-            let power = value._prev[1].borrow();
-            base.grad += power.data * (base.data.powf(power.data - 1.0)) * value.grad;
+            let power = value._prev[1].data();
+            let base = &value._prev[0];
+            // x^0 is constant: skip the 0 · 0^-1 that would make the gradient NaN at x = 0
+            if power != 0.0 {
+                base.add_grad(power * (base.data().powf(power - 1.0)) * value.grad);
+            }
         };
 
         Value::new(_Value::new(
             result,
             None,
-            Some("^".to_string()),
+            Some(Op::Pow),
             vec![self.clone(), other.clone()],
             Some(propagate_fn),
         ))
     }
 
+    #[track_caller]
     pub fn tanh(&self) -> Value {
         let result = self.borrow().data.tanh();
 
         let propagate_fn: PropagateFn = |value| {
-            let mut _prev = value._prev[0].borrow_mut();
-            _prev.grad += (1.0 - value.data.powf(2.0)) * value.grad;
+            value._prev[0].add_grad((1.0 - value.data.powf(2.0)) * value.grad);
         };
 
         Value::new(_Value::new(
             result,
             None,
-            Some("tanh".to_string()),
+            Some(Op::Tanh),
             vec![self.clone()],
             Some(propagate_fn),
         ))
     }
 
-    pub fn add_label(self, label: &str) -> Value {
-        self.borrow_mut().label = Some(label.to_string());
-        self
-    }
+    #[track_caller]
+    pub fn exp(&self) -> Value {
+        let result = self.borrow().data.exp();
 
-    pub fn data(&self) -> f64 {
-        self.borrow().data
-    }
+        let propagate_fn: PropagateFn = |value| {
+            value._prev[0].add_grad(value.data * value.grad);
+        };
 
-    pub fn grad(&self) -> f64 {
-        self.borrow().grad
+        Value::new(_Value::new(
+            result,
+            None,
+            Some(Op::Exp),
+            vec![self.clone()],
+            Some(propagate_fn),
+        ))
     }
 
-    pub fn zero_grad(&self) {
-        self.borrow_mut().grad = 0.0;
-    }
+    // natural logarithm, NaN for negative inputs like `f64::ln`
+    #[track_caller]
+    pub fn ln(&self) -> Value {
+        let result = self.borrow().data.ln();
 
-    pub fn adjust(&self, factor: f64) {
-        let mut value = self.borrow_mut();
-        value.data += factor * value.grad;
-    }
-}
+        let propagate_fn: PropagateFn = |value| {
+            let x = &value._prev[0];
+            x.add_grad(value.grad / x.data());
+        };
 
-// Create a hashed value for the `Value` instance based on the inner `_Value` instance.
-impl Hash for Value {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.0.borrow().hash(state);
+        Value::new(_Value::new(
+            result,
+            None,
+            Some(Op::Ln),
+            vec![self.clone()],
+            Some(propagate_fn),
+        ))
     }
-}
 
-// Provides dereference access to the Rc<RefCell<_Value>>. A reference to the inner storage of the `Value`.
-impl Deref for Value {
-    type Target = Rc<RefCell<_Value>>;
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-// Converts a type that can be cast into a floating point number directly into a `Value`.
-impl<T: Into<f64>> From<T> for Value {
-    fn from(t: T) -> Value {
-        Value::new(_Value::new(t.into(), None, None, Vec::new(), None))
-    }
-}
+    // the gradient is taken to be 0 at exactly 0
+    #[track_caller]
+    pub fn relu(&self) -> Value {
+        let result = self.borrow().data.max(0.0);
+
+        let propagate_fn: PropagateFn = |value| {
+            if value.data > 0.0 {
+                value._prev[0].add_grad(value.grad);
+            }
+        };
+
+        Value::new(_Value::new(
+            result,
+            None,
+            Some(Op::Relu),
+            vec![self.clone()],
+            Some(propagate_fn),
+        ))
+    }
+
+    // ln(1 + e^x), computed without overflowing for large inputs. Its gradient is the logistic sigmoid.
+    #[track_caller]
+    pub fn softplus(&self) -> Value {
+        let result = softplus(self.borrow().data);
+
+        let propagate_fn: PropagateFn = |value| {
+            let x = &value._prev[0];
+            x.add_grad(sigmoid(x.data()) * value.grad);
+        };
+
+        Value::new(_Value::new(
+            result,
+            None,
+            Some(Op::Softplus),
+            vec![self.clone()],
+            Some(propagate_fn),
+        ))
+    }
+
+    // Rounds to the nearest integer, half away from zero, while the backward pass treats the node as
+    // the identity (straight-through estimator) so that quantized models can still be trained.
+    #[track_caller]
+    pub fn round_ste(&self) -> Value {
+        let result = self.borrow().data.round();
+
+        let propagate_fn: PropagateFn = |value| {
+            value._prev[0].add_grad(value.grad);
+        };
+
+        Value::new(_Value::new(
+            result,
+            None,
+            Some(Op::RoundSte),
+            vec![self.clone()],
+            Some(propagate_fn),
+        ))
+    }
+
+    // 1 above `threshold` and 0 at or below it. The backward pass is the identity within 1 of the threshold
+    // and 0 beyond, like a hard tanh (clipped straight-through estimator).
+    // The threshold is kept as a child, which receives no gradient.
+    #[track_caller]
+    pub fn binarize_ste(&self, threshold: f64) -> Value {
+        let result = binarize(self.borrow().data, threshold);
+
+        let propagate_fn: PropagateFn = |value| {
+            let (x, threshold) = (&value._prev[0], value._prev[1].data());
+            if ste_passes(x.data(), threshold) {
+                x.add_grad(value.grad);
+            }
+        };
+
+        Value::new(_Value::new(
+            result,
+            None,
+            Some(Op::BinarizeSte),
+            vec![self.clone(), Value::constant(threshold)],
+            Some(propagate_fn),
+        ))
+    }
+
+    // An identity node for debugging, e.g. to find where NaNs come from without checking every node: checks that
+    // the data of `self` is finite as the node is built, panicking with `message` and the id and label of `self`
+    // otherwise, and that the gradient reaching `self` through it is finite in backward passes, which otherwise
+    // stop with `BackwardError::AssertionFailed`. The message is kept as the label of the node.
+    // Re-running the graph (`compile`, `rewrite`) doesn't check again; `strip_assertions` removes them.
+    #[track_caller]
+    pub fn assert_finite(&self, message: &str) -> Value {
+        self.assert_in(f64::MIN, f64::MAX, message)
+    }
+
+    // Same as `assert_finite`, also checking that the data lies within `[lo, hi]`. The bounds are kept as
+    // children, which receive no gradient; `assert_finite` uses `f64::MIN` and `f64::MAX`, which unlike
+    // infinities survive a JSON round trip.
+    #[track_caller]
+    pub fn assert_in(&self, lo: f64, hi: f64, message: &str) -> Value {
+        let data = self.data();
+        if !(data.is_finite() && lo <= data && data <= hi) {
+            let node = self.borrow();
+            let name = match &node.label {
+                Some(label) => format!("node {} `{}`", node.id, label),
+                None => format!("node {}", node.id),
+            };
+            let expected = if lo == f64::MIN && hi == f64::MAX {
+                "finite".to_string()
+            } else {
+                format!("finite and within [{}, {}]", lo, hi)
+            };
+            let message = format!("assertion `{}` failed in the forward pass at {}", message, name);
+            panic!("{}: data {} is not {}", message, data, expected);
+        }
+        assertion(self, lo, hi).add_label(message)
+    }
+
+    // A leaf holding a constant that takes no part in training: it is frozen (see `set_requires_grad`),
+    // so its grad stays zero. -1, 0 and 1, which ops like negation and division create all the time, are
+    // interned: the same node is returned on every call (unless turned off with `interning`), so their
+    // data must never be changed.
+    pub fn constant(data: f64) -> Value {
+        let interned = match INTERNING.with(Cell::get) {
+            true => INTERNED.iter().position(|c| c.to_bits() == data.to_bits()),
+            false => None,
+        };
+        let Some(i) = interned else {
+            return frozen(data);
+        };
+        CONSTANTS.with(|constants| constants.borrow_mut()[i].get_or_insert_with(|| frozen(data)).clone())
+    }
+
+    #[track_caller]
+    pub fn sqrt(&self) -> Value {
+        self.powf(0.5)
+    }
+
+    // `self` limited to `[lo, hi]`, like `f64::clamp`, e.g. `x.clamp(0.0, 6.0)` for relu6. The gradient is
+    // passed to `self` within the bounds, included, and not beyond them. The bounds are kept as children, which
+    // receive no gradient, so re-running the graph (`compile`, `rewrite`) clamps the new data. NaN data is
+    // passed through. Panics unless `lo <= hi`.
+    #[track_caller]
+    pub fn clamp(&self, lo: f64, hi: f64) -> Value {
+        assert!(lo <= hi, "clamp needs lo <= hi, got {} and {}", lo, hi);
+        let propagate_fn: PropagateFn = |value| {
+            let (x, lo, hi) = (&value._prev[0], value._prev[1].data(), value._prev[2].data());
+            if clamp_passes(x.data(), lo, hi) {
+                x.add_grad(value.grad);
+            }
+        };
+
+        Value::new(_Value::new(
+            self.data().clamp(lo, hi),
+            None,
+            Some(Op::Clamp),
+            vec![self.clone(), Value::constant(lo), Value::constant(hi)],
+            Some(propagate_fn),
+        ))
+    }
+
+    // Checked counterparts of the operators, for when bad inputs should be reported rather than
+    // propagate NaN through the graph. On success they build exactly the same nodes as the unchecked ones.
+
+    #[track_caller]
+    pub fn try_div(&self, other: &Value) -> Result<Value, GradError> {
+        if other.data() == 0.0 {
+            return Err(GradError::DivisionByZero);
+        }
+        within_node_limit(3, "div")?;
+        finite(self / other, "div")
+    }
+
+    #[track_caller]
+    pub fn try_ln(&self) -> Result<Value, GradError> {
+        let input = self.data();
+        if input <= 0.0 {
+            return Err(GradError::DomainError { op: "ln", input });
+        }
+        within_node_limit(1, "ln")?;
+        finite(self.ln(), "ln")
+    }
+
+    #[track_caller]
+    pub fn try_pow(&self, other: &Value) -> Result<Value, GradError> {
+        let (base, power) = (self.data(), other.data());
+        if base == 0.0 && power < 0.0 {
+            return Err(GradError::DivisionByZero);
+        }
+        if base < 0.0 && power.fract() != 0.0 {
+            return Err(GradError::DomainError { op: "pow", input: base });
+        }
+        within_node_limit(1, "pow")?;
+        finite(self.pow(other), "pow")
+    }
+
+    #[track_caller]
+    pub fn try_sqrt(&self) -> Result<Value, GradError> {
+        let input = self.data();
+        if input < 0.0 {
+            return Err(GradError::DomainError { op: "sqrt", input });
+        }
+        within_node_limit(2, "sqrt")?;
+        finite(self.sqrt(), "sqrt")
+    }
+
+    pub fn add_label(self, label: &str) -> Value {
+        self.borrow_mut().label = Some(label.to_string());
+        self
+    }
+
+    // The nodes this node was computed from, in the order the op takes them. Leaves have none.
+    pub fn children(&self) -> Vec<Value> {
+        self.borrow()._prev.clone()
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.borrow()._prev.is_empty()
+    }
+
+    // The operation that created this node, None for leaves.
+    pub fn op(&self) -> Option<Op> {
+        self.borrow()._op
+    }
+
+    pub fn label(&self) -> Option<String> {
+        self.borrow().label.clone()
+    }
+
+    // The live nodes computed from this one, each once, in the order they were built, when they were built with
+    // `track_consumers` on. A node rewired since (see `prune_unreachable`) is no longer a consumer.
+    pub fn consumers(&self) -> Vec<Value> {
+        let mut seen = HashSet::new();
+        let id = self.id();
+        self.borrow()
+            .consumers
+            .iter()
+            .filter_map(|consumer| consumer.upgrade().map(Value))
+            .filter(|consumer| seen.insert(consumer.id()) && consumer.borrow()._prev.iter().any(|c| c.id() == id))
+            .collect()
+    }
+
+    // Identifier of the node, unique among all nodes created by the process and increasing in creation order.
+    pub fn id(&self) -> NodeId {
+        NodeId(self.borrow().id)
+    }
+
+    // The node labelled `label` among the nodes reachable from `self`, looking from the root towards the leaves.
+    pub fn find_by_label(&self, label: &str) -> Option<Value> {
+        self.topo_order()
+            .into_iter()
+            .rev()
+            .find(|value| value.borrow().label.as_deref() == Some(label))
+    }
+
+    // All the nodes labelled `label` among the nodes reachable from `self`, in the same order as `find_by_label`.
+    pub fn find_all_by_label(&self, label: &str) -> Vec<Value> {
+        self.topo_order()
+            .into_iter()
+            .rev()
+            .filter(|value| value.borrow().label.as_deref() == Some(label))
+            .collect()
+    }
+
+    pub fn data(&self) -> f64 {
+        self.borrow().data
+    }
+
+    // Overwrites the data of the node. Nodes computed from it are not updated until the graph is rebuilt
+    // (or re-run through `compile`).
+    pub fn set_data(&self, data: f64) {
+        self.borrow_mut().data = data_precision().round(data);
+    }
+
+    pub fn grad(&self) -> f64 {
+        self.borrow().grad
+    }
+
+    // Whether the data of `self` and `other` differ by at most `abs`, or by at most `rel` times the larger magnitude.
+    // NaN is never approximately equal to anything, itself included.
+    pub fn approx_eq(&self, other: &Value, rel: f64, abs: f64) -> bool {
+        approx_eq(self.data(), other.data(), rel, abs)
+    }
+
+    // Same as `approx_eq`, comparing the grads.
+    pub fn grad_approx_eq(&self, other: &Value, rel: f64, abs: f64) -> bool {
+        approx_eq(self.grad(), other.grad(), rel, abs)
+    }
+
+    // Comparisons of the data of two nodes, with the semantics of the f64 operators: any comparison involving
+    // NaN is false. `Value` deliberately doesn't implement `PartialOrd`, which would suggest the comparison is
+    // part of the graph; these only read the data and build nothing.
+    pub fn gt(&self, other: &Value) -> bool {
+        self.data() > other.data()
+    }
+
+    pub fn lt(&self, other: &Value) -> bool {
+        self.data() < other.data()
+    }
+
+    pub fn ge(&self, other: &Value) -> bool {
+        self.data() >= other.data()
+    }
+
+    pub fn le(&self, other: &Value) -> bool {
+        self.data() <= other.data()
+    }
+
+    // A total order on the data, per `f64::total_cmp`: -NaN < -inf < ... < -0.0 < 0.0 < ... < inf < NaN.
+    // Suitable for sorting, e.g. `values.sort_by(Value::total_cmp)`, which is stable like any `sort_by`.
+    pub fn total_cmp(&self, other: &Value) -> std::cmp::Ordering {
+        self.data().total_cmp(&other.data())
+    }
+
+    pub fn zero_grad(&self) {
+        self.set_grad(0.0);
+    }
+
+    // Overwrites the grad of the node, e.g. for gradient clipping or surgery between the backward pass and
+    // the update. Every write to a grad, the ones made by backward passes included, goes through `set_grad`
+    // or `add_grad`.
+    pub fn set_grad(&self, grad: f64) {
+        let grad = grad_precision().round(grad);
+        self.borrow_mut().grad = grad;
+        run_grad_hooks(self, grad);
+    }
+
+    // Adds `grad` to the grad of the node, the way a backward pass accumulates the contribution of each
+    // consumer of a node.
+    pub fn add_grad(&self, grad: f64) {
+        let grad = {
+            let mut value = self.borrow_mut();
+            value.grad = grad_precision().round(value.grad + grad);
+            value.grad
+        };
+        run_grad_hooks(self, grad);
+    }
+
+    // Registers `hook` to be called with the node and its new grad after every write to its grad through
+    // `set_grad` or `add_grad`, e.g. to log or check the gradient reaching a node; a backward pass calls it once
+    // per consumer of the node. Hooks are kept, on the current thread, until `clear_hooks` or the node is dropped.
+    pub fn register_hook(&self, hook: impl Fn(&Value, f64) + 'static) {
+        GRAD_HOOKS.with(|hooks| hooks.borrow_mut().entry(self.id()).or_default().push(Rc::new(hook)));
+    }
+
+    pub fn clear_hooks(&self) {
+        GRAD_HOOKS.with(|hooks| hooks.borrow_mut().remove(&self.id()));
+    }
+
+    // Zeroes the grad of every node reachable from `self` and forgets earlier backward passes, so that the next
+    // one starts from a clean graph and isn't reported as stale.
+    pub fn zero_grad_all(&self) {
+        for value in self.topo_order() {
+            value.set_grad(0.0);
+            value.borrow_mut().propagated = false;
+        }
+    }
+
+    // Same as `zero_grad_all`, restricted to the nodes of `within`: the nodes outside of it are neither zeroed nor
+    // walked through, e.g. to reset the graph of one loss among several heads sharing their parameters with
+    // `loss.zero_grad_all_within(&loss.reachable_from())`. Does nothing unless `self` is in `within`.
+    pub fn zero_grad_all_within(&self, within: &NodeSet) {
+        for value in self.topo_order_within(within) {
+            value.set_grad(0.0);
+            value.borrow_mut().propagated = false;
+        }
+    }
+
+    // Every node reachable from `self`, `self` included: the nodes a backward pass from it visits.
+    pub fn reachable_from(&self) -> NodeSet {
+        let mut set = NodeSet::new();
+        for value in self.topo_order() {
+            set.insert(&value);
+        }
+        set
+    }
+
+    // The nodes of `within` reachable from `self` through nodes of `within`, children before their parents.
+    fn topo_order_within(&self, within: &NodeSet) -> Vec<Value> {
+        if !within.contains(self) {
+            return Vec::new();
+        }
+        let mut order = Vec::new();
+        let mut visited = NodeSet::new();
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((value, children_done)) = stack.pop() {
+            if children_done {
+                order.push(value);
+                continue;
+            }
+            if !visited.insert(&value) {
+                continue;
+            }
+            stack.push((value.clone(), true));
+            for child in value.borrow()._prev.iter().rev() {
+                if within.contains(child) && !visited.contains(child) {
+                    stack.push((child.clone(), false));
+                }
+            }
+        }
+        order
+    }
+
+    // Number of handles to this node, counting the parents that hold it as a child.
+    // Propagation functions are plain `fn` pointers that receive the node as an argument,
+    // so they never hold a handle themselves and a graph can't keep itself alive.
+    pub fn strong_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+
+    // A gradient descent step, `data -= lr * grad`: with a positive learning rate, the step that lowers a loss.
+    // Frozen nodes are left unchanged.
+    pub fn descend(&self, lr: f64) {
+        self.ascend(-lr);
+    }
+
+    // A gradient ascent step, `data += lr * grad`, e.g. to maximize a reward. Frozen nodes are left unchanged.
+    pub fn ascend(&self, lr: f64) {
+        let mut value = self.borrow_mut();
+        if value.requires_grad {
+            value.data = data_precision().round(value.data + lr * value.grad);
+        }
+    }
+
+    #[deprecated(note = "`adjust(lr)` moves uphill; use `descend(lr)` to minimize or `ascend(lr)` to maximize")]
+    pub fn adjust(&self, factor: f64) {
+        self.ascend(factor);
+    }
+
+    // Freezes (false) or unfreezes (true) the node: a frozen node keeps a zero grad through `backward`
+    // and `descend` and `ascend` leave its data unchanged, e.g. to train a new head on top of a pre-trained model.
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        self.borrow_mut().requires_grad = requires_grad;
+    }
+
+    pub fn requires_grad(&self) -> bool {
+        self.borrow().requires_grad
+    }
+}
+
+// Create a hashed value for the `Value` instance based on the inner `_Value` instance.
+impl Hash for Value {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.borrow().hash(state);
+    }
+}
+
+// Provides dereference access to the Rc<RefCell<_Value>>. A reference to the inner storage of the `Value`.
+impl Deref for Value {
+    type Target = Rc<RefCell<_Value>>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+// Converts a type that can be cast into a floating point number directly into a `Value`.
+impl<T: Into<f64>> From<T> for Value {
+    fn from(t: T) -> Value {
+        Value::new(_Value::new(t.into(), None, None, Vec::new(), None))
+    }
+}
 
 // Allows to add two Value instances without consuming them. 
 // Instead, it takes references to self and other, allowing to reuse the original Value instances after the addition.
-impl<'a, 'b> Add<&'b Value> for &'a Value {
+impl Add<&Value> for &Value {
     type Output = Value;
-    fn add(self, other: &'b Value) -> Self::Output {
+    #[track_caller]
+    fn add(self, other: &Value) -> Self::Output {
         add(self, other)
     }
 }
 
+// Same as `&a + &b`, for operands that are no longer needed, e.g. folding a sum with `reduce`.
+impl Add<Value> for Value {
+    type Output = Value;
+    #[track_caller]
+    fn add(self, other: Value) -> Self::Output {
+        add(&self, &other)
+    }
+}
+
+#[track_caller]
 fn add(a: &Value, b: &Value) -> Value {
     let result = a.borrow().data + b.borrow().data;
 
+    // the children are borrowed one at a time, as both may be the same node (e.g. `x + x`)
     let propagate_fn: PropagateFn = |value| {
-        let mut first = value._prev[0].borrow_mut();
-        let mut second = value._prev[1].borrow_mut();
-
-        first.grad += value.grad;
-        second.grad += value.grad;
+        for child in &value._prev {
+            child.add_grad(value.grad);
+        }
     };
 
     Value::new(_Value::new(
         result,
         None,
-        Some("+".to_string()),
+        Some(Op::Add),
         vec![a.clone(), b.clone()],
         Some(propagate_fn),
     ))
 }
 
 
-impl<'a, 'b> Mul<&'b Value> for &'a Value {
+impl Mul<&Value> for &Value {
     type Output = Value;
 
-    fn mul(self, other: &'b Value) -> Self::Output {
+    #[track_caller]
+    fn mul(self, other: &Value) -> Self::Output {
         mul(self, other)
     }
 }
 
 // the gradient of the result is multiplied by the other operand's value before being propagated back.
+#[track_caller]
 fn mul(a: &Value, b: &Value) -> Value {
     let result = a.borrow().data * b.borrow().data;
 
     let propagate_fn: PropagateFn = |value| {
-        let mut first = value._prev[0].borrow_mut();
-        let mut second = value._prev[1].borrow_mut();
-
-        first.grad += second.data * value.grad;
-        second.grad += first.data * value.grad;
+        // a product rebuilt on more or fewer children (see `with_children`) takes the n-ary path
+        let [first, second] = &value._prev[..] else {
+            return ops::propagate_product(value);
+        };
+        let (a, b) = (first.borrow().data, second.borrow().data);
+        first.add_grad(b * value.grad);
+        second.add_grad(a * value.grad);
     };
 
     Value::new(_Value::new(
         result,
         None,
-        Some("*".to_string()),
+        Some(Op::Mul),
         vec![a.clone(), b.clone()],
         Some(propagate_fn),
     ))
 }
 
-impl<'a> Neg for &'a Value {
+impl Neg for &Value {
     type Output = Value;
+    #[track_caller]
     fn neg(self) -> Self::Output {
-        mul(self, &Value::from(-1))
+        mul(self, &Value::constant(-1.0))
+    }
+}
+
+// Subtraction is the addition of the negated right-hand side.
+impl Sub<&Value> for &Value {
+    type Output = Value;
+    #[track_caller]
+    fn sub(self, other: &Value) -> Self::Output {
+        add(self, &-other)
+    }
+}
+
+// Division is the multiplication by the right-hand side raised to the power -1.
+impl Div<&Value> for &Value {
+    type Output = Value;
+    #[track_caller]
+    fn div(self, other: &Value) -> Self::Output {
+        mul(self, &other.pow(&Value::constant(-1.0)))
     }
 }
 
 // Sums all elements in an iterator over `Value` and returns a single `Value` representing the sum.
+// The elements become the children of a single n-ary node rather than a chain of binary additions.
 impl Sum for Value {
-   
-    fn sum<I: Iterator<Item = Self>>(mut iter: I) -> Self {
-        let mut sum = Value::from(0.0);
-        loop {
-            let val = iter.next();
-            if val.is_none() {
-                break;
-            }
+    #[track_caller]
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        let values: Vec<Value> = iter.collect();
+        if values.is_empty() {
+            return Value::from(0.0);
+        }
+        ops::add_n(&values)
+    }
+}
+
+// Multiplies all elements in an iterator over `Value` into a single n-ary product node.
+impl Product for Value {
+    #[track_caller]
+    fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
+        let values: Vec<Value> = iter.collect();
+        if values.is_empty() {
+            return Value::from(1.0);
+        }
+        ops::mul_n(&values)
+    }
+}
+
+// A running sum that can be added to term by term, such as a loss summed over a loop, without building the
+// left-deep chain `total = &total + &term` would: the terms are collected and `finish` adds them up in a single
+// `ops::add_n` node, so the sum adds depth 1 to the graph however many terms it has.
+#[derive(Clone, Debug, Default)]
+pub struct Accumulator {
+    terms: Vec<Value>,
+}
+
+impl Accumulator {
+    pub fn new() -> Accumulator {
+        Accumulator::default()
+    }
+
+    pub fn add(&mut self, term: &Value) {
+        self.terms.push(term.clone());
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
 
-            sum = sum + val.unwrap();
+    // The sum of the terms added so far, or a constant zero if there are none.
+    pub fn finish(self) -> Value {
+        if self.terms.is_empty() {
+            return Value::constant(0.0);
         }
-        sum
+        ops::add_n(&self.terms)
+    }
+}
+
+impl AddAssign<&Value> for Accumulator {
+    fn add_assign(&mut self, term: &Value) {
+        self.add(term);
+    }
+}
+
+// One new leaf per element of `xs`, e.g. for a row of a dataset.
+pub fn values_from(xs: &[f64]) -> Vec<Value> {
+    xs.iter().map(|&x| Value::from(x)).collect()
+}
+
+// Like `values_from`, labelling the leaves `{prefix}0`, `{prefix}1`, ... (e.g. `x0, x1, ...`).
+pub fn values_from_labeled(xs: &[f64], prefix: &str) -> Vec<Value> {
+    xs.iter()
+        .enumerate()
+        .map(|(i, &x)| Value::from(x).add_label(&format!("{}{}", prefix, i)))
+        .collect()
+}
+
+// One frozen leaf per element of `xs`, for data that should never receive a gradient such as targets.
+// Unlike `Value::constant`, every leaf is a new node, even for interned values.
+pub fn constants_from(xs: &[f64]) -> Vec<Value> {
+    xs.iter().map(|&x| frozen(x)).collect()
+}
+
+// The `Op::Assert` node checking `x` against `[lo, hi]`, without checking the data of `x` now.
+#[track_caller]
+fn assertion(x: &Value, lo: f64, hi: f64) -> Value {
+    let propagate_fn: PropagateFn = |value| {
+        value._prev[0].add_grad(value.grad);
+    };
+
+    Value::new(_Value::new(
+        x.data(),
+        None,
+        Some(Op::Assert),
+        vec![x.clone(), Value::constant(lo), Value::constant(hi)],
+        Some(propagate_fn),
+    ))
+}
+
+fn frozen(data: f64) -> Value {
+    let value = Value::from(data);
+    value.set_requires_grad(false);
+    value
+}
+
+pub(crate) fn softplus(x: f64) -> f64 {
+    x.max(0.0) + (-x.abs()).exp().ln_1p()
+}
+
+// logistic function, without overflowing for inputs of large magnitude
+pub(crate) fn binarize(x: f64, threshold: f64) -> f64 {
+    if x > threshold { 1.0 } else { 0.0 }
+}
+
+// whether the clipped straight-through estimator of `binarize_ste` lets the gradient through
+pub(crate) fn ste_passes(x: f64, threshold: f64) -> bool {
+    (x - threshold).abs() <= 1.0
+}
+
+// whether `clamp` lets the gradient through to `x`: within the bounds, included
+pub(crate) fn clamp_passes(x: f64, lo: f64, hi: f64) -> bool {
+    lo <= x && x <= hi
+}
+
+pub(crate) fn sigmoid(x: f64) -> f64 {
+    if x >= 0.0 {
+        1.0 / (1.0 + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (1.0 + e)
+    }
+}
+
+fn finite(value: Value, op: &'static str) -> Result<Value, GradError> {
+    if value.data().is_finite() {
+        Ok(value)
+    } else {
+        Err(GradError::NonFinite { op })
+    }
+}
+
+pub(crate) fn approx_eq(a: f64, b: f64, rel: f64, abs: f64) -> bool {
+    a == b || (a - b).abs() <= abs.max(rel * a.abs().max(b.abs()))
+}
+
+// Runs the propagation function of every node of a topological order, parents before children.
+// Before each one runs, its node is checked to have as many children as its op takes and children that can be
+// borrowed, which is everything a propagation function relies on.
+// Nodes with a zero grad are skipped, see `skip_zero_gradients`.
+pub(crate) fn propagate_all(order: &[Value]) -> Result<(), BackwardError> {
+    let skip_zeros = SKIP_ZERO_GRADIENTS.with(Cell::get);
+    for value in order.iter().rev() {
+        value.borrow_mut().propagated = true;
+        let borrowed_value = value.borrow();
+        if skip_zeros && skips_propagation(&borrowed_value) {
+            continue;
+        }
+        if let Some(propagate_fn) = borrowed_value.propagate {
+            check_propagation(&borrowed_value)?;
+            let start = profile::start();
+            match borrowed_value._op {
+                // the backward pass through a checkpointed segment is one of its own, whose errors stop this one
+                Some(Op::Checkpoint(segment)) => segment.propagate(&borrowed_value)?,
+                _ => propagate_fn(&borrowed_value),
+            }
+            if let Some(start) = start {
+                profile::record_propagate(borrowed_value._op, start.elapsed());
+            }
+        }
+    }
+    // frozen nodes still pass gradients on to their children, but keep none themselves
+    for value in order {
+        if !value.requires_grad() {
+            value.set_grad(0.0);
+        }
+    }
+    Ok(())
+}
+
+// Whether propagating `node` can be skipped: it only writes zeros, and there is no hook to see them.
+fn skips_propagation(node: &_Value) -> bool {
+    node.grad == 0.0 && !has_hooks(&node._prev)
+}
+
+fn check_propagation(node: &_Value) -> Result<(), BackwardError> {
+    let Some(op) = node._op else {
+        return Ok(());
+    };
+    let children = node._prev.len();
+    if op.arity().is_some_and(|arity| arity != children) {
+        return Err(BackwardError::Malformed { id: node.id, label: node.label.clone(), op, children });
+    }
+    if node._prev.iter().any(|child| child.try_borrow_mut().is_err()) {
+        return Err(borrowed(node));
+    }
+    if op == Op::Assert && !node.grad.is_finite() {
+        let checked = node._prev[0].borrow();
+        return Err(BackwardError::AssertionFailed {
+            id: checked.id,
+            label: checked.label.clone(),
+            message: node.label.clone().unwrap_or_default(),
+            grad: node.grad,
+        });
+    }
+    Ok(())
+}
+
+fn borrowed(node: &_Value) -> BackwardError {
+    let op = node._op.expect("only nodes built by an op have children");
+    BackwardError::Borrowed { id: node.id, label: node.label.clone(), op }
+}
+
+// Rebuilds the graph rooted at `root` without the nodes made by `assert_finite` and `assert_in`, the nodes
+// using them taking their checked node directly. The leaves are shared with the original graph, every other
+// node is rebuilt with a zero grad, so the gradients of a backward pass through the copy are the same.
+pub fn strip_assertions(root: &Value) -> Value {
+    rewrite(root, &StripAssertions)
+}
+
+// Common subexpression elimination: rebuilds the graph rooted at `root` so that structurally
+// identical nodes (same op applied to the same children) are stored only once.
+// Leaves are compared by identity, so two parameters that merely hold the same data stay distinct, except for
+// unlabeled constants (frozen leaves, see `Value::constant`), which are merged when their data is the same.
+// The leaves are shared with the original graph, every other node is rebuilt with a zero grad.
+pub fn cse(root: &Value) -> Value {
+    rewrite(root, &Cse::default())
+}
+
+// Cuts the graphs of `roots` down to what `keep` needs, e.g. the candidate heads that won't be back-propagated
+// once the loss to train on has been chosen: every node reachable from `roots` but not from `keep` is turned
+// into a leaf holding its data, dropping its children, so that nodes only the discarded parts used are freed as
+// soon as nothing else holds them, and graph-wide utilities no longer walk through them. The nodes reachable from
+// `keep`, and so its gradients, are left untouched. Returns the number of nodes turned into leaves.
+pub fn prune_unreachable(roots: &[Value], keep: &[Value]) -> usize {
+    let mut kept = NodeSet::new();
+    for root in keep {
+        kept.extend(&root.reachable_from());
+    }
+
+    let mut visited = NodeSet::new();
+    let mut pruned = Vec::new();
+    for root in roots {
+        for value in root.topo_order() {
+            if visited.insert(&value) && !kept.contains(&value) && !value.is_leaf() {
+                pruned.push(value);
+            }
+        }
+    }
+
+    for value in &pruned {
+        let mut node = value.borrow_mut();
+        let op = node._op.take();
+        node.propagate = None;
+        let children = std::mem::take(&mut node._prev);
+        drop(node);
+        // the node no longer belongs to its segment, and children are dropped outside of the borrow
+        if let Some(Op::Checkpoint(segment)) = op {
+            segment.release();
+        }
+        drop(children);
+    }
+    pruned.len()
+}
+
+// The Hessian of `loss` with respect to `params`, multiplied by `vector`, without materializing the Hessian:
+// the gradient graph is contracted with `vector` and differentiated once more.
+// Neither the data nor the grads of the graph are modified.
+pub fn hvp(loss: &Value, params: &[Value], vector: &[f64]) -> Vec<f64> {
+    assert_eq!(params.len(), vector.len(), "hvp expects one vector entry per parameter");
+
+    let grads = loss.backward_graph(params);
+    let terms: Vec<Value> = std::iter::zip(&grads, vector)
+        .map(|(grad, &v)| grad * &Value::constant(v))
+        .collect();
+    let directional = ops::add_n(&terms);
+
+    directional
+        .backward_graph(params)
+        .iter()
+        .map(|value| value.data())
+        .collect()
+}
+
+// The Jacobian of `outputs` with respect to `inputs`, one row per output and one column per input: each row is
+// the grads of `inputs` after a backward pass from its output, and every grad of the graphs is zeroed before
+// each pass, so that rows sharing subgraphs don't leak into each other. An input that an output doesn't depend
+// on gets a zero; so does a frozen one. The grads of the graphs are left zeroed.
+pub fn jacobian(outputs: &[Value], inputs: &[Value]) -> Vec<Vec<f64>> {
+    let zero_all = || {
+        for value in outputs.iter().chain(inputs) {
+            value.zero_grad_all();
+        }
+    };
+    let rows = outputs
+        .iter()
+        .map(|output| {
+            zero_all();
+            output.backward_unchecked();
+            inputs.iter().map(Value::grad).collect()
+        })
+        .collect();
+    zero_all();
+    rows
+}
+
+// Checks `jacobian(outputs, inputs)` against the central differences `(f(x + eps) - f(x - eps)) / 2·eps`,
+// within a relative and absolute tolerance of `tol`, e.g. to test the backward function of a custom op.
+// The differences are taken on a copy of the graphs in which each input is a new leaf, so neither the inputs
+// nor the nodes they share with other graphs are written to. Panics unless every input is a leaf that takes a
+// gradient: a constant (see `Value::constant`) has none to check.
+pub fn jacobian_check(outputs: &[Value], inputs: &[Value], eps: f64, tol: f64) -> Result<(), JacobianCheckFailure> {
+    for input in inputs {
+        let id = input.id().0;
+        assert!(input.is_leaf(), "jacobian_check inputs must be leaves, node {} isn't one", id);
+        assert!(input.requires_grad(), "jacobian_check input {} is a constant, which takes no gradient", id);
+    }
+    let analytic = jacobian(outputs, inputs);
+
+    let mut copies: NodeMap<Value> = NodeMap::new();
+    for input in inputs {
+        copies.insert(input, Value::from(input.data()));
+    }
+    let mut order = Vec::new();
+    for output in outputs {
+        for value in output.topo_order() {
+            if copies.get(&value).is_some() {
+                continue;
+            }
+            // other leaves are only read
+            let copy = match value.is_leaf() {
+                true => value.clone(),
+                false => {
+                    let children = value.children().iter().map(|child| copies[child].clone()).collect();
+                    value.with_children(children)
+                }
+            };
+            copies.insert(&value, copy.clone());
+            order.push(copy);
+        }
+    }
+    let copied: Vec<Value> = outputs.iter().map(|output| copies[output].clone()).collect();
+    let evaluate = |input: &Value, data: f64| -> Vec<f64> {
+        input.set_data(data);
+        recompute(&order);
+        copied.iter().map(Value::data).collect()
+    };
+
+    for (column, input) in inputs.iter().enumerate() {
+        let (copy, data) = (&copies[input], input.data());
+        let above = evaluate(copy, data + eps);
+        let below = evaluate(copy, data - eps);
+        copy.set_data(data);
+
+        for (row, (above, below)) in std::iter::zip(above, below).enumerate() {
+            let numeric = (above - below) / (2.0 * eps);
+            let analytic = analytic[row][column];
+            if !approx_eq(analytic, numeric, tol, tol) {
+                let label = input.label();
+                return Err(JacobianCheckFailure { output: row, input: column, label, analytic, numeric });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Recomputes the data of every node of `order` built by an op from the data of its children, which must come
+// before it in the order.
+fn recompute(order: &[Value]) {
+    let precision = data_precision();
+    for value in order {
+        let mut node = value.borrow_mut();
+        if let Some(op) = node._op {
+            let inputs: Vec<f64> = node._prev.iter().map(|child| child.borrow().data).collect();
+            node.data = precision.round(op.forward(&inputs));
+        }
+    }
+}
+
+// A graph whose topological order has been computed once and is stored as a flat Vec,
+// so that each training step only pays for the arithmetic of `forward` and `backward`.
+// The structure of the graph is fixed at `Value::compile` time: nodes built afterwards on top of it are not part of it.
+pub struct CompiledGraph {
+    order: Vec<Value>,
+    leaves: Vec<Value>,
+    // for each leaf, whether its data has been set since the graph was compiled, see `bind`
+    bound: Vec<bool>,
+}
+
+impl CompiledGraph {
+    // The leaves of the graph, in the order in which `forward` expects their data. Frozen leaves are left out:
+    // constants, some of them shared by every graph (see `Value::constant`), must not be overwritten.
+    pub fn leaves(&self) -> &[Value] {
+        &self.leaves
+    }
+
+    // The node the graph was compiled from.
+    pub fn root(&self) -> &Value {
+        self.order.last().expect("a compiled graph contains at least its root")
+    }
+
+    // Sets the data of every leaf, in the order of `leaves()`, recomputes every other node from its children
+    // and returns the new data of the root.
+    pub fn forward(&mut self, inputs: &[f64]) -> f64 {
+        assert_eq!(
+            inputs.len(),
+            self.leaves.len(),
+            "forward expects one input per leaf of the compiled graph"
+        );
+        for (leaf, &input) in std::iter::zip(&self.leaves, inputs) {
+            leaf.set_data(input);
+        }
+        self.bound.fill(true);
+        self.recompute()
+    }
+
+    // Sets the data of every leaf labeled `name`, e.g. an input `x`, for the following calls to `forward_bound`.
+    // Bindings are kept until they are replaced, so inputs that don't change between calls only need to be bound
+    // once.
+    pub fn bind(&mut self, name: &str, value: f64) -> Result<(), BindError> {
+        let mut found = false;
+        for (leaf, bound) in std::iter::zip(&self.leaves, &mut self.bound) {
+            if leaf.borrow().label.as_deref() == Some(name) {
+                leaf.set_data(value);
+                *bound = true;
+                found = true;
+            }
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(BindError::UnknownLeaf { name: name.to_string() })
+        }
+    }
+
+    // `bind` for every name of `values`. Nothing is bound if one of the names isn't the label of a leaf.
+    pub fn bind_all(&mut self, values: &HashMap<String, f64>) -> Result<(), BindError> {
+        let mut names: Vec<&String> = values.keys().collect();
+        names.sort();
+        let labels: HashSet<String> = self.leaves.iter().filter_map(Value::label).collect();
+        if let Some(name) = names.iter().find(|name| !labels.contains(name.as_str())) {
+            return Err(BindError::UnknownLeaf { name: name.to_string() });
+        }
+        for name in names {
+            self.bind(name, values[name])?;
+        }
+        Ok(())
+    }
+
+    // Recomputes every interior node from the data of the leaves, as bound by `bind`, and returns the new data of
+    // the root. Every labeled leaf must have been bound, or set by `forward`, since the graph was compiled;
+    // unlabeled leaves and constants keep their data.
+    pub fn forward_bound(&mut self) -> Result<f64, BindError> {
+        let mut names: Vec<String> = Vec::new();
+        for (leaf, &bound) in std::iter::zip(&self.leaves, &self.bound) {
+            let node = leaf.borrow();
+            match &node.label {
+                Some(label) if !bound && !names.contains(label) => names.push(label.clone()),
+                _ => {}
+            }
+        }
+        if !names.is_empty() {
+            return Err(BindError::Unbound { names });
+        }
+        Ok(self.recompute())
+    }
+
+    fn recompute(&mut self) -> f64 {
+        recompute(&self.order);
+        self.root().data()
+    }
+
+    // Back-propagates from the root like `Value::backward`, reusing the stored order.
+    // The grads of the interior nodes are reset first so that the previous pass doesn't leak into this one;
+    // leaves keep accumulating as usual and have to be zeroed by the caller, or are reported according to
+    // `set_accumulation_policy`. Errors are the same as for `Value::backward`.
+    pub fn backward(&mut self) -> Result<(), BackwardError> {
+        check_stale(&self.order)?;
+        for value in &self.order {
+            if !value.is_leaf() {
+                value.set_grad(0.0);
+            }
+        }
+        self.root().set_grad(1.0);
+        propagate_all(&self.order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assert_grad_eq, assert_value_eq};
+
+    fn node_count(root: &Value) -> usize {
+        root.topo_order().len()
+    }
+
+    #[test]
+    fn cse_merges_duplicated_subtrees() {
+        let (w, x) = (Value::from(0.5), Value::from(-2.0));
+        // `w·x` built twice, and the `tanh` and sums on top of each copy
+        let head = |w: &Value, x: &Value| (w * x).tanh();
+        let root = &head(&w, &x) + &(&head(&w, &x) * &Value::from(3.0));
+
+        let merged = cse(&root);
+        assert_eq!(node_count(&root), 9);
+        assert_eq!(node_count(&merged), 7);
+        assert_eq!(merged.data(), root.data());
+    }
+
+    #[test]
+    fn cse_keeps_gradients_of_shared_leaves() {
+        let (w, x) = (Value::from(0.5), Value::from(-2.0));
+        let root = &(&w * &x).exp() + &(&(&w * &x).exp() * &w);
+
+        root.backward().unwrap();
+        let expected = (w.grad(), x.grad());
+        w.zero_grad();
+        x.zero_grad();
+
+        cse(&root).backward().unwrap();
+        assert_grad_eq!(w, expected.0, 1e-12);
+        assert_grad_eq!(x, expected.1, 1e-12);
+    }
+
+    #[test]
+    fn cse_merges_equal_constants_but_not_parameters() {
+        let x = Value::from(0.3);
+        let root = &(&x * &Value::constant(0.5)) + &(&x * &Value::constant(0.5));
+        assert_eq!(node_count(&cse(&root)), 4);
+
+        let (a, b) = (Value::from(2.0), Value::from(2.0));
+        let root = &(&x * &a) + &(&x * &b);
+        assert_eq!(node_count(&cse(&root)), node_count(&root));
+    }
+
+    // `tanh(x·y + x) · y`, the graph used by the `CompiledGraph` tests
+    fn compiled_example(x: &Value, y: &Value) -> Value {
+        &(&(x * y) + x).tanh() * y
+    }
+
+    #[test]
+    fn compiled_graph_matches_rebuilt_graph() {
+        let (x, y) = (Value::from(0.5), Value::from(-1.5));
+        let mut compiled = compiled_example(&x, &y).compile();
+        assert_eq!(compiled.leaves(), &[x.clone(), y.clone()]);
+
+        for (a, b) in [(0.25, 2.0), (-1.0, 0.5), (3.0, -0.75)] {
+            let data = compiled.forward(&[a, b]);
+            x.zero_grad();
+            y.zero_grad();
+            compiled.backward().unwrap();
+            let compiled_grads = (x.grad(), y.grad());
+
+            let (fresh_x, fresh_y) = (Value::from(a), Value::from(b));
+            let fresh = compiled_example(&fresh_x, &fresh_y);
+            fresh.backward().unwrap();
+            assert_eq!(data, fresh.data());
+            assert_eq!(compiled_grads, (fresh_x.grad(), fresh_y.grad()));
+        }
+    }
+
+    #[test]
+    fn compiled_backward_resets_interior_grads() {
+        let (x, y) = (Value::from(0.5), Value::from(-1.5));
+        let root = compiled_example(&x, &y);
+        let mut compiled = root.compile();
+        compiled.backward().unwrap();
+        let first = x.grad();
+        x.zero_grad();
+        y.zero_grad();
+        compiled.backward().unwrap();
+        assert_eq!(x.grad(), first);
+    }
+
+    #[test]
+    fn compiled_graphs_leave_constants_alone() {
+        let (x, y) = (Value::from(0.5), Value::from(-1.5));
+        let frozen = Value::from(2.0);
+        frozen.set_requires_grad(false);
+        // `-x` and `x - y` share the interned -1, `powf` adds a frozen exponent
+        let root = &(&(&-&x + &(&x - &y)) * &frozen) + &y.powf(3.0);
+        let mut compiled = root.compile();
+        assert_eq!(compiled.leaves(), &[x.clone(), y.clone()]);
+
+        assert_eq!(compiled.forward(&[4.0, 1.0]), (-4.0 + 3.0) * 2.0 + 1.0);
+        assert_eq!(Value::constant(-1.0).data(), -1.0);
+        assert_eq!((frozen.data(), y.powf(3.0).data()), (2.0, 1.0));
+        assert!(!compiled.leaves().iter().any(|leaf| Rc::ptr_eq(leaf, &Value::constant(-1.0))));
+    }
+
+    #[test]
+    fn interned_constants_are_shared_and_leave_gradients_unchanged() {
+        let (a, b) = (Value::from(1.5), Value::from(-0.5));
+        let (na, nb) = (-&a, -&b);
+        assert!(Rc::ptr_eq(&na.children()[1], &nb.children()[1]));
+
+        let root = |a: &Value, b: &Value| &(a / b) - &(&a.tanh() * &-b);
+        root(&a, &b).backward().unwrap();
+        let expected = (a.grad(), b.grad());
+
+        interning(false);
+        let (a, b) = (Value::from(1.5), Value::from(-0.5));
+        let (na, nb) = (-&a, -&b);
+        assert!(!Rc::ptr_eq(&na.children()[1], &nb.children()[1]));
+        root(&a, &b).backward().unwrap();
+        interning(true);
+        assert_eq!((a.grad(), b.grad()), expected);
+        assert_eq!(Value::constant(-1.0).grad(), 0.0);
+    }
+
+    // `x·y` back-propagated twice under `policy`, with the grads of `x` after each pass
+    fn twice(policy: AccumulationPolicy, zero: impl Fn(&Value, &Value)) -> (f64, Result<f64, BackwardError>) {
+        set_accumulation_policy(policy);
+        let (x, y) = (Value::from(2.0), Value::from(3.0));
+        let root = &x * &y;
+        root.backward().unwrap();
+        let first = x.grad();
+        zero(&root, &x);
+        let second = root.backward().map(|()| x.grad());
+        set_accumulation_policy(AccumulationPolicy::Accumulate);
+        (first, second)
+    }
+
+    #[test]
+    fn accumulation_policies_handle_stale_gradients() {
+        let keep = |_: &Value, _: &Value| {};
+        assert_eq!(twice(AccumulationPolicy::Accumulate, keep), (3.0, Ok(6.0)));
+        assert_eq!(twice(AccumulationPolicy::Warn, keep), (3.0, Ok(6.0)));
+        assert_eq!(twice(AccumulationPolicy::Error, keep), (3.0, Err(BackwardError::StaleGradients { leaves: 2 })));
+
+        // nothing is modified by a refused pass
+        set_accumulation_policy(AccumulationPolicy::Error);
+        let x = Value::from(2.0);
+        let root = x.exp();
+        root.backward().unwrap();
+        assert!(root.backward().is_err());
+        assert_eq!(x.grad(), 2f64.exp());
+        assert!(std::panic::catch_unwind(|| {
+            let x = Value::from(1.0);
+            let root = x.tanh();
+            root.backward_unchecked();
+            root.backward_unchecked();
+        })
+        .is_err());
+        set_accumulation_policy(AccumulationPolicy::Accumulate);
+    }
+
+    #[test]
+    fn zeroing_resets_stale_detection() {
+        let zero_all = |root: &Value, _: &Value| root.zero_grad_all();
+        assert_eq!(twice(AccumulationPolicy::Error, zero_all), (3.0, Ok(3.0)));
+        // zeroing one leaf only leaves the other one stale
+        let zero_x = |_: &Value, x: &Value| x.zero_grad();
+        let stale = Err(BackwardError::StaleGradients { leaves: 1 });
+        assert_eq!(twice(AccumulationPolicy::Error, zero_x), (3.0, stale));
+    }
+
+    #[test]
+    fn compiled_graphs_follow_the_accumulation_policy() {
+        set_accumulation_policy(AccumulationPolicy::Error);
+        let (x, y) = (Value::from(0.5), Value::from(-1.5));
+        let mut compiled = compiled_example(&x, &y).compile();
+        compiled.backward().unwrap();
+        assert_eq!(compiled.backward(), Err(BackwardError::StaleGradients { leaves: 2 }));
+        x.zero_grad();
+        y.zero_grad();
+        assert_eq!(compiled.backward(), Ok(()));
+        set_accumulation_policy(AccumulationPolicy::Accumulate);
+    }
+
+    // Plain gradient descent written only against the public API, as it would be outside the crate
+    struct ToySgd(f64);
+
+    impl ToySgd {
+        fn step(&self, params: &[Value]) {
+            for param in params {
+                param.set_data(param.data() - self.0 * param.grad());
+                param.set_grad(0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn an_external_optimizer_reproduces_sgd() {
+        use crate::optim::{Optimizer, Sgd};
+
+        let loss = |w: &Value, b: &Value| {
+            let error = &(&(w * &Value::constant(2.0)) + b) - &Value::constant(1.0);
+            error.tanh().powi(2)
+        };
+        let (ours, theirs) = ([Value::from(0.3), Value::from(-0.2)], [Value::from(0.3), Value::from(-0.2)]);
+        let mut sgd = Sgd::new(0.1);
+        for _ in 0..20 {
+            loss(&ours[0], &ours[1]).backward().unwrap();
+            sgd.step(&ours);
+            ours.iter().for_each(Value::zero_grad);
+            loss(&theirs[0], &theirs[1]).backward().unwrap();
+            ToySgd(0.1).step(&theirs);
+        }
+        let bits = |params: &[Value; 2]| params.each_ref().map(|param| param.data().to_bits());
+        assert_eq!(bits(&ours), bits(&theirs));
+    }
+
+    #[test]
+    fn hooks_observe_every_grad_write() {
+        let x = Value::from(3.0);
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let seen = writes.clone();
+        x.register_hook(move |node, grad| seen.borrow_mut().push((node.data(), grad)));
+
+        x.add_grad(0.5);
+        // `x` has two consumers, each adding its part
+        (&(&x * &Value::constant(2.0)) + &x).backward().unwrap();
+        x.set_grad(-1.0);
+        assert_eq!(*writes.borrow(), [(3.0, 0.5), (3.0, 1.5), (3.0, 3.5), (3.0, -1.0)]);
+
+        x.clear_hooks();
+        x.add_grad(1.0);
+        assert_eq!(writes.borrow().len(), 4);
+        assert!(GRAD_HOOKS.with(|hooks| !hooks.borrow().contains_key(&x.id())));
+    }
+
+    #[test]
+    fn hooks_go_with_their_node() {
+        let x = Value::from(1.0);
+        x.register_hook(|_, _| {});
+        let id = x.id();
+        assert!(GRAD_HOOKS.with(|hooks| hooks.borrow().contains_key(&id)));
+        drop(x);
+        assert!(GRAD_HOOKS.with(|hooks| !hooks.borrow().contains_key(&id)));
+    }
+
+    #[test]
+    fn backward_of_aliased_operands_is_ok() {
+        let x = Value::from(3.0);
+        assert_eq!((&x + &x).backward(), Ok(()));
+        assert_eq!(x.grad(), 2.0);
+        x.zero_grad();
+        (&x * &x).backward().unwrap();
+        assert_eq!(x.grad(), 6.0);
+    }
+
+    #[test]
+    fn backward_reports_a_borrowed_child() {
+        let (x, y) = (Value::from(3.0), Value::from(2.0));
+        let product = (&x * &y).add_label("product");
+        let guard = x.borrow();
+        let error = product.backward().unwrap_err();
+        drop(guard);
+        let id = product.id().0;
+        assert_eq!(error, BackwardError::Borrowed { id, label: Some("product".to_string()), op: Op::Mul });
+        assert_eq!(
+            error.to_string(),
+            format!("backward through node {} `product` (*): a child is already borrowed", id)
+        );
+        // nothing was propagated, and the pass succeeds once the borrow is released
+        assert_eq!(x.grad(), 0.0);
+        assert_eq!(product.backward(), Ok(()));
+        assert_eq!(x.grad(), 2.0);
+    }
+
+    #[test]
+    fn backward_reports_a_malformed_node() {
+        let (x, y) = (Value::from(3.0), Value::from(2.0));
+        let broken = x.tanh().with_children(vec![x.clone(), y]).add_label("h");
+        let error = broken.backward().unwrap_err();
+        let id = broken.id().0;
+        assert_eq!(error, BackwardError::Malformed { id, label: Some("h".to_string()), op: Op::Tanh, children: 2 });
+        let message = format!("backward through node {} `h` (tanh): the op doesn't take 2 children", id);
+        assert_eq!(error.to_string(), message);
+
+        // sums and products take any number of children
+        let product = (&x * &Value::from(2.0)).with_children(vec![x.clone()]);
+        assert_eq!(product.backward(), Ok(()));
+        assert_eq!(x.grad(), 1.0);
+    }
+
+    #[test]
+    fn constant_powers_match_pow() {
+        for (base, n) in [(1.5, 2), (0.3, 3), (2.0, -1), (4.0, 0)] {
+            let (x, e) = (Value::from(base), Value::from(n as f64));
+            let pow = x.pow(&e);
+            pow.backward().unwrap();
+            let expected = (pow.data(), x.grad());
+            for power in [x.powf(n as f64), x.powi(n)] {
+                x.zero_grad();
+                power.backward().unwrap();
+                assert_value_eq!(power, expected.0, 1e-12);
+                assert_grad_eq!(x, expected.1, 1e-12);
+                // the exponent is a frozen leaf: neither an input of a compiled graph nor given a gradient
+                assert!(!power.children()[1].requires_grad() && power.children()[1].grad() == 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn integer_powers_of_negative_bases() {
+        let x = Value::from(-2.0);
+        let cube = x.powi(3);
+        cube.backward().unwrap();
+        assert_eq!((cube.data(), x.grad()), (-8.0, 12.0));
+        x.zero_grad();
+        let inverse = x.powf(-2.0);
+        inverse.backward().unwrap();
+        assert_eq!((inverse.data(), x.grad()), (0.25, 0.25));
+        // unlike a fractional one
+        assert!(x.powf(0.5).data().is_nan());
+        assert!(x.try_pow(&Value::constant(0.5)).is_err());
+    }
+
+    #[test]
+    fn constant_powers_pass_a_gradient_check() {
+        for n in [-3, -1, 0, 1, 2, 5] {
+            for base in [-1.3_f64, 0.7, 2.1] {
+                let x = Value::from(base);
+                let (powf, powi) = (x.powf(n as f64), x.powi(n));
+                powf.backward().unwrap();
+                powi.backward().unwrap();
+                let h = 1e-6;
+                let numeric = ((base + h).powi(n) - (base - h).powi(n)) / (2.0 * h);
+                // both powers added their gradient
+                assert!((x.grad() / 2.0 - numeric).abs() < 1e-6 * numeric.abs().max(1.0), "x^{} at {}", n, base);
+            }
+        }
+        // x^0 at 0 has a zero, not NaN, gradient
+        let zero = Value::from(0.0);
+        zero.powi(0).backward().unwrap();
+        assert_eq!(zero.grad(), 0.0);
+    }
+
+    #[test]
+    fn comparisons_read_the_data() {
+        let (a, b, nan) = (Value::from(1.0), Value::from(2.0), Value::from(f64::NAN));
+        assert!(a.lt(&b) && a.le(&b) && b.gt(&a) && b.ge(&a) && a.le(&a) && a.ge(&a));
+        assert!(!a.gt(&b) && !a.gt(&a));
+        assert!(!nan.lt(&a) && !nan.gt(&a) && !nan.le(&nan) && !nan.ge(&nan));
+        assert_eq!(node_count(&a), 1);
+    }
+
+    #[test]
+    fn total_cmp_orders_nan_and_sorts_stably() {
+        let data = [f64::NAN, 1.0, -0.0, f64::NEG_INFINITY, 0.0, -f64::NAN, 1.0, f64::INFINITY];
+        let mut values = values_from(&data);
+        let ids: Vec<NodeId> = values.iter().map(Value::id).collect();
+        values.sort_by(Value::total_cmp);
+
+        let bits: Vec<u64> = values.iter().map(|value| value.data().to_bits()).collect();
+        let expected = [-f64::NAN, f64::NEG_INFINITY, -0.0, 0.0, 1.0, 1.0, f64::INFINITY, f64::NAN];
+        assert_eq!(bits, expected.map(f64::to_bits));
+        // the two ones keep their order
+        assert_eq!((values[4].id(), values[5].id()), (ids[1], ids[6]));
+        assert_eq!(Value::from(f64::NAN).total_cmp(&Value::from(f64::NAN)), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn slices_lift_into_leaves() {
+        let xs = values_from(&[1.5, -2.0]);
+        assert_eq!(xs.iter().map(Value::data).collect::<Vec<f64>>(), [1.5, -2.0]);
+        assert!(xs.iter().all(|x| x.is_leaf() && x.requires_grad() && x.label().is_none()));
+
+        let labeled = values_from_labeled(&[0.0, 1.0, 2.0], "x");
+        let labels: Vec<String> = labeled.iter().filter_map(Value::label).collect();
+        assert_eq!(labels, ["x0", "x1", "x2"]);
+        assert_eq!(values_from_labeled(&[3.0], "layer.in")[0].label().as_deref(), Some("layer.in0"));
+
+        let targets = constants_from(&[1.0, 1.0, 4.0]);
+        assert!(targets.iter().all(|t| !t.requires_grad()));
+        // not the interned 1
+        assert!(!Rc::ptr_eq(&targets[0], &targets[1]) && !Rc::ptr_eq(&targets[0], &Value::constant(1.0)));
+
+        assert!(values_from(&[]).is_empty());
+        assert!(values_from_labeled(&[], "x").is_empty());
+        assert!(constants_from(&[]).is_empty());
+    }
+
+    #[test]
+    fn descend_minimizes() {
+        let w = Value::from(0.0);
+        for _ in 0..100 {
+            w.zero_grad();
+            (&w - &Value::constant(3.0)).powi(2).backward().unwrap();
+            w.descend(0.1);
+        }
+        assert_value_eq!(w, 3.0, 1e-8);
+
+        let frozen = Value::constant(2.0);
+        frozen.set_grad(1.0);
+        frozen.descend(0.5);
+        assert_eq!(frozen.data(), 2.0);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn adjust_still_ascends() {
+        let (a, b) = (Value::from(1.0), Value::from(1.0));
+        for value in [&a, &b] {
+            value.set_grad(2.0);
+        }
+        a.adjust(0.25);
+        b.ascend(0.25);
+        assert_eq!((a.data(), b.data()), (1.5, 1.5));
+        b.descend(0.25);
+        assert_eq!(b.data(), 1.0);
+    }
+
+    // A graph whose leaves are shared by many paths, so that their grads are sums of many terms
+    fn shared_graph() -> (Vec<Value>, Value) {
+        let leaves: Vec<Value> = (0..6).map(|i| Value::from(0.1 * i as f64 - 0.27)).collect();
+        let mut layer = leaves.clone();
+        let n = layer.len();
+        for _ in 0..4 {
+            layer = (0..n)
+                .map(|i| (&(&layer[i] * &layer[(i + 1) % n]) + &layer[(i + 3) % n].exp()).tanh())
+                .collect();
+        }
+        let root = ops::add_n(&layer);
+        (leaves, root)
+    }
+
+    fn grad_bits(leaves: &[Value]) -> Vec<u64> {
+        leaves.iter().map(|leaf| leaf.grad().to_bits()).collect()
+    }
+
+    #[test]
+    fn gradients_are_bit_identical_across_runs_and_paths() {
+        let (leaves, root) = shared_graph();
+        root.backward().unwrap();
+        let expected = grad_bits(&leaves);
+
+        for _ in 0..100 {
+            let (leaves, root) = shared_graph();
+            root.backward().unwrap();
+            assert_eq!(grad_bits(&leaves), expected);
+        }
+
+        let (leaves, root) = shared_graph();
+        let mut compiled = root.compile();
+        for _ in 0..3 {
+            leaves.iter().for_each(Value::zero_grad);
+            compiled.backward().unwrap();
+            assert_eq!(grad_bits(&leaves), expected);
+        }
+    }
+
+    // Builds a graph with `build`, drops it and returns the number of its nodes still alive, which is zero
+    // unless something (a cycle, a cache) keeps them. Interning is turned off as the shared constants are meant
+    // to outlive every graph.
+    fn surviving_nodes(build: impl FnOnce() -> Value) -> usize {
+        interning(false);
+        let root = build();
+        let nodes: Vec<std::rc::Weak<RefCell<_Value>>> =
+            root.topo_order().iter().map(|value| Rc::downgrade(&value.0)).collect();
+        drop(root);
+        interning(true);
+        nodes.iter().filter(|node| node.upgrade().is_some()).count()
+    }
+
+    fn every_op(x: f64) -> Value {
+        let x = Value::from(x);
+        let y = &(&x * &x) - &x.tanh();
+        let z = &(&y.exp() / &x.relu().softplus()) + &(&y.powi(2) + &Value::from(1.0)).ln();
+        ops::mul_n(&[z.round_ste(), z.binarize_ste(0.5), ops::add_n(&[x.clone(), y, z])])
+    }
+
+    #[test]
+    fn closures_hold_no_cycles() {
+        let root = every_op(0.5);
+        // the root is only held by this handle, the leaf by its parents
+        assert_eq!(root.strong_count(), 1);
+        drop(root);
+        assert_eq!(surviving_nodes(|| every_op(0.5)), 0);
+    }
+
+    #[test]
+    fn dropping_the_graph_frees_it_after_backward() {
+        let survivors = surviving_nodes(|| {
+            let root = every_op(1.5);
+            root.backward().unwrap();
+            root
+        });
+        assert_eq!(survivors, 0);
+    }
+
+    #[test]
+    fn many_graphs_are_all_freed() {
+        let survivors: usize = (0..10_000).map(|i| surviving_nodes(|| every_op(i as f64 / 1000.0))).sum();
+        assert_eq!(survivors, 0);
+    }
+
+    #[test]
+    fn second_derivative_of_cube() {
+        for x in [-2.0, -0.5, 0.0, 1.0, 3.0] {
+            let x = Value::from(x);
+            let dx = x.powi(3).backward_graph(std::slice::from_ref(&x)).remove(0);
+            assert_value_eq!(dx, 3.0 * x.data().powi(2), 1e-12);
+            dx.backward().unwrap();
+            assert_grad_eq!(x, 6.0 * x.data(), 1e-12);
+        }
+    }
+
+    #[test]
+    fn gradient_of_squared_derivative_matches_finite_differences() {
+        let squared_slope = |x: &Value| {
+            let dx = x.tanh().backward_graph(std::slice::from_ref(x)).remove(0);
+            &dx * &dx
+        };
+        for x in [-1.2, 0.3, 0.8] {
+            let leaf = Value::from(x);
+            squared_slope(&leaf).backward().unwrap();
+            let eps = 1e-6;
+            let at = |x: f64| squared_slope(&Value::from(x)).data();
+            let numeric = (at(x + eps) - at(x - eps)) / (2.0 * eps);
+            assert_grad_eq!(leaf, numeric, 1e-6);
+        }
+    }
+
+    #[test]
+    fn backward_graph_gives_zero_for_unrelated_nodes() {
+        let (x, y) = (Value::from(2.0), Value::from(3.0));
+        let grads = (&x * &x).backward_graph(&[x.clone(), y]);
+        assert_eq!(grads.iter().map(Value::data).collect::<Vec<_>>(), vec![4.0, 0.0]);
+        // nothing was accumulated into the grads of the graph
+        assert_eq!(x.grad(), 0.0);
+    }
+
+    #[test]
+    fn hvp_of_quadratic_is_twice_a_v() {
+        let a = [[2.0, -1.0, 0.5], [-1.0, 3.0, 0.0], [0.5, 0.0, 1.0]];
+        let w = values_from(&[0.3, -0.7, 1.1]);
+        let terms: Vec<Value> = (0..3)
+            .flat_map(|i| (0..3).map(move |j| (i, j)))
+            .map(|(i, j)| &(&w[i] * &w[j]) * &Value::constant(a[i][j]))
+            .collect();
+        let loss = ops::add_n(&terms);
+
+        let v = [1.0, 2.0, -0.5];
+        let expected: Vec<f64> = a.iter().map(|row| 2.0 * (0..3).map(|j| row[j] * v[j]).sum::<f64>()).collect();
+        assert_eq!(hvp(&loss, &w, &v), expected);
+        assert!(w.iter().all(|w| w.grad() == 0.0));
+    }
+
+    #[test]
+    fn hvp_of_mlp_matches_finite_differences_of_the_gradient() {
+        crate::seed(7);
+        let mlp = crate::nn::MLP::new(2, vec![3, 1]);
+        let params = mlp.parameters();
+        let loss = || {
+            let output = mlp.forward(values_from(&[0.5, -1.0])).remove(0);
+            &output * &output
+        };
+        let gradient = || {
+            let loss = loss();
+            loss.backward_graph(&params).iter().map(Value::data).collect::<Vec<f64>>()
+        };
+
+        let v: Vec<f64> = (0..params.len()).map(|i| (i as f64 * 0.37).sin()).collect();
+        let analytic = hvp(&loss(), &params, &v);
+
+        let eps = 1e-5;
+        let shift = |sign: f64| {
+            for (param, v) in std::iter::zip(&params, &v) {
+                param.set_data(param.data() + sign * eps * v);
+            }
+        };
+        shift(1.0);
+        let above = gradient();
+        shift(-2.0);
+        let below = gradient();
+        shift(1.0);
+        for (i, exact) in analytic.iter().enumerate() {
+            let numeric = (above[i] - below[i]) / (2.0 * eps);
+            assert!(approx_eq(*exact, numeric, 1e-5, 1e-7), "{}: {} against {}", i, exact, numeric);
+        }
+    }
+
+    #[test]
+    fn ids_are_unique_across_threads() {
+        let threads: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| (0..1000).map(|_| Value::from(1.0).id()).collect::<Vec<NodeId>>()))
+            .collect();
+        let mut ids: Vec<NodeId> = threads.into_iter().flat_map(|thread| thread.join().unwrap()).collect();
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+    }
+
+    #[test]
+    fn find_by_label_searches_the_whole_graph() {
+        let x = Value::from(0.5).add_label("x");
+        let mut value = &x * &Value::from(2.0);
+        let inner = value.clone().add_label("inner");
+        for _ in 0..50 {
+            value = &value.tanh() + &x;
+        }
+        assert_eq!(value.find_by_label("inner").map(|found| found.id()), Some(inner.id()));
+        assert_eq!(value.find_by_label("x").map(|found| found.id()), Some(x.id()));
+        assert!(value.find_by_label("missing").is_none());
+        assert!(value.find_all_by_label("missing").is_empty());
+    }
+
+    #[test]
+    fn find_all_by_label_goes_from_the_root_down() {
+        let x = Value::from(0.5).add_label("h");
+        let y = x.tanh().add_label("h");
+        let root = &y + &Value::from(1.0);
+        let found: Vec<NodeId> = root.find_all_by_label("h").iter().map(Value::id).collect();
+        assert_eq!(found, vec![y.id(), x.id()]);
+    }
+
+    #[test]
+    fn accessors_agree_with_construction() {
+        let (x, y) = (Value::from(0.5).add_label("x"), Value::from(2.0));
+        let cases: Vec<(Value, Op, Vec<NodeId>)> = vec![
+            (&x + &y, Op::Add, vec![x.id(), y.id()]),
+            (&x * &y, Op::Mul, vec![x.id(), y.id()]),
+            (x.pow(&y), Op::Pow, vec![x.id(), y.id()]),
+            (x.tanh(), Op::Tanh, vec![x.id()]),
+            (x.exp(), Op::Exp, vec![x.id()]),
+            (x.ln(), Op::Ln, vec![x.id()]),
+            (x.relu(), Op::Relu, vec![x.id()]),
+            (x.softplus(), Op::Softplus, vec![x.id()]),
+            (x.round_ste(), Op::RoundSte, vec![x.id()]),
+            (ops::add_n(&[x.clone(), y.clone(), x.clone()]), Op::Add, vec![x.id(), y.id(), x.id()]),
+        ];
+        for (value, op, children) in cases {
+            assert_eq!(value.op(), Some(op));
+            assert!(!value.is_leaf());
+            assert_eq!(value.children().iter().map(Value::id).collect::<Vec<_>>(), children);
+            assert_eq!(value.label(), None);
+        }
+        assert!(x.is_leaf() && x.children().is_empty() && x.op().is_none());
+        assert_eq!(x.label().as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn consumers_are_tracked_on_demand() {
+        let x = Value::from(0.5);
+        let untracked = x.tanh();
+        track_consumers(true);
+        let (a, b) = (x.exp(), &x * &x);
+        track_consumers(false);
+        let consumers: Vec<NodeId> = x.consumers().iter().map(Value::id).collect();
+        assert_eq!(consumers, vec![a.id(), b.id()]);
+        assert!(untracked.consumers().is_empty());
+
+        drop(a);
+        assert_eq!(x.consumers().iter().map(Value::id).collect::<Vec<_>>(), vec![b.id()]);
+    }
+
+    #[test]
+    fn consumer_links_keep_nothing_alive() {
+        track_consumers(true);
+        let survivors = surviving_nodes(|| every_op(0.5));
+        let x = Value::from(1.0);
+        for _ in 0..1000 {
+            drop(x.tanh());
+        }
+        track_consumers(false);
+        assert_eq!(survivors, 0);
+        assert!(x.consumers().is_empty());
+        // the links to the dropped nodes were cleared as they piled up
+        assert!(x.borrow().consumers.len() < 8);
+    }
+
+    #[test]
+    fn clone_graph_is_independent_of_the_original() {
+        let x = Value::from(1.5);
+        let root = (&x * &Value::from(2.0)).tanh();
+        let copy = root.clone_graph();
+        assert_eq!((copy.data(), node_count(&copy)), (root.data(), node_count(&root)));
+        assert!(copy.topo_order().iter().all(|node| root.topo_order().iter().all(|old| old.id() != node.id())));
+
+        let copied_x = &copy.children()[0].children()[0];
+        copied_x.set_data(-3.0);
+        assert_eq!(x.data(), 1.5);
+
+        copy.backward().unwrap();
+        assert_ne!(copied_x.grad(), 0.0);
+        assert!(root.topo_order().iter().all(|node| node.grad() == 0.0));
+    }
+
+    #[test]
+    fn clone_graph_preserves_sharing() {
+        let x = Value::from(1.5);
+        let copy = (&x + &x).clone_graph();
+        let children = copy.children();
+        assert_eq!(children[0].id(), children[1].id());
+        assert_ne!(children[0].id(), x.id());
+        assert_eq!(node_count(&copy), 2);
+    }
+
+    #[test]
+    fn checked_ops_report_bad_inputs() {
+        let (zero, two) = (Value::from(0.0), Value::from(2.0));
+        assert_eq!(two.try_div(&zero).unwrap_err(), GradError::DivisionByZero);
+        assert_eq!(zero.try_pow(&Value::from(-1.0)).unwrap_err(), GradError::DivisionByZero);
+        assert_eq!(Value::from(-3.0).try_ln().unwrap_err(), GradError::DomainError { op: "ln", input: -3.0 });
+        assert_eq!(zero.try_ln().unwrap_err(), GradError::DomainError { op: "ln", input: 0.0 });
+        assert_eq!(
+            Value::from(-2.0).try_pow(&Value::from(0.5)).unwrap_err(),
+            GradError::DomainError { op: "pow", input: -2.0 }
+        );
+        assert_eq!(Value::from(-1.0).try_sqrt().unwrap_err(), GradError::DomainError { op: "sqrt", input: -1.0 });
+        let overflow = Value::from(1e308);
+        assert_eq!(overflow.try_div(&Value::from(1e-308)).unwrap_err(), GradError::NonFinite { op: "div" });
+        assert_eq!(overflow.try_pow(&two).unwrap_err(), GradError::NonFinite { op: "pow" });
+    }
+
+    #[test]
+    fn checked_ops_match_the_unchecked_ones() {
+        type Checked = fn(&Value, &Value) -> Result<Value, GradError>;
+        type Unchecked = fn(&Value, &Value) -> Value;
+        let cases: [(Checked, Unchecked); 4] = [
+            (|a, b| a.try_div(b), |a, b| a / b),
+            (|a, _| a.try_ln(), |a, _| a.ln()),
+            (|a, b| a.try_pow(b), |a, b| a.pow(b)),
+            (|a, _| a.try_sqrt(), |a, _| a.sqrt()),
+        ];
+        for (checked, unchecked) in cases {
+            let run = |op: &dyn Fn(&Value, &Value) -> Value| {
+                let (a, b) = (Value::from(2.7), Value::from(-1.3));
+                let out = op(&a, &b);
+                out.backward().unwrap();
+                (out.data(), a.grad(), b.grad(), node_count(&out))
+            };
+            assert_eq!(run(&|a, b| checked(a, b).unwrap()), run(&unchecked));
+        }
+    }
+
+    #[test]
+    fn straight_through_ops_quantize_forward_and_pass_gradients_back() {
+        for (x, rounded) in [(1.4, 1.0), (1.5, 2.0), (-2.5, -3.0), (-0.2, -0.0)] {
+            let x = Value::from(x);
+            let out = &x.round_ste() * &Value::from(3.0);
+            out.backward().unwrap();
+            assert_eq!((out.data(), x.grad()), (3.0 * rounded, 3.0));
+        }
+        // identity within 1 of the threshold, clipped beyond; the threshold gets nothing
+        let cases = [(0.9, 1.0, 2.0), (0.5, 0.0, 2.0), (-0.4, 0.0, 2.0), (1.6, 1.0, 0.0), (-0.6, 0.0, 0.0)];
+        for (x, bit, grad) in cases {
+            let x = Value::from(x);
+            let bits = x.binarize_ste(0.5);
+            let out = &bits * &Value::from(2.0);
+            out.backward().unwrap();
+            assert_eq!((bits.data(), x.grad(), bits.children()[1].grad()), (bit, grad, 0.0));
+        }
+    }
+
+    #[test]
+    fn quantization_aware_training_reduces_the_loss() {
+        // fits a weight that is rounded in the forward pass to a target of 3
+        let w = Value::from(0.2);
+        let loss = |w: &Value| (&w.round_ste() - &Value::from(3.0)).powi(2);
+        let initial = loss(&w).data();
+        for _ in 0..20 {
+            w.zero_grad();
+            loss(&w).backward().unwrap();
+            w.descend(0.1);
+        }
+        assert_eq!((initial, loss(&w).data(), w.data().round()), (9.0, 0.0, 3.0));
+    }
+
+    // The number of nodes on the longest path from `root` down to a leaf
+    fn depth(root: &Value) -> usize {
+        let mut depths: HashMap<NodeId, usize> = HashMap::new();
+        for node in root.topo_order() {
+            let below = node.children().iter().map(|child| depths[&child.id()]).max().unwrap_or(0);
+            depths.insert(node.id(), below + 1);
+        }
+        depths[&root.id()]
+    }
+
+    #[test]
+    fn accumulated_sums_match_the_chain_and_stay_shallow() {
+        let xs: Vec<Value> = (0..100).map(|i| Value::from(i as f64 / 10.0 - 3.0)).collect();
+        let terms: Vec<Value> = xs.iter().map(|x| x * x).collect();
+
+        let chain = terms[1..].iter().fold(terms[0].clone(), |total, term| &total + term);
+        chain.backward().unwrap();
+        let chain_grads: Vec<f64> = xs.iter().map(Value::grad).collect();
+        xs.iter().chain(&terms).for_each(Value::zero_grad);
+
+        let mut total = Accumulator::new();
+        for term in &terms[..50] {
+            total.add(term);
+        }
+        for term in &terms[50..] {
+            total += term;
+        }
+        assert_eq!(total.len(), 100);
+        let sum = total.finish();
+        sum.backward().unwrap();
+
+        assert_value_eq!(sum, chain.data(), 1e-9);
+        assert_eq!(xs.iter().map(Value::grad).collect::<Vec<f64>>(), chain_grads);
+        // the leaves, the squares and one sum, against a chain of 99 additions
+        assert_eq!((depth(&sum), depth(&chain)), (3, 101));
+    }
+
+    #[test]
+    fn an_empty_accumulator_finishes_at_constant_zero() {
+        let total = Accumulator::new();
+        assert!(total.is_empty());
+        let zero = total.finish();
+        assert_eq!((zero.data(), zero.requires_grad(), zero.children().len()), (0.0, false, 0));
+    }
+
+    // `relu(tanh(w·x)·w) + exp(3w)` at a point where the relu is off, and the number of propagation functions a
+    // backward pass runs
+    fn gated(skip: bool, w: &Value) -> (f64, usize) {
+        skip_zero_gradients(skip);
+        let x = Value::from(-1.0);
+        let dead = (&(w * &x).tanh() * w).relu();
+        let root = &dead + &(w * &Value::from(3.0)).exp();
+        let ((), report) = profile::profile(|| root.backward().unwrap());
+        skip_zero_gradients(true);
+        (w.grad(), report.ops.iter().map(|op| op.propagated).sum())
+    }
+
+    #[test]
+    fn zero_gradients_are_skipped_without_changing_the_result() {
+        let (with_skip, without) = (gated(true, &Value::from(0.5)), gated(false, &Value::from(0.5)));
+        assert_eq!(with_skip.0, without.0);
+        assert_eq!(with_skip.0, 3.0 * 1.5f64.exp());
+        // the two products and the tanh behind the relu aren't propagated
+        assert_eq!((with_skip.1, without.1), (without.1 - 3, 7));
+    }
+
+    #[test]
+    fn hooks_see_the_zeros_of_skipped_subgraphs() {
+        let w = Value::from(0.5);
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let seen = Rc::clone(&writes);
+        w.register_hook(move |_, grad| seen.borrow_mut().push(grad));
+        let (grad, propagated) = gated(true, &w);
+        // one write per consumer of w, the two behind the relu adding zero to what the others wrote
+        assert_eq!(writes.borrow().len(), 3);
+        assert!(writes.borrow().iter().all(|&write| write == 0.0 || write == grad));
+        assert_eq!((grad, propagated), (3.0 * 1.5f64.exp(), 6));
+    }
+
+    #[test]
+    fn ids_follow_nodes_not_data() {
+        let x = Value::from(2.0);
+        let handle = x.clone();
+        assert_eq!(handle.id(), x.id());
+        // equal data, and even equal structure, are still different nodes
+        let (a, b) = (Value::from(2.0), Value::from(2.0));
+        assert_eq!(a, b);
+        assert_ne!(a.id(), b.id());
+        assert_ne!((&x + &a).id(), (&x + &a).id());
+        // ids are handed out in order of construction
+        assert!(a.id() < b.id());
+    }
+
+    #[test]
+    fn node_maps_find_nodes_after_their_data_and_grads_change() {
+        let (x, y) = (Value::from(2.0), Value::from(2.0));
+        let mut names = NodeMap::new();
+        names.insert(&x, "x");
+        names.insert(&y, "y");
+        assert_eq!(names.len(), 2);
+
+        let root = &x * &y;
+        root.backward().unwrap();
+        x.set_data(-5.0);
+        assert_eq!((names.get(&x.clone()), names[&y]), (Some(&"x"), "y"));
+        assert!(!names.contains(&root) && names.get(&Value::from(2.0)).is_none());
+
+        *names.entry(&root).or_insert("root") = "product";
+        *names.get_mut(&x).unwrap() = "first";
+        assert_eq!(names.remove(&root), Some("product"));
+        let mut entries: Vec<(NodeId, &str)> = names.iter().map(|(id, &name)| (id, name)).collect();
+        entries.sort();
+        assert_eq!(entries, vec![(x.id(), "first"), (y.id(), "y")]);
+
+        let mut set = NodeSet::new();
+        assert!(set.insert(&x) && !set.insert(&x.clone()) && set.insert(&y));
+        assert!(set.remove(&y) && set.contains(&x) && !set.contains(&y));
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion `logits` failed in the forward pass at node")]
+    fn assertions_check_the_data_in_the_forward_pass() {
+        (&Value::from(1.0) / &Value::from(0.0)).assert_finite("logits");
+    }
+
+    #[test]
+    fn assertions_name_the_node_and_bounds_they_check() {
+        let x = Value::from(3.0).add_label("x");
+        let checked = std::panic::AssertUnwindSafe(|| x.assert_in(0.0, 1.0, "probability"));
+        let panic = std::panic::catch_unwind(checked).unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        let expected = format!("assertion `probability` failed in the forward pass at node {} `x`", x.id());
+        assert_eq!(message, &format!("{}: data 3 is not finite and within [0, 1]", expected));
+        // within the bounds, the node passes the data through
+        assert_eq!(x.assert_in(0.0, 5.0, "in range").data(), 3.0);
+    }
+
+    #[test]
+    fn assertions_check_the_gradient_in_the_backward_pass() {
+        // d/dx sqrt(x) is infinite at 0, so the gradient reaching the checked node x·w isn't finite
+        let (x, w) = (Value::from(0.0), Value::from(2.0));
+        let checked = (&x * &w).add_label("product");
+        let root = checked.assert_finite("before sqrt").sqrt();
+        let error = root.backward().unwrap_err();
+        let expected = BackwardError::AssertionFailed {
+            id: checked.id().0,
+            label: Some("product".to_string()),
+            message: "before sqrt".to_string(),
+            grad: f64::INFINITY,
+        };
+        assert_eq!(error, expected);
+        assert!(error.to_string().contains("failed in the backward pass"));
+        // the backward pass stopped at the assertion
+        assert_eq!((x.grad(), w.grad()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn stripping_assertions_restores_the_graph() {
+        let (x, w) = (Value::from(0.5), Value::from(-1.5));
+        let plain = (&(&x * &w).tanh() * &w).exp();
+        let inner = (&x * &w).assert_finite("product").tanh().assert_in(-1.0, 1.0, "tanh");
+        let checked = (&inner * &w).assert_finite("before exp").exp();
+        // each assertion adds itself and its two bounds
+        assert_eq!(node_count(&checked), node_count(&plain) + 9);
+
+        let stripped = strip_assertions(&checked);
+        assert_eq!(node_count(&stripped), node_count(&plain));
+        assert!(stripped.topo_order().iter().all(|node| node.op() != Some(Op::Assert)));
+        assert_eq!(stripped.data(), plain.data());
+
+        plain.backward().unwrap();
+        let expected = (x.grad(), w.grad());
+        x.zero_grad();
+        w.zero_grad();
+        stripped.backward().unwrap();
+        assert_eq!((x.grad(), w.grad()), expected);
+        x.zero_grad();
+        w.zero_grad();
+        checked.backward().unwrap();
+        assert_eq!((x.grad(), w.grad()), expected);
+    }
+
+    // `tanh(w·x + b)·scale` with labeled leaves and a labeled constant
+    fn bindable() -> (CompiledGraph, Value, Value) {
+        let (x, w, b) = (Value::from(0.0).add_label("x"), Value::from(0.5).add_label("w"), Value::from(0.0));
+        let scale = frozen(2.0).add_label("scale");
+        let root = &(&(&w * &x) + &b).tanh() * &scale;
+        (root.compile(), x, w)
+    }
+
+    #[test]
+    fn forward_bound_names_the_unbound_leaves() {
+        let (mut graph, ..) = bindable();
+        // the constant and the unlabeled bias need no binding
+        let error = graph.forward_bound().unwrap_err();
+        assert_eq!(error, BindError::Unbound { names: vec!["w".to_string(), "x".to_string()] });
+        graph.bind("x", 1.0).unwrap();
+        assert_eq!(graph.forward_bound().unwrap_err(), BindError::Unbound { names: vec!["w".to_string()] });
+        assert_eq!(graph.forward_bound().unwrap_err().to_string(), "unbound leaves: w");
+        graph.bind("w", 0.5).unwrap();
+        assert_eq!(graph.forward_bound().unwrap(), 2.0 * 0.5f64.tanh());
+    }
+
+    #[test]
+    fn rebinding_gives_the_gradients_of_the_new_inputs() {
+        let (mut graph, x, w) = bindable();
+        for (x_data, w_data) in [(1.0, 0.5), (-2.0, 0.25), (0.3, -1.5)] {
+            graph.bind("x", x_data).unwrap();
+            graph.bind("w", w_data).unwrap();
+            let expected = 2.0 * (w_data * x_data).tanh();
+            assert_value_eq!(graph.forward_bound().unwrap(), expected, 1e-15);
+            x.zero_grad();
+            w.zero_grad();
+            graph.backward().unwrap();
+            // d/dx = 2·w·(1 - tanh²), d/dw = 2·x·(1 - tanh²)
+            let slope = 2.0 * (1.0 - (w_data * x_data).tanh().powi(2));
+            assert_grad_eq!(x, slope * w_data, 1e-15);
+            assert_grad_eq!(w, slope * x_data, 1e-15);
+        }
+    }
+
+    #[test]
+    fn bindings_are_kept_between_runs() {
+        let (mut graph, ..) = bindable();
+        graph.bind_all(&HashMap::from([("x".to_string(), 1.0), ("w".to_string(), 0.5)])).unwrap();
+        let first = graph.forward_bound().unwrap();
+        graph.bind("x", 2.0).unwrap();
+        assert_eq!(graph.forward_bound().unwrap(), 2.0 * 1.0f64.tanh());
+        assert_ne!(first, 2.0 * 1.0f64.tanh());
+        // positional forward sets every leaf
+        let (mut graph, ..) = bindable();
+        graph.forward(&[0.5, 1.0, 0.0]);
+        assert!(graph.forward_bound().is_ok());
+    }
+
+    #[test]
+    fn unknown_names_are_rejected_and_bind_nothing() {
+        let (mut graph, x, _) = bindable();
+        assert_eq!(graph.bind("y", 1.0), Err(BindError::UnknownLeaf { name: "y".to_string() }));
+        // constants aren't leaves that can be bound
+        assert_eq!(graph.bind("scale", 1.0), Err(BindError::UnknownLeaf { name: "scale".to_string() }));
+        let values = HashMap::from([("x".to_string(), 3.0), ("y".to_string(), 1.0)]);
+        assert_eq!(graph.bind_all(&values), Err(BindError::UnknownLeaf { name: "y".to_string() }));
+        assert_eq!(x.data(), 0.0);
+        assert!(matches!(graph.forward_bound(), Err(BindError::Unbound { .. })));
+    }
+
+    fn auto_label(node: &Value) -> (String, u32) {
+        let label = node.label().expect("the node is labeled");
+        let (file, line) = label.rsplit_once(':').expect("labels are file:line");
+        (file.to_string(), line.parse().unwrap())
+    }
+
+    #[test]
+    fn auto_labels_name_the_line_building_the_node() {
+        set_auto_label(true);
+        let (x, y) = (Value::from(1.0), Value::from(2.0));
+        let line = line!();
+        let (product, squashed) = (&x * &y, x.tanh());
+        let sum = ops::add_n(&[x.clone(), y.clone(), product.clone()]);
+        let power = y.powi(2);
+        set_auto_label(false);
+
+        assert_eq!(auto_label(&product), (file!().to_string(), line + 1));
+        assert_eq!(auto_label(&squashed), (file!().to_string(), line + 1));
+        assert_eq!(auto_label(&sum), (file!().to_string(), line + 2));
+        assert_eq!(auto_label(&power), (file!().to_string(), line + 3));
+        assert!(auto_label(&sum).0.ends_with("engine.rs"));
+        // leaves aren't built by an op
+        assert_eq!((x.label(), y.label()), (None, None));
+    }
+
+    #[test]
+    fn auto_labeling_is_off_by_default_and_can_be_turned_off() {
+        let (x, y) = (Value::from(1.0), Value::from(2.0));
+        assert_eq!((&x * &y).label(), None);
+        set_auto_label(true);
+        assert!((&x * &y).label().is_some());
+        set_auto_label(false);
+        assert_eq!((&x * &y).exp().label(), None);
+    }
+
+    #[test]
+    fn explicit_labels_replace_auto_labels() {
+        set_auto_label(true);
+        let x = Value::from(1.0);
+        let labeled = x.exp().add_label("growth");
+        // copies keep the label of the original rather than the place they are built at
+        let copy = labeled.with_children(vec![x.clone()]);
+        set_auto_label(false);
+        assert_eq!(labeled.label().as_deref(), Some("growth"));
+        assert_eq!(copy.label().as_deref(), Some("growth"));
+    }
+
+    #[test]
+    fn the_jacobian_of_a_linear_map_is_its_matrix() {
+        let matrix = [[1.0, -2.0, 0.5], [0.0, 3.0, -1.0]];
+        let inputs = values_from(&[0.3, -0.7, 1.1]);
+        let row = |row: &[f64; 3]| {
+            let terms: Vec<Value> = std::iter::zip(row, &inputs).map(|(&a, x)| x * &frozen(a)).collect();
+            ops::add_n(&terms)
+        };
+        let outputs: Vec<Value> = matrix.iter().map(row).collect();
+        assert_eq!(jacobian(&outputs, &inputs), matrix.map(|row| row.to_vec()).to_vec());
+        assert!(jacobian_check(&outputs, &inputs, 1e-6, 1e-8).is_ok());
+        // the grads are left zeroed
+        assert!(inputs.iter().all(|input| input.grad() == 0.0));
+    }
+
+    #[test]
+    fn rows_sharing_a_subgraph_dont_leak_into_each_other() {
+        let (x, y) = (Value::from(0.4), Value::from(-1.3));
+        let shared = (&x * &y).tanh();
+        let outputs = [&shared * &x, &shared + &y, shared.exp()];
+        let (s, ds) = ((0.4f64 * -1.3).tanh(), 1.0 - (0.4f64 * -1.3).tanh().powi(2));
+        let expected = [
+            [s + 0.4 * ds * -1.3, 0.4 * ds * 0.4],
+            [ds * -1.3, ds * 0.4 + 1.0],
+            [s.exp() * ds * -1.3, s.exp() * ds * 0.4],
+        ];
+        let rows = jacobian(&outputs, &[x.clone(), y.clone()]);
+        for (row, expected) in std::iter::zip(&rows, expected) {
+            for (entry, expected) in std::iter::zip(row, expected) {
+                assert!((entry - expected).abs() < 1e-15, "{} != {}", entry, expected);
+            }
+        }
+        // an input none of the outputs depends on gets zeros
+        let z = Value::from(2.0);
+        assert!(jacobian(&outputs, &[z]).iter().all(|row| row == &[0.0]));
+    }
+
+    #[test]
+    fn the_check_flags_a_wrong_backward() {
+        fn cube(x: f64) -> f64 {
+            x.powi(3)
+        }
+        // the derivative of x³ is 3x², not 2x²
+        fn wrong(x: f64, _: f64, grad: f64) -> f64 {
+            2.0 * x * x * grad
+        }
+        fn right(x: f64, _: f64, grad: f64) -> f64 {
+            3.0 * x * x * grad
+        }
+        let wrong = register_unary("jacobian_wrong_cube", cube, wrong).unwrap();
+        let right = register_unary("jacobian_cube", cube, right).unwrap();
+        let (x, y) = (Value::from(1.5).add_label("x"), Value::from(0.5));
+        let outputs = [&x.custom_unary(right) + &y, &x.custom_unary(wrong) * &y];
+        let failure = jacobian_check(&outputs, &[y.clone(), x.clone()], 1e-6, 1e-6).unwrap_err();
+        assert_eq!((failure.output, failure.input, failure.label.as_deref()), (1, 1, Some("x")));
+        assert!((failure.analytic - 2.0 * 2.25 * 0.5).abs() < 1e-12);
+        assert!((failure.numeric - 3.0 * 2.25 * 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn the_check_writes_neither_inputs_nor_shared_constants() {
+        let x = Value::from(0.25);
+        let one = Value::constant(1.0);
+        let root = &(&x + &one).ln() * &x;
+        let data: Vec<u64> = root.topo_order().iter().map(|node| node.data().to_bits()).collect();
+        jacobian_check(std::slice::from_ref(&root), std::slice::from_ref(&x), 1e-6, 1e-8).unwrap();
+        assert_eq!(root.topo_order().iter().map(|node| node.data().to_bits()).collect::<Vec<_>>(), data);
+        assert_eq!(Value::constant(1.0).data(), 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "is a constant, which takes no gradient")]
+    fn the_check_refuses_constant_inputs() {
+        let (x, scale) = (Value::from(0.25), Value::constant(2.0));
+        let root = &x * &scale;
+        let _ = jacobian_check(&[root], &[scale], 1e-6, 1e-8);
+    }
+
+    // Two heads on a shared hidden node: `(tanh(w·x) - 1)²` and `exp(tanh(w·x))·v`
+    fn two_heads(w: &Value, x: &Value, v: &Value) -> (Value, Value, Value) {
+        let hidden = (w * x).tanh();
+        ((&hidden - &Value::from(1.0)).powi(2), &hidden.exp() * v, hidden)
+    }
+
+    #[test]
+    fn reachable_sets_hold_the_graph_of_their_root() {
+        let (w, x, v) = (Value::from(0.5), Value::from(2.0), Value::from(-1.0));
+        let (loss, other, hidden) = two_heads(&w, &x, &v);
+        let reachable = loss.reachable_from();
+        assert_eq!(reachable.len(), node_count(&loss));
+        assert!(reachable.contains(&loss) && reachable.contains(&hidden) && reachable.contains(&w));
+        assert!(!reachable.contains(&other) && !reachable.contains(&v));
+        assert_eq!(hidden.reachable_from().len(), 4);
+    }
+
+    #[test]
+    fn zeroing_within_a_set_leaves_the_other_nodes() {
+        let (w, x, v) = (Value::from(0.5), Value::from(2.0), Value::from(-1.0));
+        let (loss, other, hidden) = two_heads(&w, &x, &v);
+        other.backward().unwrap();
+        let within = loss.reachable_from();
+        other.zero_grad_all_within(&within);
+        // the other head isn't in the set, so nothing is walked
+        assert_ne!(hidden.grad(), 0.0);
+        loss.zero_grad_all_within(&within);
+        assert_eq!((hidden.grad(), w.grad(), x.grad()), (0.0, 0.0, 0.0));
+        assert_eq!((other.grad(), v.grad()), (1.0, hidden.data().exp()));
+    }
+
+    #[test]
+    fn pruning_cuts_what_the_kept_roots_dont_need() {
+        let (w, x, v) = (Value::from(0.5), Value::from(2.0), Value::from(-1.0));
+        let (loss, other, _) = two_heads(&w, &x, &v);
+        let (plain, ..) = two_heads(&w, &x, &v);
+        plain.backward().unwrap();
+        let expected = (w.grad(), x.grad());
+        w.zero_grad();
+        x.zero_grad();
+
+        let (before, data) = (node_count(&loss), other.data());
+        // the product and the exp of the other head
+        assert_eq!(prune_unreachable(&[loss.clone(), other.clone()], std::slice::from_ref(&loss)), 2);
+        assert!(other.is_leaf() && other.op().is_none());
+        assert_eq!(other.data(), data);
+        assert_eq!(node_count(&loss), before);
+
+        loss.backward().unwrap();
+        assert_eq!((w.grad(), x.grad()), expected);
+        // a second pruning has nothing left to do
+        assert_eq!(prune_unreachable(&[loss.clone(), other], &[loss]), 0);
+    }
+
+    #[test]
+    fn pruned_nodes_are_dropped() {
+        let (w, x, v) = (Value::from(0.5), Value::from(2.0), Value::from(-1.0));
+        let (loss, other, hidden) = two_heads(&w, &x, &v);
+        let exp = Rc::downgrade(&other.children()[0]);
+        assert!(exp.upgrade().is_some());
+        prune_unreachable(&[loss.clone(), other.clone()], std::slice::from_ref(&loss));
+        // the exp was only held by the other head, the hidden node is still held by the square of the loss
+        assert!(exp.upgrade().is_none());
+        assert_eq!(other.children().len(), 0);
+        assert_eq!(Rc::strong_count(&hidden), 2);
+        assert_eq!(loss.data(), (hidden.data() - 1.0).powi(2));
+    }
+
+    #[test]
+    fn clamp_passes_the_gradient_within_its_bounds_only() {
+        let xs = values_from(&[-1.0, 0.0, 2.5, 6.0, 7.5]);
+        let clamped: Vec<Value> = xs.iter().map(|x| x.clamp(0.0, 6.0)).collect();
+        assert_eq!(clamped.iter().map(Value::data).collect::<Vec<_>>(), [0.0, 0.0, 2.5, 6.0, 6.0]);
+        ops::add_n(&clamped).backward().unwrap();
+        assert_eq!(xs.iter().map(Value::grad).collect::<Vec<_>>(), [0.0, 1.0, 1.0, 1.0, 0.0]);
+        // a node of its own, whose bounds are constants, not parameters
+        assert_eq!(clamped[0].op(), Some(Op::Clamp));
+        assert!(clamped[0].children()[1..].iter().all(|bound| !bound.requires_grad() && bound.grad() == 0.0));
+        assert!(Value::from(f64::NAN).clamp(0.0, 1.0).data().is_nan());
+    }
+
+    #[test]
+    #[should_panic(expected = "clamp needs lo <= hi, got 1 and 0")]
+    fn clamp_needs_ordered_bounds() {
+        Value::from(0.5).clamp(1.0, 0.0);
     }
 }
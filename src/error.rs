@@ -0,0 +1,298 @@
+use std::fmt::{self, Display};
+
+use crate::engine::Op;
+
+// Errors reported by the checked operations (`Value::try_div`, `try_ln`, ...) instead of putting NaN or
+// infinities into the graph, and by the reductions in `ops` and the matrix ops in `tensor` when given
+// inputs they are not defined for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GradError {
+    // the right-hand side of a division, or a zero base raised to a negative power
+    DivisionByZero,
+    // the input lies outside the domain of the op, e.g. the logarithm of a negative number
+    DomainError { op: &'static str, input: f64 },
+    // the op produced an infinite or NaN result from valid inputs
+    NonFinite { op: &'static str },
+    // a reduction that has no value for zero elements, such as a maximum, was given an empty slice
+    EmptyInput { op: &'static str },
+    // more elements were asked for than the slice holds
+    TooFewElements { op: &'static str, requested: usize, len: usize },
+    // the shapes (rows, columns) of the operands of a matrix op don't fit together
+    ShapeMismatch { op: &'static str, left: (usize, usize), right: (usize, usize) },
+    // building the op would take the number of live nodes past the limit set by `engine::set_node_limit`
+    NodeLimit { limit: usize, op: &'static str },
+}
+
+impl Display for GradError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GradError::DivisionByZero => write!(f, "division by zero"),
+            GradError::DomainError { op, input } => write!(f, "{} is not defined for {}", op, input),
+            GradError::NonFinite { op } => write!(f, "{} produced a non-finite result", op),
+            GradError::EmptyInput { op } => write!(f, "{} of an empty slice", op),
+            GradError::TooFewElements { op, requested, len } => {
+                write!(f, "{} asked for {} elements of a slice of length {}", op, requested, len)
+            }
+            GradError::ShapeMismatch { op, left, right } => write!(
+                f,
+                "{} of a {}x{} and a {}x{} operand: shapes don't match",
+                op, left.0, left.1, right.0, right.1
+            ),
+            GradError::NodeLimit { limit, op } => {
+                write!(f, "{} would exceed the limit of {} live nodes", op, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GradError {}
+
+// Errors reported by `Value::backward` and `CompiledGraph::backward` instead of panicking in the middle of
+// the pass.
+// The node-level errors name the node whose gradient was being propagated, by id, label and op.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackwardError {
+    // a child of the node was already borrowed, e.g. by a guard held across the call, so its grad couldn't be
+    // updated
+    Borrowed { id: u64, label: Option<String>, op: Op },
+    // the node doesn't have the number of children its op takes, e.g. after editing a graph by hand
+    Malformed { id: u64, label: Option<String>, op: Op, children: usize },
+    // leaves still hold the gradients of an earlier pass, see `engine::set_accumulation_policy`
+    StaleGradients { leaves: usize },
+    // the gradient reaching a node checked by `Value::assert_finite` or `assert_in` isn't finite; the node is the
+    // checked one and `message` that of the assertion
+    AssertionFailed { id: u64, label: Option<String>, message: String, grad: f64 },
+}
+
+impl Display for BackwardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let node = |id: &u64, label: &Option<String>| match label {
+            Some(label) => format!("node {} `{}`", id, label),
+            None => format!("node {}", id),
+        };
+        match self {
+            BackwardError::Borrowed { id, label, op } => {
+                write!(f, "backward through {} ({}): a child is already borrowed", node(id, label), op)
+            }
+            BackwardError::Malformed { id, label, op, children } => {
+                let node = node(id, label);
+                write!(f, "backward through {} ({}): the op doesn't take {} children", node, op, children)
+            }
+            BackwardError::StaleGradients { leaves } => {
+                write!(f, "backward over {} leaves still holding gradients from an earlier pass", leaves)
+            }
+            BackwardError::AssertionFailed { id, label, message, grad } => {
+                let node = node(id, label);
+                let message = format!("assertion `{}` failed in the backward pass at {}", message, node);
+                write!(f, "{}: grad {} is not finite", message, grad)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BackwardError {}
+
+// Errors that stop a `train::Trainer`: the loss couldn't be built or back-propagated, or it or the gradients
+// aren't finite, in which case the parameters are left as they were instead of being trained on NaN, or the
+// steps of an epoch built many more nodes than those of the previous one, see `Trainer::with_monitor`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrainError {
+    Grad(GradError),
+    Backward(BackwardError),
+    NodeGrowth(NodeGrowthError),
+}
+
+impl Display for TrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrainError::Grad(error) => write!(f, "training stopped: {}", error),
+            TrainError::Backward(error) => write!(f, "training stopped: {}", error),
+            TrainError::NodeGrowth(error) => write!(f, "training stopped: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for TrainError {}
+
+impl From<GradError> for TrainError {
+    fn from(error: GradError) -> TrainError {
+        TrainError::Grad(error)
+    }
+}
+
+impl From<BackwardError> for TrainError {
+    fn from(error: BackwardError) -> TrainError {
+        TrainError::Backward(error)
+    }
+}
+
+impl From<NodeGrowthError> for TrainError {
+    fn from(error: NodeGrowthError) -> TrainError {
+        TrainError::NodeGrowth(error)
+    }
+}
+
+// Errors reported by `CompiledGraph::bind` and `CompiledGraph::forward_bound` when leaves addressed by name
+// aren't in the graph or haven't been given data.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BindError {
+    // no leaf of the graph is labeled `name`
+    UnknownLeaf { name: String },
+    // the labeled leaves that haven't been bound, in the order of `CompiledGraph::leaves`
+    Unbound { names: Vec<String> },
+}
+
+impl Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindError::UnknownLeaf { name } => write!(f, "no leaf of the graph is labeled `{}`", name),
+            BindError::Unbound { names } => write!(f, "unbound leaves: {}", names.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for BindError {}
+
+// Errors reported by `engine::replace_subgraph` and `replace_all_subgraphs` when the label doesn't pick the
+// nodes to replace.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplaceError {
+    // no node of the graph has the label
+    NotFound { label: String },
+    // several nodes have the label, where a single one was to be replaced
+    Ambiguous { label: String, count: usize },
+}
+
+impl Display for ReplaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplaceError::NotFound { label } => write!(f, "no node of the graph is labelled `{}`", label),
+            ReplaceError::Ambiguous { label, count } => {
+                let advice = "use replace_all_subgraphs to replace them all";
+                write!(f, "{} nodes are labelled `{}`: {}", count, label, advice)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplaceError {}
+
+// Reported by `train::StepMonitor::end_epoch` when the steps of an epoch build or hold many more nodes than those
+// of the previous one, which usually means that each step keeps the graph of the one before alive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeGrowthError {
+    // what grew, "nodes created" or "peak live nodes", per step on average
+    pub metric: &'static str,
+    // the epoch that just ended
+    pub epoch: usize,
+    pub previous: f64,
+    pub current: f64,
+    // the growth limit that was exceeded
+    pub factor: f64,
+}
+
+impl Display for NodeGrowthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} per step grew from {} to {} in epoch {}, more than {} times",
+            self.metric, self.previous, self.current, self.epoch, self.factor
+        )
+    }
+}
+
+impl std::error::Error for NodeGrowthError {}
+
+// Reported by `engine::jacobian_check` for the first entry of a Jacobian where back-propagation and finite
+// differences disagree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JacobianCheckFailure {
+    // the row of the entry, the position of the output
+    pub output: usize,
+    // the column of the entry, the position of the input
+    pub input: usize,
+    pub label: Option<String>,
+    pub analytic: f64,
+    pub numeric: f64,
+}
+
+impl Display for JacobianCheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "output {} with respect to input {}", self.output, self.input)?;
+        if let Some(label) = &self.label {
+            write!(f, " ({})", label)?;
+        }
+        write!(f, ": backward gives {} but finite differences give {}", self.analytic, self.numeric)
+    }
+}
+
+impl std::error::Error for JacobianCheckFailure {}
+
+// Errors reported by `engine::register_unary` and `register_binary`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RegisterError {
+    // the name is already registered with other functions, which it keeps; `arity` is their number of inputs
+    AlreadyRegistered { name: &'static str, arity: usize },
+}
+
+impl Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::AlreadyRegistered { name, arity } => {
+                write!(f, "custom op `{}` is already registered with other functions of {} inputs", name, arity)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegisterError {}
+
+// Errors reported by `Value::to_bytes` for graphs the binary format can't hold.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EncodeError {
+    // the node at `node` in topological order is an output of `engine::checkpoint`, whose segment is a closure
+    Checkpoint { node: usize },
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::Checkpoint { node } => {
+                write!(f, "node {} is a checkpointed segment, which can't be serialized", node)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+// Errors reported by `Value::from_bytes` for input that isn't a graph written by `Value::to_bytes`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    // the input doesn't start with the magic bytes of the format
+    NotAGraph,
+    // the input was written by a version of the format this one can't read
+    UnsupportedVersion { version: u8 },
+    // the input ends in the middle of the graph
+    Truncated,
+    // the checksum doesn't match the contents, which have been corrupted
+    ChecksumMismatch,
+    // the contents don't describe a valid graph, e.g. a node with an unknown op
+    Malformed { message: String },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::NotAGraph => write!(f, "not a serialized graph"),
+            DecodeError::UnsupportedVersion { version } => write!(f, "unsupported format version {}", version),
+            DecodeError::Truncated => write!(f, "the serialized graph is truncated"),
+            DecodeError::ChecksumMismatch => {
+                write!(f, "the checksum doesn't match: the serialized graph is corrupted")
+            }
+            DecodeError::Malformed { message } => write!(f, "malformed graph: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
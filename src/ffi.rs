@@ -0,0 +1,229 @@
+// A C interface to the engine, for embedding it in other languages; `include/angstromgrad.h` declares it.
+//
+// Nodes are reached through opaque handles. Every function returning a handle gives the caller a new one,
+// which the caller owns and must release with `ag_free_graph` exactly once; a handle keeps its node, and the
+// graph below it, alive, so nodes are freed once no handle and no parent refers to them. Handles can only be
+// used on the thread that created them, like the nodes themselves.
+//
+// Nothing panics across the boundary, and handles are looked up before use: null, released or foreign
+// pointers, and handles from another thread, are reported as `AG_INVALID_HANDLE` (or a null handle) rather
+// than dereferenced. The address of a released handle may be reused for a later one, after which it is taken for
+// that handle: released handles must still not be used.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::engine::Value;
+
+/// A handle to a node.
+pub struct AgValue {
+    value: Value,
+}
+
+/// The outcome of a call that doesn't return a handle.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AgStatus {
+    Ok = 0,
+    /// A handle was null, already released, or not created by this library on this thread.
+    InvalidHandle = 1,
+    /// An output pointer was null.
+    NullPointer = 2,
+    /// The graph couldn't be back-propagated, see `BackwardError`.
+    BackwardFailed = 3,
+    /// The engine panicked; the call had no effect that can be relied on.
+    Panic = 4,
+}
+
+thread_local! {
+    // the handles created on this thread and not yet released
+    static HANDLES: RefCell<HashSet<usize>> = RefCell::new(HashSet::new());
+}
+
+fn new_handle(value: Value) -> *mut AgValue {
+    let handle = Box::into_raw(Box::new(AgValue { value }));
+    HANDLES.with(|handles| handles.borrow_mut().insert(handle as usize));
+    handle
+}
+
+// The node of a live handle.
+fn node(handle: *const AgValue) -> Option<Value> {
+    let live = HANDLES.with(|handles| handles.borrow().contains(&(handle as usize)));
+    // SAFETY: live handles were created by `new_handle` and not yet released
+    live.then(|| unsafe { (*handle).value.clone() })
+}
+
+// Runs `f`, making a panic into `on_panic`.
+fn guarded<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+fn unary(a: *const AgValue, op: fn(&Value) -> Value) -> *mut AgValue {
+    guarded(ptr::null_mut(), || node(a).map_or(ptr::null_mut(), |a| new_handle(op(&a))))
+}
+
+fn binary(a: *const AgValue, b: *const AgValue, op: fn(&Value, &Value) -> Value) -> *mut AgValue {
+    guarded(ptr::null_mut(), || match (node(a), node(b)) {
+        (Some(a), Some(b)) => new_handle(op(&a, &b)),
+        _ => ptr::null_mut(),
+    })
+}
+
+// SAFETY: `out` is null or valid for writing a double
+unsafe fn read(handle: *const AgValue, out: *mut f64, f: fn(&Value) -> f64) -> AgStatus {
+    guarded(AgStatus::Panic, || {
+        let Some(value) = node(handle) else {
+            return AgStatus::InvalidHandle;
+        };
+        if out.is_null() {
+            return AgStatus::NullPointer;
+        }
+        unsafe { *out = f(&value) };
+        AgStatus::Ok
+    })
+}
+
+/// A new leaf holding `data`.
+#[no_mangle]
+pub extern "C" fn ag_value_new(data: f64) -> *mut AgValue {
+    guarded(ptr::null_mut(), || new_handle(Value::from(data)))
+}
+
+/// The node `a + b`, or null if either handle is invalid.
+#[no_mangle]
+pub extern "C" fn ag_add(a: *const AgValue, b: *const AgValue) -> *mut AgValue {
+    binary(a, b, |a, b| a + b)
+}
+
+/// The node `a * b`, or null if either handle is invalid.
+#[no_mangle]
+pub extern "C" fn ag_mul(a: *const AgValue, b: *const AgValue) -> *mut AgValue {
+    binary(a, b, |a, b| a * b)
+}
+
+/// The node `tanh(a)`, or null if the handle is invalid.
+#[no_mangle]
+pub extern "C" fn ag_tanh(a: *const AgValue) -> *mut AgValue {
+    unary(a, Value::tanh)
+}
+
+/// Back-propagates from the node, see `Value::backward`.
+#[no_mangle]
+pub extern "C" fn ag_backward(a: *const AgValue) -> AgStatus {
+    guarded(AgStatus::Panic, || match node(a) {
+        Some(value) => match value.backward() {
+            Ok(()) => AgStatus::Ok,
+            Err(_) => AgStatus::BackwardFailed,
+        },
+        None => AgStatus::InvalidHandle,
+    })
+}
+
+/// Writes the data of the node to `out`.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writing a double.
+#[no_mangle]
+pub unsafe extern "C" fn ag_data(a: *const AgValue, out: *mut f64) -> AgStatus {
+    read(a, out, Value::data)
+}
+
+/// Writes the grad of the node to `out`.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writing a double.
+#[no_mangle]
+pub unsafe extern "C" fn ag_grad(a: *const AgValue, out: *mut f64) -> AgStatus {
+    read(a, out, Value::grad)
+}
+
+/// Releases the handle, and with it the nodes that no other handle or node refers to. Releasing a handle twice
+/// is reported as `AG_INVALID_HANDLE`.
+// not `unsafe`: the handle is only dereferenced once found among the live handles
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn ag_free_graph(a: *mut AgValue) -> AgStatus {
+    guarded(AgStatus::Panic, || {
+        if !HANDLES.with(|handles| handles.borrow_mut().remove(&(a as usize))) {
+            return AgStatus::InvalidHandle;
+        }
+        // SAFETY: the handle was live, so it came from `Box::into_raw` and hasn't been freed
+        drop(unsafe { Box::from_raw(a) });
+        AgStatus::Ok
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{set_accumulation_policy, AccumulationPolicy};
+
+    fn grad(handle: *const AgValue) -> f64 {
+        let mut out = f64::NAN;
+        assert_eq!(unsafe { ag_grad(handle, &mut out) }, AgStatus::Ok);
+        out
+    }
+
+    #[test]
+    fn the_c_interface_back_propagates() {
+        let (x, y) = (ag_value_new(2.0), ag_value_new(3.0));
+        let product = ag_mul(y, x);
+        let z = ag_add(x, product);
+        let t = ag_tanh(x);
+        assert_eq!(ag_backward(z), AgStatus::Ok);
+
+        let mut data = 0.0;
+        assert_eq!(unsafe { ag_data(z, &mut data) }, AgStatus::Ok);
+        assert_eq!((data, grad(x), grad(y)), (8.0, 4.0, 2.0));
+        assert_eq!(unsafe { ag_data(t, &mut data) }, AgStatus::Ok);
+        assert_eq!(data, 2f64.tanh());
+
+        // x and the product are released, but z still holds them
+        assert_eq!(ag_free_graph(product), AgStatus::Ok);
+        assert_eq!(ag_free_graph(x), AgStatus::Ok);
+        assert_eq!(unsafe { ag_data(z, &mut data) }, AgStatus::Ok);
+        assert_eq!((data, ag_backward(z)), (8.0, AgStatus::Ok));
+        for handle in [y, z, t] {
+            assert_eq!(ag_free_graph(handle), AgStatus::Ok);
+        }
+        assert!(HANDLES.with(|handles| handles.borrow().is_empty()));
+    }
+
+    #[test]
+    fn invalid_handles_are_reported() {
+        // y is made first, so that it can't be given the address of the released x
+        let (x, y) = (ag_value_new(1.0), ag_value_new(1.0));
+        assert_eq!(ag_free_graph(x), AgStatus::Ok);
+        // released twice, used after release, and null
+        assert_eq!(ag_free_graph(x), AgStatus::InvalidHandle);
+        assert_eq!(ag_backward(x), AgStatus::InvalidHandle);
+        assert!(ag_tanh(x).is_null());
+        assert_eq!(ag_free_graph(ptr::null_mut()), AgStatus::InvalidHandle);
+        assert!(ag_add(ptr::null(), ptr::null()).is_null());
+        let mut out = 0.0;
+        assert_eq!(unsafe { ag_data(ptr::null(), &mut out) }, AgStatus::InvalidHandle);
+
+        assert!(ag_mul(y, x).is_null());
+        assert_eq!(unsafe { ag_grad(y, ptr::null_mut()) }, AgStatus::NullPointer);
+        // a handle of another thread
+        let foreign = std::thread::spawn(|| ag_value_new(1.0) as usize).join().unwrap() as *mut AgValue;
+        assert_eq!(ag_backward(foreign), AgStatus::InvalidHandle);
+        assert_eq!(ag_free_graph(foreign), AgStatus::InvalidHandle);
+        assert_eq!(ag_free_graph(y), AgStatus::Ok);
+    }
+
+    #[test]
+    fn backward_errors_are_reported() {
+        let x = ag_value_new(1.0);
+        let y = ag_tanh(x);
+        set_accumulation_policy(AccumulationPolicy::Error);
+        assert_eq!(ag_backward(y), AgStatus::Ok);
+        assert_eq!(ag_backward(y), AgStatus::BackwardFailed);
+        set_accumulation_policy(AccumulationPolicy::Accumulate);
+        assert_eq!((ag_free_graph(y), ag_free_graph(x)), (AgStatus::Ok, AgStatus::Ok));
+    }
+}
@@ -0,0 +1,210 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::engine::{binarize, clamp_passes, sigmoid, softplus, ste_passes, NodeMap, Op, Value};
+
+/// A dual number `val + eps·ε` with `ε² = 0`: evaluating a function on duals gives its value in `val`
+/// and its directional derivative along the seeded tangents in `eps`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dual {
+    pub val: f64,
+    pub eps: f64,
+}
+
+impl Dual {
+    /// A dual carrying the value `val` with tangent `eps`.
+    pub fn new(val: f64, eps: f64) -> Dual {
+        Dual { val, eps }
+    }
+
+    /// A dual whose tangent is zero, i.e. a value that doesn't depend on the seeded inputs.
+    pub fn constant(val: f64) -> Dual {
+        Dual { val, eps: 0.0 }
+    }
+
+    pub fn pow(self, other: Dual) -> Dual {
+        let val = self.val.powf(other.val);
+        let mut eps = other.val * self.val.powf(other.val - 1.0) * self.eps;
+        // only involve ln(base) when the exponent actually varies, so that negative bases
+        // with a constant exponent keep a finite tangent
+        if other.eps != 0.0 {
+            eps += val * self.val.ln() * other.eps;
+        }
+        Dual { val, eps }
+    }
+
+    pub fn tanh(self) -> Dual {
+        let val = self.val.tanh();
+        Dual { val, eps: (1.0 - val * val) * self.eps }
+    }
+
+    pub fn exp(self) -> Dual {
+        let val = self.val.exp();
+        Dual { val, eps: val * self.eps }
+    }
+
+    pub fn ln(self) -> Dual {
+        Dual { val: self.val.ln(), eps: self.eps / self.val }
+    }
+
+    pub fn softplus(self) -> Dual {
+        Dual { val: softplus(self.val), eps: sigmoid(self.val) * self.eps }
+    }
+
+    pub fn relu(self) -> Dual {
+        if self.val > 0.0 {
+            self
+        } else {
+            Dual::constant(0.0)
+        }
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, other: Dual) -> Dual {
+        Dual { val: self.val + other.val, eps: self.eps + other.eps }
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub(self, other: Dual) -> Dual {
+        Dual { val: self.val - other.val, eps: self.eps - other.eps }
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, other: Dual) -> Dual {
+        Dual {
+            val: self.val * other.val,
+            eps: self.eps * other.val + self.val * other.eps,
+        }
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div(self, other: Dual) -> Dual {
+        Dual {
+            val: self.val / other.val,
+            eps: (self.eps * other.val - self.val * other.eps) / (other.val * other.val),
+        }
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg(self) -> Dual {
+        Dual { val: -self.val, eps: -self.eps }
+    }
+}
+
+impl Op {
+    // Same as `Op::forward`, in dual arithmetic.
+    pub(crate) fn forward_dual(&self, inputs: &[Dual]) -> Dual {
+        match self {
+            Op::Add | Op::SumCompensated => inputs.iter().fold(Dual::constant(0.0), |acc, &x| acc + x),
+            Op::Mul => inputs.iter().fold(Dual::constant(1.0), |acc, &x| acc * x),
+            Op::Pow => inputs[0].pow(inputs[1]),
+            Op::Tanh => inputs[0].tanh(),
+            Op::Exp => inputs[0].exp(),
+            Op::Ln => inputs[0].ln(),
+            Op::Relu => inputs[0].relu(),
+            Op::Softplus => inputs[0].softplus(),
+            Op::RoundSte => Dual { val: inputs[0].val.round(), eps: inputs[0].eps },
+            Op::Assert => inputs[0],
+            Op::BinarizeSte => {
+                let (x, threshold) = (inputs[0].val, inputs[1].val);
+                let eps = if ste_passes(x, threshold) { inputs[0].eps } else { 0.0 };
+                Dual { val: binarize(x, threshold), eps }
+            }
+            Op::Clamp => {
+                let (x, lo, hi) = (inputs[0].val, inputs[1].val, inputs[2].val);
+                let eps = if clamp_passes(x, lo, hi) { inputs[0].eps } else { 0.0 };
+                Dual { val: x.clamp(lo, hi), eps }
+            }
+            Op::Custom(op) => {
+                let values: Vec<f64> = inputs.iter().map(|input| input.val).collect();
+                let val = op.forward(&values);
+                let slopes = op.backward(&values, val, 1.0);
+                let eps = std::iter::zip(slopes, inputs).map(|(slope, input)| slope * input.eps).sum();
+                Dual { val, eps }
+            }
+            Op::Checkpoint(segment) => segment.forward_dual(inputs),
+        }
+    }
+}
+
+impl Value {
+    /// Re-evaluates the graph rooted at `self` in dual arithmetic and returns the Jacobian-vector product,
+    /// i.e. the derivative of `self` when each leaf of `seed` moves along its given tangent.
+    ///
+    /// Leaves that are not seeded are treated as constants. Neither `data` nor `grad` of any node is modified.
+    pub fn eval_jvp(&self, seed: &[(Value, f64)]) -> f64 {
+        let mut tangents = NodeMap::new();
+        for (v, t) in seed {
+            tangents.insert(v, *t);
+        }
+        let mut duals = NodeMap::new();
+
+        for value in self.topo_order() {
+            let node = value.borrow();
+            let dual = match node._op {
+                Some(op) => {
+                    let inputs: Vec<Dual> = node._prev.iter().map(|child| duals[child]).collect();
+                    op.forward_dual(&inputs)
+                }
+                None => Dual::new(node.data, tangents.get(&value).copied().unwrap_or(0.0)),
+            };
+            drop(node);
+            duals.insert(&value, dual);
+        }
+
+        duals[self].eps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::approx_eq;
+
+    // a scalar function of two leaves going through most ops
+    fn example(x: &Value, y: &Value) -> Value {
+        let z = &(&x.tanh() * y) + &x.exp();
+        &(&z.powi(3) / &y.softplus()) + &(&(x * x) + &Value::from(1.0)).ln()
+    }
+
+    #[test]
+    fn jvp_agrees_with_reverse_mode() {
+        let (x, y) = (Value::from(0.4), Value::from(-1.3));
+        let root = example(&x, &y);
+        root.backward().unwrap();
+
+        for (tx, ty) in [(1.0, 0.0), (0.0, 1.0), (0.5, -2.0)] {
+            let jvp = root.eval_jvp(&[(x.clone(), tx), (y.clone(), ty)]);
+            let reverse = tx * x.grad() + ty * y.grad();
+            assert!(approx_eq(jvp, reverse, 1e-12, 1e-12), "{} against {}", jvp, reverse);
+        }
+    }
+
+    #[test]
+    fn unseeded_leaves_are_constants() {
+        let (x, y) = (Value::from(2.0), Value::from(5.0));
+        let root = &x * &y;
+        assert_eq!(root.eval_jvp(&[(x.clone(), 1.0)]), 5.0);
+        assert_eq!(root.eval_jvp(&[]), 0.0);
+        assert_eq!((x.grad(), root.data()), (0.0, 10.0));
+    }
+
+    #[test]
+    fn dual_arithmetic() {
+        let x = Dual::new(3.0, 1.0);
+        assert_eq!(x * x, Dual::new(9.0, 6.0));
+        assert_eq!(Dual::constant(1.0) / x, Dual::new(1.0 / 3.0, -1.0 / 9.0));
+        assert_eq!(x.pow(Dual::constant(2.0)), Dual::new(9.0, 6.0));
+        assert_eq!(Dual::new(-1.0, 1.0).relu(), Dual::constant(0.0));
+        assert_eq!(x.ln(), Dual::new(3.0f64.ln(), 1.0 / 3.0));
+    }
+}
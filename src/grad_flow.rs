@@ -0,0 +1,138 @@
+use crate::engine::Value;
+
+/// A node of the graph listed by `Value::grad_flow_report`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradFlowEntry {
+    pub id: u64,
+    pub label: Option<String>,
+    pub data: f64,
+    pub grad: f64,
+    pub is_leaf: bool,
+    pub issue: Option<GradFlowIssue>,
+}
+
+/// Why an entry of a `Value::grad_flow_report` deserves a look.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GradFlowIssue {
+    /// The leaf's grad is exactly zero: the loss doesn't depend on it, or not at this point (e.g. a dead relu).
+    /// A small but nonzero grad, like that of a saturated tanh, is not flagged.
+    ZeroGrad,
+    /// The leaf's grad is infinite or NaN.
+    NonFiniteGrad,
+    /// No backward pass has run through this interior node, so nothing below it can have received a gradient
+    /// from it, e.g. because `backward` was called on a different root.
+    NotPropagated,
+}
+
+impl Value {
+    /// After `backward`, lists every trainable leaf of the graph rooted at `self` (shared leaves once, frozen
+    /// ones and constants left out) with its label, data and grad, flagging zero and non-finite grads, followed
+    /// by the interior nodes that no backward pass has gone through.
+    pub fn grad_flow_report(&self) -> Vec<GradFlowEntry> {
+        let order = self.topo_order();
+        let leaves = order.iter().filter(|value| value.is_leaf() && value.requires_grad());
+        let unpropagated = order
+            .iter()
+            .filter(|value| !value.is_leaf() && !value.borrow().propagated);
+
+        leaves
+            .chain(unpropagated)
+            .map(|value| {
+                let node = value.borrow();
+                let issue = if !node._prev.is_empty() {
+                    Some(GradFlowIssue::NotPropagated)
+                } else if !node.grad.is_finite() {
+                    Some(GradFlowIssue::NonFiniteGrad)
+                } else if node.grad == 0.0 {
+                    Some(GradFlowIssue::ZeroGrad)
+                } else {
+                    None
+                };
+                GradFlowEntry {
+                    id: node.id,
+                    label: node.label.clone(),
+                    data: node.data,
+                    grad: node.grad,
+                    is_leaf: node._prev.is_empty(),
+                    issue,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issues(report: &[GradFlowEntry]) -> Vec<(Option<String>, Option<GradFlowIssue>)> {
+        report.iter().map(|entry| (entry.label.clone(), entry.issue)).collect()
+    }
+
+    fn leaf(data: f64, label: &str) -> Value {
+        Value::from(data).add_label(label)
+    }
+
+    #[test]
+    fn detached_branches_are_flagged() {
+        let (w, x, u) = (leaf(0.5, "w"), leaf(2.0, "x"), leaf(3.0, "u"));
+        // `u` only reaches the loss through a product with zero
+        let root = &(&w * &x) + &(&u.tanh() * &Value::constant(0.0));
+        root.backward().unwrap();
+        let report = root.grad_flow_report();
+        let label = |name: &str| Some(name.to_string());
+        assert_eq!(
+            issues(&report),
+            [(label("w"), None), (label("x"), None), (label("u"), Some(GradFlowIssue::ZeroGrad))]
+        );
+    }
+
+    #[test]
+    fn nodes_outside_the_backward_pass_are_flagged() {
+        let (w, x) = (leaf(0.5, "w"), leaf(2.0, "x"));
+        let hidden = &w * &x;
+        let root = hidden.tanh();
+        hidden.backward().unwrap();
+        let report = root.grad_flow_report();
+        let unpropagated: Vec<&GradFlowEntry> =
+            report.iter().filter(|entry| entry.issue == Some(GradFlowIssue::NotPropagated)).collect();
+        assert_eq!(unpropagated.len(), 1);
+        assert_eq!((unpropagated[0].id, unpropagated[0].is_leaf), (root.id().0, false));
+
+        let (w, x) = (leaf(0.5, "w"), leaf(2.0, "x"));
+        let fresh = (&w * &x).tanh();
+        let issues = issues(&fresh.grad_flow_report());
+        assert_eq!(issues.iter().filter(|(_, issue)| *issue == Some(GradFlowIssue::NotPropagated)).count(), 2);
+    }
+
+    #[test]
+    fn saturated_tanh_is_not_flagged() {
+        let w = leaf(4.0, "w");
+        let root = &(&w * &Value::constant(2.0)).tanh() + &Value::constant(0.0);
+        root.backward().unwrap();
+        let report = root.grad_flow_report();
+        assert_eq!(report.len(), 1);
+        assert!(report[0].grad > 0.0 && report[0].grad < 1e-5);
+        assert_eq!(report[0].issue, None);
+    }
+
+    #[test]
+    fn non_finite_grads_are_flagged() {
+        let x = leaf(0.0, "x");
+        let root = x.sqrt();
+        root.backward_unchecked();
+        assert_eq!(root.grad_flow_report()[0].issue, Some(GradFlowIssue::NonFiniteGrad));
+    }
+
+    #[test]
+    fn shared_leaves_are_listed_once() {
+        let (w, x) = (leaf(0.5, "w"), leaf(2.0, "x"));
+        let root = &(&(&w * &x) * &w) + &w.exp();
+        root.backward().unwrap();
+        let report = root.grad_flow_report();
+        assert_eq!(report.iter().filter(|entry| entry.id == w.id().0).count(), 1);
+        assert_eq!(report.len(), 2);
+        let entry = report.iter().find(|entry| entry.id == w.id().0).unwrap();
+        assert_eq!((entry.data, entry.grad), (0.5, 2.0 * 0.5 * 2.0 + 0.5f64.exp()));
+    }
+}
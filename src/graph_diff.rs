@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use crate::engine::{NodeId, Op, Value};
+
+/// What `graph_diff_with_options` compares besides the structure of the graphs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffOptions {
+    /// The largest difference between the data of two leaves that are still considered the same.
+    pub tolerance: f64,
+    /// Whether the labels of the nodes have to match too.
+    pub compare_labels: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        DiffOptions { tolerance: 1e-12, compare_labels: false }
+    }
+}
+
+/// The result of `graph_diff`: the first place where two graphs differ, if any.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphDiff {
+    pub divergence: Option<Divergence>,
+}
+
+impl GraphDiff {
+    /// Whether the graphs are the same.
+    pub fn is_empty(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+/// Two nodes at the same place in both graphs that don't match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// The positions of the children taken from the roots down to the nodes, empty for the roots themselves.
+    pub path: Vec<usize>,
+    pub kind: DivergenceKind,
+}
+
+/// How the nodes of a `Divergence` differ. In each variant `left` is about the first graph, `right` the second.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DivergenceKind {
+    /// The nodes weren't built by the same op, or only one of them is a leaf (`None`).
+    Op { left: Option<Op>, right: Option<Op> },
+    /// The nodes were built by the same op from a different number of children, e.g. two `ops::add_n`.
+    ChildCount { left: usize, right: usize },
+    /// The data of the leaves differs by more than the tolerance.
+    LeafData { left: f64, right: f64 },
+    /// The labels of the nodes differ, when they are compared.
+    Label { left: Option<String>, right: Option<String> },
+    /// One graph uses the same node here as at another place where the other graph has a different node, e.g.
+    /// `x + x` against `x + y` for two leaves of the same data: the graphs compute the same values but don't
+    /// accumulate the same gradients.
+    Sharing,
+}
+
+impl Display for GraphDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.divergence {
+            Some(divergence) => write!(f, "{}", divergence),
+            None => write!(f, "the graphs are the same"),
+        }
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut at = "root".to_string();
+        for child in &self.path {
+            at += &format!("/{}", child);
+        }
+        let op = |op: &Option<Op>| op.map_or("leaf".to_string(), |op| op.to_string());
+        match &self.kind {
+            DivergenceKind::Op { left, right } => write!(f, "at {}: {} against {}", at, op(left), op(right)),
+            DivergenceKind::ChildCount { left, right } => {
+                write!(f, "at {}: {} children against {}", at, left, right)
+            }
+            DivergenceKind::LeafData { left, right } => {
+                write!(f, "at {}: leaf data {} against {} (difference {})", at, left, right, left - right)
+            }
+            DivergenceKind::Label { left, right } => write!(f, "at {}: label {:?} against {:?}", at, left, right),
+            DivergenceKind::Sharing => write!(f, "at {}: a node is shared in one graph only", at),
+        }
+    }
+}
+
+/// Compares the graphs rooted at `a` and `b` with the default options, see `graph_diff_with_options`.
+pub fn graph_diff(a: &Value, b: &Value) -> GraphDiff {
+    graph_diff_with_options(a, b, &DiffOptions::default())
+}
+
+/// Compares the graphs rooted at `a` and `b` node by node and reports the first place where they differ, e.g. to
+/// check that a refactored model builds the same graph as before.
+///
+/// Both graphs are walked together from the roots, depth first, children in the order they are stored in;
+/// matching nodes must have the same op, the same number of children and, for leaves, data within the tolerance.
+/// The data of interior nodes, grads and ids are not compared. Shared nodes are compared once, and must be
+/// shared the same way in both graphs.
+pub fn graph_diff_with_options(a: &Value, b: &Value, options: &DiffOptions) -> GraphDiff {
+    // the node of the other graph each node was matched with
+    let mut left_to_right: HashMap<NodeId, NodeId> = HashMap::new();
+    let mut right_to_left: HashMap<NodeId, NodeId> = HashMap::new();
+    let mut stack = vec![(a.clone(), b.clone(), Vec::new())];
+
+    while let Some((a, b, path)) = stack.pop() {
+        let matched = (left_to_right.get(&a.id()), right_to_left.get(&b.id()));
+        match matched {
+            (Some(&right), Some(&left)) if right == b.id() && left == a.id() => continue,
+            (None, None) => {}
+            _ => return GraphDiff { divergence: Some(Divergence { path, kind: DivergenceKind::Sharing }) },
+        }
+        left_to_right.insert(a.id(), b.id());
+        right_to_left.insert(b.id(), a.id());
+
+        if let Some(kind) = compare(&a, &b, options) {
+            return GraphDiff { divergence: Some(Divergence { path, kind }) };
+        }
+        let children = std::iter::zip(a.children(), b.children()).enumerate();
+        for (i, (a, b)) in children.rev() {
+            let mut child_path = path.clone();
+            child_path.push(i);
+            stack.push((a, b, child_path));
+        }
+    }
+    GraphDiff { divergence: None }
+}
+
+// How two nodes differ, leaving their children aside
+fn compare(a: &Value, b: &Value, options: &DiffOptions) -> Option<DivergenceKind> {
+    let (left, right) = (a.borrow(), b.borrow());
+    if left._op != right._op {
+        return Some(DivergenceKind::Op { left: left._op, right: right._op });
+    }
+    if left._prev.len() != right._prev.len() {
+        return Some(DivergenceKind::ChildCount { left: left._prev.len(), right: right._prev.len() });
+    }
+    let close = left.data.to_bits() == right.data.to_bits() || (left.data - right.data).abs() <= options.tolerance;
+    if left._op.is_none() && !close {
+        return Some(DivergenceKind::LeafData { left: left.data, right: right.data });
+    }
+    if options.compare_labels && left.label != right.label {
+        return Some(DivergenceKind::Label { left: left.label.clone(), right: right.label.clone() });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops;
+
+    // `tanh(w·x + b)·c`, as built by two different paths when `activation` is the same
+    fn neuron(x: f64, activation: fn(&Value) -> Value) -> Value {
+        let (w, b, c) = (Value::from(0.5), Value::from(-0.1), Value::from(2.0));
+        &activation(&(&(&w * &Value::from(x)) + &b)) * &c
+    }
+
+    fn divergence(diff: GraphDiff) -> Divergence {
+        diff.divergence.expect("the graphs differ")
+    }
+
+    #[test]
+    fn identical_constructions_have_an_empty_diff() {
+        let diff = graph_diff(&neuron(1.5, Value::tanh), &neuron(1.5, Value::tanh));
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "the graphs are the same");
+        let a = Value::from(1.0);
+        assert!(graph_diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn a_different_op_is_reported_at_its_path() {
+        let diff = divergence(graph_diff(&neuron(1.5, Value::tanh), &neuron(1.5, Value::relu)));
+        // the activation is the first child of the product at the root
+        assert_eq!(diff.path, [0]);
+        assert_eq!(diff.kind, DivergenceKind::Op { left: Some(Op::Tanh), right: Some(Op::Relu) });
+        assert_eq!(diff.to_string(), "at root/0: tanh against relu");
+
+        let leaf = Value::from(0.5);
+        let diff = divergence(graph_diff(&leaf.exp(), &leaf));
+        assert_eq!((diff.path, diff.kind), (vec![], DivergenceKind::Op { left: Some(Op::Exp), right: None }));
+    }
+
+    #[test]
+    fn leaf_data_beyond_the_tolerance_is_reported_with_both_values() {
+        let diff = divergence(graph_diff(&neuron(1.5, Value::tanh), &neuron(1.25, Value::tanh)));
+        // root/0 is the tanh, root/0/0 the sum, root/0/0/0 the product and x its second child
+        assert_eq!(diff.path, [0, 0, 0, 1]);
+        assert_eq!(diff.kind, DivergenceKind::LeafData { left: 1.5, right: 1.25 });
+        assert_eq!(diff.to_string(), "at root/0/0/0/1: leaf data 1.5 against 1.25 (difference 0.25)");
+
+        let close = graph_diff(&neuron(1.5, Value::tanh), &neuron(1.5 + 1e-13, Value::tanh));
+        assert!(close.is_empty());
+        let options = DiffOptions { tolerance: 0.5, ..DiffOptions::default() };
+        let loose = graph_diff_with_options(&neuron(1.5, Value::tanh), &neuron(1.25, Value::tanh), &options);
+        assert!(loose.is_empty());
+    }
+
+    #[test]
+    fn sums_of_a_different_number_of_terms() {
+        let xs: Vec<Value> = (0..3).map(|i| Value::from(i as f64)).collect();
+        let diff = divergence(graph_diff(&ops::add_n(&xs), &ops::add_n(&xs[..2])));
+        assert_eq!(diff.to_string(), "at root: 3 children against 2");
+        assert_eq!((diff.path, diff.kind), (vec![], DivergenceKind::ChildCount { left: 3, right: 2 }));
+    }
+
+    #[test]
+    fn labels_are_compared_only_when_asked() {
+        let (a, b) = (Value::from(1.0).add_label("x"), Value::from(1.0).add_label("y"));
+        assert!(graph_diff(&a.tanh(), &b.tanh()).is_empty());
+        let options = DiffOptions { compare_labels: true, ..DiffOptions::default() };
+        let diff = divergence(graph_diff_with_options(&a.tanh(), &b.tanh(), &options));
+        let labels = DivergenceKind::Label { left: Some("x".to_string()), right: Some("y".to_string()) };
+        assert_eq!((diff.path, diff.kind), (vec![0], labels));
+    }
+
+    #[test]
+    fn shared_nodes_must_be_shared_in_both_graphs() {
+        let (x, y, z) = (Value::from(2.0), Value::from(2.0), Value::from(2.0));
+        let diff = divergence(graph_diff(&(&x * &x), &(&y * &z)));
+        assert_eq!((diff.path, diff.kind), (vec![1], DivergenceKind::Sharing));
+        assert!(graph_diff(&(&x * &x), &(&y * &y)).is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+use crate::engine::{NodeMap, Op, Value};
+
+// How tightly a rendered expression binds, used to decide where parentheses are needed.
+const SUM: u8 = 1;
+const NEGATION: u8 = 2;
+const PRODUCT: u8 = 3;
+const POWER: u8 = 4;
+const ATOM: u8 = 5;
+
+impl Value {
+    /// Renders the expression computing `self` as LaTeX: `\frac` for divisions, superscripts for powers,
+    /// `\tanh`, `\ln`, `e^{...}` and `\operatorname{relu}` for the unary ops, and labels (or data) for leaves.
+    ///
+    /// Interior nodes used more than once are never expanded inline, which would repeat them exponentially
+    /// in deep shared graphs; they are defined once, named by their label or `t_{i}`, in an `aligned` block
+    /// that ends with the expression itself.
+    pub fn to_latex(&self) -> String {
+        let order = self.topo_order();
+
+        let mut uses: NodeMap<usize> = NodeMap::new();
+        for value in &order {
+            for child in &value.borrow()._prev {
+                *uses.entry(child).or_default() += 1;
+            }
+        }
+
+        let mut renderer = LatexRenderer { names: NodeMap::new() };
+        let mut definitions = Vec::new();
+        for value in &order {
+            let shared = uses.get(value).copied().unwrap_or(0) > 1;
+            if shared && !value.borrow()._prev.is_empty() {
+                let (body, _) = renderer.expand(value);
+                let name = value
+                    .borrow()
+                    .label
+                    .clone()
+                    .unwrap_or_else(|| format!("t_{{{}}}", definitions.len() + 1));
+                definitions.push(format!("{} &= {}", name, body));
+                renderer.names.insert(value, name);
+            }
+        }
+
+        let (body, _) = renderer.expand(self);
+        if definitions.is_empty() {
+            return body;
+        }
+        let name = self.borrow().label.clone().unwrap_or_else(|| "f".to_string());
+        definitions.push(format!("{} &= {}", name, body));
+        format!("\\begin{{aligned}}\n{}\n\\end{{aligned}}", definitions.join(" \\\\\n"))
+    }
+}
+
+struct LatexRenderer {
+    // nodes that have been given a definition and are referred to by name
+    names: NodeMap<String>,
+}
+
+impl LatexRenderer {
+    fn render(&self, value: &Value) -> (String, u8) {
+        match self.names.get(value) {
+            Some(name) => (name.clone(), ATOM),
+            None => self.expand(value),
+        }
+    }
+
+    fn is_named(&self, value: &Value) -> bool {
+        self.names.contains(value)
+    }
+
+    // `value` wrapped in parentheses if it binds less tightly than `rank`
+    fn operand(&self, value: &Value, rank: u8) -> String {
+        let (text, own) = self.render(value);
+        if own < rank {
+            format!("\\left({}\\right)", text)
+        } else {
+            text
+        }
+    }
+
+    fn expand(&self, value: &Value) -> (String, u8) {
+        let node = value.borrow();
+        let children = &node._prev;
+        let Some(op) = node._op else {
+            return leaf(node.label.as_deref(), node.data);
+        };
+
+        match op {
+            Op::Add | Op::SumCompensated => {
+                let mut text = self.operand(&children[0], SUM);
+                for child in &children[1..] {
+                    match negated(child).filter(|_| !self.is_named(child)) {
+                        Some(inner) => text += &format!(" - {}", self.operand(&inner, PRODUCT)),
+                        None => text += &format!(" + {}", self.operand(child, SUM)),
+                    }
+                }
+                (text, SUM)
+            }
+            Op::Mul => {
+                if let [x, minus_one] = &children[..] {
+                    // a negation `-x` is built as `x * -1` and a division `x / y` as `x * y^-1`
+                    if is_constant(minus_one, -1.0) {
+                        return (format!("-{}", self.operand(x, PRODUCT)), NEGATION);
+                    }
+                    if let Some(denominator) = reciprocal(minus_one).filter(|_| !self.is_named(minus_one)) {
+                        let (numerator, _) = self.render(x);
+                        let (denominator, _) = self.render(&denominator);
+                        return (format!("\\frac{{{}}}{{{}}}", numerator, denominator), PRODUCT);
+                    }
+                }
+                let factors: Vec<String> = children.iter().map(|child| self.operand(child, PRODUCT)).collect();
+                (factors.join(" \\cdot "), PRODUCT)
+            }
+            Op::Pow => {
+                if is_constant(&children[1], -1.0) {
+                    let (denominator, _) = self.render(&children[0]);
+                    return (format!("\\frac{{1}}{{{}}}", denominator), PRODUCT);
+                }
+                let (exponent, _) = self.render(&children[1]);
+                (format!("{}^{{{}}}", self.operand(&children[0], ATOM), exponent), POWER)
+            }
+            Op::Exp => (format!("e^{{{}}}", self.render(&children[0]).0), POWER),
+            Op::Tanh => (format!("\\tanh\\left({}\\right)", self.render(&children[0]).0), ATOM),
+            Op::Ln => (format!("\\ln\\left({}\\right)", self.render(&children[0]).0), ATOM),
+            Op::Relu => (format!("\\operatorname{{relu}}\\left({}\\right)", self.render(&children[0]).0), ATOM),
+            Op::Softplus => (
+                format!("\\operatorname{{softplus}}\\left({}\\right)", self.render(&children[0]).0),
+                ATOM,
+            ),
+            Op::RoundSte => (format!("\\left\\lfloor {}\\right\\rceil", self.render(&children[0]).0), ATOM),
+            Op::BinarizeSte => {
+                let (x, _) = self.render(&children[0]);
+                let (threshold, _) = self.render(&children[1]);
+                (format!("\\mathbb{{1}}\\left[{} > {}\\right]", x, threshold), ATOM)
+            }
+            Op::Assert => self.render(&children[0]),
+            Op::Clamp => {
+                let args: Vec<String> = children.iter().map(|child| self.render(child).0).collect();
+                (format!("\\operatorname{{clamp}}\\left({}\\right)", args.join(", ")), ATOM)
+            }
+            Op::Custom(op) => {
+                let args: Vec<String> = children.iter().map(|child| self.render(child).0).collect();
+                let name = op.name().replace('_', "\\_");
+                (format!("\\operatorname{{{}}}\\left({}\\right)", name, args.join(", ")), ATOM)
+            }
+            Op::Checkpoint(segment) => {
+                let args: Vec<String> = children.iter().map(|child| self.render(child).0).collect();
+                let output = segment.output();
+                (format!("\\operatorname{{checkpoint}}_{{{}}}\\left({}\\right)", output, args.join(", ")), ATOM)
+            }
+        }
+    }
+}
+
+fn leaf(label: Option<&str>, data: f64) -> (String, u8) {
+    match label {
+        Some(label) => (label.to_string(), ATOM),
+        None if data < 0.0 => (format!("{}", data), NEGATION),
+        None => (format!("{}", data), ATOM),
+    }
+}
+
+fn is_constant(value: &Value, data: f64) -> bool {
+    let node = value.borrow();
+    node._prev.is_empty() && node.label.is_none() && node.data == data
+}
+
+// `x` for a node built as `-x`, i.e. `x * -1`
+fn negated(value: &Value) -> Option<Value> {
+    let node = value.borrow();
+    match (node._op, &node._prev[..]) {
+        (Some(Op::Mul), [x, minus_one]) if is_constant(minus_one, -1.0) => Some(x.clone()),
+        _ => None,
+    }
+}
+
+// `x` for a node built as `x ^ -1`, the right-hand side of a division
+fn reciprocal(value: &Value) -> Option<Value> {
+    let node = value.borrow();
+    match (node._op, &node._prev[..]) {
+        (Some(Op::Pow), [x, minus_one]) if is_constant(minus_one, -1.0) => Some(x.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(label: &str, data: f64) -> Value {
+        let value = Value::from(data);
+        value.borrow_mut().label = Some(label.to_string());
+        value
+    }
+
+    #[test]
+    fn golden_strings() {
+        let (x, y, z) = (leaf("x", 1.0), leaf("y", 2.0), leaf("z", 3.0));
+        let cases = [
+            (&x + &(&y * &z), r"x + y \cdot z"),
+            (&(&x + &y) * &z, r"\left(x + y\right) \cdot z"),
+            (&x - &(&y - &z), r"x - \left(y - z\right)"),
+            (&(&x / &y) / &(&z + &x), r"\frac{\frac{x}{y}}{z + x}"),
+            (&(&x + &y).powf(2.0).tanh() / &z.exp(), r"\frac{\tanh\left(\left(x + y\right)^{2}\right)}{e^{z}}"),
+            (x.pow(&y.pow(&z)), r"x^{y^{z}}"),
+            ((&x * &y).pow(&z), r"\left(x \cdot y\right)^{z}"),
+            (-&(&x + &y), r"-\left(x + y\right)"),
+            (
+                (&x.relu() + &y.ln()).softplus(),
+                r"\operatorname{softplus}\left(\operatorname{relu}\left(x\right) + \ln\left(y\right)\right)",
+            ),
+        ];
+        for (value, expected) in &cases {
+            assert_eq!(value.to_latex(), *expected);
+        }
+    }
+
+    #[test]
+    fn shared_nodes_are_defined_once() {
+        let (x, y) = (leaf("x", 1.0), leaf("y", 2.0));
+        let shared = (&x * &y).tanh();
+        let expected = "\\begin{aligned}\nt_{1} &= \\tanh\\left(x \\cdot y\\right) \\\\\n\
+                        f &= t_{1} + e^{t_{1}}\n\\end{aligned}";
+        assert_eq!((&shared + &shared.exp()).to_latex(), expected);
+
+        // 40 doublings would expand to 2^40 leaves inline
+        let mut value = x.clone();
+        for _ in 0..40 {
+            value = &value + &value;
+        }
+        let latex = value.to_latex();
+        assert_eq!(latex.matches("&=").count(), 40);
+        assert!(latex.len() < 40 * 30, "{}", latex);
+    }
+}
@@ -1,5 +1,84 @@
+mod macros;
+
+pub mod error;
+pub use crate::error::{
+    BackwardError, BindError, DecodeError, EncodeError, GradError, JacobianCheckFailure, NodeGrowthError,
+    RegisterError, ReplaceError, TrainError,
+};
+
 pub mod engine;
 pub use crate::engine::Value;
 
+pub mod ops;
+
+pub mod tensor;
+
+#[cfg(feature = "ndarray")]
+mod array;
+
+pub mod loss;
+
+pub mod metrics;
+
+pub mod train;
+
+pub mod optim;
+
+pub mod constraints;
+
+pub mod forward_diff;
+
+pub mod rand;
+pub use crate::rand::{seed, with_seed};
+
+pub mod data;
+
+mod parser;
+
+mod profile;
+
+mod rewrite;
+
+mod grad_flow;
+
+mod stats;
+
+mod tape;
+
+#[cfg(feature = "bench")]
+mod bench;
+
+mod latex;
+
+mod dot;
+
+mod graph_diff;
+
+mod custom;
+
+mod checkpoint;
+
+mod node_table;
+
+mod binary;
+
+#[cfg(feature = "serde")]
+mod serialize;
+
+#[cfg(feature = "test-suite")]
+pub mod conformance;
+
+#[cfg(feature = "proptest")]
+pub mod testing;
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
 pub mod nn;
-pub use crate::nn::{MLP, Neuron, Layer};
\ No newline at end of file
+pub use crate::nn::{MLP, Neuron, Layer, Linear, Embedding, SoftmaxClassifier, LayerNorm};
@@ -0,0 +1,381 @@
+use crate::engine::Value;
+use crate::ops;
+
+/// Binary cross-entropy of the probability `sigmoid(logit)` against `target` in [0, 1], computed from the logit
+/// as `softplus(logit) - target · logit`, which stays finite however large the logit.
+pub fn bce_with_logits(logit: &Value, target: f64) -> Value {
+    &logit.softplus() - &(logit * &Value::constant(target))
+}
+
+/// The mean of `bce_with_logits` over the outputs of a multi-label classifier, one target per output.
+pub fn multilabel_bce(logits: &[Value], targets: &[f64]) -> Value {
+    assert_eq!(logits.len(), targets.len(), "{} logits for {} targets", logits.len(), targets.len());
+    let terms: Vec<Value> = std::iter::zip(logits, targets)
+        .map(|(logit, &target)| bce_with_logits(logit, target))
+        .collect();
+    mean(&terms)
+}
+
+/// Like `multilabel_bce`, with each class's term scaled by its weight, e.g. to counter class imbalance.
+/// The mean is still taken over the number of classes.
+pub fn multilabel_bce_weighted(logits: &[Value], targets: &[f64], weights: &[f64]) -> Value {
+    assert_eq!(logits.len(), targets.len(), "{} logits for {} targets", logits.len(), targets.len());
+    assert_eq!(logits.len(), weights.len(), "{} logits for {} class weights", logits.len(), weights.len());
+    let terms: Vec<Value> = logits
+        .iter()
+        .zip(targets)
+        .zip(weights)
+        .map(|((logit, &target), &weight)| &bce_with_logits(logit, target) * &Value::constant(weight))
+        .collect();
+    mean(&terms)
+}
+
+/// Mean squared error of `preds` against `targets`, one target per prediction.
+pub fn mse(preds: &[Value], targets: &[f64]) -> Value {
+    assert_eq!(preds.len(), targets.len(), "{} predictions for {} targets", preds.len(), targets.len());
+    let terms: Vec<Value> = std::iter::zip(preds, targets)
+        .map(|(pred, &target)| (pred - &Value::constant(target)).powi(2))
+        .collect();
+    mean(&terms)
+}
+
+/// Like `mse`, with the squared error of sample `i` scaled by `weights[i]` and the mean taken over the sum of the
+/// weights, e.g. to rebalance classes without duplicating samples; see `weighted_mean`.
+pub fn mse_weighted(preds: &[Value], targets: &[f64], weights: &[f64]) -> Value {
+    assert_eq!(preds.len(), targets.len(), "{} predictions for {} targets", preds.len(), targets.len());
+    let terms: Vec<Value> = std::iter::zip(preds, targets)
+        .map(|(pred, &target)| (pred - &Value::constant(target)).powi(2))
+        .collect();
+    weighted_mean(&terms, weights)
+}
+
+/// Mean squared error of a model with several outputs, e.g. (x, y) coordinates: `preds[i][j]` is output `j` for
+/// sample `i`, and the mean is taken over both the samples and the outputs.
+///
+/// Panics unless every sample has a target and both hold the same number of outputs, the same for all samples.
+pub fn mse_multi(preds: &[Vec<Value>], targets: &[Vec<f64>]) -> Value {
+    let dims = multi_output_dims(preds, targets);
+    mse_multi_weighted(preds, targets, &vec![1.0; dims])
+}
+
+/// Like `mse_multi`, with the squared error of output `j` scaled by `weights[j]`, e.g. to balance outputs on
+/// different scales. The mean is still taken over the number of samples times the number of outputs.
+pub fn mse_multi_weighted(preds: &[Vec<Value>], targets: &[Vec<f64>], weights: &[f64]) -> Value {
+    let dims = multi_output_dims(preds, targets);
+    assert_eq!(dims, weights.len(), "{} outputs for {} output weights", dims, weights.len());
+    let terms: Vec<Value> = std::iter::zip(preds, targets)
+        .flat_map(|(pred, target)| {
+            pred.iter()
+                .zip(target)
+                .zip(weights)
+                .map(|((pred, &target), &weight)| {
+                    &(pred - &Value::constant(target)).powi(2) * &Value::constant(weight)
+                })
+        })
+        .collect();
+    mean(&terms)
+}
+
+// The number of outputs per sample of a multi-output batch, checking that the shapes agree.
+pub(crate) fn multi_output_dims<P, T>(preds: &[Vec<P>], targets: &[Vec<T>]) -> usize {
+    assert_eq!(preds.len(), targets.len(), "{} predictions for {} targets", preds.len(), targets.len());
+    let dims = preds.first().map_or(0, Vec::len);
+    for (i, (pred, target)) in std::iter::zip(preds, targets).enumerate() {
+        assert_eq!(pred.len(), dims, "sample {} has {} outputs, sample 0 has {}", i, pred.len(), dims);
+        assert_eq!(target.len(), dims, "sample {} has {} targets for {} outputs", i, target.len(), dims);
+    }
+    dims
+}
+
+/// Cross-entropy of the categorical distribution `softmax(logits)` against the class `target`, i.e. the negative
+/// log-probability `ln Σ exp(logits) - logits[target]`.
+///
+/// With `label_smoothing` ε > 0 the target is the one-hot distribution of `target` mixed with the uniform one,
+/// `(1 - ε)·onehot + ε/K` for `K` logits, which keeps a classifier from driving its logits apart without bound:
+/// the loss is `ln Σ exp(logits) - (1 - ε)·logits[target] - ε/K·Σ logits` and the gradient of each logit is
+/// its softmax probability minus its smoothed target. With ε = 0 the loss is the plain one, node for node.
+///
+/// The largest logit is subtracted inside the logarithm as a constant, so the loss stays finite however large the
+/// logits. Panics if `target` is not the index of a logit or `label_smoothing` is not within [0, 1].
+pub fn cross_entropy(logits: &[Value], target: usize, label_smoothing: f64) -> Value {
+    assert!(target < logits.len(), "target class {} for {} logits", target, logits.len());
+    assert!((0.0..=1.0).contains(&label_smoothing), "label smoothing of {} outside [0, 1]", label_smoothing);
+    let max = ops::max_of(logits).expect("there is at least one logit").data();
+    let shift = Value::constant(-max);
+    let exps: Vec<Value> = logits.iter().map(|logit| (logit + &shift).exp()).collect();
+    let log_sum = &ops::add_n(&exps).ln() - &shift;
+    if label_smoothing == 0.0 {
+        return &log_sum - &logits[target];
+    }
+    let uniform = &ops::add_n(logits) * &Value::constant(label_smoothing / logits.len() as f64);
+    let expected = &(&logits[target] * &Value::constant(1.0 - label_smoothing)) + &uniform;
+    &log_sum - &expected
+}
+
+/// The mean of the `cross_entropy` of every sample of a batch, the logits of sample `i` against the class
+/// `targets[i]`, with each scaled by `weights[i]` and the mean taken over the sum of the weights; see
+/// `weighted_mean`.
+pub fn cross_entropy_weighted(
+    logits: &[Vec<Value>],
+    targets: &[usize],
+    weights: &[f64],
+    label_smoothing: f64,
+) -> Value {
+    assert_eq!(logits.len(), targets.len(), "{} samples for {} targets", logits.len(), targets.len());
+    let terms: Vec<Value> = std::iter::zip(logits, targets)
+        .map(|(logits, &target)| cross_entropy(logits, target, label_smoothing))
+        .collect();
+    weighted_mean(&terms, weights)
+}
+
+/// `Σ weights[i]·terms[i] / Σ weights`, the mean of per-sample losses when samples count unequally. A sample
+/// of weight zero contributes nothing, gradients included. Equal weights give exactly `mean(terms)`.
+///
+/// Panics unless there is one weight per term, none of them is negative and they don't all vanish.
+pub fn weighted_mean(terms: &[Value], weights: &[f64]) -> Value {
+    assert_eq!(terms.len(), weights.len(), "{} terms for {} weights", terms.len(), weights.len());
+    assert!(weights.iter().all(|&weight| weight >= 0.0), "sample weights must not be negative");
+    let total: f64 = weights.iter().sum();
+    assert!(total > 0.0, "the sample weights sum to zero");
+    if weights.iter().all(|&weight| weight == weights[0]) {
+        return mean(terms);
+    }
+    let scaled: Vec<Value> = std::iter::zip(terms, weights)
+        .filter(|&(_, &weight)| weight != 0.0)
+        .map(|(term, &weight)| term * &Value::constant(weight))
+        .collect();
+    &ops::add_n(&scaled) * &Value::constant(1.0 / total)
+}
+
+/// The mean of `terms`, e.g. to combine the per-sample losses of a batch into a single loss, or a constant zero
+/// if there are none.
+///
+/// Above `ops::COMPENSATED_SUM_THRESHOLD` terms, they are added up by `ops::sum_compensated`.
+pub fn mean(terms: &[Value]) -> Value {
+    if terms.is_empty() {
+        return Value::constant(0.0);
+    }
+    let sum = if terms.len() > ops::COMPENSATED_SUM_THRESHOLD {
+        ops::sum_compensated(terms)
+    } else {
+        ops::add_n(terms)
+    };
+    &sum * &Value::constant(1.0 / terms.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_value_eq;
+    use crate::engine::values_from;
+
+    fn grads(values: &[Value]) -> Vec<f64> {
+        values.iter().map(Value::grad).collect()
+    }
+
+    #[test]
+    fn multilabel_bce_is_the_mean_of_the_individual_terms() {
+        let (logits, targets) = (values_from(&[2.0, -1.0, 0.5]), [1.0, 0.0, 0.3]);
+        let loss = multilabel_bce(&logits, &targets);
+        let terms: Vec<f64> =
+            std::iter::zip(&logits, targets).map(|(logit, target)| bce_with_logits(logit, target).data()).collect();
+        assert_value_eq!(loss, terms.iter().sum::<f64>() / 3.0, 1e-15);
+
+        // d/dlogit = (sigmoid(logit) - target) / n
+        loss.backward().unwrap();
+        for (logit, target) in std::iter::zip(&logits, targets) {
+            let expected = (1.0 / (1.0 + (-logit.data()).exp()) - target) / 3.0;
+            assert!((logit.grad() - expected).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn class_weights_scale_the_gradients() {
+        let targets = [1.0, 0.0, 1.0];
+        let plain = values_from(&[0.3, 0.7, -1.2]);
+        multilabel_bce(&plain, &targets).backward().unwrap();
+        let weighted = values_from(&[0.3, 0.7, -1.2]);
+        multilabel_bce_weighted(&weighted, &targets, &[2.0, 0.0, 0.5]).backward().unwrap();
+        let expected = [2.0 * plain[0].grad(), 0.0, 0.5 * plain[2].grad()];
+        for (grad, expected) in std::iter::zip(grads(&weighted), expected) {
+            assert!((grad - expected).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn bce_of_extreme_logits_stays_finite() {
+        let logits = values_from(&[800.0, -800.0, 800.0, -800.0]);
+        let loss = multilabel_bce(&logits, &[1.0, 0.0, 0.0, 1.0]);
+        loss.backward().unwrap();
+        // the confident right answers cost nothing, the wrong ones 800 each
+        assert_value_eq!(loss, 400.0, 1e-12);
+        assert_eq!(grads(&logits), [0.0, 0.0, 0.25, -0.25]);
+    }
+
+    #[test]
+    fn cross_entropy_is_the_negative_log_softmax() {
+        let logits = values_from(&[1.0, -0.5, 2.0]);
+        let loss = cross_entropy(&logits, 0, 0.0);
+        let probabilities: Vec<f64> = ops::softmax(&logits).iter().map(Value::data).collect();
+        assert_value_eq!(loss, -probabilities[0].ln(), 1e-12);
+
+        loss.backward().unwrap();
+        let expected = [probabilities[0] - 1.0, probabilities[1], probabilities[2]];
+        for (grad, expected) in std::iter::zip(grads(&logits), expected) {
+            assert!((grad - expected).abs() < 1e-12, "{} != {}", grad, expected);
+        }
+    }
+
+    #[test]
+    fn cross_entropy_of_huge_logits_stays_finite() {
+        let logits = values_from(&[1000.0, -1000.0, 999.0]);
+        let loss = cross_entropy(&logits, 2, 0.0);
+        assert_value_eq!(loss, (1.0 + (-1.0f64).exp()).ln() + 1.0, 1e-9);
+        loss.backward().unwrap();
+        assert!(grads(&logits).iter().all(|grad| grad.is_finite()));
+        // the shift is a constant, not a leaf of the loss that would receive a gradient
+        assert!(loss.topo_order().iter().filter(|node| node.is_leaf()).all(|leaf| {
+            !leaf.requires_grad() || logits.iter().any(|logit| logit.id() == leaf.id())
+        }));
+    }
+
+    #[test]
+    fn no_label_smoothing_is_the_plain_loss() {
+        let logits = values_from(&[0.3, 2.5, -1.0, 0.8]);
+        let loss = cross_entropy(&logits, 1, 0.0);
+        let shift = Value::constant(-2.5);
+        let exps: Vec<Value> = logits.iter().map(|logit| (logit + &shift).exp()).collect();
+        let plain = &(&ops::add_n(&exps).ln() - &shift) - &logits[1];
+        assert_eq!(loss.data().to_bits(), plain.data().to_bits());
+        assert_eq!(loss.topo_order().len(), plain.topo_order().len());
+
+        loss.backward().unwrap();
+        let smoothed = grads(&logits);
+        for logit in &logits {
+            logit.zero_grad();
+        }
+        plain.backward().unwrap();
+        assert_eq!(smoothed, grads(&logits));
+    }
+
+    #[test]
+    fn label_smoothing_gradients_are_softmax_minus_the_smoothed_target() {
+        let (logits, target, smoothing) = (values_from(&[0.3, 2.5, -1.0, 0.8]), 2, 0.2);
+        let loss = cross_entropy(&logits, target, smoothing);
+        let probabilities: Vec<f64> = ops::softmax(&logits).iter().map(Value::data).collect();
+        let smoothed: Vec<f64> =
+            (0..4).map(|i| (if i == target { 1.0 - smoothing } else { 0.0 }) + smoothing / 4.0).collect();
+        let expected: f64 = -std::iter::zip(&probabilities, &smoothed).map(|(p, t)| t * p.ln()).sum::<f64>();
+        assert_value_eq!(loss, expected, 1e-12);
+
+        loss.backward().unwrap();
+        // target: p - (1 - ε) - ε/K, others: p - ε/K
+        for (i, grad) in grads(&logits).into_iter().enumerate() {
+            let expected = probabilities[i] - smoothed[i];
+            assert!((grad - expected).abs() < 1e-12, "logit {}: {} != {}", i, grad, expected);
+        }
+        assert!(grads(&logits).iter().sum::<f64>().abs() < 1e-12);
+    }
+
+    #[test]
+    fn full_label_smoothing_is_the_loss_against_the_uniform_target() {
+        let logits = values_from(&[0.3, 2.5, -1.0, 0.8]);
+        let probabilities: Vec<f64> = ops::softmax(&logits).iter().map(Value::data).collect();
+        let uniform = -probabilities.iter().map(|p| p.ln() / 4.0).sum::<f64>();
+        for target in 0..4 {
+            assert_value_eq!(cross_entropy(&logits, target, 1.0), uniform, 1e-12);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "label smoothing of 1.5 outside [0, 1]")]
+    fn label_smoothing_must_be_a_probability() {
+        cross_entropy(&values_from(&[1.0, 2.0]), 0, 1.5);
+    }
+
+    #[test]
+    fn equal_sample_weights_are_the_plain_mean() {
+        let (preds, targets) = (values_from(&[1.0, -0.5, 2.0]), [0.5, 0.0, 1.0]);
+        assert_eq!(mse_weighted(&preds, &targets, &[0.7; 3]).data(), mse(&preds, &targets).data());
+        let logits = vec![values_from(&[1.0, 2.0]), values_from(&[0.5, -1.0])];
+        let weighted = cross_entropy_weighted(&logits, &[0, 1], &[3.0, 3.0], 0.1);
+        let plain = mean(&[cross_entropy(&logits[0], 0, 0.1), cross_entropy(&logits[1], 1, 0.1)]);
+        assert_eq!(weighted.data(), plain.data());
+    }
+
+    #[test]
+    fn zero_weight_samples_get_no_gradient() {
+        let (preds, targets) = (values_from(&[1.0, -0.5, 2.0]), [0.5, 0.0, 1.0]);
+        let loss = mse_weighted(&preds, &targets, &[1.0, 0.0, 2.0]);
+        loss.backward().unwrap();
+        assert_eq!(preds[1].grad(), 0.0);
+        // the terms of the other samples over the sum of the weights
+        assert_value_eq!(loss, (0.25 + 2.0 * 1.0) / 3.0, 1e-15);
+        assert_eq!(grads(&preds), [2.0 * 0.5 / 3.0, 0.0, 2.0 * 2.0 * 1.0 / 3.0]);
+    }
+
+    #[test]
+    fn weighted_cross_entropy_is_normalized_by_the_weight_sum() {
+        let logits = vec![values_from(&[1.0, 2.0]), values_from(&[0.5, -1.0])];
+        let loss = cross_entropy_weighted(&logits, &[0, 1], &[1.0, 3.0], 0.0);
+        let terms = [cross_entropy(&logits[0], 0, 0.0).data(), cross_entropy(&logits[1], 1, 0.0).data()];
+        assert_value_eq!(loss, (terms[0] + 3.0 * terms[1]) / 4.0, 1e-15);
+    }
+
+    #[test]
+    #[should_panic(expected = "sample weights must not be negative")]
+    fn sample_weights_must_not_be_negative() {
+        weighted_mean(&values_from(&[1.0, 2.0]), &[1.0, -1.0]);
+    }
+
+    #[test]
+    fn equally_weighted_mse_multi_is_the_flattened_mse() {
+        let preds = vec![values_from(&[1.0, -2.0]), values_from(&[0.5, 3.0])];
+        let targets = vec![vec![0.0, -1.0], vec![1.5, 2.0]];
+        let loss = mse_multi(&preds, &targets);
+        let flat: Vec<Value> = preds.iter().flatten().cloned().collect();
+        let flat_loss = mse(&flat, &targets.concat());
+        assert_value_eq!(loss, flat_loss.data(), 1e-15);
+        assert_value_eq!(loss, 1.0, 1e-15);
+
+        loss.backward().unwrap();
+        let multi_grads = grads(&flat);
+        flat.iter().for_each(Value::zero_grad);
+        flat_loss.backward().unwrap();
+        assert_eq!(multi_grads, grads(&flat));
+    }
+
+    #[test]
+    fn output_weights_scale_the_gradients_of_their_outputs() {
+        let targets = vec![vec![0.0, 0.0], vec![1.0, 1.0]];
+        let plain = vec![values_from(&[1.0, -2.0]), values_from(&[0.5, 3.0])];
+        mse_multi(&plain, &targets).backward().unwrap();
+        let weighted = vec![values_from(&[1.0, -2.0]), values_from(&[0.5, 3.0])];
+        let loss = mse_multi_weighted(&weighted, &targets, &[3.0, 0.5]);
+        loss.backward().unwrap();
+        for (plain, weighted) in std::iter::zip(&plain, &weighted) {
+            assert_eq!(weighted[0].grad(), 3.0 * plain[0].grad());
+            assert_eq!(weighted[1].grad(), 0.5 * plain[1].grad());
+        }
+        // (3·(1 + 0.25) + 0.5·(4 + 4)) / 4
+        assert_value_eq!(loss, 1.9375, 1e-15);
+    }
+
+    #[test]
+    #[should_panic(expected = "sample 1 has 1 targets for 2 outputs")]
+    fn mse_multi_rejects_ragged_targets() {
+        mse_multi(&[values_from(&[1.0, 2.0]), values_from(&[3.0, 4.0])], &[vec![0.0, 0.0], vec![0.0]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "sample 1 has 3 outputs, sample 0 has 2")]
+    fn mse_multi_rejects_ragged_predictions() {
+        mse_multi(&[values_from(&[1.0, 2.0]), values_from(&[3.0, 4.0, 5.0])], &[vec![0.0, 0.0], vec![0.0, 0.0]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "2 outputs for 3 output weights")]
+    fn mse_multi_weighted_needs_one_weight_per_output() {
+        mse_multi_weighted(&[values_from(&[1.0, 2.0])], &[vec![0.0, 0.0]], &[1.0, 1.0, 1.0]);
+    }
+}
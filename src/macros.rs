@@ -0,0 +1,177 @@
+/// Asserts that the data of two values (or of a value and a number) differ by at most `tol`.
+///
+/// On failure the data, grad and label of both sides are printed. NaN never satisfies the assertion.
+#[macro_export]
+macro_rules! assert_value_eq {
+    ($a:expr, $b:expr, $tol:expr) => {{
+        let (a, b): ($crate::Value, $crate::Value) = ($crate::Value::from(($a).clone()), $crate::Value::from(($b).clone()));
+        let tol: f64 = $tol;
+        if !a.approx_eq(&b, 0.0, tol) {
+            panic!(
+                "assertion `{} ≈ {}` failed (tolerance {})\n  left: data = {}, grad = {}, label = {:?}\n right: data = {}, grad = {}, label = {:?}",
+                stringify!($a),
+                stringify!($b),
+                tol,
+                a.data(),
+                a.grad(),
+                a.label(),
+                b.data(),
+                b.grad(),
+                b.label(),
+            );
+        }
+    }};
+}
+
+/// Asserts that the grad of a value differs from `expected` by at most `tol`.
+///
+/// On failure the data, grad and label of the value are printed. A NaN grad never satisfies the assertion.
+#[macro_export]
+macro_rules! assert_grad_eq {
+    ($v:expr, $expected:expr, $tol:expr) => {{
+        let v: &$crate::Value = &$v;
+        let (expected, tol): (f64, f64) = ($expected, $tol);
+        let grad = v.grad();
+        if !(grad == expected || (grad - expected).abs() <= tol) {
+            panic!(
+                "assertion `grad of {} ≈ {}` failed (tolerance {})\n value: data = {}, grad = {}, label = {:?}\n expected grad: {}",
+                stringify!($v),
+                stringify!($expected),
+                tol,
+                v.data(),
+                grad,
+                v.label(),
+                expected,
+            );
+        }
+    }};
+}
+
+/// Builds a graph from an arithmetic expression over `Value`s in scope, labelling every node it builds with
+/// the source text of its subexpression, so that graph dumps show where each node came from.
+///
+/// `expr!(tanh(w1*x1 + w2*x2 + b))` evaluates to the root of the graph, and `expr!{ o = tanh(...) }` binds it
+/// to a new variable `o`, also labelled `o`. The syntax is that of `engine::parse`: `+ - * / ^`, unary minus,
+/// parentheses, number literals and the functions `tanh`, `exp`, `ln`, `relu` and `softplus`; every other
+/// identifier must name a `Value` in scope, which is used as is. The graph is the same, node for node, as
+/// the one built by the equivalent Rust expression. A malformed expression panics.
+#[macro_export]
+macro_rules! expr {
+    ($name:ident = $($rhs:tt)+) => {
+        let $name: $crate::Value = $crate::expr!($($rhs)+).add_label(stringify!($name));
+    };
+    ($($rhs:tt)+) => {{
+        let mut vars = ::std::collections::HashMap::new();
+        $crate::__expr_vars!(vars; $($rhs)+);
+        $crate::engine::parse_labeled(stringify!($($rhs)+), &vars).unwrap_or_else(|e| panic!("expr!: {}", e))
+    }};
+}
+
+// Collects the variables of an `expr!` expression into `$vars`: every identifier that isn't called as a function.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __expr_vars {
+    ($vars:ident;) => {};
+    ($vars:ident; $f:ident ( $($inner:tt)* ) $($rest:tt)*) => {
+        $crate::__expr_vars!($vars; $($inner)*);
+        $crate::__expr_vars!($vars; $($rest)*);
+    };
+    ($vars:ident; $v:ident $($rest:tt)*) => {
+        let value: &$crate::Value = &$v;
+        $vars.insert(stringify!($v).to_string(), value.clone());
+        $crate::__expr_vars!($vars; $($rest)*);
+    };
+    ($vars:ident; ( $($inner:tt)* ) $($rest:tt)*) => {
+        $crate::__expr_vars!($vars; $($inner)*);
+        $crate::__expr_vars!($vars; $($rest)*);
+    };
+    ($vars:ident; $other:tt $($rest:tt)*) => {
+        $crate::__expr_vars!($vars; $($rest)*);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+
+    // The message of the panic raised by `assertion`
+    fn panic_message(assertion: impl FnOnce() + std::panic::UnwindSafe) -> String {
+        let payload = std::panic::catch_unwind(assertion).expect_err("the assertion should have failed");
+        payload.downcast_ref::<String>().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn passing_assertions() {
+        let x = Value::from(1.0);
+        assert_value_eq!(x, 1.0 + 1e-10, 1e-9);
+        assert_value_eq!(&x, Value::from(1.0), 0.0);
+        (&x * &x).backward().unwrap();
+        assert_grad_eq!(x, 2.0, 0.0);
+    }
+
+    #[test]
+    fn failure_messages_include_the_label() {
+        let message = panic_message(|| {
+            let weight = Value::from(0.5).add_label("w0");
+            assert_value_eq!(weight, 0.25, 1e-6);
+        });
+        assert!(message.contains("label = Some(\"w0\")"), "{}", message);
+        assert!(message.contains("data = 0.5"), "{}", message);
+
+        let message = panic_message(|| {
+            let weight = Value::from(0.5).add_label("w1");
+            assert_grad_eq!(weight, 1.0, 1e-6);
+        });
+        assert!(message.contains("label = Some(\"w1\")") && message.contains("grad = 0"), "{}", message);
+    }
+
+    #[test]
+    fn nan_never_passes() {
+        panic_message(|| assert_value_eq!(Value::from(f64::NAN), f64::NAN, f64::INFINITY));
+        panic_message(|| {
+            let x = Value::from(-1.0);
+            x.ln().backward().unwrap();
+            x.set_grad(f64::NAN);
+            assert_grad_eq!(x, f64::NAN, f64::INFINITY);
+        });
+    }
+
+    #[test]
+    fn expr_matches_the_hand_written_graph() {
+        let (w1, x1, w2, x2) = (Value::from(-3.0), Value::from(2.0), Value::from(1.0), Value::from(0.0));
+        let b = Value::from(6.9);
+        crate::expr! { o = tanh(w1*x1 + w2*x2 + b) }
+        let expected = (&(&(&w1 * &x1) + &(&w2 * &x2)) + &b).tanh();
+        assert_eq!(o.data(), expected.data());
+        assert_eq!(o.topo_order().len(), expected.topo_order().len());
+        assert_eq!(o.label().as_deref(), Some("o"));
+
+        o.backward().unwrap();
+        let grads: Vec<f64> = [&w1, &x1, &w2, &x2, &b].iter().map(|v| v.grad()).collect();
+        [&w1, &x1, &w2, &x2, &b].iter().for_each(|v| v.zero_grad());
+        expected.backward().unwrap();
+        assert_eq!(grads, [&w1, &x1, &w2, &x2, &b].map(|v| v.grad()));
+    }
+
+    #[test]
+    fn expr_labels_nodes_with_their_source() {
+        let (x, y) = (Value::from(2.0), Value::from(3.0));
+        let root = crate::expr!(exp(x * y) + x);
+        let labels: Vec<String> = root.topo_order().iter().filter_map(Value::label).collect();
+        assert!(labels.iter().any(|label| label.contains("x * y") && !label.contains("exp")), "{:?}", labels);
+        assert!(labels.iter().any(|label| label.starts_with("exp(") && label.contains("x * y")), "{:?}", labels);
+        // the variables keep their own (absent) labels
+        assert_eq!((x.label(), y.label()), (None, None));
+    }
+
+    #[test]
+    fn expr_parses_parentheses_unary_minus_and_literals() {
+        let (a, b) = (Value::from(1.5), Value::from(-0.5));
+        let root = crate::expr!(-((a - b) * (a + 2)) / -b ^ 2);
+        // `^` binds tighter than unary minus: `-b ^ 2` is `-(b²)`
+        let expected = -((1.5 - -0.5) * (1.5 + 2.0)) / -(0.25_f64);
+        assert!((root.data() - expected).abs() < 1e-12, "{} != {}", root.data(), expected);
+        root.backward().unwrap();
+        assert!(a.grad().is_finite() && b.grad().is_finite());
+    }
+}
@@ -0,0 +1,47 @@
+use crate::engine::Value;
+use crate::loss::multi_output_dims;
+
+/// The mean squared error of each output of a multi-output model over a batch, in the layout of
+/// `loss::mse_multi`, computed from the data without building any node, e.g. to report how well each
+/// coordinate of a prediction is fitted.
+///
+/// Panics on the same ragged shapes as `loss::mse_multi`; an empty batch gives an empty result.
+pub fn mse_per_dim(preds: &[Vec<Value>], targets: &[Vec<f64>]) -> Vec<f64> {
+    let dims = multi_output_dims(preds, targets);
+    let mut sums = vec![0.0; dims];
+    for (pred, target) in std::iter::zip(preds, targets) {
+        for (sum, (pred, &target)) in sums.iter_mut().zip(std::iter::zip(pred, target)) {
+            *sum += (pred.data() - target).powi(2);
+        }
+    }
+    sums.into_iter().map(|sum| sum / preds.len() as f64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::values_from;
+    use crate::loss::mse_multi;
+
+    #[test]
+    fn per_dim_errors_average_to_mse_multi() {
+        let preds = vec![values_from(&[1.0, -2.0]), values_from(&[0.5, 3.0])];
+        let targets = vec![vec![0.0, -1.0], vec![1.5, 1.0]];
+        let per_dim = mse_per_dim(&preds, &targets);
+        assert_eq!(per_dim, vec![1.0, 2.5]);
+        assert_eq!(per_dim.iter().sum::<f64>() / 2.0, mse_multi(&preds, &targets).data());
+        // no nodes are built on the predictions
+        assert!(preds.iter().flatten().all(|pred| pred.grad() == 0.0));
+    }
+
+    #[test]
+    fn an_empty_batch_has_no_dimensions() {
+        assert!(mse_per_dim(&[], &[]).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "2 predictions for 1 targets")]
+    fn per_dim_errors_reject_missing_targets() {
+        mse_per_dim(&[values_from(&[1.0]), values_from(&[2.0])], &[vec![0.0]]);
+    }
+}
@@ -1,11 +1,75 @@
-use crate::engine::Value;
+use crate::engine::{NodeMap, Value};
+use crate::tensor::Matrix;
 
-use rand::{thread_rng, Rng};
+mod activation;
+pub use activation::{Dropout, Lambda, ReLU, Sigmoid, Tanh, GELU};
+
+mod grad_check;
+pub use grad_check::{grad_check_module, GradCheckFailure};
+
+mod prune;
+pub use prune::{prune_by_magnitude, PruneMask};
+
+mod sequential;
+pub use sequential::{BoxedModule, Sequential};
+
+mod sharing;
+pub use sharing::{assert_no_unintended_sharing, sharing_report, SharedParam};
+
+#[cfg(feature = "serde")]
+mod export;
+#[cfg(feature = "serde")]
+pub use export::{export, import, FORMAT_VERSION};
 
 #[derive(Clone)] // use the clone method to create a new instance of the struct
 pub struct Neuron {
     w: Vec<Value>,
     b: Value,
+    activation: Activation,
+}
+
+/// The function a `Neuron` applies to its weighted sum: tanh unless set otherwise, e.g. by
+/// `MLP::with_activations`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Activation {
+    #[default]
+    Tanh,
+    Relu,
+    /// The weighted sum itself, e.g. for the output layer of a regression.
+    Identity,
+}
+
+impl Activation {
+    /// The name of the activation in files written by `export`: `tanh`, `relu` or `identity`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Activation::Tanh => "tanh",
+            Activation::Relu => "relu",
+            Activation::Identity => "identity",
+        }
+    }
+
+    /// The activation called `name`, see `name`.
+    pub fn from_name(name: &str) -> Option<Activation> {
+        [Activation::Tanh, Activation::Relu, Activation::Identity].into_iter().find(|a| a.name() == name)
+    }
+
+    fn apply(self, x: Value) -> Value {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.relu(),
+            Activation::Identity => x,
+        }
+    }
+
+    // `apply` on plain numbers, with the same results as the data of the nodes
+    fn apply_f64(self, x: f64) -> f64 {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.max(0.0),
+            Activation::Identity => x,
+        }
+    }
 }
 
 #[derive(Clone)] // use the clone method to create a new instance of the struct
@@ -18,15 +82,214 @@ pub struct MLP {
     layers: Vec<Layer>,
 }
 
+/// A fully-connected layer computing `W·x + b`, with `W` an `out_dim x in_dim` matrix and no activation.
+///
+/// Unlike a `Layer` of neurons, which builds the weighted sums neuron by neuron and applies tanh, the
+/// weights are kept as a single `Matrix`, so each output is one `ops::add_n` node over its products.
+#[derive(Clone)]
+pub struct Linear {
+    weight: Matrix,
+    bias: Option<Vec<Value>>,
+}
+
+/// A lookup table of `vocab_size` learned vectors of `dim` values, e.g. one per character of a vocabulary.
+///
+/// Looking up an index returns the parameters of its row themselves, so every occurrence of the same index in a
+/// batch accumulates gradient into the same leaves.
+#[derive(Clone)]
+pub struct Embedding {
+    weight: Matrix,
+}
+
+/// A linear layer whose outputs are the logits of a categorical distribution over `n_classes` classes, with
+/// inference that reads the parameters directly and builds no graph.
+#[derive(Clone)]
+pub struct SoftmaxClassifier {
+    linear: Linear,
+}
+
+/// Layer normalization over the `dim` features of an input: each is centered on the mean of the features and scaled
+/// by their standard deviation, then by a learned gain and shifted by a learned bias, one of each per feature.
+#[derive(Clone)]
+pub struct LayerNorm {
+    gain: Vec<Value>,
+    bias: Vec<Value>,
+    eps: f64,
+}
+
+/// How the weights of a `Linear` layer are initialized. Biases always start at zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Init {
+    /// Uniform in `[-bound, bound]`.
+    Uniform(f64),
+    /// Glorot uniform, in `[-b, b]` with `b = sqrt(6 / (in_dim + out_dim))`, suited to tanh.
+    Xavier,
+    /// He normal, with standard deviation `sqrt(2 / in_dim)`, suited to relu.
+    He,
+}
+
 pub trait Module {
     // Retrieve all trainable parameters as a vector.
     fn parameters(&self) -> Vec<&Value>;
 
     // Perform a forward pass through the module.
     fn forward(&self, inputs: &[Value]) -> Vec<Value>;
+
+    // Forward pass over a mini-batch, one output vector per sample. The parameters are the same leaves
+    // for every sample, so a single loss averaged over the batch (see `loss::mean`) back-propagates the
+    // mean of the per-sample gradients into them.
+    fn forward_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        batch.iter().map(|inputs| self.forward(inputs)).collect()
+    }
+
+    // Name, dimensions and parameter count of the module, and of its sub-modules, for `summary`.
+    // By default only the type name and the parameter count are known.
+    fn describe(&self) -> LayerInfo {
+        let name = std::any::type_name::<Self>().rsplit("::").next().unwrap_or_default().to_string();
+        LayerInfo { name, inputs: None, outputs: None, parameters: self.parameters().len(), children: Vec::new() }
+    }
+
+    // Labels the parameters with hierarchical names under `prefix`, e.g. `layer1.neuron3.w2` for the third
+    // weight of the fourth neuron of the second layer of an MLP (indices start at 0); an empty prefix gives
+    // the names relative to the module. Constructors already label parameters this way with an empty prefix.
+    // By default the i-th parameter is named `prefix.p{i}`.
+    fn set_name_prefix(&mut self, prefix: &str) {
+        for (i, param) in self.parameters().into_iter().enumerate() {
+            set_label(param, scoped(prefix, &format!("p{}", i)));
+        }
+    }
+
+    // The parameters, in the order of `parameters`, each with its path in the module tree: the name
+    // `set_name_prefix` gives it for an empty prefix, e.g. `layer1.neuron3.w2`. Unlike labels, which a
+    // parameter shared between two places can only hold one of, every occurrence gets its own path.
+    // By default the i-th parameter is named `p{i}`.
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        self.parameters().into_iter().enumerate().map(|(i, param)| (format!("p{}", i), param)).collect()
+    }
+
+    // Freezes every parameter of the module, see `Value::set_requires_grad`.
+    fn freeze(&self) {
+        for param in self.parameters() {
+            param.set_requires_grad(false);
+        }
+    }
+
+    fn unfreeze(&self) {
+        for param in self.parameters() {
+            param.set_requires_grad(true);
+        }
+    }
+
+    // Deep copy of the module: its parameters are new leaves with the same data and zero grads,
+    // so training the copy leaves the original untouched. Parameters the module shares, e.g. tied weights,
+    // stay shared in the copy.
+    fn clone_module(&self) -> Self
+    where
+        Self: Sized,
+    {
+        self.clone_module_with(&mut NodeMap::new())
+    }
+
+    // Same as `clone_module`, copying the parameters through `copies` (see `Value::clone_graph_with`), so that a
+    // module made of other modules clones them all through a single map and keeps the nodes they share as one.
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> Self
+    where
+        Self: Sized;
 }
 
 
+/// Adds Gaussian noise with standard deviation `std` to the data of each of `params`, leaving their grads untouched.
+///
+/// Meant for simple evolutionary or noise-injection experiments; seed `rng` to make them reproducible.
+pub fn perturb_params(params: &[Value], std: f64, rng: &mut crate::rand::Rng) {
+    for param in params {
+        param.set_data(param.data() + rng.normal(0.0, std));
+    }
+}
+
+/// The description of a module given by `Module::describe`, one per layer of a model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerInfo {
+    pub name: String,
+    pub inputs: Option<usize>,
+    pub outputs: Option<usize>,
+    /// The number of parameters, including those of the children.
+    pub parameters: usize,
+    /// The layers the module is made of, e.g. the layers of an `MLP`.
+    pub children: Vec<LayerInfo>,
+}
+
+/// A table of the layers of `module`, sub-modules indented under the module they belong to, with their input
+/// and output dimensions and parameter counts, followed by the total number of parameters.
+pub fn summary(module: &impl Module) -> String {
+    fn rows(info: &LayerInfo, depth: usize, out: &mut String) {
+        let dim = |d: Option<usize>| d.map_or("?".to_string(), |d| d.to_string());
+        let name = format!("{}{}", "  ".repeat(depth), info.name);
+        out.push_str(&format!(
+            "{:<24} {:>8} {:>8} {:>12}\n",
+            name,
+            dim(info.inputs),
+            dim(info.outputs),
+            info.parameters
+        ));
+        for child in &info.children {
+            rows(child, depth + 1, out);
+        }
+    }
+
+    let info = module.describe();
+    let mut out = format!("{:<24} {:>8} {:>8} {:>12}\n", "Layer", "Inputs", "Outputs", "Parameters");
+    rows(&info, 0, &mut out);
+    out.push_str(&format!("Total parameters: {}\n", info.parameters));
+    out
+}
+
+// `prefix.name`, or just `name` at the top level
+fn scoped(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+// the named parameters of each sub-module, under the sub-module's name
+fn nested<'a, M: Module + 'a>(modules: impl Iterator<Item = (String, &'a M)>) -> Vec<(String, &'a Value)> {
+    modules
+        .flat_map(|(prefix, module)| {
+            module.named_parameters().into_iter().map(move |(name, param)| (scoped(&prefix, &name), param))
+        })
+        .collect()
+}
+
+// `{letter}{row}_{col}` for each element of a weight matrix, row by row as in `Matrix::parameters`
+fn matrix_names<'a>(weight: &'a Matrix, letter: &str) -> Vec<(String, &'a Value)> {
+    let cols = weight.cols();
+    let names = (0..weight.rows()).flat_map(|row| (0..cols).map(move |col| (row, col)));
+    names
+        .zip(weight.parameters())
+        .map(|((row, col), param)| (format!("{}{}_{}", letter, row, col), param))
+        .collect()
+}
+
+// so that models built inside a `Tape::scope` don't need their parameters listed by hand
+fn register_parameters(module: &impl Module) {
+    for param in module.parameters() {
+        crate::tape::register(param);
+    }
+}
+
+fn set_label(param: &Value, label: String) {
+    param.borrow_mut().label = Some(label);
+}
+
+/// `params` with repeated nodes kept only once, in order of first appearance, for modules that share
+/// parameters between layers (e.g. tied weights) so that an optimizer doesn't update them twice.
+pub fn dedup_parameters(params: Vec<&Value>) -> Vec<&Value> {
+    let mut seen = std::collections::HashSet::new();
+    params.into_iter().filter(|param| seen.insert(param.id())).collect()
+}
+
 impl Neuron {
 
     /// Constructs a new `Neuron` with randomly initialized weights and a bias.
@@ -36,10 +299,10 @@ impl Neuron {
     ///
     /// # Returns
     /// Returns a `Neuron` instance with `nin` weights and one bias, all initialized to random values between -1.0 and 1.0.
+    /// They are drawn from the default generator, see `crate::seed`.
     pub fn new(nin: usize) -> Neuron {
-        let mut rng = thread_rng();
-        let mut rand_value_fn = || {
-            let data = rng.gen_range(-1.0..1.0);
+        let rand_value_fn = || {
+            let data = crate::rand::with_rng(|rng| rng.uniform(-1.0, 1.0));
             Value::from(data)
         };
 
@@ -48,10 +311,14 @@ impl Neuron {
             w.push(rand_value_fn());
         }
 
-        Neuron {
+        let mut neuron = Neuron {
             w,
-            b: rand_value_fn().add_label("b"),
-        }
+            b: rand_value_fn(),
+            activation: Activation::Tanh,
+        };
+        Module::set_name_prefix(&mut neuron, "");
+        register_parameters(&neuron);
+        neuron
     }
 
     /// Performs a forward pass of the neuron using the given inputs.
@@ -60,15 +327,26 @@ impl Neuron {
     /// - `xs`: A vector of `Value` representing the inputs to the neuron.
     ///
     /// # Returns
-    /// The output of the neuron as a `Value`, applying the tanh activation function to the weighted sum of inputs plus the bias.
-    
+    /// The output of the neuron as a `Value`, applying the activation function (tanh by default) to the weighted
+    /// sum of inputs plus the bias.
     pub fn forward(&self, xs: &Vec<Value>) -> Value {
         let products = std::iter::zip(&self.w, xs)
             .map(|(a, b)| a * b)
             .collect::<Vec<Value>>();
 
         let sum = self.b.clone() + products.into_iter().reduce(|acc, prd| acc + prd).unwrap();
-        sum.tanh()
+        self.activation.apply(sum)
+    }
+
+    // Same as `forward` on plain numbers, reading the data of the parameters: the sums are taken in the same
+    // order and rounded to `engine::data_precision` like the nodes would be, so that the result is identical.
+    fn eval_f64(&self, xs: &[f64]) -> f64 {
+        let precision = crate::engine::data_precision();
+        let sum = std::iter::zip(&self.w, xs)
+            .map(|(w, x)| precision.round(w.data() * x))
+            .reduce(|acc, product| precision.round(acc + product))
+            .unwrap();
+        precision.round(self.activation.apply_f64(precision.round(self.b.data() + sum)))
     }
 
     /// Retrieves all parameters (weights and bias) of the neuron.
@@ -96,11 +374,13 @@ impl MLP {
         let nout_len = nout.len();
         let layer_sizes: Vec<usize> = [nin].into_iter().chain(nout).collect();
 
-        MLP {
+        let mut mlp = MLP {
             layers: (0..nout_len)
                 .map(|i| Layer::new(layer_sizes[i], layer_sizes[i + 1]))
                 .collect(),
-        }
+        };
+        Module::set_name_prefix(&mut mlp, "");
+        mlp
     }
 
     /// Performs a forward pass through the entire MLP network.
@@ -117,6 +397,38 @@ impl MLP {
         xs
     }
 
+    /// Sets the activation of the neurons of every layer but the last to `hidden`, and of the last one to
+    /// `output`, e.g. `(Activation::Relu, Activation::Identity)` for a regression. Both are tanh by default.
+    pub fn with_activations(mut self, hidden: Activation, output: Activation) -> MLP {
+        let last = self.layers.len().saturating_sub(1);
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            let activation = if i == last { output } else { hidden };
+            layer.neurons.iter_mut().for_each(|neuron| neuron.activation = activation);
+        }
+        self
+    }
+
+    /// Evaluates the network on the inputs `x` in plain `f64` arithmetic, reading the current data of the
+    /// parameters without building any node, e.g. for inference once training is done.
+    ///
+    /// The result is exactly the data of the outputs of `forward`. Panics if `x` doesn't have as many values as
+    /// the network has inputs.
+    pub fn eval_f64(&self, x: &[f64]) -> Vec<f64> {
+        let nin = self.layers.first().and_then(|layer| layer.neurons.first()).map_or(x.len(), |n| n.w.len());
+        assert_eq!(x.len(), nin, "{} inputs for a network of {} inputs", x.len(), nin);
+        let precision = crate::engine::data_precision();
+        let mut xs: Vec<f64> = x.iter().map(|&x| precision.round(x)).collect();
+        for layer in &self.layers {
+            xs = layer.neurons.iter().map(|neuron| neuron.eval_f64(&xs)).collect();
+        }
+        xs
+    }
+
+    /// `eval_f64` of every row of `batch`.
+    pub fn eval_batch(&self, batch: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        batch.iter().map(|x| self.eval_f64(x)).collect()
+    }
+
     /// Retrieves all trainable parameters from every layer of the MLP.
     ///
     /// # Returns
@@ -136,11 +448,13 @@ impl Layer {
     /// # Returns
     /// Returns a `Layer` instance containing `nout` neurons, each with `nin` inputs.
     pub fn new(nin: usize, nout: usize) -> Layer {
-        Layer {
+        let mut layer = Layer {
             neurons: (0..nout)
                 .map(|_| Neuron::new(nin))
                 .collect(),
-        }
+        };
+        Module::set_name_prefix(&mut layer, "");
+        layer
     }
 
     /// Performs a forward pass through the layer using the given inputs.
@@ -161,4 +475,1044 @@ impl Layer {
     pub fn parameters(&self) -> Vec<Value> {
         self.neurons.iter().flat_map(|n| n.parameters()).collect()
     }
-}
\ No newline at end of file
+}
+
+impl Linear {
+    /// Constructs a new `Linear` layer with weights drawn from `rng` according to `init`.
+    ///
+    /// # Parameters
+    /// - `in_dim`: The number of inputs.
+    /// - `out_dim`: The number of outputs.
+    /// - `bias`: Whether a bias is added to each output.
+    /// - `init`: The weight initialization scheme.
+    /// - `rng`: The source of randomness, seeded for reproducible initializations.
+    pub fn new(in_dim: usize, out_dim: usize, bias: bool, init: Init, rng: &mut crate::rand::Rng) -> Linear {
+        let mut sample = || match init {
+            Init::Uniform(bound) => rng.uniform(-bound, bound),
+            Init::Xavier => {
+                let bound = (6.0 / (in_dim + out_dim) as f64).sqrt();
+                rng.uniform(-bound, bound)
+            }
+            Init::He => rng.normal(0.0, (2.0 / in_dim as f64).sqrt()),
+        };
+        let weight = Matrix::from_fn(out_dim, in_dim, |_, _| Value::from(sample()));
+        let bias = bias.then(|| (0..out_dim).map(|_| Value::from(0.0)).collect());
+        let mut linear = Linear { weight, bias };
+        Module::set_name_prefix(&mut linear, "");
+        register_parameters(&linear);
+        linear
+    }
+
+    /// Builds a layer from existing weights, one row per output, and optional biases, one per output.
+    pub fn from_weights(weight: Matrix, bias: Option<Vec<Value>>) -> Linear {
+        if let Some(bias) = &bias {
+            assert_eq!(bias.len(), weight.rows(), "{} biases for {} outputs", bias.len(), weight.rows());
+        }
+        Linear { weight, bias }
+    }
+
+    pub fn in_dim(&self) -> usize {
+        self.weight.cols()
+    }
+
+    pub fn out_dim(&self) -> usize {
+        self.weight.rows()
+    }
+
+    pub fn weight(&self) -> &Matrix {
+        &self.weight
+    }
+
+    pub fn bias(&self) -> Option<&[Value]> {
+        self.bias.as_deref()
+    }
+
+    /// Performs a forward pass through the layer. Panics if `xs` doesn't have `in_dim` values.
+    ///
+    /// # Returns
+    /// The `out_dim` outputs `W·xs + b`.
+    pub fn forward(&self, xs: &[Value]) -> Vec<Value> {
+        let outputs = self.weight.matvec(xs).unwrap_or_else(|e| panic!("{}", e));
+        match &self.bias {
+            Some(bias) => std::iter::zip(&outputs, bias).map(|(y, b)| y + b).collect(),
+            None => outputs,
+        }
+    }
+}
+
+impl Embedding {
+    /// Constructs a table of `vocab_size` rows of `dim` values drawn from the standard normal distribution.
+    pub fn new(vocab_size: usize, dim: usize, rng: &mut crate::rand::Rng) -> Embedding {
+        let weight = Matrix::from_fn(vocab_size, dim, |_, _| Value::randn(rng));
+        let mut embedding = Embedding { weight };
+        Module::set_name_prefix(&mut embedding, "");
+        register_parameters(&embedding);
+        embedding
+    }
+
+    /// Builds a table from existing vectors, one row per index.
+    pub fn from_weights(weight: Matrix) -> Embedding {
+        Embedding { weight }
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.weight.rows()
+    }
+
+    pub fn dim(&self) -> usize {
+        self.weight.cols()
+    }
+
+    pub fn weight(&self) -> &Matrix {
+        &self.weight
+    }
+
+    /// The vector of `index`. Panics if `index` is not below `vocab_size`.
+    pub fn lookup(&self, index: usize) -> Vec<Value> {
+        assert!(index < self.vocab_size(), "index {} in an embedding of {} rows", index, self.vocab_size());
+        self.weight.row(index).to_vec()
+    }
+}
+
+impl SoftmaxClassifier {
+    /// Constructs a classifier of `in_dim` features with Xavier-initialized weights drawn from `rng`.
+    pub fn new(in_dim: usize, n_classes: usize, rng: &mut crate::rand::Rng) -> SoftmaxClassifier {
+        SoftmaxClassifier { linear: Linear::new(in_dim, n_classes, true, Init::Xavier, rng) }
+    }
+
+    /// Builds a classifier from an existing layer, one output per class.
+    pub fn from_linear(linear: Linear) -> SoftmaxClassifier {
+        SoftmaxClassifier { linear }
+    }
+
+    pub fn n_classes(&self) -> usize {
+        self.linear.out_dim()
+    }
+
+    pub fn linear(&self) -> &Linear {
+        &self.linear
+    }
+
+    /// The logits of every class for the features `x`, as graph nodes to build a loss on.
+    pub fn logits(&self, x: &[f64]) -> Vec<Value> {
+        let inputs: Vec<Value> = x.iter().map(|&x| Value::constant(x)).collect();
+        self.linear.forward(&inputs)
+    }
+
+    /// `loss::cross_entropy` of the logits of `x` against the class `target`.
+    pub fn loss(&self, x: &[f64], target: usize) -> Value {
+        crate::loss::cross_entropy(&self.logits(x), target, 0.0)
+    }
+
+    /// The probability of every class for the features `x`, computed from the data of the parameters without
+    /// building any node. Panics if `x` doesn't have `in_dim` values.
+    pub fn predict_proba(&self, x: &[f64]) -> Vec<f64> {
+        let linear = &self.linear;
+        assert_eq!(x.len(), linear.in_dim(), "{} features for a classifier of {} inputs", x.len(), linear.in_dim());
+        let logits: Vec<f64> = (0..linear.out_dim())
+            .map(|class| {
+                let weighted: f64 = std::iter::zip(linear.weight().row(class), x).map(|(w, x)| w.data() * x).sum();
+                weighted + linear.bias().map_or(0.0, |bias| bias[class].data())
+            })
+            .collect();
+        let max = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let exps: Vec<f64> = logits.iter().map(|logit| (logit - max).exp()).collect();
+        let total: f64 = exps.iter().sum();
+        exps.iter().map(|e| e / total).collect()
+    }
+
+    /// The most probable class for the features `x`, the first one on ties, without building any node.
+    pub fn predict(&self, x: &[f64]) -> usize {
+        let probabilities = self.predict_proba(x);
+        (0..probabilities.len())
+            .reduce(|best, class| if probabilities[class] > probabilities[best] { class } else { best })
+            .expect("a classifier has at least one class")
+    }
+}
+
+impl LayerNorm {
+    /// Constructs a normalization of `dim` features with gains of 1 and biases of 0, adding `1e-5` to the
+    /// variance.
+    pub fn new(dim: usize) -> LayerNorm {
+        LayerNorm::with_eps(dim, 1e-5)
+    }
+
+    /// Same as `new`, adding `eps` to the variance before taking its square root.
+    pub fn with_eps(dim: usize, eps: f64) -> LayerNorm {
+        assert!(dim > 0, "layer normalization of no features");
+        let mut norm = LayerNorm {
+            gain: (0..dim).map(|_| Value::from(1.0)).collect(),
+            bias: (0..dim).map(|_| Value::from(0.0)).collect(),
+            eps,
+        };
+        Module::set_name_prefix(&mut norm, "");
+        register_parameters(&norm);
+        norm
+    }
+
+    pub fn dim(&self) -> usize {
+        self.gain.len()
+    }
+
+    pub fn gain(&self) -> &[Value] {
+        &self.gain
+    }
+
+    pub fn bias(&self) -> &[Value] {
+        &self.bias
+    }
+
+    /// Performs a forward pass over the features `xs`. Panics if there aren't `dim` of them.
+    ///
+    /// # Returns
+    /// `gain[i] · (xs[i] - mean) / sqrt(variance + eps) + bias[i]` for each feature, with the mean and the
+    /// (biased) variance taken over the features.
+    pub fn forward(&self, xs: &[Value]) -> Vec<Value> {
+        assert_eq!(xs.len(), self.dim(), "{} features for a layer norm of {}", xs.len(), self.dim());
+        let inv_n = Value::constant(1.0 / xs.len() as f64);
+        let mean = &crate::ops::add_n(xs) * &inv_n;
+        let centered: Vec<Value> = xs.iter().map(|x| x - &mean).collect();
+        let squares: Vec<Value> = centered.iter().map(|c| c * c).collect();
+        let variance = &crate::ops::add_n(&squares) * &inv_n;
+        let inv_std = (&variance + &Value::constant(self.eps)).powf(-0.5);
+        centered
+            .iter()
+            .zip(&self.gain)
+            .zip(&self.bias)
+            .map(|((c, g), b)| &(&(c * &inv_std) * g) + b)
+            .collect()
+    }
+}
+
+impl Module for Neuron {
+    fn parameters(&self) -> Vec<&Value> {
+        std::iter::once(&self.b).chain(&self.w).collect()
+    }
+
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        vec![Neuron::forward(self, &inputs.to_vec())]
+    }
+
+    fn describe(&self) -> LayerInfo {
+        LayerInfo {
+            name: "Neuron".to_string(),
+            inputs: Some(self.w.len()),
+            outputs: Some(1),
+            parameters: self.w.len() + 1,
+            children: Vec::new(),
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        let weights = self.w.iter().enumerate().map(|(i, w)| (format!("w{}", i), w));
+        std::iter::once(("b".to_string(), &self.b)).chain(weights).collect()
+    }
+
+    fn set_name_prefix(&mut self, prefix: &str) {
+        for (i, w) in self.w.iter().enumerate() {
+            set_label(w, scoped(prefix, &format!("w{}", i)));
+        }
+        set_label(&self.b, scoped(prefix, "b"));
+    }
+
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> Neuron {
+        Neuron {
+            w: self.w.iter().map(|w| w.clone_graph_with(copies)).collect(),
+            b: self.b.clone_graph_with(copies),
+            activation: self.activation,
+        }
+    }
+}
+
+impl Module for Layer {
+    fn parameters(&self) -> Vec<&Value> {
+        self.neurons.iter().flat_map(Module::parameters).collect()
+    }
+
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        Layer::forward(self, &inputs.to_vec())
+    }
+
+    fn describe(&self) -> LayerInfo {
+        LayerInfo {
+            name: "Layer".to_string(),
+            inputs: self.neurons.first().map(|n| n.w.len()),
+            outputs: Some(self.neurons.len()),
+            parameters: Module::parameters(self).len(),
+            children: Vec::new(),
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        nested(self.neurons.iter().enumerate().map(|(i, neuron)| (format!("neuron{}", i), neuron)))
+    }
+
+    fn set_name_prefix(&mut self, prefix: &str) {
+        for (i, neuron) in self.neurons.iter_mut().enumerate() {
+            neuron.set_name_prefix(&scoped(prefix, &format!("neuron{}", i)));
+        }
+    }
+
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> Layer {
+        Layer {
+            neurons: self.neurons.iter().map(|neuron| neuron.clone_module_with(copies)).collect(),
+        }
+    }
+}
+
+impl Module for MLP {
+    fn parameters(&self) -> Vec<&Value> {
+        self.layers.iter().flat_map(Module::parameters).collect()
+    }
+
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        MLP::forward(self, inputs.to_vec())
+    }
+
+    fn describe(&self) -> LayerInfo {
+        let children: Vec<LayerInfo> = self.layers.iter().map(Module::describe).collect();
+        LayerInfo {
+            name: "MLP".to_string(),
+            inputs: children.first().and_then(|layer| layer.inputs),
+            outputs: children.last().and_then(|layer| layer.outputs),
+            parameters: children.iter().map(|layer| layer.parameters).sum(),
+            children,
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        nested(self.layers.iter().enumerate().map(|(i, layer)| (format!("layer{}", i), layer)))
+    }
+
+    fn set_name_prefix(&mut self, prefix: &str) {
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            layer.set_name_prefix(&scoped(prefix, &format!("layer{}", i)));
+        }
+    }
+
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> MLP {
+        MLP {
+            layers: self.layers.iter().map(|layer| layer.clone_module_with(copies)).collect(),
+        }
+    }
+}
+
+impl Module for Linear {
+    fn parameters(&self) -> Vec<&Value> {
+        self.weight.parameters().into_iter().chain(self.bias.iter().flatten()).collect()
+    }
+
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        Linear::forward(self, inputs)
+    }
+
+    fn describe(&self) -> LayerInfo {
+        LayerInfo {
+            name: "Linear".to_string(),
+            inputs: Some(self.in_dim()),
+            outputs: Some(self.out_dim()),
+            parameters: Module::parameters(self).len(),
+            children: Vec::new(),
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        let mut named = matrix_names(&self.weight, "w");
+        named.extend(self.bias.iter().flatten().enumerate().map(|(row, b)| (format!("b{}", row), b)));
+        named
+    }
+
+    // `w{row}_{col}` for the weights and `b{row}` for the biases
+    fn set_name_prefix(&mut self, prefix: &str) {
+        for row in 0..self.weight.rows() {
+            for col in 0..self.weight.cols() {
+                set_label(self.weight.get(row, col), scoped(prefix, &format!("w{}_{}", row, col)));
+            }
+        }
+        for (row, b) in self.bias.iter().flatten().enumerate() {
+            set_label(b, scoped(prefix, &format!("b{}", row)));
+        }
+    }
+
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> Linear {
+        let (rows, cols) = self.weight.shape();
+        let weight = self.weight.data().iter().map(|w| w.clone_graph_with(copies)).collect();
+        Linear {
+            weight: Matrix::new(rows, cols, weight),
+            bias: self.bias.as_ref().map(|bias| bias.iter().map(|b| b.clone_graph_with(copies)).collect()),
+        }
+    }
+}
+
+impl Module for Embedding {
+    fn parameters(&self) -> Vec<&Value> {
+        self.weight.parameters()
+    }
+
+    // The vectors of the indices held in the data of `inputs`, concatenated.
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        inputs.iter().flat_map(|index| self.lookup(index.data() as usize)).collect()
+    }
+
+    fn describe(&self) -> LayerInfo {
+        LayerInfo {
+            name: "Embedding".to_string(),
+            inputs: Some(self.vocab_size()),
+            outputs: Some(self.dim()),
+            parameters: Module::parameters(self).len(),
+            children: Vec::new(),
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        matrix_names(&self.weight, "e")
+    }
+
+    // `e{index}_{col}`
+    fn set_name_prefix(&mut self, prefix: &str) {
+        for row in 0..self.weight.rows() {
+            for col in 0..self.weight.cols() {
+                set_label(self.weight.get(row, col), scoped(prefix, &format!("e{}_{}", row, col)));
+            }
+        }
+    }
+
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> Embedding {
+        let (rows, cols) = self.weight.shape();
+        let weight = self.weight.data().iter().map(|w| w.clone_graph_with(copies)).collect();
+        Embedding {
+            weight: Matrix::new(rows, cols, weight),
+        }
+    }
+}
+
+impl Module for SoftmaxClassifier {
+    fn parameters(&self) -> Vec<&Value> {
+        self.linear.parameters()
+    }
+
+    // The logits of every class.
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.linear.forward(inputs)
+    }
+
+    fn describe(&self) -> LayerInfo {
+        let linear = self.linear.describe();
+        LayerInfo {
+            name: "SoftmaxClassifier".to_string(),
+            inputs: linear.inputs,
+            outputs: linear.outputs,
+            parameters: linear.parameters,
+            children: vec![linear],
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        self.linear.named_parameters()
+    }
+
+    fn set_name_prefix(&mut self, prefix: &str) {
+        self.linear.set_name_prefix(prefix);
+    }
+
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> SoftmaxClassifier {
+        SoftmaxClassifier { linear: self.linear.clone_module_with(copies) }
+    }
+}
+
+impl Module for LayerNorm {
+    fn parameters(&self) -> Vec<&Value> {
+        self.gain.iter().chain(&self.bias).collect()
+    }
+
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        LayerNorm::forward(self, inputs)
+    }
+
+    fn describe(&self) -> LayerInfo {
+        LayerInfo {
+            name: "LayerNorm".to_string(),
+            inputs: Some(self.dim()),
+            outputs: Some(self.dim()),
+            parameters: 2 * self.dim(),
+            children: Vec::new(),
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        let gains = self.gain.iter().enumerate().map(|(i, g)| (format!("g{}", i), g));
+        gains.chain(self.bias.iter().enumerate().map(|(i, b)| (format!("b{}", i), b))).collect()
+    }
+
+    // `g{i}` for the gains and `b{i}` for the biases
+    fn set_name_prefix(&mut self, prefix: &str) {
+        for (i, g) in self.gain.iter().enumerate() {
+            set_label(g, scoped(prefix, &format!("g{}", i)));
+        }
+        for (i, b) in self.bias.iter().enumerate() {
+            set_label(b, scoped(prefix, &format!("b{}", i)));
+        }
+    }
+
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> LayerNorm {
+        LayerNorm {
+            gain: self.gain.iter().map(|g| g.clone_graph_with(copies)).collect(),
+            bias: self.bias.iter().map(|b| b.clone_graph_with(copies)).collect(),
+            eps: self.eps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{set_data_precision, set_grad_precision, values_from, Precision};
+    use crate::optim::Optimizer;
+
+    fn outputs(module: &impl Module, x: &[f64]) -> Vec<f64> {
+        module.forward(&values_from(x)).iter().map(Value::data).collect()
+    }
+
+    #[test]
+    fn clone_module_copies_the_parameters() {
+        crate::seed(1);
+        let mlp = MLP::new(2, vec![3, 1]);
+        let copy = mlp.clone_module();
+        assert_eq!(outputs(&copy, &[0.5, -0.5]), outputs(&mlp, &[0.5, -0.5]));
+
+        let before = outputs(&mlp, &[0.5, -0.5]);
+        for param in Module::parameters(&copy) {
+            param.set_data(param.data() + 1.0);
+        }
+        Module::forward(&copy, &values_from(&[0.5, -0.5]))[0].backward().unwrap();
+        assert_eq!(outputs(&mlp, &[0.5, -0.5]), before);
+        assert!(Module::parameters(&mlp).iter().all(|param| param.grad() == 0.0));
+        assert_eq!(
+            Module::named_parameters(&copy).iter().map(|(name, _)| name.clone()).collect::<Vec<_>>(),
+            Module::named_parameters(&mlp).iter().map(|(name, _)| name.clone()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn clone_module_keeps_a_tied_parameter_a_single_node() {
+        let w = Value::from(0.5);
+        let linear = Linear::from_weights(Matrix::new(1, 2, vec![w.clone(), w.clone()]), Some(vec![w.clone()]));
+        let copy = linear.clone_module();
+        let params = Module::parameters(&copy);
+        assert!(params.iter().all(|param| param.id() == params[0].id()) && params[0].id() != w.id());
+        assert_eq!(outputs(&copy, &[1.0, 2.0]), outputs(&linear, &[1.0, 2.0]));
+
+        // and so does a weight shared by two layers
+        let (encoder, decoder) = tied_autoencoder();
+        let model = Sequential::new().with_layer(encoder).with_layer(decoder);
+        let copy = model.clone_module();
+        let paths = |model: &Sequential| -> Vec<Vec<String>> {
+            sharing_report(model).into_iter().map(|shared| shared.paths).collect()
+        };
+        assert_eq!(paths(&copy).len(), 6);
+        assert_eq!(paths(&copy), paths(&model));
+        let (original, copied) = (Module::parameters(&model), Module::parameters(&copy));
+        assert!(std::iter::zip(original, copied).all(|(original, copied)| original.id() != copied.id()));
+    }
+
+    #[test]
+    fn perturb_params_changes_data_but_not_grads() {
+        let params: Vec<Value> = [1.0, -2.0, 0.5].into_iter().map(Value::from).collect();
+        for (i, param) in params.iter().enumerate() {
+            param.set_grad(i as f64);
+        }
+        perturb_params(&params, 0.1, &mut crate::rand::Rng::seed(4));
+
+        let mut rng = crate::rand::Rng::seed(4);
+        for (i, (param, data)) in std::iter::zip(&params, [1.0, -2.0, 0.5]).enumerate() {
+            assert_eq!(param.data(), data + rng.normal(0.0, 0.1));
+            assert_ne!(param.data(), data);
+            assert_eq!(param.grad(), i as f64);
+        }
+    }
+
+    #[test]
+    fn linear_matches_a_layer_of_neurons_with_the_same_weights() {
+        let linear = Linear::new(3, 2, true, Init::Uniform(1.0), &mut crate::rand::Rng::seed(2));
+        for (b, param) in linear.bias().unwrap().iter().zip([0.3, -0.7]) {
+            b.set_data(param);
+        }
+        let layer = Layer {
+            neurons: (0..2)
+                .map(|i| Neuron {
+                    w: linear.weight().row(i).to_vec(),
+                    b: linear.bias().unwrap()[i].clone(),
+                    activation: Activation::Tanh,
+                })
+                .collect(),
+        };
+        let x = [0.5, -1.0, 2.0];
+        let expected = outputs(&layer, &x);
+        let actual: Vec<f64> = outputs(&linear, &x).iter().map(|y| y.tanh()).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn linear_without_bias_has_only_weights() {
+        let mut rng = crate::rand::Rng::seed(2);
+        let linear = Linear::new(3, 2, false, Init::He, &mut rng);
+        assert!(linear.bias().is_none());
+        assert_eq!(Module::parameters(&linear).len(), 6);
+        assert_eq!(Module::parameters(&Linear::new(3, 2, true, Init::Xavier, &mut rng)).len(), 8);
+    }
+
+    #[test]
+    fn linear_gradients_match_finite_differences() {
+        let linear = Linear::new(3, 2, true, Init::Xavier, &mut crate::rand::Rng::seed(5));
+        let loss = || {
+            let ys = Module::forward(&linear, &values_from(&[0.5, -1.0, 2.0]));
+            crate::ops::add_n(&ys.iter().map(|y| y.powi(2)).collect::<Vec<Value>>())
+        };
+        loss().backward().unwrap();
+        let h = 1e-6;
+        for param in Module::parameters(&linear) {
+            let data = param.data();
+            param.set_data(data + h);
+            let up = loss().data();
+            param.set_data(data - h);
+            let down = loss().data();
+            param.set_data(data);
+            assert!((param.grad() - (up - down) / (2.0 * h)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn forward_batch_equals_forward_per_sample() {
+        crate::seed(3);
+        let mlp = MLP::new(2, vec![3, 2]);
+        let batch = vec![values_from(&[0.5, -1.0]), values_from(&[2.0, 0.0]), values_from(&[-0.3, 0.8])];
+        let outputs = mlp.forward_batch(&batch);
+        assert_eq!(outputs.len(), 3);
+        for (inputs, batched) in std::iter::zip(&batch, &outputs) {
+            let single: Vec<f64> = Module::forward(&mlp, inputs).iter().map(Value::data).collect();
+            assert_eq!(batched.iter().map(Value::data).collect::<Vec<f64>>(), single);
+        }
+    }
+
+    #[test]
+    fn batch_loss_gradient_is_the_mean_of_the_sample_gradients() {
+        crate::seed(4);
+        let mlp = MLP::new(2, vec![3, 1]);
+        let params = Module::parameters(&mlp);
+        let (x, y) = ([[0.5, -1.0], [2.0, 0.0], [-0.3, 0.8]], [0.2, -0.4, 0.9]);
+        let sample_loss = |outputs: &[Value], target: f64| (&outputs[0] - &Value::from(target)).powi(2);
+
+        let mut mean_grads = vec![0.0; params.len()];
+        for (x, &y) in std::iter::zip(&x, &y) {
+            params.iter().for_each(|p| p.zero_grad());
+            sample_loss(&Module::forward(&mlp, &values_from(x)), y).backward().unwrap();
+            for (mean, param) in std::iter::zip(&mut mean_grads, &params) {
+                *mean += param.grad() / 3.0;
+            }
+        }
+
+        params.iter().for_each(|p| p.zero_grad());
+        let batch: Vec<Vec<Value>> = x.iter().map(|x| values_from(x)).collect();
+        let terms: Vec<Value> =
+            std::iter::zip(mlp.forward_batch(&batch), y).map(|(outputs, y)| sample_loss(&outputs, y)).collect();
+        crate::loss::mean(&terms).backward().unwrap();
+        for (mean, param) in std::iter::zip(mean_grads, params) {
+            assert!((param.grad() - mean).abs() < 1e-12);
+        }
+    }
+
+    // an autoencoder 3 -> 2 -> 3 whose decoder uses the transpose of the encoder's weights
+    fn tied_autoencoder() -> (Linear, Linear) {
+        let encoder = Linear::new(3, 2, true, Init::Xavier, &mut crate::rand::Rng::seed(6));
+        let decoder = Linear::from_weights(encoder.weight().transposed_view(), Some(values_from(&[0.0; 3])));
+        (encoder, decoder)
+    }
+
+    #[test]
+    fn tied_parameters_are_counted_once() {
+        let (encoder, decoder) = tied_autoencoder();
+        let mut all = Module::parameters(&encoder);
+        all.extend(Module::parameters(&decoder));
+        assert_eq!(all.len(), 8 + 9);
+        // the 6 shared weights, the 2 encoder biases and the 3 decoder biases
+        assert_eq!(dedup_parameters(all).len(), 11);
+    }
+
+    #[test]
+    fn gradients_of_both_uses_accumulate_into_the_shared_weight() {
+        let (encoder, decoder) = tied_autoencoder();
+        let x = values_from(&[0.5, -1.0, 2.0]);
+        let hidden = Module::forward(&encoder, &x);
+        let reconstruction = Module::forward(&decoder, &hidden);
+        crate::ops::add_n(&reconstruction).backward().unwrap();
+
+        // w(0, 1) is used by the encoder as hidden[0] += w·x[1] and by the decoder as out[1] += w·hidden[0]
+        let w = encoder.weight().get(0, 1);
+        assert_eq!(w.id(), decoder.weight().get(1, 0).id());
+        let column_sum: f64 = (0..3).map(|i| decoder.weight().get(i, 0).data()).sum();
+        assert!((w.grad() - (hidden[0].data() + x[1].data() * column_sum)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn updating_a_tied_weight_changes_both_layers() {
+        let (encoder, decoder) = tied_autoencoder();
+        let before = (outputs(&encoder, &[1.0; 3]), outputs(&decoder, &[1.0; 2]));
+        let w = encoder.weight().get(1, 2);
+        w.set_data(w.data() + 1.0);
+        let after = (outputs(&encoder, &[1.0; 3]), outputs(&decoder, &[1.0; 2]));
+        assert_eq!((after.0[1] - before.0[1], after.1[2] - before.1[2]), (1.0, 1.0));
+        assert_eq!((after.0[0], after.1[0]), (before.0[0], before.1[0]));
+    }
+
+    #[test]
+    fn summary_breaks_the_parameters_down_per_layer() {
+        crate::seed(1);
+        let mlp = MLP::new(2, vec![16, 16, 1]);
+        let info = mlp.describe();
+        let per_layer: Vec<usize> = info.children.iter().map(|layer| layer.parameters).collect();
+        assert_eq!((per_layer, info.parameters), (vec![48, 272, 17], 337));
+        assert_eq!(info.parameters, Module::parameters(&mlp).len());
+
+        let table = summary(&mlp);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].starts_with("MLP ") && lines[1].ends_with(" 337"));
+        assert!(lines[2].starts_with("  Layer ") && lines[2].contains("       2       16"));
+        assert_eq!(lines[5], "Total parameters: 337");
+    }
+
+    #[test]
+    fn summary_indents_nested_sequentials() {
+        let mut rng = crate::rand::Rng::seed(1);
+        let inner = Sequential::new().with_layer(Linear::new(4, 3, true, Init::He, &mut rng));
+        let model = Sequential::new().with_layer(Linear::new(2, 4, true, Init::He, &mut rng)).with_layer(inner);
+        let table = summary(&model);
+        let rows: Vec<&str> = table.lines().skip(1).collect();
+        assert!(rows[0].starts_with("Sequential "));
+        assert!(rows[1].starts_with("  Linear "));
+        assert!(rows[2].starts_with("  Sequential "));
+        assert!(rows[3].starts_with("    Linear "));
+        assert_eq!(rows[4], format!("Total parameters: {}", Module::parameters(&model).len()));
+        let info = model.describe();
+        assert_eq!((info.inputs, info.outputs, info.parameters), (Some(2), Some(3), 12 + 15));
+    }
+
+    #[test]
+    fn frozen_parameters_are_neither_graded_nor_updated() {
+        crate::seed(2);
+        let mlp = MLP::new(2, vec![3, 1]);
+        let data = |m: &MLP| Module::parameters(m).iter().map(|p| p.data()).collect::<Vec<f64>>();
+        let train_step = |m: &MLP| {
+            let loss = (&Module::forward(m, &values_from(&[0.5, -1.0]))[0] - &Value::from(2.0)).powi(2);
+            loss.backward().unwrap();
+            let params: Vec<Value> = Module::parameters(m).into_iter().cloned().collect();
+            crate::optim::Optimizer::step(&mut crate::optim::Sgd::new(0.1), &params);
+            params.iter().for_each(Value::zero_grad);
+        };
+
+        mlp.freeze();
+        let before = data(&mlp);
+        let loss = Module::forward(&mlp, &values_from(&[0.5, -1.0]))[0].powi(2);
+        loss.backward().unwrap();
+        assert!(Module::parameters(&mlp).iter().all(|p| p.grad() == 0.0));
+        train_step(&mlp);
+        assert_eq!(data(&mlp), before);
+
+        mlp.unfreeze();
+        train_step(&mlp);
+        assert!(std::iter::zip(data(&mlp), before).all(|(after, before)| after != before));
+    }
+
+    #[test]
+    fn a_frozen_trunk_trains_only_the_head() {
+        let mut rng = crate::rand::Rng::seed(8);
+        let trunk = Linear::new(2, 3, true, Init::He, &mut rng);
+        let head = Linear::new(3, 1, true, Init::He, &mut rng);
+        for b in trunk.bias().unwrap() {
+            b.set_data(0.5);
+        }
+        trunk.freeze();
+        let snapshot = |m: &Linear| Module::parameters(m).iter().map(|p| p.data()).collect::<Vec<f64>>();
+        let (trunk_before, head_before) = (snapshot(&trunk), snapshot(&head));
+
+        let params: Vec<Value> =
+            Module::parameters(&trunk).into_iter().chain(Module::parameters(&head)).cloned().collect();
+        let mut sgd = crate::optim::Sgd::new(0.1);
+        for _ in 0..5 {
+            let hidden = Module::forward(&trunk, &values_from(&[1.0, -0.5]));
+            let loss = (&Module::forward(&head, &hidden)[0] - &Value::from(1.0)).powi(2);
+            loss.backward().unwrap();
+            crate::optim::Optimizer::step(&mut sgd, &params);
+            params.iter().for_each(Value::zero_grad);
+        }
+        assert_eq!(snapshot(&trunk), trunk_before);
+        assert!(std::iter::zip(snapshot(&head), head_before).all(|(after, before)| after != before));
+    }
+
+    #[test]
+    fn layer_norm_centers_and_scales_the_features() {
+        let norm = LayerNorm::with_eps(4, 0.0);
+        let ys = outputs(&norm, &[1.0, 2.0, 3.0, 6.0]);
+        let mean = ys.iter().sum::<f64>() / 4.0;
+        let variance = ys.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / 4.0;
+        assert!(mean.abs() < 1e-15 && (variance - 1.0).abs() < 1e-12);
+
+        norm.gain()[0].set_data(2.0);
+        norm.bias()[0].set_data(0.5);
+        assert!((outputs(&norm, &[1.0, 2.0, 3.0, 6.0])[0] - (2.0 * ys[0] + 0.5)).abs() < 1e-12);
+        assert_eq!(Module::parameters(&norm).len(), 8);
+        assert_eq!(norm.gain()[1].label().as_deref(), Some("g1"));
+    }
+
+    #[test]
+    fn grad_check_passes_for_the_nn_stack() {
+        let squared_error = |outputs: &[Value], target: &[f64]| crate::loss::mse(outputs, target);
+        crate::seed(9);
+        let mlp = MLP::new(3, vec![4, 2]);
+        let linear = Linear::new(3, 2, true, Init::He, &mut crate::rand::Rng::seed(9));
+        let norm = LayerNorm::new(3);
+        norm.gain()[2].set_data(-1.5);
+        let (x, target) = ([0.5, -1.0, 2.0], [0.3, -0.2]);
+        grad_check_module(&mlp, &x, &target, squared_error, 1e-6, 1e-6).unwrap();
+        grad_check_module(&linear, &x, &target, squared_error, 1e-6, 1e-6).unwrap();
+        grad_check_module(&norm, &x, &[0.3, -0.2, 1.0], squared_error, 1e-6, 1e-6).unwrap();
+    }
+
+    // a `Linear` whose forward pass leaves out its last bias
+    struct Disconnected(Linear);
+
+    impl Module for Disconnected {
+        fn parameters(&self) -> Vec<&Value> {
+            Module::parameters(&self.0)
+        }
+
+        fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+            let mut outputs = self.0.weight().matvec(inputs).unwrap();
+            let bias = self.0.bias().unwrap();
+            outputs[0] = &outputs[0] + &bias[0];
+            // the stale data of the bias still moves the output, but no gradient reaches it
+            outputs[1] = &outputs[1] + &Value::from(bias[1].data());
+            outputs
+        }
+
+        fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> Disconnected {
+            Disconnected(self.0.clone_module_with(copies))
+        }
+    }
+
+    #[test]
+    fn grad_check_reports_a_disconnected_parameter() {
+        let linear = Linear::new(2, 2, true, Init::He, &mut crate::rand::Rng::seed(3));
+        let module = Disconnected(linear);
+        let error = grad_check_module(&module, &[1.0, 2.0], &[0.0, 0.0], crate::loss::mse, 1e-6, 1e-6).unwrap_err();
+        assert_eq!((error.index, error.label.as_deref(), error.analytic), (5, Some("b1"), 0.0));
+        assert!(error.numeric.abs() > 1e-3);
+    }
+
+    #[test]
+    fn constructors_label_parameters_hierarchically() {
+        crate::seed(1);
+        let mlp = MLP::new(3, vec![4, 2]);
+        let labels: Vec<String> = Module::parameters(&mlp).iter().map(|p| p.label().unwrap()).collect();
+        assert_eq!(labels[..3], ["layer0.neuron0.b", "layer0.neuron0.w0", "layer0.neuron0.w1"]);
+        assert!(labels.contains(&"layer1.neuron1.w3".to_string()));
+        let named: Vec<String> = mlp.named_parameters().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(named, labels);
+
+        let linear = Linear::new(2, 2, true, Init::He, &mut crate::rand::Rng::seed(1));
+        let labels: Vec<String> = Module::parameters(&linear).iter().map(|p| p.label().unwrap()).collect();
+        assert_eq!(labels, ["w0_0", "w0_1", "w1_0", "w1_1", "b0", "b1"]);
+    }
+
+    #[test]
+    fn prefixes_compose_through_nested_sequentials() {
+        let mut rng = crate::rand::Rng::seed(1);
+        let inner = Sequential::new().with_layer(Linear::new(2, 1, false, Init::He, &mut rng));
+        let mut model = Sequential::new().with_layer(LayerNorm::new(2)).with_layer(inner);
+        let labels = |model: &Sequential| -> Vec<String> {
+            Module::parameters(model).iter().map(|p| p.label().unwrap()).collect()
+        };
+        let expected =
+            ["layer0.g0", "layer0.g1", "layer0.b0", "layer0.b1", "layer1.layer0.w0_0", "layer1.layer0.w0_1"];
+        assert_eq!(labels(&model), expected);
+        let named: Vec<String> = model.named_parameters().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(named, expected);
+
+        model.set_name_prefix("encoder");
+        assert_eq!(labels(&model)[5], "encoder.layer1.layer0.w0_1");
+    }
+
+    #[test]
+    fn embedding_rows_are_shared_parameters() {
+        let embedding = Embedding::new(4, 3, &mut crate::rand::Rng::seed(0));
+        assert_eq!((embedding.vocab_size(), embedding.dim(), embedding.parameters().len()), (4, 3, 12));
+        assert_eq!(embedding.lookup(2)[1].label().as_deref(), Some("e2_1"));
+        assert_eq!(embedding.lookup(1)[0].id(), embedding.weight().get(1, 0).id());
+
+        // index 1 looked up twice accumulates into the same leaves
+        let looked_up = embedding.forward(&values_from(&[1.0, 3.0, 1.0]));
+        assert_eq!(looked_up.len(), 9);
+        crate::ops::add_n(&looked_up).backward().unwrap();
+        let row_grads = |row| (0..3).map(|col| embedding.weight().get(row, col).grad()).collect::<Vec<f64>>();
+        assert_eq!((row_grads(0), row_grads(1), row_grads(3)), (vec![0.0; 3], vec![2.0; 3], vec![1.0; 3]));
+    }
+
+    #[test]
+    #[should_panic(expected = "index 4 in an embedding of 4 rows")]
+    fn embedding_lookup_checks_the_index() {
+        Embedding::new(4, 2, &mut crate::rand::Rng::seed(0)).lookup(4);
+    }
+
+    #[test]
+    fn classifier_predictions_agree_with_the_graph() {
+        let classifier = SoftmaxClassifier::new(3, 4, &mut crate::rand::Rng::seed(2));
+        for x in [[0.5, -1.0, 2.0], [0.0, 0.0, 0.0], [-3.0, 1.0, 0.25]] {
+            let probabilities = classifier.predict_proba(&x);
+            assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+            let graph = crate::ops::softmax(&classifier.logits(&x));
+            let from_graph: Vec<f64> = graph.iter().map(Value::data).collect();
+            for (p, q) in std::iter::zip(&probabilities, &from_graph) {
+                assert!((p - q).abs() < 1e-12);
+            }
+            // the first of the most probable classes, e.g. of four equal ones at the origin with zero biases
+            let max = probabilities.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let argmax = probabilities.iter().position(|&p| p == max).unwrap();
+            assert_eq!(classifier.predict(&x), argmax);
+            assert!((classifier.loss(&x, argmax).data() + probabilities[argmax].ln()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn dropout_keeps_the_expected_value_and_passes_through_out_of_training() {
+        let mut dropout = Dropout::new(0.25);
+        let xs: Vec<Value> = (0..4000).map(|_| Value::from(3.0)).collect();
+        let ys = crate::with_seed(0, || dropout.forward(&xs));
+        let mean = ys.iter().map(Value::data).sum::<f64>() / xs.len() as f64;
+        assert!((mean - 3.0).abs() < 0.1, "mean {}", mean);
+        // a dropped input gets no gradient, a kept one the scale
+        let y = crate::ops::add_n(&ys);
+        y.backward_unchecked();
+        for (x, y) in xs.iter().zip(&ys) {
+            assert_eq!(x.grad(), if y.data() == 0.0 { 0.0 } else { 4.0 / 3.0 });
+        }
+
+        dropout.set_training(false);
+        let ys = dropout.forward(&xs[..3]);
+        assert!(ys.iter().zip(&xs).all(|(y, x)| y.id() == x.id()));
+    }
+
+    #[test]
+    #[should_panic(expected = "dropout probability 1 is not in [0, 1)")]
+    fn dropout_rejects_dropping_everything() {
+        Dropout::new(1.0);
+    }
+
+    // The parameters of an MLP after one SGD step on a squared error, with data kept in `data` precision
+    fn first_step(data: Precision, grads: Precision) -> Vec<f64> {
+        set_data_precision(data);
+        set_grad_precision(grads);
+        crate::seed(4);
+        let mlp = MLP::new(3, vec![4, 1]);
+        let loss = crate::loss::mse(&Module::forward(&mlp, &values_from(&[0.3, -1.2, 0.7])), &[0.5]);
+        loss.backward().unwrap();
+        crate::optim::Sgd::new(0.1).step(&Module::parameters(&mlp).into_iter().cloned().collect::<Vec<_>>());
+        let params = Module::parameters(&mlp).iter().map(|param| param.data()).collect();
+        set_data_precision(Precision::F64);
+        set_grad_precision(Precision::F64);
+        params
+    }
+
+    #[test]
+    fn f32_data_with_f64_grads_steps_like_f64() {
+        let mixed = first_step(Precision::F32, Precision::F64);
+        let double = first_step(Precision::F64, Precision::F64);
+        assert_eq!(mixed.len(), double.len());
+        for (&a, &b) in mixed.iter().zip(&double) {
+            assert_eq!(a, a as f32 as f64);
+            assert!((a - b).abs() <= 4.0 * f32::EPSILON as f64 * b.abs().max(1.0), "{} vs {}", a, b);
+        }
+        assert_ne!(mixed, double);
+    }
+
+    #[test]
+    fn f64_grads_do_not_drift_over_many_contributions() {
+        // 10⁵ contributions of 0.1 to the grad of one leaf
+        let accumulate = |grads: Precision| {
+            set_data_precision(Precision::F32);
+            set_grad_precision(grads);
+            let (x, tenth) = (Value::from(1.0), Value::constant(0.1));
+            let terms: Vec<Value> = (0..100_000).map(|_| &x * &tenth).collect();
+            crate::ops::add_n(&terms).backward().unwrap();
+            set_data_precision(Precision::F64);
+            set_grad_precision(Precision::F64);
+            (x.grad(), 100_000.0 * tenth.data())
+        };
+        let (grad, exact) = accumulate(Precision::F64);
+        assert!((grad - exact).abs() < 1e-6);
+        let (grad, exact) = accumulate(Precision::F32);
+        assert!((grad - exact).abs() > 1e-2);
+    }
+
+    #[test]
+    fn all_f32_still_passes_grad_checks_at_a_looser_tolerance() {
+        set_data_precision(Precision::F32);
+        set_grad_precision(Precision::F32);
+        crate::seed(9);
+        let mlp = MLP::new(3, vec![4, 2]);
+        let (x, target) = ([0.5, -1.0, 2.0], [0.3, -0.2]);
+        let result = grad_check_module(&mlp, &x, &target, crate::loss::mse, 1e-3, 1e-2);
+        assert!(Module::parameters(&mlp).iter().all(|param| param.grad() == param.grad() as f32 as f64));
+        set_data_precision(Precision::F64);
+        set_grad_precision(Precision::F64);
+        result.unwrap();
+    }
+
+    #[test]
+    fn eval_f64_equals_the_graph_for_every_activation() {
+        let activations = [Activation::Tanh, Activation::Relu, Activation::Identity];
+        let mut rng = crate::rand::Rng::seed(12);
+        for (hidden, output) in activations.iter().flat_map(|&h| activations.iter().map(move |&o| (h, o))) {
+            crate::seed(rng.next_u64());
+            let mlp = MLP::new(3, vec![5, 4, 2]).with_activations(hidden, output);
+            for _ in 0..10 {
+                let x: Vec<f64> = (0..3).map(|_| rng.uniform(-3.0, 3.0)).collect();
+                assert_eq!(mlp.eval_f64(&x), outputs(&mlp, &x), "{:?} then {:?} at {:?}", hidden, output, x);
+            }
+        }
+    }
+
+    #[test]
+    fn activations_apply_to_the_hidden_and_output_layers() {
+        crate::seed(5);
+        let mlp = MLP::new(2, vec![8, 3]).with_activations(Activation::Relu, Activation::Identity);
+        let x = [2.0, -1.5];
+        let hidden: Vec<f64> = mlp.layers[0].forward(&values_from(&x)).iter().map(Value::data).collect();
+        assert!(hidden.iter().all(|&h| h >= 0.0) && hidden.contains(&0.0));
+        // the outputs are the plain weighted sums of the hidden ones
+        let output = &mlp.layers[1].neurons[0];
+        let sum = output.b.data() + std::iter::zip(&output.w, &hidden).map(|(w, h)| w.data() * h).sum::<f64>();
+        assert!((mlp.eval_f64(&x)[0] - sum).abs() < 1e-12);
+        assert!(Activation::from_name("relu") == Some(Activation::Relu) && Activation::from_name("gelu").is_none());
+    }
+
+    #[test]
+    fn eval_f64_reads_the_current_parameters() {
+        crate::seed(8);
+        let mlp = MLP::new(2, vec![3, 1]);
+        let x = [0.4, -0.9];
+        let before = mlp.eval_f64(&x);
+        let loss = crate::loss::mse(&mlp.forward(values_from(&x)), &[1.0]);
+        loss.backward().unwrap();
+        crate::optim::Sgd::new(0.5).step(&mlp.parameters());
+        let after = mlp.eval_f64(&x);
+        assert_ne!(after, before);
+        assert_eq!(after, outputs(&mlp, &x));
+        assert_eq!(mlp.eval_batch(&[x.to_vec(), vec![0.0, 0.0]]), [after, outputs(&mlp, &[0.0, 0.0])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "3 inputs for a network of 2 inputs")]
+    fn eval_f64_checks_the_number_of_inputs() {
+        MLP::new(2, vec![1]).eval_f64(&[1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::f64::consts::PI;
+use std::fmt::{self, Debug};
+use std::rc::Rc;
+
+use crate::engine::{NodeMap, Value};
+use crate::nn::Module;
+
+/// `max(0, x)` applied to each input, as a module without parameters, e.g. between two `Linear` layers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReLU;
+
+/// `tanh(x)` applied to each input, the activation of the neurons of an `MLP`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tanh;
+
+/// The logistic function `1 / (1 + e^-x)` applied to each input, built as `exp(-softplus(-x))`, which neither
+/// overflows nor divides for inputs of any magnitude.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sigmoid;
+
+/// The Gaussian error linear unit `x·Φ(x)` applied to each input, in its usual tanh approximation
+/// `0.5·x·(1 + tanh(sqrt(2/π)·(x + 0.044715·x³)))`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GELU;
+
+/// Inverted dropout: while training, each input is zeroed with probability `p` and the others are scaled by
+/// `1 / (1 - p)`, so that the expected output is the input. Out of training it passes the inputs through.
+///
+/// The masks are drawn from the default generator, so `crate::seed` makes them reproducible.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dropout {
+    p: f64,
+    training: bool,
+}
+
+/// A custom function applied to each input, as a module without parameters, e.g. `Lambda::new(|v| v.powi(2))`.
+///
+/// The function is shared, not copied, by `clone_module`. Leaves it captures are constants of the graphs it builds
+/// but aren't parameters of the module: a learned activation should be a module of its own.
+#[derive(Clone)]
+pub struct Lambda(pub Rc<dyn Fn(&Value) -> Value>);
+
+impl Lambda {
+    pub fn new(function: impl Fn(&Value) -> Value + 'static) -> Lambda {
+        Lambda(Rc::new(function))
+    }
+}
+
+impl Dropout {
+    /// A dropout of probability `p`, in training. Panics unless `0 <= p < 1`.
+    pub fn new(p: f64) -> Dropout {
+        assert!((0.0..1.0).contains(&p), "dropout probability {} is not in [0, 1)", p);
+        Dropout { p, training: true }
+    }
+
+    pub fn p(&self) -> f64 {
+        self.p
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    /// Switches dropout on (`true`) or off (`false`), e.g. off before evaluating a trained model.
+    pub fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+impl Debug for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Lambda")
+    }
+}
+
+fn sigmoid(x: &Value) -> Value {
+    (-&(-x).softplus()).exp()
+}
+
+fn gelu(x: &Value) -> Value {
+    let inner = &(x + &(&x.powi(3) * &Value::constant(0.044715))) * &Value::constant((2.0 / PI).sqrt());
+    &(x * &Value::constant(0.5)) * &(&inner.tanh() + &Value::constant(1.0))
+}
+
+// The modules of the activations, which differ only by the function applied to each input
+macro_rules! elementwise_module {
+    ($module:ident, $function:expr) => {
+        impl Module for $module {
+            fn parameters(&self) -> Vec<&Value> {
+                Vec::new()
+            }
+
+            fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+                inputs.iter().map($function).collect()
+            }
+
+            fn clone_module_with(&self, _: &mut NodeMap<Value>) -> $module {
+                *self
+            }
+        }
+    };
+}
+
+elementwise_module!(ReLU, Value::relu);
+elementwise_module!(Tanh, Value::tanh);
+elementwise_module!(Sigmoid, sigmoid);
+elementwise_module!(GELU, gelu);
+
+impl Module for Dropout {
+    fn parameters(&self) -> Vec<&Value> {
+        Vec::new()
+    }
+
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        if !self.training || self.p == 0.0 {
+            return inputs.to_vec();
+        }
+        let kept: Vec<bool> =
+            crate::rand::with_rng(|rng| inputs.iter().map(|_| rng.uniform(0.0, 1.0) >= self.p).collect());
+        let scale = Value::constant(1.0 / (1.0 - self.p));
+        let dropped = Value::constant(0.0);
+        inputs.iter().zip(kept).map(|(input, kept)| input * if kept { &scale } else { &dropped }).collect()
+    }
+
+    fn clone_module_with(&self, _: &mut NodeMap<Value>) -> Dropout {
+        *self
+    }
+}
+
+impl Module for Lambda {
+    fn parameters(&self) -> Vec<&Value> {
+        Vec::new()
+    }
+
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        inputs.iter().map(|input| (self.0)(input)).collect()
+    }
+
+    fn clone_module_with(&self, _: &mut NodeMap<Value>) -> Lambda {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::values_from;
+    use crate::nn::{Activation, Sequential, MLP};
+    use crate::tensor::Matrix;
+    use crate::Linear;
+
+    fn data(values: &[Value]) -> Vec<f64> {
+        values.iter().map(Value::data).collect()
+    }
+
+    fn grads(values: &[&Value]) -> Vec<f64> {
+        values.iter().map(|value| value.grad()).collect()
+    }
+
+    // A `Linear` layer on the weights and biases of a layer of neurons, whose parameters are each bias followed
+    // by the weights of its neuron.
+    fn tied(parameters: &[&Value], in_dim: usize) -> Linear {
+        let neurons: Vec<&[&Value]> = parameters.chunks(in_dim + 1).collect();
+        let weights = neurons.iter().flat_map(|neuron| neuron[1..].iter().map(|&w| w.clone())).collect();
+        let biases = neurons.iter().map(|neuron| neuron[0].clone()).collect();
+        Linear::from_weights(Matrix::new(neurons.len(), in_dim, weights), Some(biases))
+    }
+
+    #[test]
+    fn sequential_linear_relu_linear_equals_a_relu_mlp() {
+        crate::with_seed(7, || {
+            let mlp = MLP::new(3, vec![4, 2]).with_activations(Activation::Relu, Activation::Identity);
+            let parameters = Module::parameters(&mlp);
+            let (hidden, output) = parameters.split_at(4 * (3 + 1));
+            let sequential =
+                Sequential::new().with_layer(tied(hidden, 3)).with_layer(ReLU).with_layer(tied(output, 4));
+            assert_eq!(Module::parameters(&sequential).len(), parameters.len());
+
+            for x in [[0.5, -1.0, 2.0], [-0.3, 0.8, 0.1], [1.5, 1.5, -2.0]] {
+                let (expected, outputs) = (mlp.forward(values_from(&x)), sequential.forward(&values_from(&x)));
+                for (expected, output) in std::iter::zip(data(&expected), data(&outputs)) {
+                    assert!((expected - output).abs() < 1e-12, "{} != {}", expected, output);
+                }
+
+                for param in &parameters {
+                    param.zero_grad();
+                }
+                crate::ops::add_n(&expected.iter().map(|y| y.powi(2)).collect::<Vec<_>>()).backward().unwrap();
+                let expected = grads(&parameters);
+                for param in &parameters {
+                    param.zero_grad();
+                }
+                crate::ops::add_n(&outputs.iter().map(|y| y.powi(2)).collect::<Vec<_>>()).backward().unwrap();
+                for (expected, grad) in std::iter::zip(expected, grads(&parameters)) {
+                    assert!((expected - grad).abs() < 1e-12, "{} != {}", expected, grad);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn a_clamping_lambda_is_relu6() {
+        let relu6 = Lambda::new(|v| v.clamp(0.0, 6.0));
+        let xs = values_from(&[-2.0, 0.5, 3.0, 6.0, 9.0]);
+        let ys = relu6.forward(&xs);
+        assert_eq!(data(&ys), [0.0, 0.5, 3.0, 6.0, 6.0]);
+        crate::ops::add_n(&ys).backward().unwrap();
+        assert_eq!(grads(&xs.iter().collect::<Vec<_>>()), [0.0, 1.0, 1.0, 1.0, 0.0]);
+        assert!(relu6.parameters().is_empty());
+        // clones share the function
+        let copy = relu6.clone_module();
+        assert!(Rc::ptr_eq(&copy.0, &relu6.0));
+        assert_eq!(format!("{:?}", copy), "Lambda");
+    }
+
+    #[test]
+    fn a_compiled_relu6_clamps_the_rebound_inputs() {
+        let x = Value::from(3.0).add_label("x");
+        let y = Lambda::new(|v| v.clamp(0.0, 6.0)).forward(std::slice::from_ref(&x)).remove(0);
+        let mut graph = y.compile();
+        for (input, expected, grad) in [(-2.0, 0.0, 0.0), (9.0, 6.0, 0.0), (0.5, 0.5, 1.0), (6.0, 6.0, 1.0)] {
+            graph.bind("x", input).unwrap();
+            assert_eq!(graph.forward_bound().unwrap(), expected);
+            x.set_grad(0.0);
+            graph.backward().unwrap();
+            assert_eq!(x.grad(), grad, "at {}", input);
+        }
+        // as for a graph rebuilt on the same data
+        x.set_data(-2.0);
+        assert_eq!(crate::engine::rewrite(&y, &crate::engine::ConstantFold).data(), 0.0);
+    }
+
+    // Checks that `module` applies `f` to each of `xs`, within `tol`, and has no parameters.
+    fn applies(module: &dyn Module, f: fn(f64) -> f64, tol: f64) {
+        let xs = values_from(&[-1.5, 0.0, 0.7]);
+        for (y, x) in std::iter::zip(data(&module.forward(&xs)), data(&xs)) {
+            assert!((y - f(x)).abs() <= tol, "{} != {}", y, f(x));
+        }
+        assert!(module.parameters().is_empty());
+    }
+
+    #[test]
+    fn activations_apply_their_function_to_each_input() {
+        applies(&ReLU, |x| x.max(0.0), 0.0);
+        applies(&Tanh, f64::tanh, 0.0);
+        applies(&Sigmoid, |x| 1.0 / (1.0 + (-x).exp()), 1e-15);
+        applies(&GELU, |x| 0.5 * x * (1.0 + ((2.0 / PI).sqrt() * (x + 0.044715 * x.powi(3))).tanh()), 1e-15);
+    }
+
+    #[test]
+    fn sigmoid_stays_finite_and_has_its_derivative() {
+        let xs = values_from(&[-800.0, -2.0, 0.0, 3.0, 800.0]);
+        let ys = Sigmoid.forward(&xs);
+        assert!(data(&ys).iter().all(|y| y.is_finite() && (0.0..=1.0).contains(y)));
+        crate::ops::add_n(&ys).backward().unwrap();
+        for (x, y) in std::iter::zip(&xs, &ys) {
+            let expected = y.data() * (1.0 - y.data());
+            assert!((x.grad() - expected).abs() < 1e-12, "{} != {}", x.grad(), expected);
+        }
+    }
+
+    #[test]
+    fn dropout_scales_the_kept_inputs_and_passes_through_out_of_training() {
+        let xs = values_from(&[1.0; 200]);
+        let mut dropout = Dropout::new(0.25);
+        let ys = crate::with_seed(3, || dropout.forward(&xs));
+        let kept = data(&ys).iter().filter(|&&y| y != 0.0).count();
+        assert!(data(&ys).iter().all(|&y| y == 0.0 || y == 1.0 / 0.75));
+        assert!((125..175).contains(&kept), "{} kept", kept);
+        // the same seed drops the same inputs
+        assert_eq!(data(&crate::with_seed(3, || dropout.forward(&xs))), data(&ys));
+
+        dropout.set_training(false);
+        assert!(!dropout.is_training());
+        assert!(std::iter::zip(dropout.forward(&xs), &xs).all(|(y, x)| Rc::ptr_eq(&y, x)));
+        assert_eq!(data(&Dropout::new(0.0).forward(&xs)), data(&xs));
+    }
+
+    #[test]
+    #[should_panic(expected = "dropout probability 1 is not in [0, 1)")]
+    fn dropout_of_one_is_rejected() {
+        Dropout::new(1.0);
+    }
+}
@@ -0,0 +1,201 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::Value;
+use crate::nn::{Activation, Layer, Module, Neuron, MLP};
+
+/// Version of the JSON model format written by `export`. Files with a higher version are rejected by `import`.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct ModelFile {
+    format_version: u32,
+    inputs: usize,
+    layers: Vec<LayerRecord>,
+}
+
+// `weights[i]` holds the input weights of the i-th neuron and `biases[i]` its bias.
+#[derive(Serialize, Deserialize)]
+struct LayerRecord {
+    activation: String,
+    weights: Vec<Vec<f64>>,
+    biases: Vec<f64>,
+}
+
+/// Writes the layer sizes, activations and weights of `module` to a JSON file at `path`.
+///
+/// The file is meant to be read outside of this crate as well: each layer is an object with an
+/// `activation` name (see `Activation::name`), a `weights` matrix (one row per neuron) and a `biases` vector.
+pub fn export(module: &MLP, path: impl AsRef<Path>) -> Result<()> {
+    let inputs = module
+        .layers
+        .first()
+        .and_then(|layer| layer.neurons.first())
+        .map_or(0, |neuron| neuron.w.len());
+
+    let layers = module
+        .layers
+        .iter()
+        .map(|layer| LayerRecord {
+            activation: layer.neurons.first().map_or(Activation::Tanh, |n| n.activation).name().to_string(),
+            weights: layer
+                .neurons
+                .iter()
+                .map(|neuron| neuron.w.iter().map(|w| w.data()).collect())
+                .collect(),
+            biases: layer.neurons.iter().map(|neuron| neuron.b.data()).collect(),
+        })
+        .collect();
+
+    let file = ModelFile { format_version: FORMAT_VERSION, inputs, layers };
+    fs::write(path, serde_json::to_string_pretty(&file)?)
+}
+
+/// Reads a model written by `export` (or by hand in the same format) and rebuilds the `MLP`.
+///
+/// Malformed files, layers whose sizes don't chain, unsupported activations and files
+/// from a newer format version are reported as `InvalidData` errors.
+pub fn import(path: impl AsRef<Path>) -> Result<MLP> {
+    let json = fs::read_to_string(path)?;
+    let file: ModelFile = serde_json::from_str(&json).map_err(|error| invalid(error.to_string()))?;
+
+    if file.format_version > FORMAT_VERSION {
+        return Err(invalid(format!(
+            "format_version {} is newer than the supported version {}",
+            file.format_version, FORMAT_VERSION
+        )));
+    }
+
+    let mut nin = file.inputs;
+    let mut layers = Vec::with_capacity(file.layers.len());
+    for (i, record) in file.layers.into_iter().enumerate() {
+        let Some(activation) = Activation::from_name(&record.activation) else {
+            return Err(invalid(format!("layer {}: unsupported activation {:?}", i, record.activation)));
+        };
+        if record.weights.len() != record.biases.len() {
+            return Err(invalid(format!(
+                "layer {}: {} weight rows but {} biases",
+                i,
+                record.weights.len(),
+                record.biases.len()
+            )));
+        }
+        if let Some(row) = record.weights.iter().position(|row| row.len() != nin) {
+            return Err(invalid(format!(
+                "layer {}: neuron {} has {} weights, expected {}",
+                i,
+                row,
+                record.weights[row].len(),
+                nin
+            )));
+        }
+
+        nin = record.weights.len();
+        layers.push(Layer {
+            neurons: std::iter::zip(record.weights, record.biases)
+                .map(|(w, b)| Neuron {
+                    w: w.into_iter().map(Value::from).collect(),
+                    b: Value::from(b),
+                    activation,
+                })
+                .collect(),
+        });
+    }
+
+    let mut mlp = MLP { layers };
+    Module::set_name_prefix(&mut mlp, "");
+    super::register_parameters(&mlp);
+    Ok(mlp)
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::values_from;
+    use std::path::PathBuf;
+
+    // A file in the temporary directory, unique to the test and the process
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("angstromgrad-{}-{}.json", std::process::id(), name))
+    }
+
+    fn outputs(mlp: &MLP, x: &[f64]) -> Vec<f64> {
+        mlp.forward(values_from(x)).iter().map(Value::data).collect()
+    }
+
+    #[test]
+    fn activations_are_exported_by_name() {
+        crate::seed(4);
+        let mlp = MLP::new(2, vec![3, 1]).with_activations(Activation::Relu, Activation::Identity);
+        let path = temp_file("activations");
+        export(&mlp, &path).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        let imported = import(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(json.contains(r#""activation": "relu""#) && json.contains(r#""activation": "identity""#));
+        for x in [[1.0, -2.0], [-0.5, 0.25]] {
+            assert_eq!(outputs(&imported, &x), outputs(&mlp, &x));
+        }
+    }
+
+    #[test]
+    fn export_import_gives_the_same_outputs() {
+        crate::seed(3);
+        let mlp = MLP::new(3, vec![4, 2]);
+        let path = temp_file("round-trip");
+        export(&mlp, &path).unwrap();
+        let imported = import(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let x = [0.5, -1.0, 2.0];
+        assert_eq!(outputs(&imported, &x), outputs(&mlp, &x));
+    }
+
+    #[test]
+    fn hand_written_file_imports() {
+        let path = temp_file("hand-written");
+        let json = r#"{"format_version": 1, "inputs": 2, "layers": [
+            {"activation": "tanh", "weights": [[1.0, -1.0]], "biases": [0.5]}
+        ]}"#;
+        fs::write(&path, json).unwrap();
+        let mlp = import(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(outputs(&mlp, &[2.0, 1.0]), vec![1.5f64.tanh()]);
+    }
+
+    #[test]
+    fn corrupted_files_are_reported() {
+        let cases = [
+            ("newer", r#"{"format_version": 2, "inputs": 1, "layers": []}"#, "newer than the supported"),
+            ("truncated", r#"{"format_version": 1, "inputs": 1, "lay"#, "EOF"),
+            (
+                "activation",
+                r#"{"format_version": 1, "inputs": 1, "layers": [{"activation": "swish", "weights": [[1.0]],
+                "biases": [0.0]}]}"#,
+                "unsupported activation",
+            ),
+            (
+                "sizes",
+                r#"{"format_version": 1, "inputs": 2, "layers": [{"activation": "tanh", "weights": [[1.0]],
+                "biases": [0.0]}]}"#,
+                "has 1 weights, expected 2",
+            ),
+        ];
+        for (name, json, message) in cases {
+            let path = temp_file(name);
+            fs::write(&path, json).unwrap();
+            let error = import(&path).err().unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", name);
+            assert!(error.to_string().contains(message), "{}: {}", name, error);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::{self, Display};
+
+use crate::engine::{approx_eq, Value};
+use crate::nn::Module;
+
+/// The first parameter whose back-propagated gradient disagrees with its finite-difference estimate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradCheckFailure {
+    /// Position of the parameter in `Module::parameters`.
+    pub index: usize,
+    pub label: Option<String>,
+    pub analytic: f64,
+    pub numeric: f64,
+}
+
+impl Display for GradCheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "parameter {}", self.index)?;
+        if let Some(label) = &self.label {
+            write!(f, " ({})", label)?;
+        }
+        write!(f, ": backward gives {} but finite differences give {}", self.analytic, self.numeric)
+    }
+}
+
+impl std::error::Error for GradCheckFailure {}
+
+/// Checks the gradient of `loss_fn(module.forward(input), target)` with respect to every parameter of `module`
+/// against the central difference `(loss(p + eps) - loss(p - eps)) / 2·eps`, within a relative and absolute
+/// tolerance of `tol`.
+///
+/// This catches wiring bugs that op-level checks can't, such as a parameter the loss doesn't depend on
+/// (a zero gradient with a nonzero finite difference). Frozen parameters are skipped. The parameter data
+/// is left as it was, and their grads hold the result of the backward pass.
+pub fn grad_check_module(
+    module: &impl Module,
+    input: &[f64],
+    target: &[f64],
+    loss_fn: impl Fn(&[Value], &[f64]) -> Value,
+    eps: f64,
+    tol: f64,
+) -> Result<(), GradCheckFailure> {
+    let inputs: Vec<Value> = input.iter().map(|&x| Value::from(x)).collect();
+    let loss = || loss_fn(&module.forward(&inputs), target);
+
+    let params = module.parameters();
+    for param in &params {
+        param.zero_grad();
+    }
+    loss().backward_unchecked();
+
+    for (index, param) in params.iter().enumerate().filter(|(_, param)| param.requires_grad()) {
+        let data = param.data();
+        param.set_data(data + eps);
+        let above = loss().data();
+        param.set_data(data - eps);
+        let below = loss().data();
+        param.set_data(data);
+
+        let numeric = (above - below) / (2.0 * eps);
+        let analytic = param.grad();
+        if !approx_eq(analytic, numeric, tol, tol) {
+            return Err(GradCheckFailure { index, label: param.label(), analytic, numeric });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use crate::engine::Value;
+
+/// Which of a list of parameters were pruned by `prune_by_magnitude`, to keep them at zero while training
+/// goes on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PruneMask {
+    pruned: Vec<bool>,
+}
+
+impl PruneMask {
+    /// Whether the parameter at `index`, in the order given to `prune_by_magnitude`, was pruned.
+    pub fn is_pruned(&self, index: usize) -> bool {
+        self.pruned[index]
+    }
+
+    /// The number of pruned parameters.
+    pub fn count(&self) -> usize {
+        self.pruned.iter().filter(|&&pruned| pruned).count()
+    }
+
+    /// The fraction of the parameters that were pruned.
+    pub fn sparsity(&self) -> f64 {
+        if self.pruned.is_empty() {
+            return 0.0;
+        }
+        self.count() as f64 / self.pruned.len() as f64
+    }
+
+    /// Zeroes the data and grad of the pruned parameters among `params`, the same list the mask was made from,
+    /// so that they stay pruned however an optimizer step moved them. Call it after every step.
+    pub fn apply_after_step(&self, params: &[Value]) {
+        let (len, mask_len) = (params.len(), self.pruned.len());
+        assert_eq!(len, mask_len, "{} parameters for a mask of {}", len, mask_len);
+        for (param, _) in std::iter::zip(params, &self.pruned).filter(|(_, &pruned)| pruned) {
+            param.set_data(0.0);
+            param.set_grad(0.0);
+        }
+    }
+}
+
+/// Zeroes the `fraction` of `params` with the smallest |data| and returns the mask of those it pruned, e.g. for
+/// lottery-ticket experiments: train, prune, and retrain with `PruneMask::apply_after_step` after each step.
+///
+/// The number pruned is `fraction · params.len()` rounded to the nearest integer; parameters of equal magnitude
+/// are pruned in order. Panics if `fraction` is not in [0, 1].
+pub fn prune_by_magnitude(params: &[Value], fraction: f64) -> PruneMask {
+    assert!((0.0..=1.0).contains(&fraction), "can't prune a fraction {} of the parameters", fraction);
+    let count = (fraction * params.len() as f64).round() as usize;
+
+    let mut order: Vec<usize> = (0..params.len()).collect();
+    order.sort_by(|&i, &j| params[i].data().abs().total_cmp(&params[j].data().abs()));
+    let mut pruned = vec![false; params.len()];
+    for &i in &order[..count] {
+        pruned[i] = true;
+    }
+
+    let mask = PruneMask { pruned };
+    mask.apply_after_step(params);
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::values_from;
+    use crate::optim::{Optimizer, Sgd};
+
+    #[test]
+    fn the_requested_fraction_of_the_smallest_is_pruned() {
+        let params = values_from(&[0.3, -0.1, 2.0, -0.05, 0.7, 1.5, -0.2, 0.0, 4.0, -3.0]);
+        // 2.5 rounds up to 3
+        let mask = prune_by_magnitude(&params, 0.25);
+        assert_eq!((mask.count(), mask.sparsity()), (3, 0.3));
+        assert_eq!((0..10).filter(|&i| mask.is_pruned(i)).collect::<Vec<usize>>(), [1, 3, 7]);
+        let data: Vec<f64> = params.iter().map(Value::data).collect();
+        assert_eq!(data, [0.3, 0.0, 2.0, 0.0, 0.7, 1.5, -0.2, 0.0, 4.0, -3.0]);
+
+        for (fraction, count) in [(0.0, 0), (0.04, 0), (0.05, 1), (0.5, 5), (1.0, 10)] {
+            assert_eq!(prune_by_magnitude(&values_from(&[1.0; 10]), fraction).count(), count);
+        }
+        // ties go in order
+        let tied = prune_by_magnitude(&values_from(&[1.0, -1.0, 1.0]), 0.5);
+        assert_eq!([tied.is_pruned(0), tied.is_pruned(1), tied.is_pruned(2)], [true, true, false]);
+        assert_eq!(prune_by_magnitude(&[], 0.5).sparsity(), 0.0);
+    }
+
+    #[test]
+    fn pruned_weights_stay_zero_through_training() {
+        let params = values_from(&[0.01, 1.0, -0.02, 2.0]);
+        let mask = prune_by_magnitude(&params, 0.5);
+        let mut sgd = Sgd::new(0.05);
+        for _ in 0..100 {
+            params.iter().for_each(Value::zero_grad);
+            // pulls every parameter towards 1
+            let terms: Vec<Value> = params.iter().map(|p| (p - &Value::from(1.0)).powi(2)).collect();
+            crate::ops::add_n(&terms).backward().unwrap();
+            sgd.step(&params);
+            mask.apply_after_step(&params);
+        }
+        let state: Vec<(f64, f64)> = params.iter().map(|p| (p.data(), p.grad())).collect();
+        assert_eq!((state[0], state[2]), ((0.0, 0.0), (0.0, 0.0)));
+        assert!((state[1].0 - 1.0).abs() < 1e-6 && (state[3].0 - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't prune a fraction 1.5")]
+    fn fractions_above_one_are_rejected() {
+        prune_by_magnitude(&values_from(&[1.0]), 1.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "2 parameters for a mask of 1")]
+    fn masks_apply_to_their_own_parameters() {
+        prune_by_magnitude(&values_from(&[1.0]), 1.0).apply_after_step(&values_from(&[1.0, 2.0]));
+    }
+}
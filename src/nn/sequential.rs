@@ -0,0 +1,106 @@
+use std::fmt::{self, Debug};
+
+use crate::engine::{NodeMap, Value};
+use crate::nn::{scoped, LayerInfo, Module};
+
+/// A module a `Sequential` can hold: any `Module` that owns its parameters, boxed so that layers of different
+/// types can follow each other.
+pub trait BoxedModule: Module {
+    /// `Module::clone_module_with`, boxed.
+    fn clone_boxed(&self, copies: &mut NodeMap<Value>) -> Box<dyn BoxedModule>;
+}
+
+impl<M: Module + 'static> BoxedModule for M {
+    fn clone_boxed(&self, copies: &mut NodeMap<Value>) -> Box<dyn BoxedModule> {
+        Box::new(self.clone_module_with(copies))
+    }
+}
+
+/// Layers applied one after the other, the outputs of each being the inputs of the next, e.g.
+/// `Sequential::new().with_layer(linear).with_layer(ReLU).with_layer(output)`.
+///
+/// The parameters of the `i`-th layer are named under `layer{i}`, as in an `MLP`. Layers built on shared nodes,
+/// e.g. a decoder tied to an encoder through `Linear::from_weights`, keep sharing them, in copies by
+/// `clone_module` too.
+#[derive(Default)]
+pub struct Sequential {
+    layers: Vec<Box<dyn BoxedModule>>,
+}
+
+impl Sequential {
+    pub fn new() -> Sequential {
+        Sequential::default()
+    }
+
+    /// Appends `layer`, relabeling its parameters under `layer{i}` for its position.
+    pub fn with_layer(mut self, mut layer: impl Module + 'static) -> Sequential {
+        layer.set_name_prefix(&format!("layer{}", self.layers.len()));
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn layers(&self) -> &[Box<dyn BoxedModule>] {
+        &self.layers
+    }
+}
+
+impl Debug for Sequential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.layers.iter().map(|layer| layer.describe().name).collect();
+        f.debug_tuple("Sequential").field(&names).finish()
+    }
+}
+
+impl Module for Sequential {
+    fn parameters(&self) -> Vec<&Value> {
+        self.layers.iter().flat_map(|layer| layer.parameters()).collect()
+    }
+
+    fn forward(&self, inputs: &[Value]) -> Vec<Value> {
+        self.layers.iter().fold(inputs.to_vec(), |xs, layer| layer.forward(&xs))
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        self.layers.iter().fold(batch.to_vec(), |xs, layer| layer.forward_batch(&xs))
+    }
+
+    fn describe(&self) -> LayerInfo {
+        let children: Vec<LayerInfo> = self.layers.iter().map(|layer| layer.describe()).collect();
+        LayerInfo {
+            name: "Sequential".to_string(),
+            inputs: children.iter().find_map(|layer| layer.inputs),
+            outputs: children.iter().rev().find_map(|layer| layer.outputs),
+            parameters: children.iter().map(|layer| layer.parameters).sum(),
+            children,
+        }
+    }
+
+    fn named_parameters(&self) -> Vec<(String, &Value)> {
+        self.layers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, layer)| {
+                let prefix = format!("layer{}", i);
+                layer.named_parameters().into_iter().map(move |(name, param)| (scoped(&prefix, &name), param))
+            })
+            .collect()
+    }
+
+    fn set_name_prefix(&mut self, prefix: &str) {
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            layer.set_name_prefix(&scoped(prefix, &format!("layer{}", i)));
+        }
+    }
+
+    fn clone_module_with(&self, copies: &mut NodeMap<Value>) -> Sequential {
+        Sequential { layers: self.layers.iter().map(|layer| layer.clone_boxed(copies)).collect() }
+    }
+}
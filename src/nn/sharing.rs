@@ -0,0 +1,102 @@
+use crate::engine::{NodeId, NodeMap};
+use crate::nn::Module;
+
+/// A parameter that appears at more than one place of a module tree, e.g. a bias whose handle was cloned into two
+/// layers instead of creating a new leaf.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedParam {
+    pub id: NodeId,
+    /// Every path of the parameter in the module tree (see `Module::named_parameters`), in order.
+    pub paths: Vec<String>,
+}
+
+/// The parameters of `module` that appear more than once, told apart by identity, in order of first appearance.
+/// Deliberate sharing such as tied weights shows up here too, see `assert_no_unintended_sharing`.
+pub fn sharing_report(module: &impl Module) -> Vec<SharedParam> {
+    let mut index: NodeMap<usize> = NodeMap::new();
+    let mut params: Vec<SharedParam> = Vec::new();
+    for (path, param) in module.named_parameters() {
+        match index.get(param) {
+            Some(&i) => params[i].paths.push(path),
+            None => {
+                index.insert(param, params.len());
+                params.push(SharedParam { id: param.id(), paths: vec![path] });
+            }
+        }
+    }
+    params.retain(|param| param.paths.len() > 1);
+    params
+}
+
+/// Panics, listing the paths involved, if `module` shares a parameter between places that aren't all within
+/// `allowed`: path prefixes, such as `layer0` or `layer0.neuron1.b`, under which sharing is intended, e.g. between
+/// an encoder and a decoder with tied weights. A prefix covers a path when it is the path itself or one of its
+/// leading components.
+pub fn assert_no_unintended_sharing(module: &impl Module, allowed: &[&str]) {
+    let covered = |path: &str| {
+        allowed
+            .iter()
+            .any(|prefix| path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('.')))
+    };
+    let unintended: Vec<String> = sharing_report(module)
+        .into_iter()
+        .filter(|param| !param.paths.iter().all(|path| covered(path)))
+        .map(|param| format!("node {} at {}", param.id, param.paths.join(", ")))
+        .collect();
+    assert!(unintended.is_empty(), "parameters shared unintentionally: {}", unintended.join("; "));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Value;
+    use crate::nn::{Init, Linear, ReLU, Sequential, MLP};
+    use crate::rand::Rng;
+
+    // An encoder and a decoder whose bias is, by mistake, the handle of the encoder's
+    fn with_copied_bias() -> Sequential {
+        let encoder = Linear::new(2, 2, true, Init::He, &mut Rng::seed(1));
+        let bias = encoder.bias().unwrap().to_vec();
+        let weight = Linear::new(2, 2, false, Init::He, &mut Rng::seed(2)).weight().clone();
+        Sequential::new().with_layer(encoder).with_layer(ReLU).with_layer(Linear::from_weights(weight, Some(bias)))
+    }
+
+    #[test]
+    fn a_clean_mlp_shares_nothing() {
+        let mlp = MLP::new(3, vec![4, 4, 1]);
+        assert!(sharing_report(&mlp).is_empty());
+        assert_no_unintended_sharing(&mlp, &[]);
+    }
+
+    #[test]
+    fn tied_weights_are_reported_and_can_be_allowed() {
+        let encoder = Linear::new(2, 2, false, Init::He, &mut Rng::seed(1));
+        let bias = vec![Value::from(0.0), Value::from(0.0)];
+        let decoder = Linear::from_weights(encoder.weight().clone(), Some(bias));
+        let model = Sequential::new().with_layer(encoder).with_layer(decoder);
+
+        let report = sharing_report(&model);
+        assert_eq!(report.len(), 4);
+        assert_eq!(report[0].paths, ["layer0.w0_0", "layer1.w0_0"]);
+        let weights = model.layers()[0].parameters();
+        assert!(std::iter::zip(&report, weights).all(|(shared, weight)| shared.id == weight.id()));
+        assert_no_unintended_sharing(&model, &["layer0", "layer1"]);
+    }
+
+    #[test]
+    fn an_accidental_duplicate_is_caught() {
+        let model = with_copied_bias();
+        let report = sharing_report(&model);
+        let paths: Vec<&[String]> = report.iter().map(|shared| &shared.paths[..]).collect();
+        assert_eq!(paths, [["layer0.b0", "layer2.b0"], ["layer0.b1", "layer2.b1"]]);
+        // a prefix only covers whole path components
+        let allowed = ["layer0", "layer"];
+        assert!(std::panic::catch_unwind(|| assert_no_unintended_sharing(&with_copied_bias(), &allowed)).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "parameters shared unintentionally: node")]
+    fn sharing_within_only_part_of_the_allowlist_panics() {
+        assert_no_unintended_sharing(&with_copied_bias(), &["layer0"]);
+    }
+}
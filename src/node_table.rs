@@ -0,0 +1,84 @@
+use crate::engine::{NodeMap, Op, Value};
+
+// A graph flattened into a table of nodes in topological order: children always come before
+// the nodes that use them and refer to them by index, so shared nodes are stored once.
+// The root is the last node.
+// This is the representation of both the JSON dumps of the `serde` feature and the binary format.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct NodeTable {
+    pub nodes: Vec<NodeRecord>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct NodeRecord {
+    pub op: Option<Op>,
+    pub data: f64,
+    pub grad: f64,
+    pub label: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub frozen: bool,
+    pub children: Vec<usize>,
+}
+
+impl NodeTable {
+    // The table of the graph reachable from `root`.
+    pub fn of(root: &Value) -> NodeTable {
+        let order = root.topo_order();
+        let mut index = NodeMap::new();
+        for (i, value) in order.iter().enumerate() {
+            index.insert(value, i);
+        }
+
+        let nodes = order
+            .iter()
+            .map(|value| {
+                let node = value.borrow();
+                NodeRecord {
+                    op: node._op,
+                    data: node.data,
+                    grad: node.grad,
+                    label: node.label.clone(),
+                    frozen: !node.requires_grad,
+                    children: node._prev.iter().map(|child| index[child]).collect(),
+                }
+            })
+            .collect();
+
+        NodeTable { nodes }
+    }
+
+    // Rebuilds the graph and returns its root, or a description of what makes the table invalid.
+    //
+    // Every interior node is rebuilt through the constructor of its op, which restores its propagation
+    // function; the stored data and grad then overwrite the recomputed ones, so a graph saved after `backward`
+    // keeps its grads.
+    pub fn build(self) -> Result<Value, String> {
+        let mut values: Vec<Value> = Vec::with_capacity(self.nodes.len());
+
+        for (i, record) in self.nodes.into_iter().enumerate() {
+            if let Some(&child) = record.children.iter().find(|&&child| child >= i) {
+                return Err(format!("node {} refers to node {} which doesn't come before it", i, child));
+            }
+            let children: Vec<Value> = record.children.iter().map(|&child| values[child].clone()).collect();
+
+            let value = match record.op {
+                Some(op) => op.apply(&children).ok_or_else(|| {
+                    format!("node {}: op {} can't be applied to {} children", i, op, children.len())
+                })?,
+                None if children.is_empty() => Value::from(record.data),
+                None => return Err(format!("node {} has children but no op", i)),
+            };
+
+            {
+                let mut node = value.borrow_mut();
+                node.data = record.data;
+                node.label = record.label;
+                node.requires_grad = !record.frozen;
+            }
+            value.set_grad(record.grad);
+            values.push(value);
+        }
+
+        values.pop().ok_or_else(|| "the graph contains no nodes".to_string())
+    }
+}
@@ -0,0 +1,1000 @@
+use std::cell::Ref;
+use std::cmp::Ordering;
+
+use crate::engine::{Op, PropagateFn, Value, _Value};
+use crate::error::GradError;
+use crate::tensor::Matrix;
+
+/// Adds all of `values` in a single node, whose backward hands the upstream gradient to every child.
+///
+/// Compared to folding with `+`, the graph is one node deep whatever the number of terms.
+#[track_caller]
+pub fn add_n(values: &[Value]) -> Value {
+    let result = values.iter().map(|v| v.data()).sum();
+
+    let propagate_fn: PropagateFn = |value| {
+        for child in &value._prev {
+            child.add_grad(value.grad);
+        }
+    };
+
+    Value::new(_Value::new(
+        result,
+        None,
+        Some(Op::Add),
+        values.to_vec(),
+        Some(propagate_fn),
+    ))
+}
+
+/// Sums above this many terms are computed with compensated summation by `loss::mean`.
+pub const COMPENSATED_SUM_THRESHOLD: usize = 1024;
+
+/// Same as `add_n`, with the data computed by Kahan summation, which carries the rounding error of each
+/// addition over to the next. The error of the sum stays within a few ulps however many terms it has where a
+/// plain sum's grows with their number, e.g. when adding up a million per-sample losses.
+///
+/// The backward is that of `add_n`. The node is an `Op::SumCompensated`, so re-running the graph through
+/// `compile` sums the terms the same way.
+#[track_caller]
+pub fn sum_compensated(values: &[Value]) -> Value {
+    let result = kahan_sum(values.iter().map(Value::data));
+
+    let propagate_fn: PropagateFn = |value| {
+        for child in &value._prev {
+            child.add_grad(value.grad);
+        }
+    };
+
+    Value::new(_Value::new(result, None, Some(Op::SumCompensated), values.to_vec(), Some(propagate_fn)))
+}
+
+pub(crate) fn kahan_sum(xs: impl Iterator<Item = f64>) -> f64 {
+    let (mut sum, mut compensation) = (0.0, 0.0);
+    for x in xs {
+        let y = x - compensation;
+        let t = sum + y;
+        compensation = (t - sum) - y;
+        sum = t;
+    }
+    sum
+}
+
+/// Multiplies all of `values` in a single node, whose backward hands each child the product of the others.
+///
+/// Zero factors are counted rather than divided by: with a single zero only that child receives a gradient,
+/// with two or more every gradient is zero.
+#[track_caller]
+pub fn mul_n(values: &[Value]) -> Value {
+    let result = values.iter().map(|v| v.data()).product();
+
+    Value::new(_Value::new(
+        result,
+        None,
+        Some(Op::Mul),
+        values.to_vec(),
+        Some(propagate_product),
+    ))
+}
+
+// The propagation of a product of any number of factors, each receiving the product of the others.
+pub(crate) fn propagate_product(value: &Ref<_Value>) {
+    let factors: Vec<f64> = value._prev.iter().map(|child| child.borrow().data).collect();
+    let zeros = factors.iter().filter(|&&f| f == 0.0).count();
+    let nonzero_product: f64 = factors.iter().filter(|&&f| f != 0.0).product();
+
+    for (child, factor) in std::iter::zip(&value._prev, factors) {
+        let others = match zeros {
+            0 => nonzero_product / factor,
+            1 if factor == 0.0 => nonzero_product,
+            _ => 0.0,
+        };
+        child.add_grad(others * value.grad);
+    }
+}
+
+/// Log-density of the constant observation `x` under a Gaussian with the given mean and log-variance:
+/// `-0.5·(ln(2π) + log_var + (x - mean)²·exp(-log_var))`.
+///
+/// Parameterizing by the log-variance keeps the variance positive without constraints, e.g. for
+/// heteroscedastic regression where a model outputs both the mean and the log-variance.
+pub fn gaussian_log_pdf(x: f64, mean: &Value, log_var: &Value) -> Value {
+    let diff = &Value::constant(x) - mean;
+    let scaled = &(&diff * &diff) * &(-log_var).exp();
+    let sum = add_n(&[Value::constant((2.0 * std::f64::consts::PI).ln()), log_var.clone(), scaled]);
+    &sum * &Value::constant(-0.5)
+}
+
+/// Maps any value to a positive one through softplus, `ln(1 + e^v)`, e.g. to turn a raw model output into a variance.
+pub fn positive(v: &Value) -> Value {
+    v.softplus()
+}
+
+/// Evaluates the polynomial with the given coefficients at `x` using Horner's scheme, i.e. with one
+/// multiplication and one addition per degree instead of a power per term.
+///
+/// Coefficients are ordered from the highest degree down, `coeffs[n]` being the constant term.
+/// A single coefficient is returned as is, and no coefficients evaluate to a constant zero.
+pub fn polyval(coeffs: &[Value], x: &Value) -> Value {
+    let Some((first, rest)) = coeffs.split_first() else {
+        return Value::constant(0.0);
+    };
+    rest.iter().fold(first.clone(), |acc, c| &(&acc * x) + c)
+}
+
+/// Linear interpolation `a + t·(b - a)`, differentiable in the endpoints as well as in `t`.
+pub fn lerp(a: &Value, b: &Value, t: &Value) -> Value {
+    a + &(t * &(b - a))
+}
+
+/// Hermite interpolation `3t² - 2t³` of `t = (x - edge0) / (edge1 - edge0)` clamped to `[0, 1]`.
+///
+/// Below `edge0` and above `edge1` the result is the constant 0 or 1, so `x` receives no gradient there;
+/// the cubic's slope also vanishes at both edges, which keeps the gradient continuous.
+pub fn smoothstep(edge0: f64, edge1: f64, x: &Value) -> Value {
+    assert!(edge0 < edge1, "smoothstep needs edge0 < edge1, got {} and {}", edge0, edge1);
+    if x.data() <= edge0 {
+        return Value::constant(0.0);
+    }
+    if x.data() >= edge1 {
+        return Value::constant(1.0);
+    }
+    let t = &(x - &Value::constant(edge0)) * &Value::constant(1.0 / (edge1 - edge0));
+    &(&t * &t) * &(&Value::constant(3.0) - &(&t * &Value::constant(2.0)))
+}
+
+/// The piecewise-linear function through the points `(knots_x[i], knots_y[i])`, evaluated at `x`: the line
+/// between the two knots bracketing `x`, e.g. as a learnable calibration curve over fixed x-knots.
+///
+/// The bracketing y-knots receive gradient by their interpolation weights and `x` by the slope of the segment.
+/// Below the first knot and above the last, the end segments are extended with their slope. At a knot exactly,
+/// the segment on its right is used (the last segment at the last knot), so `x` gets that segment's slope.
+/// Panics unless there are at least two knots, as many y-knots as x-knots, and strictly increasing x-knots.
+pub fn piecewise_linear(x: &Value, knots_x: &[f64], knots_y: &[Value]) -> Value {
+    assert!(knots_x.len() >= 2, "piecewise_linear needs at least two knots, got {}", knots_x.len());
+    assert_eq!(
+        knots_x.len(),
+        knots_y.len(),
+        "piecewise_linear got {} x-knots but {} y-knots",
+        knots_x.len(),
+        knots_y.len()
+    );
+    for pair in knots_x.windows(2) {
+        assert!(pair[0] < pair[1], "piecewise_linear needs increasing x-knots, got {} then {}", pair[0], pair[1]);
+    }
+    let i = knots_x[1..knots_x.len() - 1].partition_point(|&knot| knot <= x.data());
+    let (x0, x1) = (knots_x[i], knots_x[i + 1]);
+    let t = &(x - &Value::constant(x0)) * &Value::constant(1.0 / (x1 - x0));
+    lerp(&knots_y[i], &knots_y[i + 1], &t)
+}
+
+/// `a` if `cond` holds and `b` otherwise. The chosen node itself is returned, so the gradient of anything
+/// built on the result flows into the selected branch only.
+pub fn select(cond: bool, a: &Value, b: &Value) -> Value {
+    if cond { a.clone() } else { b.clone() }
+}
+
+/// `a` if `gate.data > 0` and `b` otherwise, including for a gate of exactly 0, as in a piecewise loss.
+///
+/// The branch is decided by the current data; the gate is not part of the result and receives no gradient.
+pub fn where_positive(gate: &Value, a: &Value, b: &Value) -> Value {
+    select(gate.data() > 0.0, a, b)
+}
+
+/// `a / b` with the denominator kept away from zero: `b` is replaced by `signum(b) · max(|b|, eps)`, e.g. to
+/// divide by a norm or a variance that may underflow. Unlike `Value::try_div`, which reports a zero
+/// denominator, the result and its gradients are always finite for finite `a` and positive `eps`.
+///
+/// For `|b| >= eps` this is exactly `a / b`. Below, the denominator is `eps` with the sign of `b`, positive for
+/// both signed zeros, and `b` still receives the gradient of a division by it, `-a / d²`: it is built as `b`
+/// shifted by a constant, so that a denominator that has underflowed can grow back. The branch is decided by the
+/// current data of `b`.
+pub fn safe_div(a: &Value, b: &Value, eps: f64) -> Value {
+    assert!(eps > 0.0, "safe_div needs a positive epsilon, got {}", eps);
+    let denominator = b.data();
+    if denominator.abs() >= eps {
+        return a / b;
+    }
+    let stabilized = if denominator < 0.0 { -eps } else { eps };
+    a / &(b + &Value::constant(stabilized - denominator))
+}
+
+/// Running sums of `xs`, the i-th output being `xs[0] + … + xs[i]`.
+///
+/// Each output is built from the previous one and a single new element, so the graph has one node per element.
+pub fn cumsum(xs: &[Value]) -> Vec<Value> {
+    scan(xs, |acc, x| acc + x)
+}
+
+/// Running products of `xs`, the i-th output being `xs[0] · … · xs[i]`, e.g. for discount factors.
+///
+/// Like `cumsum`, each output multiplies the previous one by a single new element, so a zero element
+/// gives exact gradients instead of dividing by zero.
+pub fn cumprod(xs: &[Value]) -> Vec<Value> {
+    scan(xs, |acc, x| acc * x)
+}
+
+fn scan(xs: &[Value], f: impl Fn(&Value, &Value) -> Value) -> Vec<Value> {
+    let mut outputs: Vec<Value> = Vec::with_capacity(xs.len());
+    for x in xs {
+        let next = match outputs.last() {
+            Some(acc) => f(acc, x),
+            None => x.clone(),
+        };
+        outputs.push(next);
+    }
+    outputs
+}
+
+/// Sum of the elements of `xs` whose `mask` entry is true, e.g. to leave padding out of a sequence loss.
+///
+/// Masked-out elements are not part of the result and receive no gradient. An all-false mask sums to a
+/// constant zero.
+pub fn masked_sum(xs: &[Value], mask: &[bool]) -> Value {
+    assert_eq!(xs.len(), mask.len(), "masked_sum got {} values but {} mask entries", xs.len(), mask.len());
+    let selected: Vec<Value> = std::iter::zip(xs, mask)
+        .filter(|&(_, &keep)| keep)
+        .map(|(x, _)| x.clone())
+        .collect();
+    if selected.is_empty() {
+        return Value::constant(0.0);
+    }
+    add_n(&selected)
+}
+
+/// Mean of the elements of `xs` whose `mask` entry is true, dividing by the number of true entries.
+///
+/// As with `masked_sum`, an all-false mask gives a constant zero rather than dividing by zero, so that
+/// a sequence made only of padding adds nothing to a loss.
+pub fn masked_mean(xs: &[Value], mask: &[bool]) -> Value {
+    let count = mask.iter().filter(|&&keep| keep).count();
+    let sum = masked_sum(xs, mask);
+    if count == 0 {
+        return sum;
+    }
+    &sum * &Value::constant(1.0 / count as f64)
+}
+
+/// The element of `xs` with the largest data. It is returned itself, so the whole upstream gradient goes
+/// to that element and none to the others, as in max pooling.
+///
+/// Elements are compared with `Value::total_cmp`, so a NaN (of positive sign, as produced by arithmetic) is
+/// larger than any number, and ties go to the first occurrence. An empty slice is an error.
+pub fn max_of(xs: &[Value]) -> Result<Value, GradError> {
+    argmax_value(xs).map(|(_, x)| x)
+}
+
+/// The element of `xs` with the smallest data, with the same gradient routing and tie rule as `max_of`.
+pub fn min_of(xs: &[Value]) -> Result<Value, GradError> {
+    best_of(xs, "min_of", Ordering::Less).map(|(_, x)| x)
+}
+
+/// Like `max_of`, also returning the index of the selected element.
+pub fn argmax_value(xs: &[Value]) -> Result<(usize, Value), GradError> {
+    best_of(xs, "max_of", Ordering::Greater)
+}
+
+// the first element that no later element compares `better` than
+fn best_of(xs: &[Value], op: &'static str, better: Ordering) -> Result<(usize, Value), GradError> {
+    let mut best = 0;
+    for (i, x) in xs.iter().enumerate().skip(1) {
+        if x.total_cmp(&xs[best]) == better {
+            best = i;
+        }
+    }
+    xs.get(best).map(|x| (best, x.clone())).ok_or(GradError::EmptyInput { op })
+}
+
+/// The `k` elements of `xs` with the largest data, with their indices, from the largest down; e.g. to build
+/// a loss over the hardest examples of a batch only.
+///
+/// The elements are the original nodes, so only their subgraphs receive gradient from what is built on them.
+/// Elements are ordered by `Value::total_cmp`, NaN included. The sort is stable, equal elements keeping their
+/// order in `xs`. Asking for more than `xs.len()` is an error.
+pub fn topk(xs: &[Value], k: usize) -> Result<Vec<(usize, Value)>, GradError> {
+    if k > xs.len() {
+        return Err(GradError::TooFewElements { op: "topk", requested: k, len: xs.len() });
+    }
+    let mut indices: Vec<usize> = (0..xs.len()).collect();
+    indices.sort_by(|&i, &j| xs[j].total_cmp(&xs[i]));
+    Ok(indices.into_iter().take(k).map(|i| (i, xs[i].clone())).collect())
+}
+
+/// Cross-correlation of `signal` with `kernel` (the "convolution" of neural networks, without flipping the
+/// kernel), with `padding` zeros on each side of the signal and the kernel moved `stride` positions at a time.
+///
+/// There are `(signal.len() + 2·padding - kernel.len()) / stride + 1` outputs, each one `add_n` node over the
+/// products of the kernel with the signal under it; the padding adds no nodes, and a window lying entirely in
+/// the padding is a constant zero. Gradients flow into both the signal and the kernel. An empty kernel, or one
+/// longer than the padded signal, is an error.
+pub fn conv1d(
+    signal: &[Value],
+    kernel: &[Value],
+    stride: usize,
+    padding: usize,
+) -> Result<Vec<Value>, GradError> {
+    assert!(stride > 0, "conv1d needs a positive stride");
+    if kernel.is_empty() {
+        return Err(GradError::EmptyInput { op: "conv1d" });
+    }
+    let padded = signal.len() + 2 * padding;
+    if kernel.len() > padded {
+        return Err(GradError::TooFewElements { op: "conv1d", requested: kernel.len(), len: padded });
+    }
+
+    let outputs = (padded - kernel.len()) / stride + 1;
+    Ok((0..outputs)
+        .map(|o| {
+            let products: Vec<Value> = kernel
+                .iter()
+                .enumerate()
+                .filter_map(|(k, w)| {
+                    let i = (o * stride + k).checked_sub(padding)?;
+                    signal.get(i).map(|x| w * x)
+                })
+                .collect();
+            if products.is_empty() {
+                return Value::constant(0.0);
+            }
+            add_n(&products)
+        })
+        .collect())
+}
+
+/// The maximum of each window of `width` elements of `xs`, windows starting every `stride` elements.
+///
+/// There are `(xs.len() - width) / stride + 1` outputs, each the winning element itself as in `max_of`, so the
+/// gradient of an output lands on its window's maximum only. A window wider than `xs` is an error.
+pub fn max_pool1d(xs: &[Value], width: usize, stride: usize) -> Result<Vec<Value>, GradError> {
+    assert!(width > 0 && stride > 0, "max_pool1d needs a positive width and stride");
+    if width > xs.len() {
+        return Err(GradError::TooFewElements { op: "max_pool1d", requested: width, len: xs.len() });
+    }
+    (0..(xs.len() - width) / stride + 1)
+        .map(|o| max_of(&xs[o * stride..o * stride + width]))
+        .collect()
+}
+
+/// The elements of all of `slices` one after the other. The nodes are the same, not copies, so gradients
+/// flowing into the result reach the original slices.
+pub fn concat(slices: &[&[Value]]) -> Vec<Value> {
+    slices.concat()
+}
+
+/// Cuts `xs` into consecutive pieces of the given sizes, the reverse of `concat`.
+///
+/// The sizes must add up to `xs.len()` exactly; otherwise the error gives both lengths as `n x 1` shapes.
+pub fn split(xs: &[Value], sizes: &[usize]) -> Result<Vec<Vec<Value>>, GradError> {
+    let total: usize = sizes.iter().sum();
+    if total != xs.len() {
+        return Err(GradError::ShapeMismatch { op: "split", left: (xs.len(), 1), right: (total, 1) });
+    }
+    let mut rest = xs;
+    Ok(sizes
+        .iter()
+        .map(|&size| {
+            let (piece, tail) = rest.split_at(size);
+            rest = tail;
+            piece.to_vec()
+        })
+        .collect())
+}
+
+/// A matrix with `rows` as its rows, which must all have the same length. No rows give a 0x0 matrix.
+pub fn stack_rows(rows: &[Vec<Value>]) -> Result<Matrix, GradError> {
+    let cols = rows.first().map_or(0, Vec::len);
+    if let Some(row) = rows.iter().find(|row| row.len() != cols) {
+        return Err(GradError::ShapeMismatch { op: "stack_rows", left: (1, cols), right: (1, row.len()) });
+    }
+    Ok(Matrix::new(rows.len(), cols, rows.concat()))
+}
+
+/// The softmax `exp(x_i) / Σ exp(x_j)` of `xs`, e.g. turning logits into probabilities.
+pub fn softmax(xs: &[Value]) -> Vec<Value> {
+    softmax_t(xs, 1.0)
+}
+
+/// The softmax of `xs / temperature`: high temperatures flatten the distribution towards uniform, low ones
+/// sharpen it towards a one-hot of the largest element.
+///
+/// The largest input is subtracted first as a constant, which leaves the result and its gradients unchanged
+/// but keeps the exponentials from overflowing.
+pub fn softmax_t(xs: &[Value], temperature: f64) -> Vec<Value> {
+    assert!(temperature > 0.0, "softmax needs a positive temperature, got {}", temperature);
+    let Ok(max) = max_of(xs).map(|x| x.data()) else {
+        return Vec::new();
+    };
+    let scale = Value::constant(1.0 / temperature);
+    let shift = Value::constant(-max / temperature);
+    let exps: Vec<Value> = xs.iter().map(|x| (&(x * &scale) + &shift).exp()).collect();
+    let total = add_n(&exps);
+    exps.iter().map(|e| e / &total).collect()
+}
+
+/// A differentiable sample of the categorical distribution with logits `xs`: `softmax_t(xs + g, temperature)`
+/// with `g` standard Gumbel noise drawn from `rng`.
+///
+/// The noise is drawn once and added as constants, so the result is differentiable with respect to the logits.
+/// As the temperature goes to 0 the sample approaches the one-hot of a draw from `softmax(xs)`.
+pub fn gumbel_softmax(xs: &[Value], temperature: f64, rng: &mut crate::rand::Rng) -> Vec<Value> {
+    let perturbed: Vec<Value> = xs.iter().map(|x| x + &Value::constant(rng.gumbel())).collect();
+    softmax_t(&perturbed, temperature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    use crate::engine::values_from;
+    use crate::{assert_grad_eq, assert_value_eq};
+
+    fn grads(values: &[Value]) -> Vec<f64> {
+        values.iter().map(Value::grad).collect()
+    }
+
+    #[test]
+    fn mul_n_gradients_with_one_zero_factor() {
+        let xs = values_from(&[2.0, 0.0, 3.0, 4.0]);
+        let product = mul_n(&xs);
+        product.backward().unwrap();
+        assert_eq!(product.data(), 0.0);
+        assert_eq!(grads(&xs), vec![0.0, 24.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn mul_n_gradients_with_two_zero_factors() {
+        let xs = values_from(&[2.0, 0.0, 3.0, 0.0]);
+        mul_n(&xs).backward().unwrap();
+        assert_eq!(grads(&xs), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn mul_n_gradients_without_zero_factors() {
+        let xs = values_from(&[2.0, -0.5, 3.0]);
+        mul_n(&xs).backward().unwrap();
+        assert_eq!(grads(&xs), vec![-1.5, 6.0, -1.0]);
+    }
+
+    #[test]
+    fn add_n_is_a_single_node_and_stack_safe() {
+        let xs: Vec<Value> = (0..100_000).map(|i| Value::from(i as f64)).collect();
+        let sum = add_n(&xs);
+        assert_eq!(sum.children().len(), 100_000);
+        assert_eq!(sum.data(), 4_999_950_000.0);
+        sum.backward().unwrap();
+        assert!(xs.iter().all(|x| x.grad() == 1.0));
+    }
+
+    #[test]
+    fn sum_and_product_build_n_ary_nodes() {
+        let xs = values_from(&[1.0, 2.0, 3.0]);
+        let sum: Value = xs.iter().cloned().sum();
+        let product: Value = xs.iter().cloned().product();
+        assert_eq!((sum.op(), sum.children().len()), (Some(Op::Add), 3));
+        assert_eq!((product.op(), product.children().len(), product.data()), (Some(Op::Mul), 3, 6.0));
+    }
+
+    #[test]
+    fn gaussian_log_pdf_gradients_match_the_closed_forms() {
+        let (x, m, lv): (f64, f64, f64) = (1.7, 0.4, -0.3);
+        let (mean, log_var) = (Value::from(m), Value::from(lv));
+        let log_pdf = gaussian_log_pdf(x, &mean, &log_var);
+        log_pdf.backward().unwrap();
+
+        let scaled = (x - m) * (x - m) * (-lv).exp();
+        let expected = -0.5 * ((2.0 * std::f64::consts::PI).ln() + lv + scaled);
+        assert_value_eq!(log_pdf, expected, 1e-12);
+        assert_grad_eq!(mean, (x - m) * (-lv).exp(), 1e-12);
+        assert_grad_eq!(log_var, -0.5 + 0.5 * scaled, 1e-12);
+    }
+
+    #[test]
+    fn gaussian_log_pdf_stays_finite_for_extreme_log_variances() {
+        for lv in [-700.0, -50.0, 50.0, 700.0] {
+            let (mean, log_var) = (Value::from(0.5), Value::from(lv));
+            let log_pdf = gaussian_log_pdf(1.0, &mean, &log_var);
+            log_pdf.backward().unwrap();
+            assert!(log_pdf.data().is_finite() && mean.grad().is_finite() && log_var.grad().is_finite(), "{}", lv);
+        }
+    }
+
+    #[test]
+    fn positive_is_softplus() {
+        for x in [-800.0_f64, -3.0, 0.0, 3.0, 800.0] {
+            let v = Value::from(x);
+            let out = positive(&v);
+            out.backward().unwrap();
+            assert!(out.data() >= 0.0 && out.data().is_finite());
+            assert_value_eq!(out, if x > 30.0 { x } else { x.exp().ln_1p() }, 1e-12);
+            assert_grad_eq!(v, 1.0 / (1.0 + (-x).exp()), 1e-12);
+        }
+    }
+
+    #[test]
+    fn polyval_matches_the_sum_of_powers() {
+        let run = |build: &dyn Fn(&[Value], &Value) -> Value| {
+            let coeffs = values_from(&[0.5, -1.0, 2.0, 0.25, -3.0, 1.5]);
+            let x = Value::from(1.3);
+            let y = build(&coeffs, &x);
+            y.backward().unwrap();
+            (y.data(), grads(&coeffs), x.grad(), y.topo_order().len())
+        };
+        let naive = run(&|coeffs, x| {
+            let n = coeffs.len() - 1;
+            coeffs.iter().enumerate().map(|(i, c)| c * &x.powi((n - i) as i32)).sum()
+        });
+        let horner = run(&|coeffs, x| polyval(coeffs, x));
+        assert!((horner.0 - naive.0).abs() < 1e-12);
+        for (h, n) in std::iter::zip(&horner.1, &naive.1) {
+            assert!((h - n).abs() < 1e-12);
+        }
+        assert!((horner.2 - naive.2).abs() < 1e-12);
+        // five multiply-adds on top of the six coefficients and x
+        assert_eq!(horner.3, 7 + 2 * 5);
+        assert!(horner.3 < naive.3);
+    }
+
+    #[test]
+    fn polyval_of_a_constant_or_no_coefficients() {
+        let (c, x) = (Value::from(4.0), Value::from(2.0));
+        assert_eq!(polyval(std::slice::from_ref(&c), &x).id(), c.id());
+        let zero = polyval(&[], &x);
+        assert_eq!((zero.data(), zero.children().len()), (0.0, 0));
+    }
+
+    #[test]
+    fn lerp_at_the_ends_routes_the_gradient_to_that_end() {
+        for (t, expected) in [(0.0, [1.0, 0.0]), (1.0, [0.0, 1.0])] {
+            let (a, b, t) = (Value::from(2.0), Value::from(5.0), Value::from(t));
+            let out = lerp(&a, &b, &t);
+            out.backward().unwrap();
+            assert_eq!(out.data(), if t.data() == 0.0 { 2.0 } else { 5.0 });
+            assert_eq!([a.grad(), b.grad()], expected);
+            assert_eq!(t.grad(), 3.0);
+        }
+    }
+
+    #[test]
+    fn smoothstep_is_flat_outside_the_band_and_smooth_at_the_edges() {
+        let slope = |x: f64| {
+            let v = Value::from(x);
+            let out = smoothstep(1.0, 3.0, &v);
+            out.backward().unwrap();
+            (out.data(), v.grad())
+        };
+        assert_eq!(slope(0.5), (0.0, 0.0));
+        assert_eq!(slope(1.0), (0.0, 0.0));
+        assert_eq!(slope(3.0), (1.0, 0.0));
+        assert_eq!(slope(4.0), (1.0, 0.0));
+        // the slope tends to 0 from inside the band, and peaks at 1.5 / (edge1 - edge0) in the middle
+        let (near_low, near_high) = (slope(1.0 + 1e-6), slope(3.0 - 1e-6));
+        assert!(near_low.0.abs() < 1e-11 && near_low.1.abs() < 1e-5);
+        assert!((near_high.0 - 1.0).abs() < 1e-11 && near_high.1.abs() < 1e-5);
+        assert_eq!(slope(2.0), (0.5, 0.75));
+    }
+
+    #[test]
+    fn select_passes_the_gradient_to_the_chosen_branch_only() {
+        for gate in [2.0, -2.0, 0.0, -0.0] {
+            let (gate, a, b) = (Value::from(gate), Value::from(3.0), Value::from(5.0));
+            let out = &where_positive(&gate, &a, &b) * &gate;
+            out.backward().unwrap();
+            // a gate of exactly 0 picks `b`
+            let chosen_a = gate.data() > 0.0;
+            assert_eq!((a.grad(), b.grad()), if chosen_a { (gate.data(), 0.0) } else { (0.0, gate.data()) });
+            // only through the product: the selection itself gives the gate no gradient
+            assert_eq!(gate.grad(), if chosen_a { 3.0 } else { 5.0 });
+        }
+        let (gate, a, b) = (Value::from(1.0), Value::from(1.0), Value::from(2.0));
+        where_positive(&gate, &a, &b).backward().unwrap();
+        assert_eq!(gate.grad(), 0.0);
+        assert_eq!((select(true, &a, &b).id(), select(false, &a, &b).id()), (a.id(), b.id()));
+    }
+
+    #[test]
+    fn cumsum_gradients_accumulate_from_every_later_output() {
+        let xs = values_from(&[1.0, 2.0, 3.0, 4.0]);
+        let sums = cumsum(&xs);
+        assert_eq!(sums.iter().map(Value::data).collect::<Vec<f64>>(), [1.0, 3.0, 6.0, 10.0]);
+        sums[3].backward().unwrap();
+        assert_eq!(grads(&xs), [1.0; 4]);
+
+        let xs = values_from(&[1.0, 2.0, 3.0, 4.0]);
+        add_n(&cumsum(&xs)).backward().unwrap();
+        assert_eq!(grads(&xs), [4.0, 3.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn cumprod_gradient_with_a_zero_element() {
+        let xs = values_from(&[2.0, 0.0, 3.0]);
+        let products = cumprod(&xs);
+        assert_eq!(products.iter().map(Value::data).collect::<Vec<f64>>(), [2.0, 0.0, 0.0]);
+        products[2].backward().unwrap();
+        // d(x0·x1·x2)/dx_i is the product of the others
+        assert_eq!(grads(&xs), [0.0, 6.0, 0.0]);
+    }
+
+    #[test]
+    fn scans_of_short_slices() {
+        assert!(cumsum(&[]).is_empty() && cumprod(&[]).is_empty());
+        let x = Value::from(5.0);
+        assert_eq!(cumsum(std::slice::from_ref(&x))[0].id(), x.id());
+        assert_eq!(cumprod(std::slice::from_ref(&x))[0].id(), x.id());
+    }
+
+    #[test]
+    fn masked_out_positions_get_no_gradient() {
+        let xs = values_from(&[1.0, 2.0, 3.0, 4.0]);
+        let mask = [true, false, true, false];
+        let sum = masked_sum(&xs, &mask);
+        sum.backward().unwrap();
+        assert_eq!((sum.data(), grads(&xs)), (4.0, vec![1.0, 0.0, 1.0, 0.0]));
+        assert_eq!(sum.children().len(), 2);
+
+        let xs = values_from(&[1.0, 2.0, 3.0, 4.0]);
+        let mean = masked_mean(&xs, &mask);
+        mean.backward().unwrap();
+        assert_eq!((mean.data(), grads(&xs)), (2.0, vec![0.5, 0.0, 0.5, 0.0]));
+    }
+
+    #[test]
+    fn masked_mean_of_a_full_mask_is_the_mean() {
+        let xs = values_from(&[1.5, -2.0, 0.25]);
+        let masked = masked_mean(&xs, &[true; 3]);
+        masked.backward().unwrap();
+        let masked_grads = grads(&xs);
+        xs.iter().for_each(Value::zero_grad);
+        let mean = crate::loss::mean(&xs);
+        mean.backward().unwrap();
+        assert_value_eq!(masked, mean.data(), 1e-15);
+        assert_eq!(masked_grads, grads(&xs));
+    }
+
+    #[test]
+    fn empty_masks_give_a_constant_zero() {
+        let xs = values_from(&[1.0, 2.0]);
+        for reduced in [masked_sum(&xs, &[false; 2]), masked_mean(&xs, &[false; 2]), masked_mean(&[], &[])] {
+            reduced.backward().unwrap();
+            assert_eq!((reduced.data(), reduced.children().len()), (0.0, 0));
+        }
+        assert_eq!(grads(&xs), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn max_and_min_route_the_gradient_to_the_winner() {
+        let xs = values_from(&[1.0, 4.0, -2.0, 3.0]);
+        let spread = &max_of(&xs).unwrap() - &min_of(&xs).unwrap();
+        spread.backward().unwrap();
+        assert_eq!((spread.data(), grads(&xs)), (6.0, vec![0.0, 1.0, -1.0, 0.0]));
+        let (index, best) = argmax_value(&xs).unwrap();
+        assert_eq!((index, best.id()), (1, xs[1].id()));
+    }
+
+    #[test]
+    fn ties_go_to_the_first_occurrence() {
+        let xs = values_from(&[2.0, 5.0, 5.0, 0.0, 0.0]);
+        assert_eq!(argmax_value(&xs).unwrap().0, 1);
+        assert_eq!(min_of(&xs).unwrap().id(), xs[3].id());
+        let with_nan = values_from(&[1.0, f64::NAN, 2.0]);
+        assert_eq!(argmax_value(&with_nan).unwrap().0, 1);
+    }
+
+    #[test]
+    fn max_of_short_slices() {
+        let x = Value::from(-1.0);
+        assert_eq!(max_of(std::slice::from_ref(&x)).unwrap().id(), x.id());
+        assert_eq!(max_of(&[]).unwrap_err(), GradError::EmptyInput { op: "max_of" });
+        assert_eq!(min_of(&[]).unwrap_err(), GradError::EmptyInput { op: "min_of" });
+    }
+
+    #[test]
+    fn topk_orders_stably_with_duplicates() {
+        let xs = values_from(&[3.0, 7.0, 3.0, 9.0, 7.0]);
+        let top = topk(&xs, 4).unwrap();
+        let indices: Vec<usize> = top.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, [3, 1, 4, 0]);
+        assert!(top.iter().all(|(i, x)| x.id() == xs[*i].id()));
+        assert!(topk(&xs, 0).unwrap().is_empty());
+        assert_eq!(topk(&xs, 6).unwrap_err(), GradError::TooFewElements { op: "topk", requested: 6, len: 5 });
+    }
+
+    #[test]
+    fn selection_orders_nan_by_total_cmp() {
+        let xs = values_from(&[1.0, f64::NAN, -2.0, f64::NAN, 5.0]);
+        assert_eq!(argmax_value(&xs).unwrap().0, 1);
+        assert_eq!(min_of(&xs).unwrap().id(), xs[2].id());
+        let indices: Vec<usize> = topk(&xs, 3).unwrap().into_iter().map(|(i, _)| i).collect();
+        assert_eq!(indices, [1, 3, 4]);
+    }
+
+    // The leaves of `root` that could be trained, which should only be the inputs it was built from
+    fn trainable_leaves(root: &Value) -> usize {
+        root.topo_order().iter().filter(|node| node.is_leaf() && node.requires_grad()).count()
+    }
+
+    #[test]
+    fn scalars_of_the_ops_are_constants() {
+        let (x, y) = (Value::from(0.3), Value::from(-0.4));
+        let xs = [x.clone(), y.clone()];
+        assert_eq!(trainable_leaves(&gaussian_log_pdf(1.0, &x, &y)), 2);
+        assert_eq!(trainable_leaves(&smoothstep(0.0, 1.0, &x)), 1);
+        assert_eq!(trainable_leaves(&smoothstep(0.5, 1.0, &x)), 0);
+        assert_eq!(trainable_leaves(&masked_mean(&xs, &[true, true])), 2);
+        assert_eq!(trainable_leaves(&masked_sum(&xs, &[false, false])), 0);
+        assert_eq!(trainable_leaves(&polyval(&[], &x)), 0);
+        assert_eq!(trainable_leaves(&piecewise_linear(&x, &[0.0, 1.0], &xs)), 2);
+        assert_eq!(trainable_leaves(&add_n(&softmax_t(&xs, 0.5))), 2);
+        let sample = gumbel_softmax(&xs, 0.5, &mut crate::rand::Rng::seed(0));
+        assert_eq!(trainable_leaves(&add_n(&sample)), 2);
+    }
+
+    #[test]
+    fn a_loss_on_the_top_two_only_reaches_their_subgraphs() {
+        let inputs = values_from(&[0.5, 2.0, -1.0, 1.5, 0.0]);
+        let losses: Vec<Value> = inputs.iter().map(|x| x.powi(2)).collect();
+        let hardest: Vec<Value> = topk(&losses, 2).unwrap().into_iter().map(|(_, loss)| loss).collect();
+        add_n(&hardest).backward().unwrap();
+        assert_eq!(grads(&inputs), [0.0, 4.0, 0.0, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn conv1d_of_a_hand_computed_example() {
+        let (signal, kernel) = (values_from(&[1.0, 2.0, 3.0, 4.0]), values_from(&[1.0, 0.0, -1.0]));
+        let out = conv1d(&signal, &kernel, 1, 1).unwrap();
+        // [0, 1, 2, 3, 4, 0] against the kernel
+        assert_eq!(out.iter().map(Value::data).collect::<Vec<f64>>(), [-2.0, -2.0, -2.0, 3.0]);
+        add_n(&out).backward().unwrap();
+        assert_eq!(grads(&signal), [1.0, 0.0, 0.0, -1.0]);
+        assert_eq!(grads(&kernel), [6.0, 10.0, 9.0]);
+    }
+
+    #[test]
+    fn conv1d_output_lengths() {
+        let signal = values_from(&[1.0; 7]);
+        for (kernel, stride, padding) in [(3, 1, 0), (3, 2, 0), (3, 2, 1), (2, 3, 2), (7, 1, 0), (1, 4, 3)] {
+            let out = conv1d(&signal, &values_from(&vec![1.0; kernel]), stride, padding).unwrap();
+            assert_eq!(out.len(), (7 + 2 * padding - kernel) / stride + 1, "{:?}", (kernel, stride, padding));
+        }
+        let error = conv1d(&signal, &values_from(&[1.0; 10]), 1, 1).unwrap_err();
+        assert_eq!(error, GradError::TooFewElements { op: "conv1d", requested: 10, len: 9 });
+        assert_eq!(conv1d(&signal, &[], 1, 0).unwrap_err(), GradError::EmptyInput { op: "conv1d" });
+    }
+
+    #[test]
+    fn windows_in_the_padding_are_constant_zeros() {
+        let (signal, kernel) = (values_from(&[1.0, 2.0]), values_from(&[1.0, 1.0]));
+        let out = conv1d(&signal, &kernel, 1, 3).unwrap();
+        assert_eq!(out.iter().map(Value::data).collect::<Vec<f64>>(), [0.0, 0.0, 1.0, 3.0, 2.0, 0.0, 0.0]);
+        assert!(!out[0].requires_grad() && out[0].children().is_empty());
+        // the graph can be re-run, which a node adding no children couldn't
+        let total = add_n(&out);
+        let mut compiled = total.compile();
+        let inputs: Vec<f64> = compiled.leaves().iter().map(Value::data).collect();
+        assert_eq!(compiled.forward(&inputs), 6.0);
+        assert_eq!(total.clone_graph().data(), 6.0);
+    }
+
+    #[test]
+    fn pooling_gradients_land_on_the_window_maxima() {
+        let xs = values_from(&[1.0, 3.0, 2.0, 5.0, 4.0, 0.0]);
+        let pooled = max_pool1d(&xs, 2, 2).unwrap();
+        assert_eq!(pooled.iter().map(Value::data).collect::<Vec<f64>>(), [3.0, 5.0, 4.0]);
+        add_n(&pooled).backward().unwrap();
+        assert_eq!(grads(&xs), [0.0, 1.0, 0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(max_pool1d(&xs, 3, 1).unwrap().len(), 4);
+        assert!(max_pool1d(&xs, 7, 1).is_err());
+    }
+
+    #[test]
+    fn concat_then_split_gives_back_the_same_nodes() {
+        let (a, b, c) = (values_from(&[1.0, 2.0]), values_from(&[3.0]), values_from(&[4.0, 5.0, 6.0]));
+        let joined = concat(&[&a, &b, &c]);
+        assert_eq!(joined.len(), 6);
+        let pieces = split(&joined, &[2, 1, 3]).unwrap();
+        for (piece, original) in std::iter::zip(&pieces, [&a, &b, &c]) {
+            assert!(std::iter::zip(piece, original).all(|(x, y)| Rc::ptr_eq(x, y)));
+        }
+        let error = split(&joined, &[2, 2]).unwrap_err();
+        assert_eq!(error, GradError::ShapeMismatch { op: "split", left: (6, 1), right: (4, 1) });
+    }
+
+    #[test]
+    fn gradients_through_a_concatenation_reach_the_original_slices() {
+        let (a, b) = (values_from(&[1.0, 2.0]), values_from(&[3.0]));
+        let joined = concat(&[&a, &b]);
+        let weighted: Vec<Value> = joined.iter().enumerate().map(|(i, x)| x * &Value::from(i as f64)).collect();
+        add_n(&weighted).backward().unwrap();
+        assert_eq!((grads(&a), grads(&b)), (vec![0.0, 1.0], vec![2.0]));
+    }
+
+    #[test]
+    fn stack_rows_checks_the_lengths() {
+        let rows = vec![values_from(&[1.0, 2.0]), values_from(&[3.0, 4.0])];
+        let m = stack_rows(&rows).unwrap();
+        assert_eq!((m.shape(), m.get(1, 0).id()), ((2, 2), rows[1][0].id()));
+        assert_eq!(stack_rows(&[]).unwrap().shape(), (0, 0));
+        let ragged = vec![values_from(&[1.0, 2.0]), values_from(&[3.0])];
+        let error = stack_rows(&ragged).unwrap_err();
+        assert_eq!(error, GradError::ShapeMismatch { op: "stack_rows", left: (1, 2), right: (1, 1) });
+    }
+
+    #[test]
+    fn temperature_moves_between_uniform_and_one_hot() {
+        let xs = values_from(&[1.0, 3.0, 2.0]);
+        let data = |t: f64| softmax_t(&xs, t).iter().map(Value::data).collect::<Vec<f64>>();
+        for t in [1e-3, 0.5, 1.0, 10.0, 1e6] {
+            assert!((data(t).iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        }
+        assert!(data(1e6).iter().all(|p| (p - 1.0 / 3.0).abs() < 1e-5));
+        let cold = data(1e-3);
+        assert!((cold[1] - 1.0).abs() < 1e-12 && cold[0] < 1e-12 && cold[2] < 1e-12);
+        assert_eq!(data(1.0), softmax(&xs).iter().map(Value::data).collect::<Vec<f64>>());
+    }
+
+    #[test]
+    fn gumbel_softmax_is_differentiable_with_the_noise_fixed() {
+        let xs = values_from(&[0.5, -1.0, 2.0, 0.0]);
+        let weights = [1.0, -2.0, 0.5, 3.0];
+        let loss = || {
+            let sample = gumbel_softmax(&xs, 0.7, &mut crate::rand::Rng::seed(11));
+            assert!((sample.iter().map(Value::data).sum::<f64>() - 1.0).abs() < 1e-12);
+            let weighted: Vec<Value> = std::iter::zip(&sample, weights).map(|(p, w)| p * &Value::from(w)).collect();
+            add_n(&weighted)
+        };
+        loss().backward().unwrap();
+        let h = 1e-6;
+        for x in &xs {
+            let data = x.data();
+            x.set_data(data + h);
+            let up = loss().data();
+            x.set_data(data - h);
+            let down = loss().data();
+            x.set_data(data);
+            assert!((x.grad() - (up - down) / (2.0 * h)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn safe_div_is_division_away_from_zero() {
+        for (x, y) in [(3.0, 0.5), (-2.0, -1e-3), (1.0, 1e-3)] {
+            let (a, b) = (Value::from(x), Value::from(y));
+            let safe = safe_div(&a, &b, 1e-3);
+            safe.backward().unwrap();
+            let expected = (a.grad(), b.grad());
+            a.zero_grad();
+            b.zero_grad();
+            let plain = &a / &b;
+            plain.backward().unwrap();
+            assert_eq!((safe.data(), expected), (plain.data(), (a.grad(), b.grad())));
+        }
+    }
+
+    #[test]
+    fn safe_div_stabilizes_small_denominators() {
+        let eps = 1e-6;
+        for (y, stabilized) in [(0.0, eps), (-0.0, eps), (1e-300, eps), (-1e-9, -eps)] {
+            let (a, b) = (Value::from(2.0), Value::from(y));
+            let q = safe_div(&a, &b, eps);
+            q.backward().unwrap();
+            let expected = [2.0 / stabilized, 1.0 / stabilized, -2.0 / (stabilized * stabilized)];
+            for (got, expected) in [q.data(), a.grad(), b.grad()].into_iter().zip(expected) {
+                assert!(got.is_finite() && (got - expected).abs() <= 1e-9 * expected.abs(), "{} {}", got, expected);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "positive epsilon")]
+    fn safe_div_needs_a_positive_epsilon() {
+        safe_div(&Value::from(1.0), &Value::from(1.0), 0.0);
+    }
+
+    #[test]
+    fn compensated_sums_are_accurate_where_plain_ones_drift() {
+        // a million terms of 0.1, all the same leaf
+        let x = Value::from(0.1);
+        let terms = vec![x.clone(); 1_000_000];
+        let (plain, compensated) = (add_n(&terms), sum_compensated(&terms));
+        assert_eq!(compensated.data(), 100_000.0);
+        assert!((plain.data() - 100_000.0).abs() > 1e-6, "{}", plain.data());
+        assert_eq!((compensated.op(), compensated.children().len()), (Some(Op::SumCompensated), 1_000_000));
+
+        compensated.backward().unwrap();
+        assert_eq!(x.grad(), 1_000_000.0);
+    }
+
+    #[test]
+    fn compensated_sums_have_the_gradients_of_plain_ones() {
+        let xs = crate::engine::values_from(&[0.5, -2.0, 1e-9, 3.0]);
+        let terms: Vec<Value> = xs.iter().map(|x| x.tanh()).chain([&xs[0] * &xs[1]]).collect();
+        let grads = |sum: Value| {
+            sum.zero_grad_all();
+            sum.backward().unwrap();
+            xs.iter().map(Value::grad).collect::<Vec<f64>>()
+        };
+        assert_eq!(grads(sum_compensated(&terms)), grads(add_n(&terms)));
+    }
+
+    #[test]
+    fn compiled_sums_stay_compensated() {
+        let x = Value::from(0.3);
+        let mut compiled = sum_compensated(&vec![x.clone(); 10_000]).compile();
+        let data = compiled.forward(&[0.1]);
+        assert_eq!(data, sum_compensated(&vec![Value::from(0.1); 10_000]).data());
+        assert_eq!(data, 1000.0);
+    }
+
+    #[test]
+    fn means_are_compensated_above_the_threshold() {
+        for (n, op) in [(COMPENSATED_SUM_THRESHOLD, Op::Add), (COMPENSATED_SUM_THRESHOLD + 1, Op::SumCompensated)] {
+            let terms = vec![Value::from(0.1); n];
+            let mean = crate::loss::mean(&terms);
+            assert_eq!(mean.children()[0].op(), Some(op));
+        }
+    }
+
+    // The gradients of `piecewise_linear` at `at`, into x then into each y-knot, against finite differences:
+    // central ones or, at a knot where the slope changes, forward ones as the segment on the right is used there
+    fn check_piecewise(at: f64, central: bool) {
+        let (knots_x, ys) = ([-1.0, 0.0, 2.0, 3.0], [0.5, -1.0, 3.0, 2.0]);
+        let f = |x: f64, ys: &[f64]| piecewise_linear(&Value::from(x), &knots_x, &values_from(ys)).data();
+        let numeric = |shift: &dyn Fn(f64) -> f64| {
+            let h = 1e-6;
+            if central {
+                (shift(h) - shift(-h)) / (2.0 * h)
+            } else {
+                (shift(h) - shift(0.0)) / h
+            }
+        };
+        let (x, knots_y) = (Value::from(at), values_from(&ys));
+        piecewise_linear(&x, &knots_x, &knots_y).backward().unwrap();
+        assert!((x.grad() - numeric(&|h| f(at + h, &ys))).abs() < 1e-6, "d/dx at {}", at);
+        for i in 0..ys.len() {
+            let shifted = |h: f64| {
+                let mut ys = ys;
+                ys[i] += h;
+                f(at, &ys)
+            };
+            assert!((knots_y[i].grad() - numeric(&shifted)).abs() < 1e-6, "d/dy{} at {}", i, at);
+        }
+    }
+
+    #[test]
+    fn piecewise_linear_interpolates_between_the_knots() {
+        let knots_y = values_from(&[0.5, -1.0, 3.0, 2.0]);
+        let y = piecewise_linear(&Value::from(0.5), &[-1.0, 0.0, 2.0, 3.0], &knots_y);
+        assert_value_eq!(y, 0.0, 1e-15);
+        y.backward().unwrap();
+        assert_eq!(grads(&knots_y), [0.0, 0.75, 0.25, 0.0]);
+        check_piecewise(0.5, true);
+        check_piecewise(2.7, true);
+    }
+
+    #[test]
+    fn piecewise_linear_at_a_knot_uses_the_segment_on_the_right() {
+        let (x, knots_y) = (Value::from(0.0), values_from(&[0.5, -1.0, 3.0, 2.0]));
+        let y = piecewise_linear(&x, &[-1.0, 0.0, 2.0, 3.0], &knots_y);
+        y.backward().unwrap();
+        assert_eq!((y.data(), x.grad()), (-1.0, 2.0));
+        assert_eq!(grads(&knots_y), [0.0, 1.0, 0.0, 0.0]);
+        check_piecewise(0.0, false);
+        check_piecewise(2.0, false);
+    }
+
+    #[test]
+    fn piecewise_linear_extrapolates_the_end_segments() {
+        let (x, knots_y) = (Value::from(5.0), values_from(&[0.5, -1.0, 3.0, 2.0]));
+        let y = piecewise_linear(&x, &[-1.0, 0.0, 2.0, 3.0], &knots_y);
+        assert_value_eq!(y, 0.0, 1e-15);
+        check_piecewise(5.0, true);
+        check_piecewise(-4.0, true);
+        check_piecewise(3.0, true);
+    }
+
+    #[test]
+    #[should_panic(expected = "piecewise_linear needs increasing x-knots, got 1 then 1")]
+    fn piecewise_linear_rejects_duplicate_knots() {
+        piecewise_linear(&Value::from(0.0), &[0.0, 1.0, 1.0], &values_from(&[0.0, 1.0, 2.0]));
+    }
+}
@@ -0,0 +1,665 @@
+// Optimizers: gradient-based ones, which update the parameters from the grads left by a backward pass and can be
+// wrapped to change how they step, and gradient-free ones, which only read and write the data of the parameters and
+// never run backward, e.g. to sanity-check a loss surface independently of the gradients.
+
+use crate::constraints::Constraint;
+use crate::engine::Value;
+use crate::rand::Rng;
+
+/// A gradient-based optimizer: `step` moves the parameters using their current grads, which the caller computes
+/// beforehand with a backward pass (and zeroes afterwards). Frozen parameters are left unchanged.
+pub trait Optimizer {
+    fn step(&mut self, params: &[Value]);
+
+    /// The learning rate the next step will use.
+    fn lr(&self) -> f64;
+
+    fn set_lr(&mut self, lr: f64);
+}
+
+/// Plain gradient descent, `data -= lr * grad`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sgd {
+    pub lr: f64,
+}
+
+impl Sgd {
+    pub fn new(lr: f64) -> Sgd {
+        Sgd { lr }
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(&mut self, params: &[Value]) {
+        for param in params {
+            param.descend(self.lr);
+        }
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// Wraps an optimizer to add Gaussian noise to the grad of each parameter before every step, with a standard
+/// deviation given by `std_schedule` from the index of the step (0 for the first), e.g. a decaying
+/// `η / (1 + t)^γ`, to help escape flat regions of the loss. The noise stays in the grads after the step.
+///
+/// The noise is drawn from a generator seeded with `seed`, so a run is reproducible; steps with a standard
+/// deviation of 0 leave the grads untouched and don't draw from it.
+pub struct WithGradNoise<O, F> {
+    inner: O,
+    std_schedule: F,
+    rng: Rng,
+    steps: usize,
+}
+
+impl<O: Optimizer, F: Fn(usize) -> f64> WithGradNoise<O, F> {
+    pub fn new(inner: O, std_schedule: F, seed: u64) -> WithGradNoise<O, F> {
+        WithGradNoise { inner, std_schedule, rng: Rng::seed(seed), steps: 0 }
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    /// The number of steps taken so far, i.e. the index of the next one.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+}
+
+impl<O: Optimizer, F: Fn(usize) -> f64> Optimizer for WithGradNoise<O, F> {
+    fn step(&mut self, params: &[Value]) {
+        let std = (self.std_schedule)(self.steps);
+        if std != 0.0 {
+            for param in params.iter().filter(|param| param.requires_grad()) {
+                param.add_grad(self.rng.normal(0.0, std));
+            }
+        }
+        self.inner.step(params);
+        self.steps += 1;
+    }
+
+    fn lr(&self) -> f64 {
+        self.inner.lr()
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.inner.set_lr(lr);
+    }
+}
+
+/// Wraps an optimizer to scale its learning rate by a temperature given by `temperature` from the index of the
+/// step (0 for the first), e.g. a geometric cooling `T0 · α^t` as in simulated annealing. The learning rate of
+/// the inner optimizer is only scaled for the duration of each step: `lr()` is the unscaled one.
+pub struct Annealed<O, F> {
+    inner: O,
+    temperature: F,
+    steps: usize,
+}
+
+impl<O: Optimizer, F: Fn(usize) -> f64> Annealed<O, F> {
+    pub fn new(inner: O, temperature: F) -> Annealed<O, F> {
+        Annealed { inner, temperature, steps: 0 }
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    /// The number of steps taken so far, i.e. the index of the next one.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+}
+
+impl<O: Optimizer, F: Fn(usize) -> f64> Optimizer for Annealed<O, F> {
+    fn step(&mut self, params: &[Value]) {
+        let lr = self.inner.lr();
+        self.inner.set_lr(lr * (self.temperature)(self.steps));
+        self.inner.step(params);
+        self.inner.set_lr(lr);
+        self.steps += 1;
+    }
+
+    fn lr(&self) -> f64 {
+        self.inner.lr()
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.inner.set_lr(lr);
+    }
+}
+
+/// Wraps an optimizer with Lookahead: the inner optimizer moves the parameters ("fast weights") as usual, and every
+/// `k` steps a copy of them ("slow weights") moves a fraction `alpha` of the way towards them, after which the
+/// parameters are reset to the slow weights. The slow weights start from the parameters at the first step.
+pub struct Lookahead<O> {
+    inner: O,
+    k: usize,
+    alpha: f64,
+    slow: Vec<f64>,
+    steps: usize,
+}
+
+impl<O: Optimizer> Lookahead<O> {
+    pub fn new(inner: O, k: usize, alpha: f64) -> Lookahead<O> {
+        assert!(k > 0, "lookahead synchronizes every k > 0 steps");
+        Lookahead { inner, k, alpha, slow: Vec::new(), steps: 0 }
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    /// The slow weights, empty before the first step.
+    pub fn slow_weights(&self) -> &[f64] {
+        &self.slow
+    }
+}
+
+impl<O: Optimizer> Optimizer for Lookahead<O> {
+    fn step(&mut self, params: &[Value]) {
+        if self.slow.len() != params.len() {
+            self.slow = data(params);
+        }
+        self.inner.step(params);
+        self.steps += 1;
+        if self.steps.is_multiple_of(self.k) {
+            for (slow, param) in std::iter::zip(&mut self.slow, params) {
+                *slow += self.alpha * (param.data() - *slow);
+            }
+            set(params, &self.slow);
+        }
+    }
+
+    fn lr(&self) -> f64 {
+        self.inner.lr()
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.inner.set_lr(lr);
+    }
+}
+
+/// Wraps an optimizer with stochastic weight averaging: after the step of index `start_step` (0 for the first) and
+/// then after every `period` steps, the parameter data is added to a running average, which usually generalizes
+/// better than the last iterate. `swap_to_average` puts the average into the parameters, e.g. to evaluate it, and
+/// `swap_back` restores the data they held; swap back before stepping again.
+pub struct Swa<O> {
+    inner: O,
+    start_step: usize,
+    period: usize,
+    params: Vec<Value>,
+    sum: Vec<f64>,
+    snapshots: usize,
+    swapped: Option<Vec<f64>>,
+    steps: usize,
+}
+
+impl<O: Optimizer> Swa<O> {
+    pub fn new(inner: O, start_step: usize, period: usize) -> Swa<O> {
+        assert!(period > 0, "weights are averaged every period > 0 steps");
+        Swa {
+            inner,
+            start_step,
+            period,
+            params: Vec::new(),
+            sum: Vec::new(),
+            snapshots: 0,
+            swapped: None,
+            steps: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    /// The number of parameter snapshots in the average.
+    pub fn snapshots(&self) -> usize {
+        self.snapshots
+    }
+
+    /// The average of the snapshots so far, or None before the first one.
+    pub fn average(&self) -> Option<Vec<f64>> {
+        if self.snapshots == 0 {
+            return None;
+        }
+        Some(self.sum.iter().map(|sum| sum / self.snapshots as f64).collect())
+    }
+
+    /// Puts the average into the parameters of the last step, keeping their data for `swap_back`. Does nothing when
+    /// already swapped. Panics before the first snapshot.
+    pub fn swap_to_average(&mut self) {
+        if self.swapped.is_some() {
+            return;
+        }
+        let average = self.average().expect("no snapshot has been averaged yet");
+        self.swapped = Some(data(&self.params));
+        set(&self.params, &average);
+    }
+
+    /// Restores the data the parameters held before `swap_to_average`, exactly. Does nothing when not swapped.
+    pub fn swap_back(&mut self) {
+        if let Some(data) = self.swapped.take() {
+            set(&self.params, &data);
+        }
+    }
+}
+
+impl<O: Optimizer> Optimizer for Swa<O> {
+    fn step(&mut self, params: &[Value]) {
+        self.inner.step(params);
+        let index = self.steps;
+        self.steps += 1;
+        if index < self.start_step || !(index - self.start_step).is_multiple_of(self.period) {
+            return;
+        }
+        if self.sum.len() != params.len() {
+            self.sum = vec![0.0; params.len()];
+            self.snapshots = 0;
+        }
+        for (sum, param) in std::iter::zip(&mut self.sum, params) {
+            *sum += param.data();
+        }
+        self.snapshots += 1;
+        self.params = params.to_vec();
+    }
+
+    fn lr(&self) -> f64 {
+        self.inner.lr()
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.inner.set_lr(lr);
+    }
+}
+
+/// Wraps an optimizer to enforce constraints on some of the parameters: after every step of the inner optimizer,
+/// each constraint projects the parameters it was added for back onto its feasible set, in the order the
+/// constraints were added. Parameters without a constraint are left to the inner optimizer.
+pub struct Constrained<O> {
+    inner: O,
+    constraints: Vec<(Vec<Value>, Box<dyn Constraint>)>,
+}
+
+impl<O: Optimizer> Constrained<O> {
+    pub fn new(inner: O) -> Constrained<O> {
+        Constrained { inner, constraints: Vec::new() }
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    /// Constrains `params`, e.g. `add_constraint(&layer.parameters(), NonNegative)`. The parameters are projected
+    /// right away, so that they start out feasible.
+    pub fn add_constraint(&mut self, params: &[Value], constraint: impl Constraint + 'static) {
+        constraint.project(params);
+        self.constraints.push((params.to_vec(), Box::new(constraint)));
+    }
+}
+
+impl<O: Optimizer> Optimizer for Constrained<O> {
+    fn step(&mut self, params: &[Value]) {
+        self.inner.step(params);
+        for (params, constraint) in &self.constraints {
+            constraint.project(params);
+        }
+    }
+
+    fn lr(&self) -> f64 {
+        self.inner.lr()
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.inner.set_lr(lr);
+    }
+}
+
+/// Random search around the current parameters: each step tries `samples` Gaussian perturbations of standard
+/// deviation `std` and moves to the best one if it lowers the loss.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RandomSearch {
+    pub std: f64,
+    pub samples: usize,
+}
+
+impl RandomSearch {
+    pub fn new(std: f64, samples: usize) -> RandomSearch {
+        RandomSearch { std, samples }
+    }
+
+    /// One step, calling `loss_fn` `samples + 1` times: once at the current parameters, then once per candidate.
+    /// `loss_fn` must build the loss afresh from the current parameter data. Returns the loss at the parameters
+    /// the step ends on.
+    pub fn step(&self, params: &[Value], mut loss_fn: impl FnMut() -> Value, rng: &mut Rng) -> f64 {
+        let current = data(params);
+        let mut best = (loss_fn().data(), current.clone());
+        for _ in 0..self.samples {
+            let candidate: Vec<f64> = current.iter().map(|&x| x + rng.normal(0.0, self.std)).collect();
+            set(params, &candidate);
+            let loss = loss_fn().data();
+            if loss < best.0 {
+                best = (loss, candidate);
+            }
+        }
+        set(params, &best.1);
+        best.0
+    }
+}
+
+/// A simple evolution strategy: each step evaluates a population of Gaussian perturbations of standard deviation
+/// `std`, with fitness the negative loss, and moves the parameters by `lr` along the perturbations averaged with
+/// their fitness relative to the population's mean as weights: `lr` times an estimate of the negative gradient of
+/// the loss smoothed over the perturbations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimpleES {
+    pub population: usize,
+    pub std: f64,
+    pub lr: f64,
+}
+
+impl SimpleES {
+    pub fn new(population: usize, std: f64, lr: f64) -> SimpleES {
+        SimpleES { population, std, lr }
+    }
+
+    /// One step, calling `loss_fn` `population` times, each with the parameters at a different perturbation of
+    /// where the step started. `loss_fn` must build the loss afresh from the current parameter data. Returns the
+    /// mean loss of the population.
+    pub fn step(&self, params: &[Value], mut loss_fn: impl FnMut() -> Value, rng: &mut Rng) -> f64 {
+        assert!(self.population > 0, "an evolution strategy needs a population");
+        let current = data(params);
+        let mut noises = Vec::with_capacity(self.population);
+        let mut fitness = Vec::with_capacity(self.population);
+        for _ in 0..self.population {
+            let noise: Vec<f64> = current.iter().map(|_| rng.normal(0.0, 1.0)).collect();
+            let candidate: Vec<f64> = std::iter::zip(&current, &noise).map(|(x, n)| x + self.std * n).collect();
+            set(params, &candidate);
+            fitness.push(-loss_fn().data());
+            noises.push(noise);
+        }
+
+        let count = self.population as f64;
+        let mean = fitness.iter().sum::<f64>() / count;
+        let mut updated = current;
+        for (noise, f) in std::iter::zip(&noises, &fitness) {
+            let weight = self.lr * (f - mean) / (count * self.std);
+            for (x, n) in std::iter::zip(&mut updated, noise) {
+                *x += weight * n;
+            }
+        }
+        set(params, &updated);
+        -mean
+    }
+}
+
+fn data(params: &[Value]) -> Vec<f64> {
+    params.iter().map(Value::data).collect()
+}
+
+fn set(params: &[Value], data: &[f64]) {
+    for (param, &x) in std::iter::zip(params, data) {
+        param.set_data(x);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use crate::engine::values_from;
+
+    fn squared_norm(params: &[Value]) -> Value {
+        crate::ops::add_n(&params.iter().map(|p| p * p).collect::<Vec<Value>>())
+    }
+
+    #[test]
+    fn es_minimizes_a_quadratic() {
+        let params = values_from(&[1.0, -2.0, 0.5, 3.0, -1.0]);
+        let es = SimpleES::new(50, 0.1, 0.2);
+        let mut rng = Rng::seed(0);
+        let first = es.step(&params, || squared_norm(&params), &mut rng);
+        for _ in 0..200 {
+            es.step(&params, || squared_norm(&params), &mut rng);
+        }
+        let last = squared_norm(&params).data();
+        // down to the noise of the estimates
+        assert!(first > 10.0 && last < 1e-2, "{} -> {}", first, last);
+    }
+
+    #[test]
+    fn es_evaluates_each_candidate_from_the_start() {
+        let start = [1.0, -2.0, 0.5];
+        let params = values_from(&start);
+        let seen = RefCell::new(Vec::new());
+        let es = SimpleES::new(7, 0.3, 0.1);
+        let loss_fn = || {
+            seen.borrow_mut().push(data(&params));
+            squared_norm(&params)
+        };
+        es.step(&params, loss_fn, &mut Rng::seed(4));
+
+        // the same draws, in the same order
+        let mut rng = Rng::seed(4);
+        let expected: Vec<Vec<f64>> =
+            (0..7).map(|_| start.iter().map(|x| x + 0.3 * rng.normal(0.0, 1.0)).collect()).collect();
+        assert_eq!(seen.into_inner(), expected);
+    }
+
+    #[test]
+    fn random_search_only_moves_downhill() {
+        let start = [0.5, -0.5];
+        let params = values_from(&start);
+        let calls = Cell::new(0);
+        let search = RandomSearch::new(0.2, 9);
+        let mut rng = Rng::seed(1);
+        let mut previous = squared_norm(&params).data();
+        for _ in 0..20 {
+            let loss_fn = || {
+                calls.set(calls.get() + 1);
+                squared_norm(&params)
+            };
+            let loss = search.step(&params, loss_fn, &mut rng);
+            assert!(loss <= previous);
+            assert_eq!(loss, squared_norm(&params).data());
+            previous = loss;
+        }
+        assert_eq!(calls.get(), 20 * 10);
+        assert!(previous < 0.05, "{}", previous);
+
+        // a step finding nothing better stays where it was
+        let params = values_from(&[0.0, 0.0]);
+        assert_eq!(search.step(&params, || squared_norm(&params), &mut rng), 0.0);
+        assert_eq!(data(&params), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn es_calls_the_loss_once_per_member() {
+        let params = values_from(&[1.0]);
+        let calls = Cell::new(0);
+        let loss_fn = || {
+            calls.set(calls.get() + 1);
+            squared_norm(&params)
+        };
+        SimpleES::new(12, 0.1, 0.1).step(&params, loss_fn, &mut Rng::seed(0));
+        assert_eq!(calls.get(), 12);
+    }
+
+    // The data of the parameters after each of `steps` steps of `optimizer` on `squared_norm`
+    fn trajectory(optimizer: &mut impl Optimizer, steps: usize) -> Vec<Vec<f64>> {
+        let params = values_from(&[1.0, -2.0, 0.5]);
+        (0..steps)
+            .map(|_| {
+                params.iter().for_each(Value::zero_grad);
+                squared_norm(&params).backward().unwrap();
+                optimizer.step(&params);
+                params.iter().map(Value::data).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn noise_of_zero_std_is_the_inner_optimizer() {
+        let plain = trajectory(&mut Sgd::new(0.1), 5);
+        let mut noisy = WithGradNoise::new(Sgd::new(0.1), |_| 0.0, 7);
+        assert_eq!(trajectory(&mut noisy, 5), plain);
+        assert_eq!((noisy.steps(), noisy.lr()), (5, 0.1));
+    }
+
+    #[test]
+    fn seeded_noise_reproduces_the_trajectory() {
+        let decaying = |t: usize| 0.5 / (1.0 + t as f64);
+        let first = trajectory(&mut WithGradNoise::new(Sgd::new(0.1), decaying, 3), 5);
+        assert_eq!(trajectory(&mut WithGradNoise::new(Sgd::new(0.1), decaying, 3), 5), first);
+        assert_ne!(trajectory(&mut WithGradNoise::new(Sgd::new(0.1), decaying, 4), 5), first);
+        assert_ne!(trajectory(&mut Sgd::new(0.1), 5), first);
+    }
+
+    #[test]
+    fn noise_leaves_frozen_parameters_alone() {
+        let (x, frozen) = (Value::from(1.0), Value::constant(2.0));
+        WithGradNoise::new(Sgd::new(0.1), |_| 1.0, 0).step(&[x.clone(), frozen.clone()]);
+        assert_ne!(x.data(), 1.0);
+        assert_eq!((frozen.data(), frozen.grad()), (2.0, 0.0));
+    }
+
+    // An optimizer that only records the learning rate of each step
+    struct Recorder(f64, Rc<RefCell<Vec<f64>>>);
+
+    impl Optimizer for Recorder {
+        fn step(&mut self, _: &[Value]) {
+            self.1.borrow_mut().push(self.0);
+        }
+
+        fn lr(&self) -> f64 {
+            self.0
+        }
+
+        fn set_lr(&mut self, lr: f64) {
+            self.0 = lr;
+        }
+    }
+
+    #[test]
+    fn temperatures_scale_the_learning_rate_of_their_step() {
+        let lrs = Rc::new(RefCell::new(Vec::new()));
+        let mut annealed = Annealed::new(Recorder(0.5, Rc::clone(&lrs)), |t| 0.5f64.powi(t as i32));
+        for _ in 0..4 {
+            annealed.step(&[]);
+        }
+        assert_eq!(*lrs.borrow(), [0.5, 0.25, 0.125, 0.0625]);
+        // the learning rate between steps is the unscaled one
+        assert_eq!((annealed.lr(), annealed.inner().lr(), annealed.steps()), (0.5, 0.5, 4));
+        annealed.set_lr(2.0);
+        annealed.step(&[]);
+        assert_eq!(lrs.borrow()[4], 2.0 / 16.0);
+    }
+
+    #[test]
+    fn lookahead_with_alpha_one_is_the_inner_optimizer() {
+        let plain = trajectory(&mut Sgd::new(0.1), 7);
+        let mut lookahead = Lookahead::new(Sgd::new(0.1), 3, 1.0);
+        assert_eq!(trajectory(&mut lookahead, 7), plain);
+        assert_eq!(lookahead.slow_weights(), &plain[5][..]);
+    }
+
+    #[test]
+    fn lookahead_pulls_the_fast_weights_back_every_k_steps() {
+        // d/dx x² = 2x, so each step of Sgd(0.1) scales x by 0.8
+        let x = values_from(&[1.0]);
+        let mut lookahead = Lookahead::new(Sgd::new(0.1), 2, 0.5);
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            x[0].zero_grad();
+            squared_norm(&x).backward().unwrap();
+            lookahead.step(&x);
+            seen.push(x[0].data());
+        }
+        // 0.8, then 1 + 0.5·(0.64 - 1), then 0.82·0.8, then 0.82 + 0.5·(0.82·0.64 - 0.82)
+        let expected = [0.8, 0.82, 0.656, 0.82 * 0.82];
+        let close = std::iter::zip(&seen, expected).all(|(seen, expected)| (seen - expected).abs() < 1e-12);
+        assert!(close, "{:?}", seen);
+    }
+
+    #[test]
+    fn swa_averages_the_snapshots_it_takes() {
+        let params = values_from(&[1.0, -2.0, 0.5]);
+        let mut swa = Swa::new(Sgd::new(0.1), 2, 3);
+        assert_eq!(swa.average(), None);
+        let mut snapshots = Vec::new();
+        for step in 0..9 {
+            params.iter().for_each(Value::zero_grad);
+            squared_norm(&params).backward().unwrap();
+            swa.step(&params);
+            if step == 2 || step == 5 || step == 8 {
+                snapshots.push(data(&params));
+            }
+        }
+        assert_eq!(swa.snapshots(), 3);
+        let average = swa.average().unwrap();
+        for (i, average) in average.iter().enumerate() {
+            let mean = snapshots.iter().map(|snapshot| snapshot[i]).sum::<f64>() / 3.0;
+            assert!((average - mean).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn swapping_to_the_average_and_back_is_exact() {
+        let params = values_from(&[0.1, 0.7]);
+        let mut swa = Swa::new(Sgd::new(0.3), 0, 1);
+        for _ in 0..3 {
+            params.iter().for_each(Value::zero_grad);
+            squared_norm(&params).backward().unwrap();
+            swa.step(&params);
+        }
+        let last = data(&params);
+        swa.swap_to_average();
+        assert_eq!(data(&params), swa.average().unwrap());
+        // a second swap doesn't lose the data kept by the first
+        swa.swap_to_average();
+        swa.swap_back();
+        assert_eq!(data(&params), last);
+        swa.swap_back();
+        assert_eq!(data(&params), last);
+    }
+
+    #[test]
+    fn constraints_hold_across_steps_on_an_adversarial_objective() {
+        use crate::constraints::{NonNegative, UnitNorm};
+
+        // the loss pulls every parameter towards -10
+        let params = values_from(&[1.0, 2.0, 0.5, -0.3, 0.4, 0.1]);
+        let free = values_from(&[1.0]);
+        let all: Vec<Value> = params.iter().chain(&free).cloned().collect();
+        let mut optimizer = Constrained::new(Sgd::new(0.05));
+        optimizer.add_constraint(&params[..3], NonNegative);
+        optimizer.add_constraint(&params[3..], UnitNorm);
+        let mut unconstrained = Sgd::new(0.05);
+        let reference = values_from(&[1.0]);
+        for _ in 0..1000 {
+            all.iter().chain(&reference).for_each(Value::zero_grad);
+            let terms: Vec<Value> = all.iter().map(|p| (p + &Value::constant(10.0)).powi(2)).collect();
+            crate::ops::add_n(&terms).backward().unwrap();
+            (&reference[0] + &Value::constant(10.0)).powi(2).backward().unwrap();
+            optimizer.step(&all);
+            unconstrained.step(&reference);
+            assert!(params[..3].iter().all(|param| param.data() >= 0.0));
+            let norm = params[3..].iter().map(|param| param.data().powi(2)).sum::<f64>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-12);
+        }
+        assert_eq!(data(&params[..3]), [0.0; 3]);
+        // the unconstrained parameter moved as plain gradient descent moves it
+        assert_eq!(free[0].data(), reference[0].data());
+        assert!((free[0].data() + 10.0).abs() < 1e-9);
+    }
+}
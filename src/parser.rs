@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use crate::engine::{Op, Value};
+
+/// An error produced by `engine::parse`, with the byte range of the offending part of the input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.message, self.span.start, self.span.end)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+// Splits the input into tokens along with their byte ranges.
+fn tokenize(expr: &str) -> Result<Vec<(Token, Range<usize>)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = expr[start..end].parse().map_err(|_| ParseError {
+                message: format!("invalid number `{}`", &expr[start..end]),
+                span: start..end,
+            })?;
+            tokens.push((Token::Number(number), start..end));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((Token::Ident(expr[start..end].to_string()), start..end));
+        } else if "+-*/^()".contains(c) {
+            chars.next();
+            tokens.push((Token::Symbol(c), start..start + 1));
+        } else {
+            return Err(ParseError {
+                message: format!("unexpected character `{}`", c),
+                span: start..start + c.len_utf8(),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+// A recursive descent parser over the token list, building graph nodes as it goes with the
+// same operators a hand-written Rust expression would use.
+//
+// expr    := term (('+' | '-') term)*
+// term    := unary (('*' | '/') unary)*
+// unary   := '-' unary | power
+// power   := primary ('^' unary)?
+// primary := number | name | name '(' expr ')' | '(' expr ')'
+struct Parser<'a> {
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+    source: &'a str,
+    vars: &'a HashMap<String, Value>,
+    // whether the nodes built by operators are labelled with their source text
+    labels: bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn span(&self) -> Range<usize> {
+        let end = self.source.len();
+        self.tokens.get(self.pos).map_or(end..end, |(_, span)| span.clone())
+    }
+
+    // Labels a node just built from the tokens starting at byte `start` with their text, when labelling.
+    fn built(&self, value: Value, start: usize) -> Value {
+        if !self.labels {
+            return value;
+        }
+        let end = self.tokens[self.pos - 1].1.end;
+        value.add_label(&self.source[start..end])
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ParseError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("expected `{}`", symbol)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        let found = match self.peek() {
+            Some(Token::Number(n)) => format!("`{}`", n),
+            Some(Token::Ident(name)) => format!("`{}`", name),
+            Some(Token::Symbol(c)) => format!("`{}`", c),
+            None => "end of input".to_string(),
+        };
+        ParseError { message: format!("{}, found {}", expected, found), span: self.span() }
+    }
+
+    fn expr(&mut self) -> Result<Value, ParseError> {
+        let start = self.span().start;
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                let rhs = self.term()?;
+                value = self.built(&value + &rhs, start);
+            } else if self.eat('-') {
+                let rhs = self.term()?;
+                value = self.built(&value - &rhs, start);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Value, ParseError> {
+        let start = self.span().start;
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                let rhs = self.unary()?;
+                value = self.built(&value * &rhs, start);
+            } else if self.eat('/') {
+                let rhs = self.unary()?;
+                value = self.built(&value / &rhs, start);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Value, ParseError> {
+        let start = self.span().start;
+        if self.eat('-') {
+            let operand = self.unary()?;
+            Ok(self.built(-&operand, start))
+        } else {
+            self.power()
+        }
+    }
+
+    // `^` binds tighter than unary minus on its left and is right associative: -a^b^c is -(a^(b^c))
+    fn power(&mut self) -> Result<Value, ParseError> {
+        let start = self.span().start;
+        let base = self.primary()?;
+        if self.eat('^') {
+            let exponent = self.unary()?;
+            Ok(self.built(base.pow(&exponent), start))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value, ParseError> {
+        let span = self.span();
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(Value::from(n))
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                if self.eat('(') {
+                    let op = function(&name).ok_or_else(|| ParseError {
+                        message: format!("unknown function `{}`", name),
+                        span: span.clone(),
+                    })?;
+                    let argument = self.expr()?;
+                    self.expect(')')?;
+                    let value = op.apply(&[argument]).expect("unary ops take one child");
+                    Ok(self.built(value, span.start))
+                } else {
+                    self.vars.get(&name).cloned().ok_or_else(|| ParseError {
+                        message: format!("unknown variable `{}`", name),
+                        span,
+                    })
+                }
+            }
+            Some(Token::Symbol('(')) => {
+                self.pos += 1;
+                let value = self.expr()?;
+                self.expect(')')?;
+                Ok(value)
+            }
+            _ => Err(self.unexpected("expected a number, a variable or `(`")),
+        }
+    }
+}
+
+// The unary ops that can be called as functions.
+fn function(name: &str) -> Option<Op> {
+    match name {
+        "tanh" => Some(Op::Tanh),
+        "exp" => Some(Op::Exp),
+        "ln" => Some(Op::Ln),
+        "relu" => Some(Op::Relu),
+        "softplus" => Some(Op::Softplus),
+        _ => None,
+    }
+}
+
+/// Builds a graph from an expression such as `tanh(w1*x1 + w2*x2 + b) ^ 2`.
+/// Names refer to the leaves supplied in `vars`, numbers become new leaves, and the supported
+/// operators are + - * / ^ (right associative), unary minus, parentheses and the functions
+/// tanh, exp, ln, relu and softplus.
+/// The graph is the same, node for node, as the one built by the equivalent Rust expression.
+pub fn parse(expr: &str, vars: &HashMap<String, Value>) -> Result<Value, ParseError> {
+    parse_with(expr, vars, false)
+}
+
+/// Same as `parse`, labelling every node built by an operator or function with the text it was parsed from,
+/// e.g. `w1 * x1` for a product, so that dumps of the graph (`to_dot`, `find_by_label`) are self-describing.
+/// Leaves keep their own labels. This is what the `expr!` macro uses.
+pub fn parse_labeled(expr: &str, vars: &HashMap<String, Value>) -> Result<Value, ParseError> {
+    parse_with(expr, vars, true)
+}
+
+fn parse_with(expr: &str, vars: &HashMap<String, Value>, labels: bool) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+        source: expr,
+        vars,
+        labels,
+    };
+    let value = parser.expr()?;
+    if parser.peek().is_some() {
+        return Err(parser.unexpected("expected an operator"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_value_eq;
+    use crate::graph_diff::graph_diff;
+
+    fn vars(names: &[(&str, f64)]) -> HashMap<String, Value> {
+        names.iter().map(|&(name, data)| (name.to_string(), Value::from(data))).collect()
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        let vars = vars(&[("a", 10.0), ("b", 3.0), ("c", 2.0)]);
+        assert_eq!(parse("a - b - c", &vars).unwrap().data(), 5.0);
+        assert_eq!(parse("a - (b - c)", &vars).unwrap().data(), 9.0);
+        assert_value_eq!(parse("a / b / c", &vars).unwrap(), 10.0 / 6.0, 1e-15);
+    }
+
+    #[test]
+    fn power_is_right_associative_and_binds_tighter_than_minus() {
+        let vars = vars(&[("a", 2.0), ("b", 3.0), ("c", 2.0)]);
+        assert_eq!(parse("a ^ b ^ c", &vars).unwrap().data(), 512.0);
+        assert_eq!(parse("-a ^ c", &vars).unwrap().data(), -4.0);
+        assert_eq!(parse("a + b * c ^ a", &vars).unwrap().data(), 14.0);
+    }
+
+    #[test]
+    fn unknown_names_have_spans() {
+        let vars = vars(&[("x", 1.0)]);
+        let error = parse("x + tanh(y)", &vars).unwrap_err();
+        assert_eq!((error.message.as_str(), error.span.clone()), ("unknown variable `y`", 9..10));
+        let error = parse("sinh(x)", &vars).unwrap_err();
+        assert_eq!((error.message.as_str(), error.span.clone()), ("unknown function `sinh`", 0..4));
+        let error = parse("x * (x + 1", &vars).unwrap_err();
+        assert_eq!(error.span, 10..10);
+        assert!(parse("x # 2", &vars).is_err());
+    }
+
+    #[test]
+    fn parsed_graph_matches_the_rust_expression() {
+        let vars = vars(&[("w", 0.7), ("x", -1.2), ("b", 0.1)]);
+        let (w, x, b) = (&vars["w"], &vars["x"], &vars["b"]);
+        let parsed = parse("tanh(w * x + b) ^ 2 - exp(x) / 3", &vars).unwrap();
+        let built = &(&(w * x) + b).tanh().pow(&Value::from(2.0)) - &(&x.exp() / &Value::from(3.0));
+        assert!(graph_diff(&parsed, &built).is_empty(), "{}", graph_diff(&parsed, &built));
+
+        parsed.backward().unwrap();
+        let parsed_grads = [w.grad(), x.grad(), b.grad()];
+        for leaf in [w, x, b] {
+            leaf.zero_grad();
+        }
+        built.backward().unwrap();
+        assert_eq!(parsed_grads, [w.grad(), x.grad(), b.grad()]);
+    }
+
+    #[test]
+    fn labeled_parse_names_the_nodes() {
+        let vars = vars(&[("x", 2.0)]);
+        let root = parse_labeled("exp(x * 3)", &vars).unwrap();
+        assert_eq!(root.label().as_deref(), Some("exp(x * 3)"));
+        assert_eq!(root.children()[0].label().as_deref(), Some("x * 3"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::engine::Op;
+
+// The number of `profile` calls running, on any thread. Checked before anything else is done, so that the graph
+// code pays a single relaxed load when profiling is off. A count rather than a flag, so that a call ending on one
+// thread doesn't turn profiling off for another still running; the records themselves are per thread.
+static ENABLED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static RECORDS: RefCell<HashMap<Option<Op>, OpProfile>> = RefCell::new(HashMap::new());
+}
+
+/// What was recorded for one op (`None` for leaves) during a `profile` call.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpProfile {
+    pub op: Option<Op>,
+    /// Number of nodes created, and total time spent allocating them.
+    pub constructed: usize,
+    pub construct_time: Duration,
+    /// Number of propagation functions run in backward passes, and total time spent in them.
+    pub propagated: usize,
+    pub propagate_time: Duration,
+}
+
+impl OpProfile {
+    pub fn total_time(&self) -> Duration {
+        self.construct_time + self.propagate_time
+    }
+}
+
+/// The per-op counts and timings recorded by `profile`, sorted by decreasing total time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileReport {
+    pub ops: Vec<OpProfile>,
+}
+
+impl ProfileReport {
+    pub fn get(&self, op: Option<Op>) -> Option<&OpProfile> {
+        self.ops.iter().find(|profile| profile.op == op)
+    }
+
+    /// A table with one row per op, the most expensive first.
+    pub fn table(&self) -> String {
+        let mut out = format!(
+            "{:<14} {:>10} {:>14} {:>10} {:>14}\n",
+            "op", "nodes", "construct", "backward", "propagate"
+        );
+        for profile in &self.ops {
+            writeln!(
+                out,
+                "{:<14} {:>10} {:>14?} {:>10} {:>14?}",
+                op_name(profile.op),
+                profile.constructed,
+                profile.construct_time,
+                profile.propagated,
+                profile.propagate_time
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// The report in the Trace Event format read by chrome://tracing and Perfetto: one complete event per op
+    /// and phase, laid end to end, with the counts as arguments.
+    pub fn to_chrome_trace(&self) -> String {
+        let mut events = Vec::new();
+        let mut ts = 0.0;
+        for (phase, tid) in [("construct", 0), ("backward", 1)] {
+            for profile in &self.ops {
+                let (count, time) = match tid {
+                    0 => (profile.constructed, profile.construct_time),
+                    _ => (profile.propagated, profile.propagate_time),
+                };
+                if count == 0 {
+                    continue;
+                }
+                let dur = time.as_secs_f64() * 1e6;
+                events.push(format!(
+                    r#"{{"name":"{}","cat":"{}","ph":"X","ts":{},"dur":{},"pid":0,"tid":{},"args":{{"count":{}}}}}"#,
+                    op_name(profile.op),
+                    phase,
+                    ts,
+                    dur,
+                    tid,
+                    count
+                ));
+                ts += dur;
+            }
+        }
+        format!(r#"{{"traceEvents":[{}]}}"#, events.join(","))
+    }
+
+    pub fn write_chrome_trace(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_chrome_trace())
+    }
+}
+
+fn op_name(op: Option<Op>) -> String {
+    op.map_or("leaf".to_string(), |op| op.to_string())
+}
+
+/// Runs `f` while recording, for each op, how many nodes are created and how many propagation functions
+/// are run, and the time spent in each, on the current thread.
+///
+/// Profiling changes no results; when no `profile` call is running the only cost is a check of a flag.
+pub fn profile<R>(f: impl FnOnce() -> R) -> (R, ProfileReport) {
+    // turns profiling off again even if `f` panics
+    struct Stop;
+    impl Drop for Stop {
+        fn drop(&mut self) {
+            ENABLED.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    RECORDS.with(|records| records.borrow_mut().clear());
+    ENABLED.fetch_add(1, Ordering::Relaxed);
+    let stop = Stop;
+    let result = f();
+    drop(stop);
+
+    let mut ops: Vec<OpProfile> = RECORDS.with(|records| records.borrow_mut().drain().map(|(_, p)| p).collect());
+    ops.sort_by_key(|profile| (std::cmp::Reverse(profile.total_time()), op_name(profile.op)));
+    (result, ProfileReport { ops })
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed) > 0
+}
+
+pub(crate) fn record_construct(op: Option<Op>, time: Duration) {
+    record(op, |profile| {
+        profile.constructed += 1;
+        profile.construct_time += time;
+    });
+}
+
+pub(crate) fn record_propagate(op: Option<Op>, time: Duration) {
+    record(op, |profile| {
+        profile.propagated += 1;
+        profile.propagate_time += time;
+    });
+}
+
+fn record(op: Option<Op>, update: impl FnOnce(&mut OpProfile)) {
+    RECORDS.with(|records| {
+        let mut records = records.borrow_mut();
+        update(records.entry(op).or_insert_with(|| OpProfile { op, ..OpProfile::default() }));
+    });
+}
+
+// Starts timing if profiling is on.
+pub(crate) fn start() -> Option<Instant> {
+    enabled().then(Instant::now)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::engine::Value;
+
+    // `tanh(x·y + x)` and its backward pass
+    fn run() -> (f64, f64) {
+        let (x, y) = (Value::from(0.5), Value::from(-1.5));
+        let out = (&(&x * &y) + &x).tanh();
+        out.backward().unwrap();
+        (out.data(), x.grad())
+    }
+
+    #[test]
+    fn counts_match_the_graph() {
+        let (result, report) = profile(run);
+        assert_eq!(result, run());
+
+        let mut nodes: HashMap<Option<Op>, usize> = HashMap::new();
+        let (x, y) = (Value::from(0.5), Value::from(-1.5));
+        for node in (&(&x * &y) + &x).tanh().topo_order() {
+            *nodes.entry(node.op()).or_default() += 1;
+        }
+        for (op, count) in nodes {
+            let profile = report.get(op).unwrap();
+            assert_eq!(profile.constructed, count, "{:?}", op);
+            assert_eq!(profile.propagated, if op.is_some() { count } else { 0 }, "{:?}", op);
+        }
+        assert_eq!(report.ops.len(), 4);
+        assert!(report.ops.windows(2).all(|pair| pair[0].total_time() >= pair[1].total_time()));
+        assert_eq!(report.table().lines().count(), 5);
+    }
+
+    #[test]
+    fn chrome_trace_is_valid_json() {
+        let (_, report) = profile(run);
+        let trace = report.to_chrome_trace();
+        assert!(trace.starts_with(r#"{"traceEvents":[{"name":"#) && trace.ends_with("}]}"));
+        // one complete event per op and phase with a count: 4 constructions and 3 backward ops
+        assert_eq!(trace.matches(r#""ph":"X""#).count(), 7);
+        assert_eq!(trace.matches('{').count(), trace.matches('}').count());
+        #[cfg(feature = "serde")]
+        {
+            let parsed: serde_json::Value = serde_json::from_str(&trace).unwrap();
+            assert_eq!(parsed["traceEvents"].as_array().unwrap().len(), 7);
+        }
+    }
+
+    #[test]
+    fn nothing_is_recorded_outside_profile() {
+        let (_, report) = profile(|| ());
+        assert!(report.ops.is_empty());
+        run();
+        assert!(RECORDS.with(|records| records.borrow().is_empty()));
+    }
+}
@@ -0,0 +1,241 @@
+// Python bindings, built into an extension module named `angstromgrad` by maturin (see `pyproject.toml`), to
+// compare the crate with micrograd from a notebook. maturin builds them with the `extension-module` feature;
+// `cargo test --features python` links libpython instead and runs the pytest file in an embedded interpreter.
+//
+// Graph nodes are reference counted without synchronization, so the classes are `unsendable`: they can only be
+// used from the Python thread that created them, and pyo3 raises an exception on any other.
+
+// pyo3's generated wrappers convert every `PyResult` error into a `PyErr` again
+#![allow(clippy::useless_conversion)]
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+use crate::engine::Value;
+use crate::error::BackwardError;
+use crate::nn::{Module, MLP};
+
+impl From<BackwardError> for PyErr {
+    fn from(error: BackwardError) -> PyErr {
+        PyRuntimeError::new_err(error.to_string())
+    }
+}
+
+/// A node of the graph, as `angstromgrad.Value`.
+#[pyclass(name = "Value", unsendable)]
+#[derive(Clone)]
+pub struct PyValue {
+    value: Value,
+}
+
+// The right-hand side of an arithmetic operator: another node, or a number made into a new leaf.
+#[derive(FromPyObject)]
+enum Operand {
+    Value(PyValue),
+    Number(f64),
+}
+
+impl Operand {
+    fn value(self) -> Value {
+        match self {
+            Operand::Value(v) => v.value,
+            Operand::Number(x) => Value::from(x),
+        }
+    }
+}
+
+impl From<Value> for PyValue {
+    fn from(value: Value) -> PyValue {
+        PyValue { value }
+    }
+}
+
+#[pymethods]
+impl PyValue {
+    #[new]
+    fn new(data: f64) -> PyValue {
+        Value::from(data).into()
+    }
+
+    #[getter]
+    fn data(&self) -> f64 {
+        self.value.data()
+    }
+
+    #[setter]
+    fn set_data(&self, data: f64) {
+        self.value.set_data(data);
+    }
+
+    #[getter]
+    fn grad(&self) -> f64 {
+        self.value.grad()
+    }
+
+    fn __add__(&self, other: Operand) -> PyValue {
+        (&self.value + &other.value()).into()
+    }
+
+    fn __radd__(&self, other: Operand) -> PyValue {
+        (&other.value() + &self.value).into()
+    }
+
+    fn __sub__(&self, other: Operand) -> PyValue {
+        (&self.value - &other.value()).into()
+    }
+
+    fn __rsub__(&self, other: Operand) -> PyValue {
+        (&other.value() - &self.value).into()
+    }
+
+    fn __mul__(&self, other: Operand) -> PyValue {
+        (&self.value * &other.value()).into()
+    }
+
+    fn __rmul__(&self, other: Operand) -> PyValue {
+        (&other.value() * &self.value).into()
+    }
+
+    fn __truediv__(&self, other: Operand) -> PyValue {
+        (&self.value / &other.value()).into()
+    }
+
+    fn __rtruediv__(&self, other: Operand) -> PyValue {
+        (&other.value() / &self.value).into()
+    }
+
+    fn __pow__(&self, exponent: Operand, _modulo: Option<PyObject>) -> PyValue {
+        self.value.pow(&exponent.value()).into()
+    }
+
+    fn __neg__(&self) -> PyValue {
+        (-&self.value).into()
+    }
+
+    fn tanh(&self) -> PyValue {
+        self.value.tanh().into()
+    }
+
+    fn relu(&self) -> PyValue {
+        self.value.relu().into()
+    }
+
+    fn exp(&self) -> PyValue {
+        self.value.exp().into()
+    }
+
+    fn log(&self) -> PyValue {
+        self.value.ln().into()
+    }
+
+    /// Back-propagates from this node, raising `RuntimeError` if the graph can't be.
+    fn backward(&self) -> PyResult<()> {
+        Ok(self.value.backward()?)
+    }
+
+    fn zero_grad(&self) {
+        self.value.zero_grad();
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Value(data={}, grad={})", self.value.data(), self.value.grad())
+    }
+}
+
+/// A multi-layer perceptron, as `angstromgrad.MLP`, trained by plain gradient descent through `step`.
+#[pyclass(name = "MLP", unsendable)]
+pub struct PyMLP {
+    mlp: MLP,
+}
+
+#[pymethods]
+impl PyMLP {
+    #[new]
+    fn new(nin: usize, nouts: Vec<usize>) -> PyMLP {
+        PyMLP { mlp: MLP::new(nin, nouts) }
+    }
+
+    /// The outputs for `inputs`, numbers or nodes. A single output is returned as is rather than in a list,
+    /// as micrograd does.
+    fn __call__(&self, py: Python<'_>, inputs: Vec<Operand>) -> PyObject {
+        let inputs: Vec<Value> = inputs.into_iter().map(Operand::value).collect();
+        let mut outputs: Vec<PyValue> = self.mlp.forward(inputs).into_iter().map(PyValue::from).collect();
+        if outputs.len() == 1 {
+            outputs.remove(0).into_py(py)
+        } else {
+            outputs.into_py(py)
+        }
+    }
+
+    fn forward(&self, inputs: Vec<Operand>) -> Vec<PyValue> {
+        let inputs: Vec<Value> = inputs.into_iter().map(Operand::value).collect();
+        self.mlp.forward(inputs).into_iter().map(PyValue::from).collect()
+    }
+
+    fn parameters(&self) -> Vec<PyValue> {
+        self.mlp.parameters().into_iter().map(PyValue::from).collect()
+    }
+
+    fn zero_grad(&self) {
+        for param in Module::parameters(&self.mlp) {
+            param.zero_grad();
+        }
+    }
+
+    /// A gradient descent step of learning rate `lr` on every parameter.
+    fn step(&self, lr: f64) {
+        for param in Module::parameters(&self.mlp) {
+            param.descend(lr);
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("MLP({} parameters)", Module::parameters(&self.mlp).len())
+    }
+}
+
+#[pymodule]
+fn angstromgrad(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyValue>()?;
+    module.add_class::<PyMLP>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    // Runs the functions of the pytest file of the bindings in an embedded interpreter, with the module
+    // registered under its name as maturin would install it.
+    #[test]
+    fn the_python_tests_pass() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = pyo3::wrap_pymodule!(angstromgrad)(py);
+            py.import_bound("sys").unwrap().getattr("modules").unwrap().set_item("angstromgrad", module).unwrap();
+            let globals = PyDict::new_bound(py);
+            py.run_bound(include_str!("../python/tests/test_angstromgrad.py"), Some(&globals), None).unwrap();
+
+            let mut ran = 0;
+            for (name, test) in globals.iter() {
+                let name: String = name.extract().unwrap();
+                if name.starts_with("test_") {
+                    test.call0().unwrap_or_else(|error| panic!("{} failed: {}", name, error));
+                    ran += 1;
+                }
+            }
+            assert_eq!(ran, 4);
+        });
+    }
+
+    #[test]
+    fn backward_errors_become_runtime_errors() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let error = PyErr::from(BackwardError::StaleGradients { leaves: 2 });
+            assert!(error.is_instance_of::<PyRuntimeError>(py));
+            assert_eq!(error.value_bound(py).to_string(), BackwardError::StaleGradients { leaves: 2 }.to_string());
+        });
+    }
+}
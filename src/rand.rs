@@ -0,0 +1,238 @@
+// A small pseudo-random number generator owned by the crate, so that experiments can be seeded
+// and reproduced without depending on an external crate.
+
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use crate::engine::Value;
+
+/// xoshiro256** generator, seeded through splitmix64.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    /// A generator whose sequence is entirely determined by `seed`.
+    pub fn seed(seed: u64) -> Rng {
+        let mut x = seed;
+        let mut splitmix = || {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        Rng { state: [splitmix(), splitmix(), splitmix(), splitmix()] }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// Uniform in [0, 1), using the top 53 bits of the next output.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Uniform in [lo, hi).
+    pub fn uniform(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (hi - lo) * self.next_f64()
+    }
+
+    /// Gaussian with the given mean and standard deviation (Box-Muller transform).
+    pub fn normal(&mut self, mean: f64, std: f64) -> f64 {
+        // 1 - u lies in (0, 1], keeping the logarithm finite
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+        mean + std * z
+    }
+
+    /// Standard Gumbel, `-ln(-ln(u))` for `u` uniform in (0, 1), as used by the Gumbel-max trick.
+    pub fn gumbel(&mut self) -> f64 {
+        // offsetting by half a step keeps u away from both 0 and 1, where the result is infinite
+        let u = ((self.next_u64() >> 11) as f64 + 0.5) * (1.0 / (1u64 << 53) as f64);
+        -(-u.ln()).ln()
+    }
+
+    /// An index drawn with probability proportional to its weight, e.g. sampling from the output of a softmax.
+    /// The weights need not sum to one; panics unless they are non-negative with a positive sum.
+    pub fn categorical(&mut self, weights: &[f64]) -> usize {
+        let total: f64 = weights.iter().sum();
+        assert!(
+            total > 0.0 && weights.iter().all(|&w| w >= 0.0),
+            "categorical needs non-negative weights with a positive sum"
+        );
+        let mut remaining = self.uniform(0.0, total);
+        for (i, &weight) in weights.iter().enumerate() {
+            if remaining < weight {
+                return i;
+            }
+            remaining -= weight;
+        }
+        // rounding can leave a sliver past the last weight: it belongs to the last non-zero one
+        weights.iter().rposition(|&w| w > 0.0).expect("the sum is positive")
+    }
+}
+
+impl Value {
+    /// A new leaf drawn from the standard normal distribution.
+    pub fn randn(rng: &mut Rng) -> Value {
+        Value::from(rng.normal(0.0, 1.0))
+    }
+}
+
+thread_local! {
+    // The generator used by the constructors that don't take one, e.g. `Neuron::new`.
+    static GLOBAL: RefCell<Rng> = RefCell::new(Rng::seed(entropy()));
+}
+
+// A different seed on every run, from the random keys of the standard library's hash maps.
+fn entropy() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Seeds the default generator of the current thread, used by the constructors that don't take a generator
+/// (`Neuron::new`, `Layer::new`, `MLP::new`), so that a script calling `seed` first builds the same model on
+/// every run. APIs that take an explicit `Rng` don't use it. Unless seeded, it starts from a different seed on
+/// every run.
+pub fn seed(seed: u64) {
+    GLOBAL.with(|global| *global.borrow_mut() = Rng::seed(seed));
+}
+
+/// Runs `f` with the default generator seeded with `seed`, then puts back the generator as it was before, so
+/// that only what `f` draws is made deterministic.
+pub fn with_seed<R>(seed: u64, f: impl FnOnce() -> R) -> R {
+    // puts the previous generator back even if `f` panics
+    struct Restore(Option<Rng>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(previous) = self.0.take() {
+                GLOBAL.with(|global| *global.borrow_mut() = previous);
+            }
+        }
+    }
+
+    let previous = GLOBAL.with(|global| global.replace(Rng::seed(seed)));
+    let _restore = Restore(Some(previous));
+    f()
+}
+
+/// Runs `f` on the default generator of the current thread. `f` must not draw from the default generator itself,
+/// e.g. by constructing a `Neuron`.
+pub fn with_rng<R>(f: impl FnOnce(&mut Rng) -> R) -> R {
+    GLOBAL.with(|global| f(&mut global.borrow_mut()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_seed_fixes_the_sequence() {
+        let draws = |seed| {
+            let mut rng = Rng::seed(seed);
+            (0..4).map(|_| rng.next_u64()).collect::<Vec<u64>>()
+        };
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+        // the reference xoshiro256** seeded through splitmix64
+        assert_eq!(draws(0)[0], 0x99EC_5F36_CB75_F2B4);
+    }
+
+    #[test]
+    fn samplers_stay_in_range() {
+        let mut rng = Rng::seed(7);
+        for _ in 0..10_000 {
+            let u = rng.uniform(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&u));
+            assert!(rng.gumbel().is_finite());
+        }
+    }
+
+    #[test]
+    fn normal_has_the_requested_mean_and_variance() {
+        let mut rng = Rng::seed(1);
+        let n = 100_000;
+        let draws: Vec<f64> = (0..n).map(|_| rng.normal(3.0, 2.0)).collect();
+        let mean = draws.iter().sum::<f64>() / n as f64;
+        let variance = draws.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+        assert!((mean - 3.0).abs() < 0.05, "mean {}", mean);
+        assert!((variance - 4.0).abs() < 0.1, "variance {}", variance);
+    }
+
+    #[test]
+    fn with_seed_restores_the_default_generator() {
+        seed(5);
+        let expected = with_rng(|rng| rng.next_u64());
+        seed(5);
+        let inner = with_seed(9, || with_rng(|rng| rng.next_u64()));
+        assert_eq!(inner, Rng::seed(9).next_u64());
+        assert_eq!(with_rng(|rng| rng.next_u64()), expected);
+        assert_eq!(Value::randn(&mut Rng::seed(3)).data(), Rng::seed(3).normal(0.0, 1.0));
+    }
+
+    #[test]
+    fn a_global_seed_reproduces_models_and_dropout_masks() {
+        use crate::nn::{Dropout, Module, MLP};
+
+        let run = |seed| {
+            with_seed(seed, || {
+                let params: Vec<f64> = MLP::new(3, vec![4, 1]).parameters().iter().map(Value::data).collect();
+                let ones: Vec<Value> = (0..32).map(|_| Value::from(1.0)).collect();
+                let mask: Vec<f64> = Dropout::new(0.5).forward(&ones).iter().map(Value::data).collect();
+                (params, mask)
+            })
+        };
+        assert_eq!(run(7), run(7));
+        let (params, mask) = run(8);
+        assert_ne!(params, run(7).0);
+        assert_ne!(mask, run(7).1);
+        // the kept inputs are scaled by 1 / (1 - p)
+        assert!(mask.iter().all(|&m| m == 0.0 || m == 2.0));
+    }
+
+    #[test]
+    fn explicit_generators_ignore_the_global_seed() {
+        let xs = crate::engine::values_from(&[0.3, -1.0, 2.0]);
+        let sample = |global| {
+            seed(global);
+            let sample = crate::ops::gumbel_softmax(&xs, 0.5, &mut Rng::seed(4));
+            let linear = crate::nn::Linear::new(2, 2, true, crate::nn::Init::He, &mut Rng::seed(4));
+            let weights: Vec<f64> = linear.weight().data().iter().map(Value::data).collect();
+            (sample.iter().map(Value::data).collect::<Vec<f64>>(), weights)
+        };
+        assert_eq!(sample(1), sample(2));
+    }
+
+    #[test]
+    fn categorical_draws_proportionally_to_the_weights() {
+        let mut rng = Rng::seed(3);
+        let weights = [1.0, 0.0, 3.0];
+        let mut counts = [0usize; 3];
+        for _ in 0..40_000 {
+            counts[rng.categorical(&weights)] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!((counts[2] as f64 / 40_000.0 - 0.75).abs() < 0.01, "{:?}", counts);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative weights")]
+    fn categorical_rejects_zero_weights() {
+        Rng::seed(0).categorical(&[0.0, 0.0]);
+    }
+}
@@ -0,0 +1,317 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::engine::{NodeId, NodeMap, Op, Value};
+use crate::error::ReplaceError;
+
+/// A transformation of a graph applied node by node by `rewrite`, such as constant folding or swapping an
+/// activation for deployment.
+pub trait GraphPass {
+    /// Called for every node of the original graph, children before parents, with the already rewritten
+    /// children of `node`. Returns the node to use in its place, or `None` to keep it: leaves are then kept
+    /// as they are and interior nodes are rebuilt on top of `children`.
+    fn rewrite(&self, node: &Value, children: &[Value]) -> Option<Value>;
+}
+
+/// Rewrites the graph rooted at `root` with `pass` and returns the new root; the original graph is untouched.
+///
+/// Each node is rewritten once and the result reused by all its parents, so the sharing of the original
+/// graph is preserved. Interior nodes that the pass keeps are rebuilt with their data recomputed from the
+/// new children and a zero grad; leaves are shared with the original graph.
+pub fn rewrite(root: &Value, pass: &dyn GraphPass) -> Value {
+    let mut rewritten: NodeMap<Value> = NodeMap::new();
+
+    for value in root.topo_order() {
+        let children: Vec<Value> = value.borrow()._prev.iter().map(|child| rewritten[child].clone()).collect();
+        let replacement = pass
+            .rewrite(&value, &children)
+            .unwrap_or_else(|| rebuild(&value, children));
+        rewritten.insert(&value, replacement);
+    }
+
+    rewritten[root].clone()
+}
+
+// `value` itself if it is a leaf, otherwise a copy computed from `children`
+fn rebuild(value: &Value, children: Vec<Value>) -> Value {
+    let Some(op) = value.op() else {
+        return value.clone();
+    };
+    let inputs: Vec<f64> = children.iter().map(|child| child.data()).collect();
+    let copy = value.with_children(children);
+    copy.set_data(op.forward(&inputs));
+    copy
+}
+
+/// Rebuilds the graph rooted at `root` with the node labelled `target_label` replaced by
+/// `replacement(children)`, e.g. to swap an activation or insert a scaling factor into a built model, and returns
+/// the new root. `children` are the children of the target, so `|children| children[0].relu()` turns a labelled
+/// tanh into a relu.
+///
+/// Only the ancestors of the target are rebuilt, with their data recomputed; every other node is kept as it is,
+/// identity included, so the rest of the graph, its sharing and its parameters are untouched. Fails unless
+/// exactly one node has the label, see `replace_all_subgraphs`.
+pub fn replace_subgraph(
+    root: &Value,
+    target_label: &str,
+    replacement: impl Fn(&[Value]) -> Value,
+) -> Result<Value, ReplaceError> {
+    let order = root.topo_order();
+    let count = order.iter().filter(|value| value.borrow().label.as_deref() == Some(target_label)).count();
+    if count > 1 {
+        return Err(ReplaceError::Ambiguous { label: target_label.to_string(), count });
+    }
+    replace_all_subgraphs(root, target_label, replacement)
+}
+
+/// Same as `replace_subgraph`, replacing every node labelled `target_label`, children before parents: a target
+/// below another one is replaced first, and the replacement of the upper one is given the new children. Fails
+/// if no node has the label.
+pub fn replace_all_subgraphs(
+    root: &Value,
+    target_label: &str,
+    replacement: impl Fn(&[Value]) -> Value,
+) -> Result<Value, ReplaceError> {
+    let mut rewritten: NodeMap<Value> = NodeMap::new();
+    let mut found = false;
+
+    for value in root.topo_order() {
+        let original: Vec<Value> = value.borrow()._prev.clone();
+        let children: Vec<Value> = original.iter().map(|child| rewritten[child].clone()).collect();
+        let replaced = if value.borrow().label.as_deref() == Some(target_label) {
+            found = true;
+            replacement(&children)
+        } else if std::iter::zip(&original, &children).all(|(old, new)| old.id() == new.id()) {
+            value.clone()
+        } else {
+            rebuild(&value, children)
+        };
+        rewritten.insert(&value, replaced);
+    }
+
+    if !found {
+        return Err(ReplaceError::NotFound { label: target_label.to_string() });
+    }
+    Ok(rewritten[root].clone())
+}
+
+// What makes two nodes the same for `Cse`
+#[derive(PartialEq, Eq, Hash)]
+enum NodeKey {
+    // an op and the identities of the children it is applied to
+    Op(Op, Vec<NodeId>),
+    // the bits of the data of an unlabeled constant
+    Constant(u64),
+}
+
+/// Stores structurally identical nodes, the same op applied to the same (rewritten) children, only once.
+/// See `engine::cse`.
+#[derive(Default)]
+pub struct Cse {
+    canonical: RefCell<HashMap<NodeKey, Value>>,
+}
+
+impl GraphPass for Cse {
+    fn rewrite(&self, node: &Value, children: &[Value]) -> Option<Value> {
+        let key = match node.op() {
+            Some(op) => NodeKey::Op(op, children.iter().map(Value::id).collect()),
+            None if !node.requires_grad() && node.label().is_none() => NodeKey::Constant(node.data().to_bits()),
+            None => return None,
+        };
+        let mut canonical = self.canonical.borrow_mut();
+        Some(canonical.entry(key).or_insert_with(|| rebuild(node, children.to_vec())).clone())
+    }
+}
+
+// Replaces every assertion node with the node it checks, see `engine::strip_assertions`.
+pub(crate) struct StripAssertions;
+
+impl GraphPass for StripAssertions {
+    fn rewrite(&self, node: &Value, children: &[Value]) -> Option<Value> {
+        (node.op() == Some(Op::Assert)).then(|| children[0].clone())
+    }
+}
+
+/// Replaces every node computed only from constants (the frozen leaves made by `Value::constant`) with a
+/// constant holding its value. Parameters and inputs are never folded, even when nothing updates them.
+pub struct ConstantFold;
+
+impl GraphPass for ConstantFold {
+    fn rewrite(&self, node: &Value, children: &[Value]) -> Option<Value> {
+        let op = node.op()?;
+        if !children.iter().all(|child| child.is_leaf() && !child.requires_grad()) {
+            return None;
+        }
+        let inputs: Vec<f64> = children.iter().map(|child| child.data()).collect();
+        Some(Value::constant(op.forward(&inputs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    struct Identity;
+
+    impl GraphPass for Identity {
+        fn rewrite(&self, _: &Value, _: &[Value]) -> Option<Value> {
+            None
+        }
+    }
+
+    struct TanhToRelu;
+
+    impl GraphPass for TanhToRelu {
+        fn rewrite(&self, node: &Value, children: &[Value]) -> Option<Value> {
+            (node.op() == Some(Op::Tanh)).then(|| children[0].relu())
+        }
+    }
+
+    // Rebuilds every interior node from fresh copies of its leaf children, which duplicates any leaf used twice
+    struct CopyLeaves(Cell<usize>);
+
+    impl GraphPass for CopyLeaves {
+        fn rewrite(&self, node: &Value, children: &[Value]) -> Option<Value> {
+            self.0.set(self.0.get() + 1);
+            node.op()?;
+            let copies: Vec<Value> = children
+                .iter()
+                .map(|child| if child.is_leaf() { Value::from(child.data()) } else { child.clone() })
+                .collect();
+            Some(rebuild(node, copies))
+        }
+    }
+
+    // `x·tanh(w·x) + tanh(w·x)`, in which `x` and `tanh(w·x)` are shared
+    fn shared_graph(w: &Value, x: &Value) -> Value {
+        let hidden = (w * x).tanh();
+        &(x * &hidden) + &hidden
+    }
+
+    fn ops(root: &Value) -> Vec<Option<Op>> {
+        root.topo_order().iter().map(Value::op).collect()
+    }
+
+    // The sharing check: a pass that doesn't change the shape of the graph must not add nodes to it
+    fn preserves_sharing(original: &Value, rewritten: &Value) -> bool {
+        rewritten.topo_order().len() == original.topo_order().len()
+    }
+
+    #[test]
+    fn identity_pass_keeps_values_and_gradients() {
+        let (w, x) = (Value::from(0.7), Value::from(-1.2));
+        let root = shared_graph(&w, &x);
+        let copy = rewrite(&root, &Identity);
+        assert!(!Rc::ptr_eq(&copy, &root));
+        assert_eq!(copy.data(), root.data());
+        assert!(preserves_sharing(&root, &copy));
+
+        root.backward().unwrap();
+        let expected = (w.grad(), x.grad());
+        w.zero_grad();
+        x.zero_grad();
+        copy.backward().unwrap();
+        assert_eq!((w.grad(), x.grad()), expected);
+    }
+
+    #[test]
+    fn swapping_tanh_changes_only_the_tanh_nodes() {
+        let (w, x) = (Value::from(0.7), Value::from(-1.2));
+        let root = shared_graph(&w, &x);
+        let swapped = rewrite(&root, &TanhToRelu);
+
+        let expected: Vec<Option<Op>> =
+            ops(&root).into_iter().map(|op| if op == Some(Op::Tanh) { Some(Op::Relu) } else { op }).collect();
+        assert_eq!(ops(&swapped), expected);
+        assert!(preserves_sharing(&root, &swapped));
+        let relu = (0.7f64 * -1.2).max(0.0);
+        assert_eq!(swapped.data(), -1.2 * relu + relu);
+    }
+
+    #[test]
+    fn duplicating_passes_fail_the_sharing_check() {
+        let (w, x) = (Value::from(0.7), Value::from(-1.2));
+        let root = shared_graph(&w, &x);
+        let pass = CopyLeaves(Cell::new(0));
+        let copied = rewrite(&root, &pass);
+        // memoization calls the pass once per node, and the shared `tanh` stays shared
+        assert_eq!(pass.0.get(), root.topo_order().len());
+        assert_eq!(copied.data(), root.data());
+        assert!(!preserves_sharing(&root, &copied));
+    }
+
+    #[test]
+    fn constant_folding_keeps_parameters() {
+        let x = Value::from(2.0);
+        let scale = &Value::constant(3.0) * &Value::constant(0.5).exp();
+        let root = &x * &scale;
+        let folded = rewrite(&root, &ConstantFold);
+        assert_eq!(ops(&folded), [None, None, Some(Op::Mul)]);
+        assert_eq!(folded.data(), root.data());
+        assert!(folded.children().iter().any(|child| Rc::ptr_eq(child, &x)));
+    }
+
+    // `exp(b) + tanh(w·x)·x`, with the tanh labelled
+    fn labelled_graph(w: &Value, x: &Value, b: &Value) -> (Value, Value) {
+        let untouched = b.exp();
+        (&untouched + &(&(w * x).tanh().add_label("activation") * x), untouched)
+    }
+
+    #[test]
+    fn replacing_tanh_with_identity_changes_values_and_gradients() {
+        let (w, x, b) = (Value::from(0.7), Value::from(-1.2), Value::from(0.3));
+        let (root, _) = labelled_graph(&w, &x, &b);
+        let replaced = replace_subgraph(&root, "activation", |children| children[0].clone()).unwrap();
+        let (wd, xd) = (0.7, -1.2);
+        assert_eq!(replaced.data(), 0.3f64.exp() + wd * xd * xd);
+        assert_eq!(root.data(), 0.3f64.exp() + (wd * xd).tanh() * xd);
+
+        // d/dw (w·x·x) = x², d/dx = 2·w·x
+        replaced.backward().unwrap();
+        assert_eq!((w.grad(), x.grad(), b.grad()), (xd * xd, 2.0 * wd * xd, 0.3f64.exp()));
+    }
+
+    #[test]
+    fn untouched_subgraphs_keep_their_nodes() {
+        let (w, x, b) = (Value::from(0.7), Value::from(-1.2), Value::from(0.3));
+        let (root, untouched) = labelled_graph(&w, &x, &b);
+        let replaced = replace_subgraph(&root, "activation", |children| children[0].relu()).unwrap();
+        let kept = |node: &Value| replaced.topo_order().iter().any(|other| Rc::ptr_eq(other, node));
+        assert!(kept(&untouched) && kept(&w) && kept(&x) && kept(&b));
+        // the product below the activation is kept too, the ancestors of the target are rebuilt
+        let product = root.children()[1].children()[0].children()[0].clone();
+        assert!(kept(&product));
+        assert!(!kept(&root));
+        assert_eq!(replaced.topo_order().len(), root.topo_order().len());
+    }
+
+    #[test]
+    fn labels_of_several_nodes_need_replace_all() {
+        let (w, x) = (Value::from(0.7), Value::from(-1.2));
+        let inner = (&w * &x).tanh().add_label("activation");
+        let root = (&inner * &x).tanh().add_label("activation");
+        let error = replace_subgraph(&root, "activation", |children| children[0].relu()).unwrap_err();
+        assert_eq!(error, ReplaceError::Ambiguous { label: "activation".to_string(), count: 2 });
+        assert_eq!(
+            error.to_string(),
+            "2 nodes are labelled `activation`: use replace_all_subgraphs to replace them all"
+        );
+
+        // the inner target is replaced first, so the outer one is applied to the relu
+        let replaced = replace_all_subgraphs(&root, "activation", |children| children[0].relu()).unwrap();
+        assert_eq!(ops(&replaced), [None, None, Some(Op::Mul), Some(Op::Relu), Some(Op::Mul), Some(Op::Relu)]);
+        assert_eq!(replaced.data(), ((0.7f64 * -1.2).max(0.0) * -1.2).max(0.0));
+    }
+
+    #[test]
+    fn missing_labels_are_reported() {
+        let (w, x, b) = (Value::from(0.7), Value::from(-1.2), Value::from(0.3));
+        let (root, _) = labelled_graph(&w, &x, &b);
+        let error = replace_subgraph(&root, "dropout", |children| children[0].clone()).unwrap_err();
+        assert_eq!(error, ReplaceError::NotFound { label: "dropout".to_string() });
+        assert!(replace_all_subgraphs(&root, "dropout", |children| children[0].clone()).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use serde::de::Error;
+
+use crate::engine::Value;
+use crate::node_table::NodeTable;
+
+impl Value {
+    /// Serializes the graph reachable from `self` (data, grads, labels, frozen flags and structure) to JSON.
+    pub fn to_graph_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&NodeTable::of(self))
+    }
+
+    /// Rebuilds a graph written by `to_graph_json` and returns its root.
+    ///
+    /// Every interior node is rebuilt through the constructor of its op, which restores its propagation function;
+    /// the stored data and grad then overwrite the recomputed ones, so a graph saved after `backward` keeps its grads.
+    pub fn from_graph_json(json: &str) -> Result<Value, serde_json::Error> {
+        let table: NodeTable = serde_json::from_str(json)?;
+        table.build().map_err(serde_json::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `tanh(x·y) + x·y` with the product shared, after a backward pass
+    fn example() -> (Value, Value) {
+        let x = Value::from(0.5);
+        x.borrow_mut().label = Some("x".to_string());
+        let product = &x * &Value::from(-2.0);
+        let root = &product.tanh() + &product;
+        root.backward().unwrap();
+        (x, root)
+    }
+
+    #[test]
+    fn round_trip_preserves_data_structure_and_grads() {
+        let (_, root) = example();
+        let loaded = Value::from_graph_json(&root.to_graph_json().unwrap()).unwrap();
+
+        let (original, copy) = (root.topo_order(), loaded.topo_order());
+        assert_eq!(copy.len(), original.len());
+        for (a, b) in std::iter::zip(&original, &copy) {
+            assert_eq!((a.op(), a.data(), a.grad(), a.label()), (b.op(), b.data(), b.grad(), b.label()));
+            assert_eq!(a.requires_grad(), b.requires_grad());
+        }
+        // the product is still a single node used twice
+        let product = &loaded.children()[1];
+        assert_eq!(loaded.children()[0].children()[0].id(), product.id());
+    }
+
+    #[test]
+    fn loaded_graph_back_propagates_the_same() {
+        let (x, root) = example();
+        let loaded = Value::from_graph_json(&root.to_graph_json().unwrap()).unwrap();
+        loaded.zero_grad_all();
+        loaded.backward().unwrap();
+        let leaf = loaded.topo_order().into_iter().find(|value| value.label().as_deref() == Some("x")).unwrap();
+        assert_eq!(leaf.grad(), x.grad());
+    }
+
+    #[test]
+    fn unknown_ops_are_errors() {
+        let (_, root) = example();
+        let json = root.to_graph_json().unwrap().replace("\"Tanh\"", "\"Frobnicate\"");
+        assert!(json.contains("Frobnicate"));
+        assert!(Value::from_graph_json(&json).is_err());
+        assert!(Value::from_graph_json("{\"nodes\": []}").is_err());
+    }
+}
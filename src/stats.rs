@@ -0,0 +1,154 @@
+use crate::engine::Value;
+
+/// Which quantity of a node `histogram` is taken over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataOrGrad {
+    Data,
+    Grad,
+}
+
+impl DataOrGrad {
+    fn of(self, value: &Value) -> f64 {
+        match self {
+            DataOrGrad::Data => value.data(),
+            DataOrGrad::Grad => value.grad(),
+        }
+    }
+}
+
+/// Summary statistics of the data or grads of a set of nodes, see `tensor_stats`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    /// The number of values, non-finite ones included.
+    pub n: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// The population standard deviation.
+    pub std: f64,
+    pub n_zero: usize,
+    /// The number of infinite and NaN values, which are left out of every other statistic.
+    pub n_nonfinite: usize,
+}
+
+/// Statistics of the data of `values`, e.g. of a model's `parameters()` to check the distribution of its
+/// weights. `min`, `max`, `mean` and `std` are taken over the finite values only and are NaN if there are none.
+pub fn tensor_stats<'a>(values: impl IntoIterator<Item = &'a Value>) -> Stats {
+    stats(values, DataOrGrad::Data)
+}
+
+/// Same as `tensor_stats` for the grads of `values`.
+pub fn grad_stats<'a>(values: impl IntoIterator<Item = &'a Value>) -> Stats {
+    stats(values, DataOrGrad::Grad)
+}
+
+fn stats<'a>(values: impl IntoIterator<Item = &'a Value>, of: DataOrGrad) -> Stats {
+    let all: Vec<f64> = values.into_iter().map(|value| of.of(value)).collect();
+    let finite: Vec<f64> = all.iter().copied().filter(|x| x.is_finite()).collect();
+    let (min, max, mean, std) = if finite.is_empty() {
+        (f64::NAN, f64::NAN, f64::NAN, f64::NAN)
+    } else {
+        let n = finite.len() as f64;
+        let mean = finite.iter().sum::<f64>() / n;
+        let variance = finite.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        let min = finite.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = finite.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        (min, max, mean, variance.sqrt())
+    };
+    Stats {
+        n: all.len(),
+        min,
+        max,
+        mean,
+        std,
+        n_zero: finite.iter().filter(|&&x| x == 0.0).count(),
+        n_nonfinite: all.len() - finite.len(),
+    }
+}
+
+/// A histogram of the data or grads of `values`: `bins` bins of equal width from the smallest to the largest
+/// finite value, each given as its lower edge and the number of values in it. Every bin is half-open except the
+/// last, which also holds the largest value. Non-finite values are left out.
+///
+/// If all the values are equal they all fall in the first bin; if there is no finite value the result is empty.
+/// Panics if `bins` is 0.
+pub fn histogram<'a>(
+    values: impl IntoIterator<Item = &'a Value>,
+    bins: usize,
+    of: DataOrGrad,
+) -> Vec<(f64, usize)> {
+    assert!(bins > 0, "a histogram needs at least one bin");
+    let finite: Vec<f64> = values.into_iter().map(|value| of.of(value)).filter(|x| x.is_finite()).collect();
+    let Some(min) = finite.iter().copied().reduce(f64::min) else {
+        return Vec::new();
+    };
+    let max = finite.iter().copied().fold(min, f64::max);
+    let width = (max - min) / bins as f64;
+
+    let mut counts = vec![0; bins];
+    for x in finite {
+        let bin = if width > 0.0 { ((x - min) / width) as usize } else { 0 };
+        counts[bin.min(bins - 1)] += 1;
+    }
+    counts.into_iter().enumerate().map(|(i, count)| (min + i as f64 * width, count)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::values_from;
+
+    #[test]
+    fn stats_of_a_known_vector_are_exact() {
+        let xs = values_from(&[1.0, 2.0, 3.0, 4.0, 0.0]);
+        let stats = tensor_stats(&xs);
+        let expected = Stats { n: 5, min: 0.0, max: 4.0, mean: 2.0, std: 2f64.sqrt(), n_zero: 1, n_nonfinite: 0 };
+        assert_eq!(stats, expected);
+
+        // d(Σ x²)/dx = 2x
+        let y = crate::ops::add_n(&xs.iter().map(|x| x * x).collect::<Vec<Value>>());
+        y.backward_unchecked();
+        let grads = grad_stats(&xs);
+        assert_eq!((grads.min, grads.max, grads.mean, grads.n_zero), (0.0, 8.0, 4.0, 1));
+    }
+
+    #[test]
+    fn nonfinite_values_are_counted_and_left_out() {
+        let xs = values_from(&[1.0, f64::NAN, 3.0, f64::INFINITY]);
+        let stats = tensor_stats(&xs);
+        assert_eq!((stats.n, stats.n_nonfinite, stats.min, stats.max, stats.mean), (4, 2, 1.0, 3.0, 2.0));
+        assert_eq!(histogram(&xs, 2, DataOrGrad::Data), vec![(1.0, 1), (2.0, 1)]);
+
+        let stats = tensor_stats(&values_from(&[f64::NAN]));
+        assert!(stats.mean.is_nan() && stats.std.is_nan());
+        assert!(histogram(&values_from(&[f64::NAN]), 3, DataOrGrad::Data).is_empty());
+    }
+
+    #[test]
+    fn histogram_bins_cover_min_to_max_inclusively() {
+        let xs = values_from(&[-1.0, -0.5, 0.0, 0.4, 0.5, 1.0, 3.0]);
+        let bins = histogram(&xs, 4, DataOrGrad::Data);
+        // width 1, the largest value in the last bin
+        assert_eq!(bins, vec![(-1.0, 2), (0.0, 3), (1.0, 1), (2.0, 1)]);
+        assert_eq!(bins.iter().map(|&(_, count)| count).sum::<usize>(), xs.len());
+
+        assert_eq!(histogram(&values_from(&[2.0, 2.0]), 3, DataOrGrad::Data), vec![(2.0, 2), (2.0, 0), (2.0, 0)]);
+    }
+
+    #[test]
+    fn statistics_over_a_graph() {
+        let x = Value::from(2.0);
+        let y = (&x * &x).tanh();
+        y.backward_unchecked();
+        let nodes = y.topo_order();
+        let grads = histogram(nodes.iter(), 1, DataOrGrad::Grad);
+        assert_eq!(grads[0].1, 3);
+        assert_eq!(tensor_stats(nodes.iter()).max, 4.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one bin")]
+    fn a_histogram_needs_a_bin() {
+        histogram(&values_from(&[1.0]), 0, DataOrGrad::Data);
+    }
+}
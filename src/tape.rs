@@ -0,0 +1,141 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::engine::Value;
+
+thread_local! {
+    // The tapes of the `Tape::scope` calls running on this thread, innermost last.
+    static ACTIVE: RefCell<Vec<Rc<RefCell<Vec<Value>>>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Collects the parameters created while it is recording, so that they don't have to be listed by hand.
+///
+/// Within `Tape::scope`, every value made with `tape.value` and every parameter created by the `nn`
+/// constructors is registered, the latter with the innermost running scope. Values made with `Value::from`
+/// are not affected.
+pub struct Tape {
+    params: Rc<RefCell<Vec<Value>>>,
+}
+
+impl Tape {
+    /// Runs `f` with a new tape recording, and returns its result together with the tape.
+    pub fn scope<R>(f: impl FnOnce(&Tape) -> R) -> (R, Tape) {
+        // stops recording even if `f` panics
+        struct Pop;
+        impl Drop for Pop {
+            fn drop(&mut self) {
+                ACTIVE.with(|active| active.borrow_mut().pop());
+            }
+        }
+
+        let tape = Tape { params: Rc::new(RefCell::new(Vec::new())) };
+        ACTIVE.with(|active| active.borrow_mut().push(tape.params.clone()));
+        let pop = Pop;
+        let result = f(&tape);
+        drop(pop);
+        (result, tape)
+    }
+
+    /// A new leaf holding `data`, registered as a parameter of this tape.
+    pub fn value(&self, data: f64) -> Value {
+        let value = Value::from(data);
+        self.params.borrow_mut().push(value.clone());
+        value
+    }
+
+    /// The registered parameters, in the order they were created.
+    pub fn parameters(&self) -> Vec<Value> {
+        self.params.borrow().clone()
+    }
+}
+
+// Registers `value` with the innermost running scope, if any.
+pub(crate) fn register(value: &Value) {
+    ACTIVE.with(|active| {
+        if let Some(params) = active.borrow().last() {
+            params.borrow_mut().push(value.clone());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Init, Linear, Module, MLP};
+    use crate::optim::{Optimizer, Sgd};
+    use crate::rand::Rng;
+
+    fn ids(values: &[Value]) -> Vec<u64> {
+        values.iter().map(|value| value.id().0).collect()
+    }
+
+    #[test]
+    fn nested_scopes_register_with_the_innermost_tape() {
+        let ((a, b), outer) = Tape::scope(|outer| {
+            let a = outer.value(1.0);
+            let (b, inner) = Tape::scope(|inner| inner.value(2.0));
+            assert_eq!(ids(&inner.parameters()), [b.id().0]);
+            (a, b)
+        });
+        assert_eq!(ids(&outer.parameters()), [a.id().0]);
+        assert_eq!(b.data(), 2.0);
+
+        let ((linear, mlp), outer) = Tape::scope(|_| {
+            let linear = Linear::new(2, 3, true, Init::Xavier, &mut Rng::seed(0));
+            let (mlp, inner) = Tape::scope(|_| MLP::new(3, vec![1]));
+            assert_eq!(ids(&inner.parameters()), ids(&mlp.parameters()));
+            (linear, mlp)
+        });
+        let linear: Vec<Value> = linear.parameters().into_iter().cloned().collect();
+        assert_eq!(ids(&outer.parameters()), ids(&linear));
+        assert_eq!(mlp.parameters().len(), 4);
+    }
+
+    #[test]
+    fn plain_values_are_not_registered() {
+        let (_, tape) = Tape::scope(|tape| {
+            let x = Value::from(1.0);
+            &tape.value(2.0) * &x
+        });
+        assert_eq!(tape.parameters().len(), 1);
+
+        // outside of any scope, constructors register nothing and nothing is left recording
+        MLP::new(2, vec![2]);
+        assert!(ACTIVE.with(|active| active.borrow().is_empty()));
+    }
+
+    #[test]
+    fn scopes_stop_recording_on_panic() {
+        let caught = std::panic::catch_unwind(|| {
+            Tape::scope(|tape| {
+                tape.value(1.0);
+                panic!("inside the scope")
+            })
+        });
+        assert!(caught.is_err());
+        assert!(ACTIVE.with(|active| active.borrow().is_empty()));
+    }
+
+    #[test]
+    fn registered_parameters_train() {
+        // fits `w·x + b` to `2x + 1` from parameters that are never listed by hand
+        let ((w, b), tape) = Tape::scope(|tape| (tape.value(0.0), tape.value(0.0)));
+        let mut sgd = Sgd::new(0.1);
+        let loss = |w: &Value, b: &Value| {
+            let errors: Vec<Value> = [-1.0, 0.0, 1.0, 2.0]
+                .iter()
+                .map(|&x| (&(&(w * &Value::constant(x)) + b) - &Value::constant(2.0 * x + 1.0)).powi(2))
+                .collect();
+            crate::ops::add_n(&errors)
+        };
+        let first = loss(&w, &b).data();
+        for _ in 0..200 {
+            let params = tape.parameters();
+            params.iter().for_each(Value::zero_grad);
+            loss(&w, &b).backward().unwrap();
+            sgd.step(&params);
+        }
+        assert!(loss(&w, &b).data() < 1e-6 * first);
+        assert!((w.data() - 2.0).abs() < 1e-3 && (b.data() - 1.0).abs() < 1e-3);
+    }
+}
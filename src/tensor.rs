@@ -0,0 +1,257 @@
+use crate::engine::Value;
+use crate::error::GradError;
+use crate::ops;
+
+#[cfg(feature = "ndarray")]
+pub use crate::array::{grads_to_array1, values_from_array1, values_to_array1};
+
+/// A dense row-major matrix of graph nodes.
+///
+/// The ops build the same scalar nodes as the equivalent loops would, each entry of a product being a single
+/// `ops::add_n` node over its terms; shape mismatches are reported as `GradError::ShapeMismatch`.
+#[derive(Clone, Debug)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<Value>,
+}
+
+impl Matrix {
+    /// Wraps `data`, given row by row. Panics if it doesn't hold `rows * cols` values.
+    pub fn new(rows: usize, cols: usize, data: Vec<Value>) -> Matrix {
+        assert_eq!(
+            data.len(),
+            rows * cols,
+            "a {}x{} matrix needs {} values, got {}",
+            rows,
+            cols,
+            rows * cols,
+            data.len()
+        );
+        Matrix { rows, cols, data }
+    }
+
+    /// Builds each entry from its row and column index.
+    pub fn from_fn(rows: usize, cols: usize, mut f: impl FnMut(usize, usize) -> Value) -> Matrix {
+        let data = (0..rows).flat_map(|i| (0..cols).map(move |j| (i, j))).map(|(i, j)| f(i, j)).collect();
+        Matrix { rows, cols, data }
+    }
+
+    /// A single column holding `values`.
+    pub fn column(values: &[Value]) -> Matrix {
+        Matrix::new(values.len(), 1, values.to_vec())
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    /// The entries, row by row.
+    pub fn data(&self) -> &[Value] {
+        &self.data
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> &Value {
+        assert!(
+            row < self.rows && col < self.cols,
+            "index ({}, {}) out of a {}x{} matrix",
+            row,
+            col,
+            self.rows,
+            self.cols
+        );
+        &self.data[row * self.cols + col]
+    }
+
+    pub fn row(&self, row: usize) -> &[Value] {
+        &self.data[row * self.cols..(row + 1) * self.cols]
+    }
+
+    /// The entries, to be handed to an optimizer when the matrix holds parameters.
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.data.iter().collect()
+    }
+
+    /// The matrix product `self · other`.
+    pub fn matmul(&self, other: &Matrix) -> Result<Matrix, GradError> {
+        if self.cols != other.rows {
+            return Err(self.mismatch("matmul", other.shape()));
+        }
+        let other = other.transpose();
+        Ok(Matrix::from_fn(self.rows, other.rows, |i, j| dot(self.row(i), other.row(j))))
+    }
+
+    /// The product of `self` with the column vector `xs`.
+    pub fn matvec(&self, xs: &[Value]) -> Result<Vec<Value>, GradError> {
+        if self.cols != xs.len() {
+            return Err(self.mismatch("matvec", (xs.len(), 1)));
+        }
+        Ok((0..self.rows).map(|i| dot(self.row(i), xs)).collect())
+    }
+
+    /// The transpose. No nodes are created, the entries are the same.
+    pub fn transpose(&self) -> Matrix {
+        Matrix::from_fn(self.cols, self.rows, |i, j| self.get(j, i).clone())
+    }
+
+    /// The transpose, as a view onto the same nodes: a `Linear` built from it through `Linear::from_weights`
+    /// shares its weights with this matrix, as for a decoder tied to its encoder, and gradients from both
+    /// uses accumulate into the same leaves.
+    pub fn transposed_view(&self) -> Matrix {
+        self.transpose()
+    }
+
+    /// The elementwise sum.
+    pub fn add(&self, other: &Matrix) -> Result<Matrix, GradError> {
+        self.zip_with("add", other, |a, b| a + b)
+    }
+
+    /// The elementwise (Hadamard) product.
+    pub fn mul(&self, other: &Matrix) -> Result<Matrix, GradError> {
+        self.zip_with("mul", other, |a, b| a * b)
+    }
+
+    /// `self + alpha · other` elementwise, e.g. for a rank-1 update `W + alpha · outer(a, b)`.
+    pub fn add_scaled(&self, other: &Matrix, alpha: f64) -> Result<Matrix, GradError> {
+        let alpha = Value::constant(alpha);
+        self.zip_with("add_scaled", other, |a, b| a + &(b * &alpha))
+    }
+
+    fn zip_with(
+        &self,
+        op: &'static str,
+        other: &Matrix,
+        f: impl Fn(&Value, &Value) -> Value,
+    ) -> Result<Matrix, GradError> {
+        if self.shape() != other.shape() {
+            return Err(self.mismatch(op, other.shape()));
+        }
+        let data = std::iter::zip(&self.data, &other.data).map(|(a, b)| f(a, b)).collect();
+        Ok(Matrix { rows: self.rows, cols: self.cols, data })
+    }
+
+    fn mismatch(&self, op: &'static str, right: (usize, usize)) -> GradError {
+        GradError::ShapeMismatch { op, left: self.shape(), right }
+    }
+}
+
+/// The outer product `a · bᵀ`, a `a.len() x b.len()` matrix whose entry `(i, j)` is `a[i] · b[j]`.
+pub fn outer(a: &[Value], b: &[Value]) -> Matrix {
+    Matrix::from_fn(a.len(), b.len(), |i, j| &a[i] * &b[j])
+}
+
+fn dot(a: &[Value], b: &[Value]) -> Value {
+    let products: Vec<Value> = std::iter::zip(a, b).map(|(a, b)| a * b).collect();
+    ops::add_n(&products)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::values_from;
+
+    fn matrix(rows: usize, cols: usize, data: &[f64]) -> Matrix {
+        Matrix::new(rows, cols, values_from(data))
+    }
+
+    fn data(m: &Matrix) -> Vec<f64> {
+        m.data().iter().map(Value::data).collect()
+    }
+
+    #[test]
+    fn matmul_values_and_gradients() {
+        let a = matrix(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let b = matrix(3, 2, &[7.0, 8.0, 9.0, 10.0, 11.0, 12.0]);
+        let product = a.matmul(&b).unwrap();
+        assert_eq!((product.shape(), data(&product)), ((2, 2), vec![58.0, 64.0, 139.0, 154.0]));
+
+        // the gradient of the sum of the entries: each a[i][k] gets the sum of row k of b, each b[k][j] that of
+        // column k of a
+        ops::add_n(product.data()).backward().unwrap();
+        let grads = |m: &Matrix| m.data().iter().map(Value::grad).collect::<Vec<f64>>();
+        assert_eq!(grads(&a), [15.0, 19.0, 23.0, 15.0, 19.0, 23.0]);
+        assert_eq!(grads(&b), [5.0, 5.0, 7.0, 7.0, 9.0, 9.0]);
+    }
+
+    #[test]
+    fn transpose_twice_is_the_same_matrix() {
+        let a = matrix(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let t = a.transpose();
+        assert_eq!((t.shape(), data(&t)), ((3, 2), vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]));
+        let back = t.transpose();
+        assert_eq!(back.shape(), a.shape());
+        assert!(std::iter::zip(back.data(), a.data()).all(|(x, y)| x.id() == y.id()));
+    }
+
+    #[test]
+    fn matvec_is_matmul_with_a_column() {
+        let a = matrix(2, 3, &[1.0, -2.0, 3.0, 0.5, 5.0, -6.0]);
+        let xs = values_from(&[2.0, 1.0, -1.0]);
+        let by_vector: Vec<f64> = a.matvec(&xs).unwrap().iter().map(Value::data).collect();
+        assert_eq!(by_vector, data(&a.matmul(&Matrix::column(&xs)).unwrap()));
+    }
+
+    #[test]
+    fn shape_mismatches_name_both_shapes() {
+        let (a, b) = (matrix(2, 3, &[0.0; 6]), matrix(2, 2, &[0.0; 4]));
+        let error = a.matmul(&b).unwrap_err();
+        assert_eq!(error, GradError::ShapeMismatch { op: "matmul", left: (2, 3), right: (2, 2) });
+        assert_eq!(error.to_string(), "matmul of a 2x3 and a 2x2 operand: shapes don't match");
+        assert!(a.matvec(&values_from(&[1.0])).is_err());
+        assert!(a.add(&b).is_err() && a.mul(&b).is_err());
+    }
+
+    #[test]
+    fn elementwise_ops() {
+        let (a, b) = (matrix(1, 3, &[1.0, 2.0, 3.0]), matrix(1, 3, &[4.0, 5.0, 6.0]));
+        assert_eq!(data(&a.add(&b).unwrap()), [5.0, 7.0, 9.0]);
+        assert_eq!(data(&a.mul(&b).unwrap()), [4.0, 10.0, 18.0]);
+        assert_eq!(data(&a.add_scaled(&b, 0.5).unwrap()), [3.0, 4.5, 6.0]);
+        assert_eq!(data(&outer(a.data(), &values_from(&[1.0, -1.0]))), [1.0, -1.0, 2.0, -2.0, 3.0, -3.0]);
+    }
+
+    #[test]
+    fn outer_gradients_match_finite_differences() {
+        let (a, b) = (values_from(&[0.5, -1.5]), values_from(&[2.0, 0.25, -1.0]));
+        // a weighted sum of the entries, so that each one contributes differently
+        let loss = |a: &[Value], b: &[Value]| {
+            let entries = outer(a, b);
+            let weighted: Vec<Value> =
+                entries.data().iter().enumerate().map(|(k, e)| e * &Value::from(k as f64 + 1.0)).collect();
+            ops::add_n(&weighted)
+        };
+        loss(&a, &b).backward().unwrap();
+        let h = 1e-6;
+        for param in a.iter().chain(&b) {
+            let data = param.data();
+            param.set_data(data + h);
+            let up = loss(&a, &b).data();
+            param.set_data(data - h);
+            let down = loss(&a, &b).data();
+            param.set_data(data);
+            assert!((param.grad() - (up - down) / (2.0 * h)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn add_scaled_is_a_rank_one_update() {
+        let w = matrix(2, 3, &[1.0; 6]);
+        let (a, b) = (values_from(&[1.0, 2.0]), values_from(&[1.0, 0.0, -1.0]));
+        let updated = w.add_scaled(&outer(&a, &b), 0.5).unwrap();
+        assert_eq!(data(&updated), [1.5, 1.0, 0.5, 2.0, 1.0, 0.0]);
+        ops::add_n(updated.data()).backward().unwrap();
+        // d/da[i] = 0.5 · Σ_j b[j], d/db[j] = 0.5 · Σ_i a[i]
+        assert_eq!(a.iter().map(Value::grad).collect::<Vec<f64>>(), [0.0, 0.0]);
+        assert_eq!(b.iter().map(Value::grad).collect::<Vec<f64>>(), [1.5, 1.5, 1.5]);
+        let error = w.add_scaled(&outer(&a, &a), 1.0).unwrap_err();
+        assert_eq!(error, GradError::ShapeMismatch { op: "add_scaled", left: (2, 3), right: (2, 2) });
+    }
+}
@@ -0,0 +1,260 @@
+// Random expression graphs for property-based testing with proptest, e.g. that the gradients of every op
+// agree with finite differences however the ops are combined:
+//
+// proptest! {
+//     #[test]
+//     fn gradients_match(expression in arb_expression(4, 3)) {
+//         prop_assert!(check_gradients(&expression, 1e-4).is_ok());
+//     }
+// }
+
+use std::fmt::{self, Debug, Display};
+
+use proptest::prelude::*;
+use proptest::sample::select;
+
+use crate::engine::{approx_eq, Domain, Op, Value};
+
+/// A random graph over leaves `x0`, `x1`, ..., generated by `arb_expression`.
+#[derive(Clone)]
+pub struct Expression {
+    pub leaves: Vec<Value>,
+    pub root: Value,
+    // the expression in the syntax of `engine::parse`, to reproduce it
+    text: String,
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// What proptest prints for a failing case: the expression and the data of its leaves, enough to rebuild it
+// with `engine::parse` as a regression test.
+impl Debug for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` at", self.text)?;
+        for (i, leaf) in self.leaves.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{} x{} = {:?}", separator, i, leaf.data())?;
+        }
+        Ok(())
+    }
+}
+
+// The structure of an expression before it is built on concrete leaves.
+#[derive(Clone, Debug)]
+enum Shape {
+    Leaf(usize),
+    Constant(f64),
+    Apply(Op, Vec<Shape>),
+}
+
+/// Expressions of at most `depth` levels of ops over `n_leaves` leaves with data in [-2, 2], using the ops
+/// whose gradients can be checked against finite differences (see `Op::is_smooth`) other than assertions.
+///
+/// Every input of an op is kept within `Op::domain`: when the subexpression generated for it falls outside, it
+/// is mapped into the domain by ops too, `lo + softplus(x)` for a domain bounded below, `hi - softplus(x)` above,
+/// and `mid + half·tanh(x)` for one bounded on both sides. Inputs that receive no gradient (see
+/// `Op::propagates_to`), such as exponents, are constants within the domain instead. Shrinking first removes
+/// levels, then children, then simplifies the data.
+pub fn arb_expression(depth: u32, n_leaves: usize) -> impl Strategy<Value = Expression> {
+    assert!(n_leaves > 0, "an expression needs at least one leaf");
+    let ops: Vec<Op> = Op::ALL.into_iter().filter(|op| op.is_smooth() && *op != Op::Assert).collect();
+    let shape = (0..n_leaves).prop_map(Shape::Leaf).prop_recursive(depth, 64, 3, move |inner| {
+        (select(ops.clone()), prop::collection::vec(inner, 2..=3), 0.0..=1.0).prop_map(|(op, mut children, t)| {
+            children.truncate(op.arity().unwrap_or(children.len()));
+            for (i, child) in children.iter_mut().enumerate().filter(|&(i, _)| !op.propagates_to(i)) {
+                let domain = op.domain(i);
+                let (lo, hi) = (domain.lo.max(-2.0), domain.hi.min(2.0));
+                *child = Shape::Constant(lo + t * (hi - lo));
+            }
+            Shape::Apply(op, children)
+        })
+    });
+    (shape, prop::collection::vec(-2.0..=2.0, n_leaves)).prop_map(|(shape, data)| {
+        let leaves: Vec<Value> =
+            data.iter().enumerate().map(|(i, &x)| Value::from(x).add_label(&format!("x{}", i))).collect();
+        let (root, text) = build(&shape, &leaves);
+        Expression { leaves, root, text }
+    })
+}
+
+fn build(shape: &Shape, leaves: &[Value]) -> (Value, String) {
+    let (op, children) = match shape {
+        Shape::Leaf(i) => return (leaves[*i].clone(), format!("x{}", i)),
+        Shape::Constant(c) => return (Value::from(*c), format!("{}", c)),
+        Shape::Apply(op, children) => (*op, children),
+    };
+    let (inputs, texts): (Vec<Value>, Vec<String>) = children
+        .iter()
+        .enumerate()
+        .map(|(i, child)| {
+            let (value, text) = build(child, leaves);
+            into_domain(value, text, op.domain(i))
+        })
+        .unzip();
+    let value = op.apply(&inputs).expect("the children fit the arity of the op");
+    let text = match op {
+        Op::Add | Op::SumCompensated => format!("({})", texts.join(" + ")),
+        Op::Mul => format!("({})", texts.join(" * ")),
+        Op::Pow => format!("({} ^ {})", texts[0], texts[1]),
+        _ => format!("{}({})", op, texts[0]),
+    };
+    (value, text)
+}
+
+fn into_domain(value: Value, text: String, domain: Domain) -> (Value, String) {
+    if domain.contains(value.data()) {
+        return (value, text);
+    }
+    match (domain.lo.is_finite(), domain.hi.is_finite()) {
+        (true, true) => {
+            let (mid, half) = ((domain.lo + domain.hi) / 2.0, (domain.hi - domain.lo) / 2.0);
+            let value = &Value::from(mid) + &(&value.tanh() * &Value::from(half));
+            (value, format!("({} + {} * tanh({}))", mid, half, text))
+        }
+        (true, false) => {
+            let value = &Value::from(domain.lo) + &value.softplus();
+            (value, format!("({} + softplus({}))", domain.lo, text))
+        }
+        (false, true) => {
+            let value = &Value::from(domain.hi) - &value.softplus();
+            (value, format!("({} - softplus({}))", domain.hi, text))
+        }
+        (false, false) => unreachable!("every input is in an unbounded domain"),
+    }
+}
+
+/// Checks the gradient of `expression.root` with respect to every leaf against the central difference of the
+/// root recomputed through `Value::compile`, within a relative and absolute tolerance of `tol`. Describes the
+/// first leaf that disagrees. The data of the graph is restored afterwards, and the grads are left to those of
+/// a single backward pass.
+pub fn check_gradients(expression: &Expression, tol: f64) -> Result<(), String> {
+    let root = &expression.root;
+    root.zero_grad_all();
+    root.backward().map_err(|e| e.to_string())?;
+
+    let mut graph = root.compile();
+    let inputs: Vec<f64> = graph.leaves().iter().map(Value::data).collect();
+    let mut result = Ok(());
+    for (i, leaf) in expression.leaves.iter().enumerate() {
+        let numeric = match graph.leaves().iter().position(|l| l.id() == leaf.id()) {
+            Some(p) => {
+                let h = 1e-6 * inputs[p].abs().max(1.0);
+                let mut at = |offset: f64| {
+                    let mut shifted = inputs.clone();
+                    shifted[p] += offset;
+                    graph.forward(&shifted)
+                };
+                (at(h) - at(-h)) / (2.0 * h)
+            }
+            None => 0.0,
+        };
+        if !approx_eq(leaf.grad(), numeric, tol, tol) {
+            result = Err(format!(
+                "d/dx{} of `{}`: backward gives {} but finite differences give {}",
+                i,
+                expression.text,
+                leaf.grad(),
+                numeric
+            ));
+            break;
+        }
+    }
+    graph.forward(&inputs);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use proptest::strategy::ValueTree;
+    use proptest::test_runner::{TestError, TestRunner};
+
+    use super::*;
+    use crate::engine::parse;
+
+    // What the seeded failure below shrinks to, rebuilt from the text proptest reports
+    const SHRUNK: &str = "(x0 + softplus((x0 + x0)))";
+    const SHRUNK_X0: f64 = 1.2554099179966078;
+
+    fn walk(node: &Value, visit: &mut impl FnMut(&Value)) {
+        visit(node);
+        for child in node.children() {
+            walk(&child, visit);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn gradients_match(expression in arb_expression(4, 3)) {
+            prop_assert!(check_gradients(&expression, 1e-4).is_ok(), "{:?}", check_gradients(&expression, 1e-4));
+        }
+
+        #[test]
+        fn inputs_stay_in_their_domains(expression in arb_expression(4, 3)) {
+            let mut outside = Vec::new();
+            walk(&expression.root, &mut |node| {
+                if let Some(op) = node.op() {
+                    for (i, child) in node.children().iter().enumerate() {
+                        if !op.domain(i).contains(child.data()) {
+                            outside.push(format!("input {} of {} is {}", i, op, child.data()));
+                        }
+                    }
+                }
+            });
+            prop_assert!(outside.is_empty(), "{:?}", outside);
+            prop_assert!(expression.leaves.iter().all(|leaf| (-2.0..=2.0).contains(&leaf.data())));
+        }
+    }
+
+    #[test]
+    fn constants_fill_the_inputs_without_gradients() {
+        let mut runner = TestRunner::deterministic();
+        for _ in 0..64 {
+            let expression = arb_expression(3, 2).new_tree(&mut runner).unwrap().current();
+            walk(&expression.root, &mut |node| {
+                if let Some(op) = node.op() {
+                    for (_, child) in node.children().iter().enumerate().filter(|&(i, _)| !op.propagates_to(i)) {
+                        assert!(child.op().is_none() && !expression.leaves.iter().any(|l| l.id() == child.id()));
+                    }
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn a_seeded_failure_shrinks_to_a_small_expression() {
+        // a property that doesn't hold, found and shrunk by the deterministic runner
+        let result = TestRunner::deterministic().run(&arb_expression(4, 3), |expression| {
+            prop_assert!(expression.root.data().abs() <= 3.0);
+            Ok(())
+        });
+        let expression = match result {
+            Err(TestError::Fail(_, expression)) => expression,
+            other => panic!("expected a failure, got {:?}", other.map(|_| ())),
+        };
+        assert_eq!(expression.to_string(), SHRUNK);
+        assert_eq!(expression.leaves[0].data(), SHRUNK_X0);
+        let mut ops = 0;
+        walk(&expression.root, &mut |node| ops += node.op().is_some() as usize);
+        assert!(ops <= 3);
+    }
+
+    #[test]
+    fn regression_shrunk_expression() {
+        let x0 = Value::from(SHRUNK_X0).add_label("x0");
+        let vars = HashMap::from([("x0".to_string(), x0.clone())]);
+        let root = parse(SHRUNK, &vars).unwrap();
+        assert!(root.data() > 3.0);
+        assert!(approx_eq(root.data(), SHRUNK_X0 + (2.0 * SHRUNK_X0).exp().ln_1p(), 1e-12, 1e-12));
+        let expression = Expression { leaves: vec![x0], root, text: SHRUNK.to_string() };
+        assert_eq!(check_gradients(&expression, 1e-6), Ok(()));
+        // d/dx (x + softplus(2x)) = 1 + 2 sigmoid(2x)
+        let sigmoid = 1.0 / (1.0 + (-2.0 * SHRUNK_X0).exp());
+        assert!(approx_eq(expression.leaves[0].grad(), 1.0 + 2.0 * sigmoid, 1e-12, 1e-12));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::engine::Value;
+use crate::error::BackwardError;
+
+mod logger;
+pub use logger::{LogRow, Logger};
+
+mod smoothed;
+pub use smoothed::{MetricTracker, Smoothed};
+
+mod monitor;
+pub use monitor::{StepMonitor, StepStats};
+
+mod trainer;
+pub use trainer::{TrainReport, Trainer};
+
+/// The loss returned by `loss_fn` with each parameter offset by `alpha · direction[i]`, for each of `alphas`,
+/// e.g. to plot a slice of the loss landscape around the current parameters.
+///
+/// `loss_fn` must build the loss afresh (or re-forward a compiled graph) from the current parameter data.
+/// The original data is always restored afterwards, bit for bit, even if `loss_fn` panics.
+pub fn loss_along(
+    params: &[Value],
+    direction: &[f64],
+    loss_fn: impl Fn() -> Value,
+    alphas: &[f64],
+) -> Vec<f64> {
+    check_direction(params, direction);
+    let saved = Restore::new(params);
+    alphas
+        .iter()
+        .map(|&alpha| {
+            saved.offset(|i| alpha * direction[i]);
+            loss_fn().data()
+        })
+        .collect()
+}
+
+/// Like `loss_along` over the plane spanned by two directions: row `i`, column `j` of the result is the loss
+/// with the parameters offset by `alphas[i] · first + betas[j] · second`.
+pub fn loss_along_2d(
+    params: &[Value],
+    first: &[f64],
+    second: &[f64],
+    loss_fn: impl Fn() -> Value,
+    alphas: &[f64],
+    betas: &[f64],
+) -> Vec<Vec<f64>> {
+    check_direction(params, first);
+    check_direction(params, second);
+    let saved = Restore::new(params);
+    alphas
+        .iter()
+        .map(|&alpha| {
+            betas
+                .iter()
+                .map(|&beta| {
+                    saved.offset(|i| alpha * first[i] + beta * second[i]);
+                    loss_fn().data()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The gradient of each of `losses` with respect to each of `params`, taken one loss at a time: row `i` holds
+/// the grads of `params` for `losses[i]` alone, e.g. to clip per-sample gradients in differentially private
+/// training.
+///
+/// Each loss's graph has its grads zeroed (see `Value::zero_grad_all`) before its backward pass, so graphs may
+/// share parameters and interior nodes. The grads the parameters had before the call are put back afterwards,
+/// even on error; the other nodes keep those of the last pass.
+pub fn per_sample_grads(params: &[Value], losses: &[Value]) -> Result<Vec<Vec<f64>>, BackwardError> {
+    let _saved = RestoreGrads { params, grads: params.iter().map(Value::grad).collect() };
+    losses
+        .iter()
+        .map(|loss| {
+            loss.zero_grad_all();
+            for param in params {
+                param.zero_grad();
+            }
+            loss.backward()?;
+            Ok(params.iter().map(Value::grad).collect())
+        })
+        .collect()
+}
+
+fn check_direction(params: &[Value], direction: &[f64]) {
+    assert_eq!(params.len(), direction.len(), "{} parameters for a direction of length {}", params.len(), direction.len());
+}
+
+// Puts the saved data back into the parameters when dropped, including while unwinding from a panic.
+struct Restore<'a> {
+    params: &'a [Value],
+    data: Vec<f64>,
+}
+
+impl<'a> Restore<'a> {
+    fn new(params: &'a [Value]) -> Restore<'a> {
+        Restore { params, data: params.iter().map(|p| p.data()).collect() }
+    }
+
+    // sets each parameter to its saved data plus `offset(i)`
+    fn offset(&self, offset: impl Fn(usize) -> f64) {
+        for (i, (param, &data)) in std::iter::zip(self.params, &self.data).enumerate() {
+            param.set_data(data + offset(i));
+        }
+    }
+}
+
+impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+        for (param, &data) in std::iter::zip(self.params, &self.data) {
+            param.borrow_mut().data = data;
+        }
+    }
+}
+
+// Same as `Restore` for the grads.
+struct RestoreGrads<'a> {
+    params: &'a [Value],
+    grads: Vec<f64>,
+}
+
+impl Drop for RestoreGrads<'_> {
+    fn drop(&mut self) {
+        for (param, &grad) in std::iter::zip(self.params, &self.grads) {
+            param.set_grad(grad);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `(a - 1)² + 2·(b + 0.5)²`, minimal at (1, -0.5)
+    fn quadratic(params: &[Value]) -> Value {
+        let a = (&params[0] - &Value::from(1.0)).powi(2);
+        let b = (&params[1] + &Value::from(0.5)).powi(2);
+        &a + &(&b * &Value::from(2.0))
+    }
+
+    #[test]
+    fn loss_along_a_direction_of_a_quadratic_is_a_parabola() {
+        let params = [Value::from(1.0), Value::from(-0.5)];
+        let alphas = [-2.0, -1.0, 0.0, 0.5, 3.0];
+        let losses = loss_along(&params, &[1.0, 1.0], || quadratic(&params), &alphas);
+        // 3·alpha² along (1, 1) from the minimum
+        for (loss, alpha) in std::iter::zip(losses, alphas) {
+            assert!((loss - 3.0 * alpha * alpha).abs() < 1e-12);
+        }
+        assert_eq!(loss_along(&params, &[0.3, -0.7], || quadratic(&params), &[0.0]), [quadratic(&params).data()]);
+    }
+
+    #[test]
+    fn loss_along_2d_covers_the_grid() {
+        let params = [Value::from(1.0), Value::from(-0.5)];
+        let (first, second) = ([1.0, 0.0], [0.0, 1.0]);
+        let grid = loss_along_2d(&params, &first, &second, || quadratic(&params), &[0.0, 1.0], &[-1.0, 2.0]);
+        assert_eq!(grid, [[2.0, 8.0], [3.0, 9.0]]);
+    }
+
+    #[test]
+    fn parameters_are_restored_bit_for_bit_even_after_a_panic() {
+        let data = [0.1, 1.0 / 3.0];
+        let params = data.map(Value::from);
+        loss_along(&params, &[1e-3, -7.1], || quadratic(&params), &[0.3, 1.7, -5.0]);
+        assert_eq!(params.each_ref().map(Value::data), data);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            loss_along(&params, &[1.0, 1.0], || panic!("loss failed"), &[0.5])
+        }));
+        assert!(result.is_err());
+        assert_eq!(params.each_ref().map(Value::data), data);
+    }
+
+    #[test]
+    fn per_sample_grads_average_to_the_grad_of_the_mean_loss() {
+        let params = [Value::from(0.5), Value::from(-1.5)];
+        let samples = [(1.0, 2.0), (-0.5, 0.3), (2.0, -1.0)];
+        // the samples share a node built on the parameters
+        let shared = (&params[0] * &params[1]).tanh();
+        let losses: Vec<Value> = samples
+            .iter()
+            .map(|&(x, y)| (&(&(&params[0] * &Value::from(x)) + &shared) - &Value::from(y)).powi(2))
+            .collect();
+
+        let rows = per_sample_grads(&params, &losses).unwrap();
+        assert_eq!(rows.len(), 3);
+        let mean = crate::loss::mean(&losses);
+        mean.zero_grad_all();
+        mean.backward().unwrap();
+        for (i, param) in params.iter().enumerate() {
+            let averaged = rows.iter().map(|row| row[i]).sum::<f64>() / rows.len() as f64;
+            assert!((averaged - param.grad()).abs() < 1e-12, "{} {}", averaged, param.grad());
+        }
+    }
+
+    #[test]
+    fn per_sample_grads_restore_the_parameters() {
+        let params = [Value::from(0.25), Value::from(2.0)];
+        params[0].set_grad(7.0);
+        params[1].set_grad(-3.0);
+        let losses = [&params[0] * &params[1], params[0].powi(3)];
+        assert_eq!(per_sample_grads(&params, &losses).unwrap(), [[2.0, 0.25], [0.1875, 0.0]]);
+        assert_eq!(params.each_ref().map(|p| (p.data(), p.grad())), [(0.25, 7.0), (2.0, -3.0)]);
+
+        // and after a failed pass: an infinite gradient reaching the assertion
+        let failing = [&params[0].assert_finite("p0") * &Value::constant(f64::INFINITY)];
+        assert!(per_sample_grads(&params, &failing).is_err());
+        assert_eq!(params.each_ref().map(|p| (p.data(), p.grad())), [(0.25, 7.0), (2.0, -3.0)]);
+    }
+}
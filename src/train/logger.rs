@@ -0,0 +1,211 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// One row of a `Logger`: where training is and how it's going, plus any metrics of the caller's, by name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogRow {
+    pub step: usize,
+    pub epoch: usize,
+    pub loss: f64,
+    pub grad_norm: f64,
+    pub lr: f64,
+    pub metrics: Vec<(String, f64)>,
+}
+
+/// A record of a training run, one row per logged step, kept in memory and written out as CSV or JSON lines
+/// for plotting instead of scraping printed output.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Logger {
+    rows: Vec<LogRow>,
+}
+
+impl Logger {
+    pub fn new() -> Logger {
+        Logger::default()
+    }
+
+    /// Records a row for the next step, numbered from 0 in the order rows are logged.
+    pub fn log(&mut self, epoch: usize, loss: f64, grad_norm: f64, lr: f64, metrics: &[(&str, f64)]) {
+        self.rows.push(LogRow {
+            step: self.rows.len(),
+            epoch,
+            loss,
+            grad_norm,
+            lr,
+            metrics: metrics.iter().map(|&(name, value)| (name.to_string(), value)).collect(),
+        });
+    }
+
+    /// Adds the metric `name` to the last row, e.g. a validation loss computed after a step the `Trainer` logged.
+    /// Panics if no row has been logged.
+    pub fn add_metric(&mut self, name: &str, value: f64) {
+        let row = self.rows.last_mut().expect("add_metric needs a logged row");
+        row.metrics.push((name.to_string(), value));
+    }
+
+    /// The rows logged so far, in order.
+    pub fn history(&self) -> &[LogRow] {
+        &self.rows
+    }
+
+    /// The rows as CSV with a header line. There is a column for every metric name logged, in order of first
+    /// appearance, left empty in the rows that don't have it; names are quoted when they need to be.
+    pub fn csv(&self) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        for row in &self.rows {
+            for (name, _) in &row.metrics {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+        }
+
+        let mut out = String::from("step,epoch,loss,grad_norm,lr");
+        for name in &names {
+            out.push(',');
+            out.push_str(&csv_field(name));
+        }
+        out.push('\n');
+        for row in &self.rows {
+            write!(out, "{},{},{},{},{}", row.step, row.epoch, row.loss, row.grad_norm, row.lr).unwrap();
+            for name in &names {
+                out.push(',');
+                if let Some((_, value)) = row.metrics.iter().find(|(n, _)| n == name) {
+                    write!(out, "{}", value).unwrap();
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The rows as JSON lines, one object per row with the metrics in a nested object. Non-finite numbers, which
+    /// JSON has no literal for, are written as `null`.
+    pub fn jsonl(&self) -> String {
+        let mut out = String::new();
+        for row in &self.rows {
+            let metrics: Vec<String> = row
+                .metrics
+                .iter()
+                .map(|(name, value)| format!("{}:{}", json_string(name), json_number(*value)))
+                .collect();
+            writeln!(
+                out,
+                r#"{{"step":{},"epoch":{},"loss":{},"grad_norm":{},"lr":{},"metrics":{{{}}}}}"#,
+                row.step,
+                row.epoch,
+                json_number(row.loss),
+                json_number(row.grad_norm),
+                json_number(row.lr),
+                metrics.join(",")
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    pub fn to_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.csv())
+    }
+
+    pub fn to_jsonl(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.jsonl())
+    }
+}
+
+// `field` quoted, with its quotes doubled, if it contains a separator, a quote or a line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{}", x)
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Logger {
+        let mut logger = Logger::new();
+        logger.log(0, 1.5, 0.25, 0.1, &[("acc, top-1", 0.5)]);
+        logger.log(0, f64::NAN, 0.2, 0.1, &[]);
+        logger.log(1, 0.75, 0.125, 0.05, &[("say \"hi\"", 2.0), ("acc, top-1", 0.75)]);
+        logger
+    }
+
+    #[test]
+    fn rows_are_numbered_in_order() {
+        let logger = example();
+        let steps: Vec<(usize, usize)> = logger.history().iter().map(|row| (row.step, row.epoch)).collect();
+        assert_eq!(steps, [(0, 0), (1, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn csv_quotes_the_names_that_need_it() {
+        let expected = "step,epoch,loss,grad_norm,lr,\"acc, top-1\",\"say \"\"hi\"\"\"\n\
+                        0,0,1.5,0.25,0.1,0.5,\n\
+                        1,0,NaN,0.2,0.1,,\n\
+                        2,1,0.75,0.125,0.05,0.75,2\n";
+        assert_eq!(example().csv(), expected);
+    }
+
+    #[test]
+    fn jsonl_lines_stand_alone() {
+        let jsonl = example().jsonl();
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], r#"{"step":1,"epoch":0,"loss":null,"grad_norm":0.2,"lr":0.1,"metrics":{}}"#);
+        #[cfg(feature = "serde")]
+        for line in lines {
+            let row: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert!(row["metrics"].is_object());
+        }
+        assert_eq!(json_string("a\tb\u{1}\\"), r#""a\tb\u0001\\""#);
+    }
+
+    #[test]
+    fn logs_are_written_to_files() {
+        let dir = std::env::temp_dir().join(format!("angstromgrad-logger-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let logger = example();
+        logger.to_csv(dir.join("log.csv")).unwrap();
+        logger.to_jsonl(dir.join("log.jsonl")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("log.csv")).unwrap(), logger.csv());
+        assert_eq!(fs::read_to_string(dir.join("log.jsonl")).unwrap(), logger.jsonl());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "add_metric needs a logged row")]
+    fn metrics_need_a_row() {
+        Logger::new().add_metric("x", 1.0);
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::{Duration, Instant};
+
+use crate::engine::{created_node_count, live_node_count, peak_live_node_count, reset_peak_live_node_count, Value};
+use crate::error::{BackwardError, NodeGrowthError};
+
+/// What a `StepMonitor` measured for one training step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StepStats {
+    pub step: usize,
+    pub epoch: usize,
+    pub loss: f64,
+    /// The nodes built during the step, the loss graph and anything else, whether still alive or not.
+    pub nodes_created: u64,
+    /// The change in the number of live nodes over the step, once the loss has been dropped; it stays at zero
+    /// unless the step keeps nodes alive past its end, e.g. a loss held on to by the caller.
+    pub nodes_retained: i64,
+    /// The most nodes alive at once during the step.
+    pub peak_live_nodes: usize,
+    pub backward_time: Duration,
+}
+
+/// Per-step instrumentation of a training loop, to catch graphs that grow from step to step (a loss closure that
+/// keeps the previous step's loss alive and builds on it, say) before they slow the run to a crawl.
+///
+/// The node counters are process-wide, so steps on other threads are counted too.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StepMonitor {
+    steps: Vec<StepStats>,
+    epoch: usize,
+    growth_limit: Option<f64>,
+}
+
+// The counters at the start of a step, see `StepMonitor::start`.
+pub(crate) struct StepStart {
+    live: usize,
+    created: u64,
+}
+
+impl StepMonitor {
+    pub fn new() -> StepMonitor {
+        StepMonitor::default()
+    }
+
+    /// Makes `end_epoch` fail when the mean nodes created or peak live nodes per step of an epoch exceed those of
+    /// the previous epoch by more than `factor`, e.g. 1.5. Panics unless `factor >= 1`.
+    pub fn with_growth_limit(mut self, factor: f64) -> StepMonitor {
+        assert!(factor >= 1.0, "a growth limit below 1 would reject steady steps, got {}", factor);
+        self.growth_limit = Some(factor);
+        self
+    }
+
+    /// Runs one step: builds the loss with `loss_fn`, back-propagates it and records the measurements of the step,
+    /// returning the loss. The parameters are left with their gradients for the caller's update.
+    pub fn step(&mut self, loss_fn: impl FnOnce() -> Value) -> Result<f64, BackwardError> {
+        let start = StepMonitor::start();
+        let loss = loss_fn();
+        let backward = Instant::now();
+        loss.backward()?;
+        let backward_time = backward.elapsed();
+        let data = loss.data();
+        drop(loss);
+        self.record(start, data, backward_time);
+        Ok(data)
+    }
+
+    // Starts measuring a step run by the caller, e.g. `Trainer::step`, which ends it with `record` once the loss
+    // has been dropped.
+    pub(crate) fn start() -> StepStart {
+        let start = StepStart { live: live_node_count(), created: created_node_count() };
+        reset_peak_live_node_count();
+        start
+    }
+
+    // Records the step begun by `start`, of loss `loss` and whose backward pass took `backward_time`.
+    pub(crate) fn record(&mut self, start: StepStart, loss: f64, backward_time: Duration) {
+        self.steps.push(StepStats {
+            step: self.steps.len(),
+            epoch: self.epoch,
+            loss,
+            nodes_created: created_node_count() - start.created,
+            nodes_retained: live_node_count() as i64 - start.live as i64,
+            peak_live_nodes: peak_live_node_count(),
+            backward_time,
+        });
+    }
+
+    /// Closes the current epoch and, with a growth limit, compares its steps to those of the previous epoch.
+    /// The next steps are counted in a new epoch either way.
+    pub fn end_epoch(&mut self) -> Result<(), NodeGrowthError> {
+        let previous = self.epoch_means(self.epoch.wrapping_sub(1));
+        let current = self.epoch_means(self.epoch);
+        self.epoch += 1;
+
+        let (Some(factor), Some(previous), Some(current)) = (self.growth_limit, previous, current) else {
+            return Ok(());
+        };
+        for (metric, before, after) in [
+            ("nodes created", previous.0, current.0),
+            ("peak live nodes", previous.1, current.1),
+        ] {
+            if after > before * factor {
+                let epoch = self.epoch - 1;
+                return Err(NodeGrowthError { metric, epoch, previous: before, current: after, factor });
+            }
+        }
+        Ok(())
+    }
+
+    // The mean nodes created and peak live nodes per step of `epoch`, if it has any steps.
+    fn epoch_means(&self, epoch: usize) -> Option<(f64, f64)> {
+        let steps: Vec<&StepStats> = self.steps.iter().filter(|stats| stats.epoch == epoch).collect();
+        if steps.is_empty() {
+            return None;
+        }
+        let n = steps.len() as f64;
+        let created = steps.iter().map(|stats| stats.nodes_created as f64).sum::<f64>() / n;
+        let peak = steps.iter().map(|stats| stats.peak_live_nodes as f64).sum::<f64>() / n;
+        Some((created, peak))
+    }
+
+    /// Every step recorded so far, in order.
+    pub fn steps(&self) -> &[StepStats] {
+        &self.steps
+    }
+
+    /// The measurements of the last step as named metrics, to pass to `Logger::log` so that they are written out
+    /// with the loss history. Empty before the first step.
+    pub fn metrics(&self) -> Vec<(&'static str, f64)> {
+        let Some(last) = self.steps.last() else {
+            return Vec::new();
+        };
+        vec![
+            ("nodes_created", last.nodes_created as f64),
+            ("nodes_retained", last.nodes_retained as f64),
+            ("peak_live_nodes", last.peak_live_nodes as f64),
+            ("backward_ms", last.backward_time.as_secs_f64() * 1000.0),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The node counters are process-wide and other tests run in parallel, so the counts are checked in
+    // `tests/step_monitor.rs`; these tests look at what doesn't depend on them.
+
+    fn square(x: &Value) -> Value {
+        x.powi(2)
+    }
+
+    #[test]
+    fn steps_are_numbered_within_their_epochs() {
+        let x = Value::from(3.0);
+        let mut monitor = StepMonitor::new();
+        assert!(monitor.metrics().is_empty());
+        assert_eq!(monitor.step(|| square(&x)).unwrap(), 9.0);
+        assert_eq!(x.grad(), 6.0);
+        monitor.end_epoch().unwrap();
+        monitor.step(|| square(&x)).unwrap();
+        monitor.step(|| x.exp()).unwrap();
+
+        let numbers: Vec<(usize, usize, f64)> =
+            monitor.steps().iter().map(|stats| (stats.step, stats.epoch, stats.loss)).collect();
+        assert_eq!(numbers, [(0, 0, 9.0), (1, 1, 9.0), (2, 1, 3f64.exp())]);
+        // every step builds at least its loss node
+        assert!(monitor.steps().iter().all(|stats| stats.nodes_created >= 1 && stats.peak_live_nodes >= 1));
+    }
+
+    #[test]
+    fn metrics_are_those_of_the_last_step() {
+        let x = Value::from(3.0);
+        let mut monitor = StepMonitor::new();
+        monitor.step(|| square(&x)).unwrap();
+        let last = monitor.steps()[0];
+        let metrics = monitor.metrics();
+        let names: Vec<&str> = metrics.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["nodes_created", "nodes_retained", "peak_live_nodes", "backward_ms"]);
+        assert_eq!(metrics[0].1, last.nodes_created as f64);
+        assert_eq!(metrics[3].1, last.backward_time.as_secs_f64() * 1000.0);
+    }
+
+    #[test]
+    fn failed_backward_passes_are_not_recorded() {
+        let x = Value::from(0.0);
+        let mut monitor = StepMonitor::new();
+        let error = monitor.step(|| x.assert_finite("x").sqrt()).unwrap_err();
+        assert!(matches!(error, BackwardError::AssertionFailed { .. }));
+        assert!(monitor.steps().is_empty());
+    }
+
+    #[test]
+    fn without_a_limit_epochs_never_fail() {
+        let x = Value::from(3.0);
+        let mut monitor = StepMonitor::new();
+        monitor.step(|| square(&x)).unwrap();
+        monitor.end_epoch().unwrap();
+        // a far bigger graph
+        monitor.step(|| crate::ops::add_n(&(0..100).map(|_| square(&x)).collect::<Vec<_>>())).unwrap();
+        monitor.end_epoch().unwrap();
+        // and the first epoch has nothing to compare to
+        let mut limited = StepMonitor::new().with_growth_limit(1.0);
+        limited.step(|| square(&x)).unwrap();
+        limited.end_epoch().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "a growth limit below 1 would reject steady steps, got 0.5")]
+    fn growth_limits_are_at_least_one() {
+        StepMonitor::new().with_growth_limit(0.5);
+    }
+}
@@ -0,0 +1,168 @@
+/// An exponential moving average of a series, such as the loss of each step, with the bias towards 0 of its
+/// first updates corrected as in Adam: after `t` updates the average is divided by `1 - beta^t`, so the first
+/// update gives the value itself rather than `(1 - beta)` times it.
+///
+/// Non-finite values (a NaN loss from a diverging step, say) are counted and left out of the average instead of
+/// turning it into NaN for good.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Smoothed {
+    beta: f64,
+    average: f64,
+    // beta^t after t updates
+    beta_power: f64,
+    updates: usize,
+    skipped: usize,
+}
+
+impl Smoothed {
+    /// An average in which each new value has weight `1 - beta`, e.g. 0.9 to average over roughly the last ten.
+    /// Panics unless `0 <= beta < 1`.
+    pub fn new(beta: f64) -> Smoothed {
+        assert!((0.0..1.0).contains(&beta), "the smoothing factor must be in [0, 1), got {}", beta);
+        Smoothed { beta, average: f64::NAN, beta_power: 1.0, updates: 0, skipped: 0 }
+    }
+
+    /// Adds `x` to the average and returns the new (bias-corrected) average. A non-finite `x` is skipped, and the
+    /// average returned unchanged.
+    pub fn update(&mut self, x: f64) -> f64 {
+        if !x.is_finite() {
+            self.skipped += 1;
+            return self.average;
+        }
+        self.updates += 1;
+        self.beta_power *= self.beta;
+        // the corrected average moves towards `x` by `(1 - beta) / (1 - beta^t)`, which is 1 for the first update
+        // and tends to `1 - beta`
+        let rate = (1.0 - self.beta) / (1.0 - self.beta_power);
+        self.average = if self.updates == 1 { x } else { self.average + rate * (x - self.average) };
+        self.average
+    }
+
+    /// The bias-corrected average, NaN before the first finite update.
+    pub fn value(&self) -> f64 {
+        self.average
+    }
+
+    /// The number of finite values averaged so far.
+    pub fn updates(&self) -> usize {
+        self.updates
+    }
+
+    /// The number of non-finite values skipped so far.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+/// Smoothed averages of several named series, e.g. the loss and accuracy of each step, all with the same `beta`.
+/// `metrics()` is in the form `Logger::log` takes, so that logged values aren't dominated by batch noise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricTracker {
+    beta: f64,
+    series: Vec<(String, Smoothed)>,
+}
+
+impl MetricTracker {
+    /// Panics unless `0 <= beta < 1`, as for `Smoothed::new`.
+    pub fn new(beta: f64) -> MetricTracker {
+        assert!((0.0..1.0).contains(&beta), "the smoothing factor must be in [0, 1), got {}", beta);
+        MetricTracker { beta, series: Vec::new() }
+    }
+
+    /// Adds `x` to the series `name`, starting it if it's new, and returns the series' new average.
+    pub fn update(&mut self, name: &str, x: f64) -> f64 {
+        let index = match self.series.iter().position(|(n, _)| n == name) {
+            Some(index) => index,
+            None => {
+                self.series.push((name.to_string(), Smoothed::new(self.beta)));
+                self.series.len() - 1
+            }
+        };
+        self.series[index].1.update(x)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Smoothed> {
+        self.series.iter().find(|(n, _)| n == name).map(|(_, smoothed)| smoothed)
+    }
+
+    /// The average of every series, in order of first update.
+    pub fn metrics(&self) -> Vec<(&str, f64)> {
+        self.series.iter().map(|(name, smoothed)| (name.as_str(), smoothed.value())).collect()
+    }
+
+    /// The series that have been given non-finite values, with how many, in order of first update.
+    pub fn flagged(&self) -> Vec<(&str, usize)> {
+        self.series
+            .iter()
+            .filter(|(_, smoothed)| smoothed.skipped() > 0)
+            .map(|(name, smoothed)| (name.as_str(), smoothed.skipped()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bias_correction_is_exact_at_the_first_update() {
+        for beta in [0.0, 0.5, 0.9, 0.999] {
+            let mut smoothed = Smoothed::new(beta);
+            assert!(smoothed.value().is_nan());
+            assert_eq!(smoothed.update(3.25), 3.25);
+        }
+    }
+
+    #[test]
+    fn bias_correction_matches_adam_and_fades_away() {
+        let (beta, xs) = (0.9, [1.0, 4.0, -2.0, 0.5]);
+        let mut smoothed = Smoothed::new(beta);
+        let mut raw = 0.0;
+        for (t, x) in xs.into_iter().enumerate() {
+            raw = beta * raw + (1.0 - beta) * x;
+            let corrected = raw / (1.0 - beta.powi(t as i32 + 1));
+            assert!((smoothed.update(x) - corrected).abs() < 1e-12);
+        }
+        // after many updates the correction `1 / (1 - beta^t)` is 1 to within rounding
+        for _ in 0..1000 {
+            raw = beta * raw + (1.0 - beta) * 2.0;
+            smoothed.update(2.0);
+        }
+        assert!((smoothed.value() - raw).abs() < 1e-12);
+    }
+
+    #[test]
+    fn a_constant_series_converges_to_the_constant() {
+        let mut smoothed = Smoothed::new(0.99);
+        smoothed.update(10.0);
+        for _ in 0..2000 {
+            smoothed.update(-1.5);
+        }
+        assert!((smoothed.value() + 1.5).abs() < 1e-6);
+        // and stays at it from the start when there is nothing else
+        let mut smoothed = Smoothed::new(0.99);
+        assert!((0..10).all(|_| (smoothed.update(-1.5) + 1.5).abs() < 1e-12));
+    }
+
+    #[test]
+    fn non_finite_values_are_flagged_without_poisoning_the_average() {
+        let mut tracker = MetricTracker::new(0.5);
+        tracker.update("loss", 2.0);
+        tracker.update("accuracy", 0.5);
+        assert_eq!(tracker.update("loss", f64::NAN), 2.0);
+        tracker.update("loss", f64::INFINITY);
+        assert_eq!(tracker.update("loss", 4.0), 2.0 + (0.5 / 0.75) * 2.0);
+
+        let loss = tracker.get("loss").unwrap();
+        assert_eq!((loss.updates(), loss.skipped()), (2, 2));
+        assert_eq!(tracker.flagged(), [("loss", 2)]);
+        assert_eq!(tracker.metrics(), [("loss", 2.0 + 4.0 / 3.0), ("accuracy", 0.5)]);
+        assert!(tracker.get("lr").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "the smoothing factor must be in [0, 1), got 1")]
+    fn a_smoothing_factor_of_one_is_rejected() {
+        MetricTracker::new(1.0);
+    }
+}
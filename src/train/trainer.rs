@@ -0,0 +1,559 @@
+use std::ops::Range;
+use std::time::Instant;
+
+use crate::engine::Value;
+use crate::error::{GradError, TrainError};
+use crate::loss;
+use crate::nn::{dedup_parameters, Module};
+use crate::optim::Optimizer;
+use crate::train::{Logger, MetricTracker, StepMonitor, StepStats};
+
+/// A training loop over a model and an optimizer: each step builds a loss from the model, back-propagates it and
+/// updates the parameters.
+///
+/// A step that can't be completed stops with an error and leaves the parameters untouched: a loss built with the
+/// checked ops (`Value::try_div`, `try_ln`, ...) reports bad inputs, and a loss or gradient that isn't finite is
+/// reported as `GradError::NonFinite` instead of being trained on.
+///
+/// With a `Logger` (see `with_logger`), every completed step is logged with its epoch, loss, the norm of the
+/// gradient and the learning rate it was taken with. With smoothing (see `with_smoothing`), the rows also hold
+/// the running averages of the loss and the gradient norm, `smoothed_loss` and `smoothed_grad_norm`, which batch
+/// noise doesn't dominate. With a `StepMonitor` (see `with_monitor`), every completed step is measured, the
+/// rows hold its measurements (nodes created and retained, peak live nodes and backward time) and `fit` checks
+/// the growth of the graphs between epochs.
+pub struct Trainer<M, O> {
+    model: M,
+    optimizer: O,
+    logger: Option<Logger>,
+    smoothing: Option<MetricTracker>,
+    monitor: Option<StepMonitor>,
+    // the epoch of the current or last run of `fit`, or counted by `end_epoch`, which steps are logged in
+    epoch: usize,
+}
+
+/// What `Trainer::fit` recorded over a run.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrainReport {
+    /// The loss of every step, in order.
+    pub losses: Vec<f64>,
+    /// The mean loss of the steps of each epoch.
+    pub epoch_losses: Vec<f64>,
+    /// The measurements of every step by the monitor of the trainer, in order, empty without one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub steps: Vec<StepStats>,
+}
+
+impl<M: Module, O: Optimizer> Trainer<M, O> {
+    pub fn new(model: M, optimizer: O) -> Trainer<M, O> {
+        Trainer { model, optimizer, logger: None, smoothing: None, monitor: None, epoch: 0 }
+    }
+
+    /// Logs every step into `logger`, e.g. a new `Logger`, or one holding the rows of an earlier run to go on
+    /// from.
+    pub fn with_logger(mut self, logger: Logger) -> Trainer<M, O> {
+        self.logger = Some(logger);
+        self
+    }
+
+    pub fn logger(&self) -> Option<&Logger> {
+        self.logger.as_ref()
+    }
+
+    /// The logger, e.g. to add metrics of the caller's to the last row with `Logger::add_metric`.
+    pub fn logger_mut(&mut self) -> Option<&mut Logger> {
+        self.logger.as_mut()
+    }
+
+    /// Keeps bias-corrected averages of the loss and the gradient norm of the completed steps, as a
+    /// `MetricTracker` with factor `beta`, and logs them with every step. Panics unless `0 <= beta < 1`.
+    pub fn with_smoothing(mut self, beta: f64) -> Trainer<M, O> {
+        self.smoothing = Some(MetricTracker::new(beta));
+        self
+    }
+
+    /// The averages kept by `with_smoothing`, under `smoothed_loss` and `smoothed_grad_norm`.
+    pub fn smoothed(&self) -> Option<&MetricTracker> {
+        self.smoothing.as_ref()
+    }
+
+    /// Measures every step with `monitor`, e.g. a `StepMonitor::new().with_growth_limit(1.5)`, and ends an epoch
+    /// of it with every epoch of `fit`, which stops with `TrainError::NodeGrowth` if the graphs grew past its
+    /// limit. The node counters are process-wide, see `StepMonitor`.
+    pub fn with_monitor(mut self, monitor: StepMonitor) -> Trainer<M, O> {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// The monitor set by `with_monitor`, holding the measurements of every step so far.
+    pub fn monitor(&self) -> Option<&StepMonitor> {
+        self.monitor.as_ref()
+    }
+
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    pub fn optimizer(&self) -> &O {
+        &self.optimizer
+    }
+
+    pub fn into_model(self) -> M {
+        self.model
+    }
+
+    /// Runs one step on the loss built by `loss_fn` from the model and returns the loss.
+    pub fn step(&mut self, loss_fn: impl FnOnce(&M) -> Result<Value, GradError>) -> Result<f64, TrainError> {
+        let params: Vec<Value> = dedup_parameters(self.model.parameters()).into_iter().cloned().collect();
+        for param in &params {
+            param.zero_grad();
+        }
+        let start = self.monitor.is_some().then(StepMonitor::start);
+        let loss = loss_fn(&self.model)?;
+        if !loss.data().is_finite() {
+            return Err(GradError::NonFinite { op: "loss" }.into());
+        }
+        let backward = Instant::now();
+        loss.backward()?;
+        let backward_time = backward.elapsed();
+        if params.iter().any(|param| !param.grad().is_finite()) {
+            return Err(GradError::NonFinite { op: "backward" }.into());
+        }
+        let grad_norm = params.iter().map(|param| param.grad().powi(2)).sum::<f64>().sqrt();
+        let lr = self.optimizer.lr();
+        self.optimizer.step(&params);
+        let data = loss.data();
+        drop(loss);
+        if let (Some(monitor), Some(start)) = (&mut self.monitor, start) {
+            monitor.record(start, data, backward_time);
+        }
+        if let Some(tracker) = &mut self.smoothing {
+            tracker.update("smoothed_loss", data);
+            tracker.update("smoothed_grad_norm", grad_norm);
+        }
+        if let Some(logger) = &mut self.logger {
+            let mut metrics = self.smoothing.as_ref().map_or_else(Vec::new, MetricTracker::metrics);
+            metrics.extend(self.monitor.as_ref().map_or_else(Vec::new, StepMonitor::metrics));
+            logger.log(self.epoch, data, grad_norm, lr, &metrics);
+        }
+        Ok(data)
+    }
+
+    /// Ends an epoch of a loop of `step`s run by the caller, as `fit` does after each of its epochs: the next
+    /// steps are logged in the next epoch and, with a monitor, the steps of the epoch are checked against the
+    /// growth limit of the monitor.
+    pub fn end_epoch(&mut self) -> Result<(), TrainError> {
+        self.epoch += 1;
+        match &mut self.monitor {
+            Some(monitor) => Ok(monitor.end_epoch()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Trains for `epochs` passes over the samples `x` with the targets `y`, one step per mini-batch of
+    /// `batch_size` consecutive samples (the last one possibly smaller), each on the `loss::mse_multi` of the
+    /// whole batch as built by `Module::forward_batch`. Stops at the first step that fails.
+    pub fn fit(
+        &mut self,
+        x: &[Vec<f64>],
+        y: &[Vec<f64>],
+        batch_size: usize,
+        epochs: usize,
+    ) -> Result<TrainReport, TrainError> {
+        assert_eq!(x.len(), y.len(), "{} samples for {} targets", x.len(), y.len());
+        self.run(x.len(), batch_size, epochs, None, |model, batch| {
+            loss::mse_multi(&model.forward_batch(&inputs(&x[batch.clone()])), &y[batch])
+        })
+    }
+
+    /// Same as `fit` with sample `i` counting `weights[i]` times, e.g. to rebalance classes without duplicating
+    /// samples: each step is on the mean squared error of each sample of the batch, over its outputs, scaled by
+    /// its weight and averaged over the sum of the weights of the batch (see `loss::weighted_mean`). A sample of
+    /// weight zero contributes no gradient, and batches whose weights all vanish take no step. Equal weights
+    /// train as `fit`, up to rounding.
+    ///
+    /// Panics unless there is one weight per sample, none is negative and they don't all vanish.
+    pub fn fit_weighted(
+        &mut self,
+        x: &[Vec<f64>],
+        y: &[Vec<f64>],
+        weights: &[f64],
+        batch_size: usize,
+        epochs: usize,
+    ) -> Result<TrainReport, TrainError> {
+        assert_eq!(x.len(), y.len(), "{} samples for {} targets", x.len(), y.len());
+        assert_eq!(x.len(), weights.len(), "{} samples for {} weights", x.len(), weights.len());
+        assert!(weights.iter().all(|&weight| weight >= 0.0), "sample weights must not be negative");
+        assert!(weights.iter().sum::<f64>() > 0.0, "the sample weights sum to zero");
+        self.run(x.len(), batch_size, epochs, Some(weights), |model, batch| {
+            let preds = model.forward_batch(&inputs(&x[batch.clone()]));
+            let terms: Vec<Value> =
+                std::iter::zip(&preds, &y[batch.clone()]).map(|(pred, target)| loss::mse(pred, target)).collect();
+            loss::weighted_mean(&terms, &weights[batch])
+        })
+    }
+
+    /// Same as `fit` for a classifier, e.g. a `SoftmaxClassifier`, whose outputs are the logits of the classes:
+    /// each step is on the mean `loss::cross_entropy` of the batch against the class indices `classes`.
+    pub fn fit_classes(
+        &mut self,
+        x: &[Vec<f64>],
+        classes: &[usize],
+        batch_size: usize,
+        epochs: usize,
+    ) -> Result<TrainReport, TrainError> {
+        assert_eq!(x.len(), classes.len(), "{} samples for {} classes", x.len(), classes.len());
+        self.run(x.len(), batch_size, epochs, None, |model, batch| {
+            let logits = model.forward_batch(&inputs(&x[batch.clone()]));
+            let terms: Vec<Value> = std::iter::zip(&logits, &classes[batch])
+                .map(|(logits, &class)| loss::cross_entropy(logits, class, 0.0))
+                .collect();
+            loss::mean(&terms)
+        })
+    }
+
+    // The loop of `fit` and its variants over `n` samples: `batch_loss` builds the loss of the samples of a
+    // batch, given by their range. Batches whose sample weights, if any, all vanish take no step.
+    fn run(
+        &mut self,
+        n: usize,
+        batch_size: usize,
+        epochs: usize,
+        weights: Option<&[f64]>,
+        batch_loss: impl Fn(&M, Range<usize>) -> Value,
+    ) -> Result<TrainReport, TrainError> {
+        assert!(batch_size > 0, "fit needs a positive batch size");
+        let mut report = TrainReport::default();
+        let recorded = self.monitor.as_ref().map_or(0, |monitor| monitor.steps().len());
+        for epoch in 0..epochs {
+            self.epoch = epoch;
+            let start = report.losses.len();
+            for batch in (0..n).step_by(batch_size).map(|start| start..n.min(start + batch_size)) {
+                if weights.is_some_and(|weights| weights[batch.clone()].iter().all(|&weight| weight == 0.0)) {
+                    continue;
+                }
+                let loss = self.step(|model| Ok(batch_loss(model, batch)))?;
+                report.losses.push(loss);
+            }
+            let losses = &report.losses[start..];
+            report.epoch_losses.push(losses.iter().sum::<f64>() / losses.len() as f64);
+            if let Some(monitor) = &self.monitor {
+                report.steps = monitor.steps()[recorded..].to_vec();
+            }
+            self.end_epoch()?;
+        }
+        Ok(report)
+    }
+}
+
+// The samples of a batch as constants of the graph, which take no gradient.
+fn inputs(x: &[Vec<f64>]) -> Vec<Vec<Value>> {
+    x.iter().map(|sample| sample.iter().map(|&x| Value::constant(x)).collect()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loss::{mse, mse_multi};
+    use crate::optim::Sgd;
+    use crate::tensor::Matrix;
+    use crate::Linear;
+
+    // `y = w0·x0 + w1·x1 + b`, from known weights
+    fn linear(w: [f64; 2], b: f64) -> Linear {
+        let weight = Matrix::new(1, 2, w.iter().map(|&w| Value::from(w)).collect());
+        Linear::from_weights(weight, Some(vec![Value::from(b)]))
+    }
+
+    fn output(model: &Linear, x: [f64; 2]) -> Value {
+        model.forward(&x.map(Value::from)).remove(0)
+    }
+
+    fn data(trainer: &Trainer<Linear, Sgd>) -> Vec<f64> {
+        Module::parameters(trainer.model()).iter().map(|p| p.data()).collect()
+    }
+
+    #[test]
+    fn steps_reduce_the_loss() {
+        let mut trainer = Trainer::new(linear([0.5, -0.5], 0.0), Sgd::new(0.1));
+        let loss_fn = |model: &Linear| Ok(mse(&[output(model, [1.0, 2.0])], &[3.0]));
+        let first = trainer.step(loss_fn).unwrap();
+        let mut last = first;
+        for _ in 0..20 {
+            last = trainer.step(loss_fn).unwrap();
+        }
+        assert!(last < first * 1e-3, "{} -> {}", first, last);
+    }
+
+    #[test]
+    fn errors_of_the_checked_ops_stop_the_step() {
+        let mut trainer = Trainer::new(linear([0.5, -0.5], 0.0), Sgd::new(0.1));
+        let before = data(&trainer);
+        // the output is -0.5, whose logarithm isn't defined
+        let error = trainer.step(|model| output(model, [1.0, 2.0]).try_ln()).unwrap_err();
+        assert_eq!(error, TrainError::Grad(GradError::DomainError { op: "ln", input: -0.5 }));
+        assert_eq!(data(&trainer), before);
+    }
+
+    #[test]
+    fn non_finite_losses_and_gradients_are_not_trained_on() {
+        let mut trainer = Trainer::new(linear([0.5, -0.5], 0.0), Sgd::new(0.1));
+        let before = data(&trainer);
+        let error = trainer.step(|model| Ok(&output(model, [1.0, 0.0]) * &Value::from(f64::NAN)));
+        assert_eq!(error.unwrap_err(), TrainError::Grad(GradError::NonFinite { op: "loss" }));
+        let error = trainer.step(|model| Ok(output(model, [1e300, 0.0]).powi(2)));
+        assert_eq!(error.unwrap_err(), TrainError::Grad(GradError::NonFinite { op: "loss" }));
+        // a finite loss whose gradient overflows: d/dw sqrt(w·x) at w·x = 0 is infinite
+        let error = trainer.step(|model| Ok(output(model, [0.0, 0.0]).sqrt()));
+        assert_eq!(error.unwrap_err(), TrainError::Grad(GradError::NonFinite { op: "backward" }));
+        assert_eq!(data(&trainer), before);
+    }
+
+    #[test]
+    fn fit_takes_one_step_per_batch_on_the_batch_loss() {
+        let (x, y) = (vec![vec![1.0, 2.0], vec![-1.0, 0.5], vec![0.0, 1.0]], vec![vec![1.0], vec![0.0], vec![2.0]]);
+        let mut trainer = Trainer::new(linear([0.5, -0.5], 0.1), Sgd::new(0.1));
+        let report = trainer.fit(&x, &y, 2, 1).unwrap();
+        assert_eq!((report.losses.len(), report.epoch_losses.len()), (2, 1));
+        assert_eq!(report.epoch_losses[0], (report.losses[0] + report.losses[1]) / 2.0);
+
+        // the same two steps, by hand
+        let mut manual = Trainer::new(linear([0.5, -0.5], 0.1), Sgd::new(0.1));
+        for (x, y) in [(&x[..2], &y[..2]), (&x[2..], &y[2..])] {
+            let batch: Vec<Vec<Value>> = x.iter().map(|x| x.iter().map(|&x| Value::from(x)).collect()).collect();
+            manual.step(|model| Ok(mse_multi(&model.forward_batch(&batch), y))).unwrap();
+        }
+        assert_eq!(data(&trainer), data(&manual));
+    }
+
+    #[test]
+    fn fit_learns_a_linear_function() {
+        let x: Vec<Vec<f64>> = (0..8).map(|i| vec![i as f64 / 4.0, (i % 3) as f64 / 2.0]).collect();
+        let y: Vec<Vec<f64>> = x.iter().map(|x| vec![2.0 * x[0] - x[1] + 0.5]).collect();
+        let mut trainer = Trainer::new(linear([0.0, 0.0], 0.0), Sgd::new(0.2));
+        let report = trainer.fit(&x, &y, 4, 200).unwrap();
+        assert!(report.epoch_losses.last().unwrap() < &1e-6);
+        assert!(report.epoch_losses[0] > 1.0);
+    }
+
+    fn weighted_data() -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        let x: Vec<Vec<f64>> = (0..6).map(|i| vec![i as f64 / 4.0, (i % 2) as f64]).collect();
+        let y: Vec<Vec<f64>> = x.iter().map(|x| vec![x[0] - 2.0 * x[1] + 1.0]).collect();
+        (x, y)
+    }
+
+    #[test]
+    fn equal_sample_weights_train_as_fit() {
+        let (x, y) = weighted_data();
+        let mut plain = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1));
+        let report = plain.fit(&x, &y, 4, 3).unwrap();
+        let mut weighted = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1));
+        let weighted_report = weighted.fit_weighted(&x, &y, &[2.5; 6], 4, 3).unwrap();
+        assert_eq!(weighted_report.losses.len(), report.losses.len());
+        for (weighted, plain) in std::iter::zip(weighted_report.losses, report.losses) {
+            assert!((weighted - plain).abs() < 1e-12, "{} != {}", weighted, plain);
+        }
+        for (weighted, plain) in std::iter::zip(data(&weighted), data(&plain)) {
+            assert!((weighted - plain).abs() < 1e-12, "{} != {}", weighted, plain);
+        }
+    }
+
+    #[test]
+    fn zero_weight_samples_contribute_no_gradient() {
+        let (x, y) = weighted_data();
+        let weights = [1.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        let mut weighted = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1));
+        weighted.fit_weighted(&x, &y, &weights, 6, 1).unwrap();
+
+        let kept: Vec<usize> = (0..6).filter(|&i| weights[i] > 0.0).collect();
+        let x_kept: Vec<Vec<f64>> = kept.iter().map(|&i| x[i].clone()).collect();
+        let y_kept: Vec<Vec<f64>> = kept.iter().map(|&i| y[i].clone()).collect();
+        let mut plain = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1));
+        plain.fit(&x_kept, &y_kept, 4, 1).unwrap();
+        for (weighted, plain) in std::iter::zip(data(&weighted), data(&plain)) {
+            assert!((weighted - plain).abs() < 1e-15, "{} != {}", weighted, plain);
+        }
+    }
+
+    #[test]
+    fn sample_weights_are_normalized_by_their_sum() {
+        let (x, y) = (vec![vec![1.0, 0.0], vec![0.0, 1.0]], vec![vec![2.0], vec![-1.0]]);
+        let mut trainer = Trainer::new(linear([0.5, 0.5], 0.0), Sgd::new(0.1));
+        let report = trainer.fit_weighted(&x, &y, &[3.0, 1.0], 2, 1).unwrap();
+        // predictions 0.5 and 0.5: errors -1.5 and 1.5
+        assert_eq!(report.losses, [(3.0 * 2.25 + 2.25) / 4.0]);
+        // d/dw0 = 3·2·(-1.5)/4, d/dw1 = 2·1.5/4, d/db = (3·2·(-1.5) + 2·1.5)/4
+        let grads = [-2.25, 0.75, -1.5];
+        let expected: Vec<f64> = std::iter::zip([0.5, 0.5, 0.0], grads).map(|(p, g)| p - 0.1 * g).collect();
+        for (param, expected) in std::iter::zip(data(&trainer), expected) {
+            assert!((param - expected).abs() < 1e-15, "{} != {}", param, expected);
+        }
+    }
+
+    #[test]
+    fn batches_of_zero_weight_take_no_step() {
+        let (x, y) = weighted_data();
+        let mut trainer = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1)).with_logger(Logger::new());
+        let report = trainer.fit_weighted(&x, &y, &[1.0, 1.0, 0.0, 0.0, 1.0, 0.0], 2, 2).unwrap();
+        assert_eq!(report.losses.len(), 4);
+        // the second batch of each epoch is skipped
+        let losses = &report.losses;
+        assert_eq!(report.epoch_losses, [(losses[0] + losses[1]) / 2.0, (losses[2] + losses[3]) / 2.0]);
+        assert_eq!(trainer.logger().unwrap().history().len(), 4);
+    }
+
+    #[test]
+    fn a_batch_of_equal_zero_weights_leaves_the_parameters_alone() {
+        let (x, y) = weighted_data();
+        let (x, y) = (&x[..4], &y[..4]);
+        let mut weighted = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1));
+        let report = weighted.fit_weighted(x, y, &[0.0, 0.0, 1.0, 1.0], 2, 1).unwrap();
+        assert_eq!(report.losses.len(), 1);
+
+        // the only step is the one on the second batch, so it starts from the initial parameters
+        let mut plain = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1));
+        plain.fit(&x[2..], &y[2..], 2, 1).unwrap();
+        for (weighted, plain) in std::iter::zip(data(&weighted), data(&plain)) {
+            assert!((weighted - plain).abs() < 1e-15, "{} != {}", weighted, plain);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the sample weights sum to zero")]
+    fn fit_weighted_needs_a_positive_weight() {
+        let (x, y) = weighted_data();
+        Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1)).fit_weighted(&x, &y, &[0.0; 6], 2, 1).unwrap();
+    }
+
+    #[test]
+    fn fit_classes_separates_blobs() {
+        use crate::data::make_blobs;
+        use crate::nn::SoftmaxClassifier;
+
+        let (x, classes) = make_blobs(150, 3, 1.0, 4);
+        let model = SoftmaxClassifier::new(2, 3, &mut crate::rand::Rng::seed(0));
+        let mut trainer = Trainer::new(model, Sgd::new(0.05));
+        let report = trainer.fit_classes(&x, &classes, 16, 30).unwrap();
+        assert!(report.epoch_losses.last().unwrap() < &(report.epoch_losses[0] / 4.0), "{:?}", report.epoch_losses);
+
+        let model = trainer.model();
+        let correct = std::iter::zip(&x, &classes).filter(|(x, &class)| model.predict(x) == class).count();
+        assert!(correct as f64 / x.len() as f64 > 0.95, "{} of {} correct", correct, x.len());
+    }
+
+    #[test]
+    fn fit_logs_every_step() {
+        let x: Vec<Vec<f64>> = (0..5).map(|i| vec![i as f64 / 4.0, 1.0]).collect();
+        let y: Vec<Vec<f64>> = x.iter().map(|x| vec![x[0] - 2.0]).collect();
+        let mut trainer = Trainer::new(linear([0.0, 0.0], 0.0), Sgd::new(0.1)).with_logger(Logger::new());
+        let report = trainer.fit(&x, &y, 2, 3).unwrap();
+        trainer.logger_mut().unwrap().add_metric("val_loss", 0.5);
+
+        let rows = trainer.logger().unwrap().history();
+        // three batches per epoch
+        assert_eq!(rows.len(), 9);
+        assert!(rows.iter().enumerate().all(|(i, row)| row.step == i && row.epoch == i / 3 && row.lr == 0.1));
+        let losses: Vec<f64> = rows.iter().map(|row| row.loss).collect();
+        assert_eq!(losses, report.losses);
+        assert_eq!(rows[8].metrics, [("val_loss".to_string(), 0.5)]);
+
+        // the norm of the gradient of the first step, at zero weights
+        let model = linear([0.0, 0.0], 0.0);
+        let loss = mse_multi(&model.forward_batch(&inputs(&x[..2])), &y[..2]);
+        loss.backward().unwrap();
+        let norm = Module::parameters(&model).iter().map(|p| p.grad().powi(2)).sum::<f64>().sqrt();
+        assert_eq!(rows[0].grad_norm, norm);
+    }
+
+    #[test]
+    fn failed_steps_are_not_logged() {
+        let mut trainer = Trainer::new(linear([0.5, -0.5], 0.0), Sgd::new(0.1)).with_logger(Logger::new());
+        assert!(trainer.step(|model| output(model, [1.0, 2.0]).try_ln()).is_err());
+        trainer.step(|model| Ok(mse(&[output(model, [1.0, 2.0])], &[3.0]))).unwrap();
+        assert_eq!(trainer.logger().unwrap().history().len(), 1);
+        assert!(Trainer::new(linear([0.0, 0.0], 0.0), Sgd::new(0.1)).logger().is_none());
+    }
+
+    #[test]
+    fn smoothed_averages_are_logged_with_every_step() {
+        let x: Vec<Vec<f64>> = (0..6).map(|i| vec![i as f64 / 4.0, 1.0]).collect();
+        let y: Vec<Vec<f64>> = x.iter().map(|x| vec![x[0] - 2.0]).collect();
+        let mut trainer =
+            Trainer::new(linear([0.0, 0.0], 0.0), Sgd::new(0.1)).with_logger(Logger::new()).with_smoothing(0.9);
+        trainer.fit(&x, &y, 2, 2).unwrap();
+
+        let rows = trainer.logger().unwrap().history();
+        let mut expected = crate::train::Smoothed::new(0.9);
+        for row in rows {
+            let names: Vec<&str> = row.metrics.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, ["smoothed_loss", "smoothed_grad_norm"]);
+            assert_eq!(row.metrics[0].1, expected.update(row.loss));
+        }
+        // the first average is the first loss itself
+        assert_eq!(rows[0].metrics[0].1, rows[0].loss);
+        assert_eq!(rows[0].metrics[1].1, rows[0].grad_norm);
+        let tracker = trainer.smoothed().unwrap();
+        assert_eq!(tracker.get("smoothed_loss").unwrap().updates(), rows.len());
+        assert_eq!(tracker.get("smoothed_loss").unwrap().value(), expected.value());
+        assert!(Trainer::new(linear([0.0, 0.0], 0.0), Sgd::new(0.1)).smoothed().is_none());
+    }
+
+    #[test]
+    fn monitored_runs_report_every_step() {
+        let (x, y) = weighted_data();
+        let mut trainer = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1))
+            .with_logger(Logger::new())
+            .with_monitor(StepMonitor::new());
+        let report = trainer.fit(&x, &y, 4, 3).unwrap();
+        assert_eq!(report.steps.len(), report.losses.len());
+        let numbers: Vec<(usize, usize, f64)> =
+            report.steps.iter().map(|stats| (stats.step, stats.epoch, stats.loss)).collect();
+        let expected: Vec<(usize, usize, f64)> = (0..6).map(|i| (i, i / 2, report.losses[i])).collect();
+        assert_eq!(numbers, expected);
+        assert_eq!(trainer.monitor().unwrap().steps(), &report.steps[..]);
+
+        // the rows hold the measurements of their steps
+        let rows = trainer.logger().unwrap().history();
+        for (row, stats) in std::iter::zip(rows, &report.steps) {
+            let names: Vec<&str> = row.metrics.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, ["nodes_created", "nodes_retained", "peak_live_nodes", "backward_ms"]);
+            assert_eq!(row.metrics[0].1, stats.nodes_created as f64);
+        }
+
+        // a second run reports its own steps, the monitor keeps them all
+        let again = trainer.fit(&x, &y, 4, 1).unwrap();
+        assert_eq!(again.steps.iter().map(|stats| stats.step).collect::<Vec<_>>(), [6, 7]);
+        assert_eq!(trainer.monitor().unwrap().steps().len(), 8);
+        assert!(Trainer::new(linear([0.0, 0.0], 0.0), Sgd::new(0.1)).fit(&x, &y, 4, 1).unwrap().steps.is_empty());
+    }
+
+    #[test]
+    fn end_epoch_counts_the_epochs_of_step_loops() {
+        let mut trainer = Trainer::new(linear([0.5, -0.5], 0.0), Sgd::new(0.1))
+            .with_logger(Logger::new())
+            .with_monitor(StepMonitor::new());
+        let loss_fn = |model: &Linear| Ok(mse(&[output(model, [1.0, 2.0])], &[3.0]));
+        for _ in 0..2 {
+            trainer.step(loss_fn).unwrap();
+            trainer.step(loss_fn).unwrap();
+            trainer.end_epoch().unwrap();
+        }
+        let epochs: Vec<usize> = trainer.logger().unwrap().history().iter().map(|row| row.epoch).collect();
+        assert_eq!(epochs, [0, 0, 1, 1]);
+        let epochs: Vec<usize> = trainer.monitor().unwrap().steps().iter().map(|stats| stats.epoch).collect();
+        assert_eq!(epochs, [0, 0, 1, 1]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn reports_serialize_with_their_steps() {
+        let (x, y) = weighted_data();
+        let mut trainer = Trainer::new(linear([0.3, -0.2], 0.1), Sgd::new(0.1)).with_monitor(StepMonitor::new());
+        let report = trainer.fit(&x, &y, 4, 2).unwrap();
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<TrainReport>(&json).unwrap(), report);
+        // reports written before the steps were recorded still load
+        let old: TrainReport = serde_json::from_str(r#"{"losses": [1.0, 0.5], "epoch_losses": [0.75]}"#).unwrap();
+        assert_eq!(old, TrainReport { losses: vec![1.0, 0.5], epoch_losses: vec![0.75], steps: Vec::new() });
+    }
+}
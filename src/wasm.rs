@@ -0,0 +1,128 @@
+// A wasm-bindgen surface for demos in the browser: a graph built from a JSON spec, back-propagated on demand,
+// with its nodes returned as JSON and its picture as DOT for rendering with e.g. viz.js.
+//
+// The spec names the leaves and gives the expression over them in the syntax of `engine::parse`:
+//
+// {"expr": "tanh(w1*x1 + w2*x2 + b)", "vars": {"w1": -3.0, "x1": 2.0, "w2": 1.0, "x2": 0.0, "b": 6.88}}
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::engine::{parse_labeled, Value};
+
+#[derive(Deserialize)]
+struct Spec {
+    expr: String,
+    vars: HashMap<String, f64>,
+}
+
+#[derive(Serialize)]
+struct NodeInfo {
+    id: u64,
+    label: Option<String>,
+    op: Option<String>,
+    data: f64,
+    grad: f64,
+    children: Vec<u64>,
+}
+
+// The root of the graph of `spec`.
+fn build(spec: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    let spec: Spec = serde_json::from_str(spec)?;
+    let vars = spec
+        .vars
+        .into_iter()
+        .map(|(name, data)| (name.clone(), Value::from(data).add_label(&name)))
+        .collect();
+    Ok(parse_labeled(&spec.expr, &vars)?)
+}
+
+/// A graph built from a spec, as `Graph` in JavaScript.
+#[wasm_bindgen(js_name = Graph)]
+pub struct WasmGraph {
+    root: Value,
+}
+
+#[wasm_bindgen(js_class = Graph)]
+impl WasmGraph {
+    /// Builds the graph of `spec`, throwing if it isn't valid JSON or the expression doesn't parse.
+    #[wasm_bindgen(constructor)]
+    pub fn new(spec: &str) -> Result<WasmGraph, JsError> {
+        let root = build(spec).map_err(|error| JsError::new(&error.to_string()))?;
+        Ok(WasmGraph { root })
+    }
+
+    /// Back-propagates from the root into grads zeroed first, throwing if the graph can't be back-propagated.
+    pub fn backward(&self) -> Result<(), JsError> {
+        self.root.zero_grad_all();
+        Ok(self.root.backward()?)
+    }
+
+    /// The nodes in topological order, leaves first and the root last, as a JSON array of objects with their
+    /// `id`, `label`, `op`, `data`, `grad` and the ids of their `children`.
+    pub fn nodes(&self) -> String {
+        let nodes: Vec<NodeInfo> = self
+            .root
+            .topo_order()
+            .iter()
+            .map(|value| NodeInfo {
+                id: value.id().0,
+                label: value.label(),
+                op: value.op().map(|op| op.to_string()),
+                data: value.data(),
+                grad: value.grad(),
+                children: value.children().iter().map(|child| child.id().0).collect(),
+            })
+            .collect();
+        serde_json::to_string(&nodes).expect("nodes serialize to JSON")
+    }
+
+    /// The graph in Graphviz DOT, see `Value::to_dot`.
+    pub fn to_dot(&self) -> String {
+        self.root.to_dot()
+    }
+}
+
+// `JsError`s can only be made on wasm targets: natively, these tests check the graphs and the errors before
+// their conversion.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEURON: &str = r#"{"expr": "tanh(w1*x1 + w2*x2 + b)",
+        "vars": {"w1": -3.0, "x1": 2.0, "w2": 1.0, "x2": 0.0, "b": 6.8813735870195432}}"#;
+
+    #[test]
+    fn graphs_back_propagate_and_list_their_nodes() {
+        let graph = WasmGraph::new(NEURON).unwrap();
+        graph.backward().unwrap();
+        // twice, from zeroed grads
+        graph.backward().unwrap();
+
+        let nodes: Vec<serde_json::Value> = serde_json::from_str(&graph.nodes()).unwrap();
+        let node = |label: &str| nodes.iter().find(|node| node["label"] == label).unwrap().clone();
+        let root = nodes.last().unwrap();
+        assert_eq!(root["op"], "tanh");
+        assert!((root["data"].as_f64().unwrap() - 0.5f64.sqrt()).abs() < 1e-12);
+        // d tanh = 1 - tanh² = 0.5
+        assert!((node("w1")["grad"].as_f64().unwrap() - 1.0).abs() < 1e-12);
+        assert!((node("x1")["grad"].as_f64().unwrap() + 1.5).abs() < 1e-12);
+        assert_eq!(node("w2")["grad"], 0.0);
+        let ids: Vec<u64> = nodes.iter().map(|node| node["id"].as_u64().unwrap()).collect();
+        for node in &nodes {
+            let children = node["children"].as_array().unwrap();
+            assert!(children.iter().all(|child| ids.contains(&child.as_u64().unwrap())));
+        }
+
+        assert!(graph.to_dot().starts_with("digraph"));
+    }
+
+    #[test]
+    fn bad_specs_are_errors() {
+        assert!(build("{").unwrap_err().to_string().contains("EOF"));
+        assert!(build(r#"{"expr": "x +", "vars": {"x": 1.0}}"#).is_err());
+        assert!(build(r#"{"expr": "x * y", "vars": {"x": 1.0}}"#).is_err());
+    }
+}
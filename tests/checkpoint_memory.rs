@@ -0,0 +1,43 @@
+// The live node counter is process-wide, so the nodes a checkpointed chain keeps are counted from a single test
+// in a binary of its own, as in `node_limit`.
+
+use angstromgrad::engine::{checkpoint, live_node_count};
+use angstromgrad::Value;
+
+const LAYERS: usize = 50;
+
+fn layer(w: &Value) -> impl Fn(&[Value]) -> Vec<Value> + 'static {
+    let w = w.clone();
+    move |xs: &[Value]| vec![(&(&xs[0] * &w) + &xs[0].powi(2)).tanh()]
+}
+
+#[test]
+fn checkpointed_chains_keep_fewer_nodes() {
+    let weights: Vec<Value> = (0..LAYERS).map(|i| Value::from(0.01 * i as f64)).collect();
+    let x = Value::from(0.5);
+
+    let before = live_node_count();
+    let plain = weights.iter().fold(x.clone(), |h, w| layer(w)(&[h]).remove(0));
+    let plain_nodes = live_node_count() - before;
+    plain.backward().unwrap();
+    let expected: Vec<f64> = weights.iter().map(Value::grad).collect();
+    drop(plain);
+    assert_eq!(live_node_count(), before);
+
+    let x_grad = x.grad();
+    x.zero_grad();
+    for w in &weights {
+        w.zero_grad();
+    }
+    let checkpointed = weights.iter().fold(x.clone(), |h, w| checkpoint(layer(w), &[h]).remove(0));
+    let checkpointed_nodes = live_node_count() - before;
+    // one node per segment, against the 4 to 5 of each plain layer
+    assert_eq!(checkpointed_nodes, LAYERS);
+    assert!(4 * checkpointed_nodes <= plain_nodes, "{} nodes against {}", checkpointed_nodes, plain_nodes);
+
+    checkpointed.backward().unwrap();
+    assert_eq!(weights.iter().map(Value::grad).collect::<Vec<_>>(), expected);
+    assert_eq!(x.grad(), x_grad);
+    // the nodes built by the backward pass are dropped with it
+    assert_eq!(live_node_count() - before, LAYERS);
+}
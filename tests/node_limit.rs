@@ -0,0 +1,62 @@
+// The live node counter and the node limit are process-wide, so they are checked from a single test in a binary
+// of their own: tests running in parallel would change the count, and a limit would stop them.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+use angstromgrad::engine::{live_node_count, set_node_limit};
+use angstromgrad::{ops, GradError, Value};
+
+#[test]
+fn node_counter_and_limit() {
+    // construction and drop
+    let before = live_node_count();
+    let (x, y) = (Value::from(2.0), Value::from(3.0));
+    let z = (&x * &y).tanh();
+    assert_eq!(live_node_count(), before + 4);
+    drop(z);
+    assert_eq!(live_node_count(), before + 2);
+    drop((x, y));
+    assert_eq!(live_node_count(), before);
+
+    // nodes alive on other threads are counted until those threads drop them
+    let barrier = Arc::new(Barrier::new(5));
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                let leaves: Vec<Value> = (0..100).map(|i| Value::from(i as f64)).collect();
+                let total = ops::add_n(&leaves);
+                barrier.wait();
+                barrier.wait();
+                total.data()
+            })
+        })
+        .collect();
+    barrier.wait();
+    let alive = live_node_count() - before;
+    barrier.wait();
+    for worker in workers {
+        assert_eq!(worker.join().unwrap(), 4950.0);
+    }
+    // 100 leaves and their sum on each thread
+    assert_eq!(alive, 4 * 101);
+    assert_eq!(live_node_count(), before);
+
+    // past the limit, checked ops fail and other constructions panic
+    let x = Value::from(4.0);
+    set_node_limit(Some(live_node_count() + 1));
+    // a division needs up to 3 nodes, one still fits
+    assert_eq!(x.try_div(&x).unwrap_err(), GradError::NodeLimit { limit: before + 2, op: "div" });
+    let y = x.tanh();
+    let panic = panic::catch_unwind(AssertUnwindSafe(|| y.exp())).unwrap_err();
+    let message = panic.downcast_ref::<String>().unwrap();
+    let expected = format!("node limit of {0} reached with {0} live nodes", before + 2);
+    assert_eq!(message, &format!("{} while constructing a exp node", expected));
+    assert_eq!(live_node_count(), before + 2);
+
+    // and removing the limit lets them through again
+    set_node_limit(None);
+    assert_eq!(x.try_ln().unwrap().data(), 4f64.ln());
+}
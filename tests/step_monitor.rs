@@ -0,0 +1,71 @@
+// The node counters measured by `StepMonitor` are process-wide, so the counts of training steps are checked from
+// a single test in a binary of its own, as in `node_limit`.
+
+use std::cell::RefCell;
+
+use angstromgrad::loss::mse;
+use angstromgrad::optim::Sgd;
+use angstromgrad::tensor::Matrix;
+use angstromgrad::train::{StepMonitor, Trainer};
+use angstromgrad::{Linear, TrainError, Value};
+
+fn model() -> Linear {
+    let weight = Matrix::new(1, 2, vec![Value::from(0.3), Value::from(-0.2)]);
+    Linear::from_weights(weight, Some(vec![Value::from(0.1)]))
+}
+
+fn samples() -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    let x: Vec<Vec<f64>> = (0..8).map(|i| vec![i as f64 / 4.0, (i % 3) as f64]).collect();
+    let y: Vec<Vec<f64>> = x.iter().map(|x| vec![x[0] - x[1]]).collect();
+    (x, y)
+}
+
+#[test]
+fn trainer_step_measurements() {
+    // a well-behaved run builds and frees the same graph at every step
+    let (x, y) = samples();
+    let monitor = StepMonitor::new().with_growth_limit(1.5);
+    let mut trainer = Trainer::new(model(), Sgd::new(0.05)).with_monitor(monitor);
+    let report = trainer.fit(&x, &y, 4, 5).unwrap();
+    assert_eq!(report.steps.len(), 10);
+    // the first step also builds the constants shared by every graph (see `Value::constant`), which stay alive
+    let (first, second) = (report.steps[0], report.steps[1]);
+    assert_eq!(first.nodes_retained as u64, first.nodes_created - second.nodes_created);
+    assert_eq!(first.peak_live_nodes, second.peak_live_nodes);
+    for stats in &report.steps[1..] {
+        assert_eq!(stats.nodes_created, second.nodes_created);
+        assert_eq!(stats.peak_live_nodes, second.peak_live_nodes);
+        assert_eq!(stats.nodes_retained, 0);
+    }
+
+    // a loss closure holding on to the previous loss and building on it keeps every graph alive
+    let mut trainer = Trainer::new(model(), Sgd::new(0.05)).with_monitor(StepMonitor::new().with_growth_limit(1.5));
+    let previous: RefCell<Option<Value>> = RefCell::new(None);
+    let leaky = |model: &Linear| {
+        let pred = model.forward(&[Value::constant(1.0), Value::constant(2.0)]).remove(0);
+        let mut loss = mse(&[pred], &[0.5]);
+        if let Some(previous) = previous.borrow().as_ref() {
+            loss = &loss + &(previous * &Value::constant(0.0));
+        }
+        *previous.borrow_mut() = Some(loss.clone());
+        Ok(loss)
+    };
+    let mut result = Ok(());
+    for _ in 0..3 {
+        for _ in 0..10 {
+            trainer.step(leaky).unwrap();
+        }
+        result = trainer.end_epoch();
+        if result.is_err() {
+            break;
+        }
+    }
+    let Err(TrainError::NodeGrowth(error)) = result else {
+        panic!("expected a growth error, got {:?}", result);
+    };
+    assert_eq!((error.metric, error.epoch), ("peak live nodes", 1));
+    assert!(error.current > 1.5 * error.previous, "{:?}", error);
+    let steps = trainer.monitor().unwrap().steps();
+    assert!(steps[1..].iter().all(|stats| stats.nodes_retained > 0));
+    assert!(steps.windows(2).all(|pair| pair[1].peak_live_nodes > pair[0].peak_live_nodes));
+}